@@ -0,0 +1,62 @@
+; Built-in ROM properties database (same format as Stella's '.pro' files).
+;
+; Entries are matched by "Cart.MD5" or "Cart.SHA1" and end with an empty value (""), ie:
+;
+; "Cart.MD5" "<md5 of the rom>"
+; "Cart.Name" "<name>"
+; "Cart.Type" "F8SC"                    (any '-c' cartridge type, or 2K/4K/FA/AUTO)
+; "Display.Format" "PAL"                (NTSC/PAL)
+; "Display.VCenter" "0"                 (scan lines to move the picture up)
+; "Controller.Left" "JOYSTICK"          (JOYSTICK/PADDLES/DRIVING/KEYBOARD)
+; "Controller.Right" "JOYSTICK"
+; "Console.LeftDifficulty" "B"          (A/B)
+; "Console.RightDifficulty" "B"
+; ""
+;
+; Additional entries (or overrides) can be supplied with '--properties <file>'.
+
+"Cart.MD5" "27c6a2ca16ad7d814626ceea62fa8fb4"
+"Cart.Name" "Frogger II - Threeedeep! (1984) (Parker Bros)"
+"Cart.Type" "E0"
+""
+
+"Cart.MD5" "3347a6dd59049b15a38394aa2dafa585"
+"Cart.Name" "Montezuma's Revenge (1984) (Parker Bros)"
+"Cart.Type" "E0"
+""
+
+"Cart.MD5" "fd0e5148162e8ec6719445d559f018a9"
+"Cart.Name" "BurgerTime (1983) (M Network)"
+"Cart.Type" "E7"
+""
+
+"Cart.MD5" "6d842c96d5a01967be9680080dd5be54"
+"Cart.Name" "Pitfall II - Lost Caverns (1984) (Activision)"
+"Cart.Type" "DPC"
+""
+
+"Cart.MD5" "ac7c2260378975614192ca2bc3d20e0b"
+"Cart.Name" "Decathlon (1983) (Activision)"
+"Cart.Type" "FE"
+""
+
+"Cart.MD5" "4f618c2429138e0280969193ed6c107e"
+"Cart.Name" "Robot Tank (1983) (Activision)"
+"Cart.Type" "FE"
+""
+
+"Cart.MD5" "f34f08e5eb96e500e851a80be3277a56"
+"Cart.Name" "Breakout (1978) (Atari)"
+"Controller.Left" "PADDLES"
+""
+
+"Cart.MD5" "5428cdfada281c569c74c7308c7f2c26"
+"Cart.Name" "Kaboom! (1981) (Activision)"
+"Controller.Left" "PADDLES"
+""
+
+"Cart.MD5" "cbe5a166550a8129a5e6d374901dffad"
+"Cart.Name" "Warlords (1981) (Atari)"
+"Controller.Left" "PADDLES"
+"Controller.Right" "PADDLES"
+""
@@ -1,6 +1,7 @@
 //! Main container for the Atari2600
 //!
 //! Holds all of the components/sub-systems and settings for the Atari. Although it's also coupled to the output (ie display and audio out).
+//! The SDL output is optional (the 'sdl' feature), 'run_headless' runs without any display/audio device.
 //!
 //! Initially structures so that it would 'work', there's definitely some more
 //! improvements to make interaction a bit more 'natural'.  Currently only
@@ -19,56 +20,57 @@
 
 use super::audio::sound;
 use super::clocks;
-use super::cpu;
+use super::debugger;
+use super::emulator;
 use super::graphics;
 use super::inputs;
 use super::memory;
-use super::ports;
+use super::properties;
+use super::symbols;
+use std::io::Write;
 
+#[cfg(feature = "sdl")]
 use sdl2::pixels;
+#[cfg(feature = "sdl")]
 use sdl2::rect;
+#[cfg(feature = "sdl")]
 use sdl2::render;
+#[cfg(feature = "sdl")]
 use sdl2::video;
 
 /// Main container and control point for the Atari Emulator.
 pub struct Atari2600 {
-    core: cpu::core::Core,
-    debug: bool,
-    realtime: bool,
+    emulator: emulator::Emulator,
+    joysticks: inputs::Joystick,
     stop_clock: clocks::ClockType,
     fullscreen: bool,
-    counter: u32,
+    cartridge_name: String,
+    #[cfg(feature = "sdl")]
+    state_slot: u8,
     pub powered: bool,
 
     // These appear as 'Options' to simplify delayed initialisation.
+    #[cfg(feature = "sdl")]
     sdl_context: Option<sdl2::Sdl>,
+    #[cfg(feature = "sdl")]
     canvas: Option<render::Canvas<video::Window>>,
+    #[cfg(feature = "sdl")]
     audio_queue: Option<Box<dyn sound::SoundQueue>>,
 }
 
 impl Atari2600 {
+    #[cfg(feature = "sdl")]
     const DISPLAY_UPDATES_PER_KEY_EVENT: u32 = 10000; // Number of display updates per key press event. (reduces texture creation overhead).
+    #[cfg(feature = "sdl")]
     const CPU_STEPS_PER_AUDIO_UPDATE: u32 = 50; // Number of times to step the CPU before updating the audio.
-
-    pub fn build_atari2600(cartridge_name: &String, cartridge_type: &memory::cartridge::CartridgeType, debug: bool, realtime: bool, pal_palette: bool) -> cpu::core::Core {
-        let clock = clocks::Clock::new();
-        let pc_state = cpu::pc_state::PcState::new();
-        let stella = graphics::stella::Stella::new(debug, realtime, pal_palette);
-        let riot = memory::riot::Riot::new();
-        let memory = memory::memory::Memory::new(cartridge_name, cartridge_type, Box::new(stella), Box::new(riot));
-        let ports = ports::Ports::new();
-
-        let mut core = cpu::core::Core::new(clock, memory, pc_state, ports);
-        core.reset();
-
-        core
-    }
+    #[cfg(feature = "sdl")]
+    const NUM_STATE_SLOTS: u8 = 10;
 
     pub fn get_console_size() -> graphics::display::ConsoleSize {
-        graphics::display::ConsoleSize::new(graphics::stella::Constants::ATARI2600_WIDTH, graphics::stella::Constants::ATARI2600_HEIGHT)
+        graphics::display::ConsoleSize::new(emulator::Emulator::WIDTH, emulator::Emulator::HEIGHT)
     }
 
-    pub fn get_window_size() -> graphics::display::WindowSize {
+    pub fn get_window_size(&self) -> graphics::display::WindowSize {
         // Default scaling (if not full screen)
         const PIXEL_WIDTH: u8 = 2;
         const PIXEL_HEIGHT: u8 = 2;
@@ -80,9 +82,10 @@ impl Atari2600 {
         let frame_height = ((frame_width as u32) * (BLIT_HEIGHT as u32) / (BLIT_WIDTH as u32)) as u16;
 
         let console_size = Self::get_console_size();
-        graphics::display::WindowSize::new(frame_width, frame_height, console_size, false)
+        graphics::display::WindowSize::new(frame_width, frame_height, console_size, self.fullscreen)
     }
 
+    #[cfg(feature = "sdl")]
     pub fn run_atari2600(me: &mut Atari2600) -> bool {
         let console_size = Self::get_console_size();
 
@@ -91,12 +94,17 @@ impl Atari2600 {
         for event in event_pump.poll_iter() {
             graphics::display::SDLUtility::handle_events(&event);
 
-            if !inputs::UserInput::handle_events(event, &mut me.core.ports.joysticks) {
+            if let Some(action) = inputs::UserInput::state_action(&event) {
+                me.handle_state_action(action);
+            }
+
+            if !inputs::UserInput::handle_events(event, &mut me.joysticks) {
                 return false;
             };
-            me.core.memory.riot.set_inputs(me.core.ports.joysticks.input);
-            me.core.memory.stella.set_inputs(me.core.ports.joysticks.input);
+            me.emulator.set_input(me.joysticks.input);
         }
+        me.joysticks.update_paddles();
+        me.emulator.set_input(me.joysticks.input);
 
         // Need to temporarily move the mutable fields out of 'self' to avoid multiple borrows of mutable self.
         if !me.draw_loop(pixel_format, &console_size, Atari2600::DISPLAY_UPDATES_PER_KEY_EVENT) {
@@ -105,25 +113,141 @@ impl Atari2600 {
         true
     }
 
+    #[cfg(feature = "sdl")]
     pub fn power_atari2600(&mut self) {
         inputs::UserInput::print_keys();
 
-        let window_size = Self::get_window_size();
+        let window_size = self.get_window_size();
 
         self.configure_sdl(window_size, graphics::display::SDLUtility::PIXEL_FORMAT);
         self.powered = true;
     }
 
-    pub fn new(debug: bool, realtime: bool, stop_clock: clocks::ClockType, cartridge_name: &String, cartridge_type: &memory::cartridge::CartridgeType, fullscreen: bool, pal_palette: bool) -> Self {
-        let core = Self::build_atari2600(cartridge_name, cartridge_type, debug, realtime, pal_palette);
-        Self { core, debug, realtime, stop_clock, fullscreen, counter:0, powered:false, sdl_context:None , canvas:None, audio_queue:None}
+    pub fn new(debug: bool, realtime: bool, stop_clock: clocks::ClockType, fullscreen: bool, pal_palette: bool) -> Self {
+        Self { emulator: emulator::Emulator::new(debug, realtime, pal_palette), joysticks: inputs::Joystick::new(), stop_clock, fullscreen, cartridge_name:String::new(), powered:false,
+               #[cfg(feature = "sdl")]
+               state_slot:0,
+               #[cfg(feature = "sdl")]
+               sdl_context:None,
+               #[cfg(feature = "sdl")]
+               canvas:None,
+               #[cfg(feature = "sdl")]
+               audio_queue:None}
+    }
+
+    /// Insert a cartridge (from the raw ROM data) and reset. 'cartridge_name' is used for reporting and the save state filenames.
+    pub fn insert_cartridge(&mut self, cartridge_name: &str, data: &[u8], cartridge_type: &memory::cartridge::CartridgeType) {
+        self.emulator.load_rom(data, *cartridge_type);
+        self.joysticks.input = self.emulator.input(); // Pick up the ROM's console switch defaults.
+        let properties = self.emulator.rom_properties();
+        let (left, right) = (properties.left_controller.unwrap_or(properties::Controller::Joystick), properties.right_controller.unwrap_or(properties::Controller::Joystick));
+        self.joysticks.set_controllers(left, right);
+        self.cartridge_name = cartridge_name.to_string();
+    }
+
+    pub fn load_cartridge_file(&mut self, cartridge_name: &str, cartridge_type: &memory::cartridge::CartridgeType) -> std::io::Result<()> {
+        let data = std::fs::read(cartridge_name)?;
+        self.insert_cartridge(cartridge_name, &data, cartridge_type);
+        let symbols = symbols::Symbols::load(cartridge_name)?;
+        self.emulator.set_symbols(symbols);
+        Ok(())
+    }
+
+    pub fn emulator(&mut self) -> &mut emulator::Emulator {
+        &mut self.emulator
+    }
+
+    pub fn save_state_file(&self, filename: &str) -> std::io::Result<()> {
+        std::fs::write(filename, self.emulator.save_state())
+    }
+
+    pub fn load_state_file(&mut self, filename: &str) -> std::io::Result<()> {
+        let data = std::fs::read(filename)?;
+        self.emulator.load_state(&data)
     }
 
-    pub fn reset(&mut self, cartridge_name: &String, cartridge_type: &memory::cartridge::CartridgeType) {
-        self.core.memory.reset(cartridge_name, cartridge_type);
-        self.core.reset();
+    #[cfg(feature = "sdl")]
+    fn state_slot_filename(&self) -> String {
+        format!("{}.state{}", self.cartridge_name, self.state_slot)
     }
 
+    #[cfg(feature = "sdl")]
+    fn handle_state_action(&mut self, action: inputs::StateAction) {
+        let filename = self.state_slot_filename();
+        match action {
+            inputs::StateAction::Save => match self.save_state_file(&filename) {
+                Ok(()) => println!("State saved: {}", filename),
+                Err(e) => println!("Error saving state \"{}\". {}", filename, e),
+            },
+            inputs::StateAction::Load => match self.load_state_file(&filename) {
+                Ok(()) => println!("State loaded: {}", filename),
+                Err(e) => println!("Error loading state \"{}\". {}", filename, e),
+            },
+            inputs::StateAction::NextSlot => {
+                self.state_slot = (self.state_slot + 1) % Atari2600::NUM_STATE_SLOTS;
+                println!("State slot: {}", self.state_slot);
+            }
+            inputs::StateAction::RewindStart => {
+                if let Some(rewind) = self.emulator.rewind() {
+                    println!("Rewinding ({} snapshots, {} bytes)", rewind.len(), rewind.used_memory());
+                    self.emulator.set_rewinding(true);
+                }
+            }
+            inputs::StateAction::RewindStop => {
+                self.emulator.set_rewinding(false);
+            }
+        }
+    }
+
+    fn is_stop_time(&self) -> bool {
+        self.stop_clock > 0 && self.emulator.clock_ticks() > self.stop_clock
+    }
+
+    /// Run without a display or audio device, for 'frames' frames (0 for no limit, ie until the stop clock).
+    /// Afterwards the last frame (PPM), all of the generated audio (WAV) and the RAM are written to
+    /// "<output>.ppm", "<output>.wav" and "<output>.ram".
+    pub fn run_headless(&mut self, frames: u32, output: &str) -> std::io::Result<()> {
+        let mut audio = Vec::new();
+        let mut frames_run = 0;
+
+        while (0 == frames || frames_run < frames) && !self.is_stop_time() {
+            if self.emulator.step() {
+                audio.append(&mut self.emulator.audio_samples());
+                frames_run += 1;
+            }
+        }
+        audio.append(&mut self.emulator.audio_samples());
+
+        println!("Headless: {} frames, {} clocks", frames_run, self.emulator.clock_ticks());
+
+        Atari2600::write_ppm(&format!("{}.ppm", output), &Self::get_console_size(), self.emulator.framebuffer())?;
+
+        let mut wav = sound::HoundOutput::new(&format!("{}.wav", output));
+        wav.write(&audio);
+
+        let ram: Vec<u8> = (0..0x80).map(|address| self.emulator.peek_ram(address)).collect();
+        std::fs::write(format!("{}.ram", output), ram)?;
+
+        Ok(())
+    }
+
+    /// Run the debugger console on stdin/stdout (without a display or audio device).
+    pub fn run_debugger(&mut self) -> std::io::Result<()> {
+        let mut debugger = debugger::Debugger::new();
+        debugger.run_console(&mut self.emulator, &mut std::io::stdin().lock(), &mut std::io::stdout())
+    }
+
+    // Binary PPM, converting from the generated ('B, G, R, unused') display.
+    fn write_ppm(filename: &str, console_size: &graphics::display::ConsoleSize, buffer: &[u8]) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        write!(file, "P6\n{} {}\n255\n", console_size.console_width, console_size.console_height)?;
+        for pixel in buffer.chunks(graphics::display::BYTES_PER_PIXEL as usize) {
+            file.write_all(&[pixel[2], pixel[1], pixel[0]])?;
+        }
+        file.flush()
+    }
+
+    #[cfg(feature = "sdl")]
     pub fn draw_loop(&mut self,  pixel_format: pixels::PixelFormatEnum, console_size: &graphics::display::ConsoleSize, iterations: u32) -> bool {
         // Number of iterations to do before getting a new texture.
         // These loops will update the display, but currently events aren't checked in this time.
@@ -131,80 +255,53 @@ impl Atari2600 {
         let mut audio_steps = 0;
         let mut display_refreshes = 0;
 
-        if self.canvas.is_some() {
-            let canvas = self.canvas.as_mut().expect("Optional canvas not set");
+        // Creating the texture creator and texture is slow, so perform multiple display updates per creation.
+        let texture_creator = self.canvas.as_mut().map(|canvas| graphics::display::SDLUtility::texture_creator(canvas));
+        let mut texture = texture_creator.as_ref().map(|texture_creator| graphics::display::SDLUtility::create_texture(texture_creator, pixel_format, console_size.console_width, console_size.console_height));
 
-            // Creating the texture creator and texture is slow, so perform multiple display updates per creation.
-            let texture_creator = graphics::display::SDLUtility::texture_creator(canvas);
-            let mut texture = graphics::display::SDLUtility::create_texture(&texture_creator, pixel_format, console_size.console_width, console_size.console_height);
+        while display_refreshes < iterations {
+            if self.is_stop_time() {
+                return false;
+            }
+            let new_frame = self.emulator.step();
 
-            while display_refreshes < iterations {
-                if self.stop_clock > 0 && self.core.clock.ticks > self.stop_clock {
-                    return false;
-                }
-                self.core.step(self.debug, self.realtime);
-                self.core.memory.stella.step_tia_sound(&self.core.clock);
-
-                if 0 == audio_steps % Atari2600::CPU_STEPS_PER_AUDIO_UPDATE {
-                    // Top-up the audio queue
-                    // TODO: Change this thing of beauty to something even better. 
-                    if self.audio_queue.is_some() {
-                        let audio_queue = self.audio_queue.as_mut().expect("Optional audio not set");
-                        sound::SDLUtility::top_up_audio_queue(&mut **audio_queue, |fill_size| self.core.memory.stella.get_next_audio_chunk(fill_size));
-                    }
-                }
-                audio_steps += 1;
-
-                if self.core.memory.stella.export() {
-                    texture.with_lock(None, |buffer: &mut [u8], _pitch: usize| self.core.memory.stella.generate_display(buffer)).unwrap();
-
-                    canvas.clear();
-                    canvas
-                            .copy(
-                                &texture,
-                                None,
-                                Some(rect::Rect::new(0, 0, graphics::stella::Constants::PIXEL_WIDTH_STRETCH as u32 * console_size.console_width as u32, console_size.console_height as u32)),
-                                )
-                            .unwrap();
-                    canvas.present();
+            if 0 == audio_steps % Atari2600::CPU_STEPS_PER_AUDIO_UPDATE {
+                // Top-up the audio queue
+                // TODO: Change this thing of beauty to something even better. 
+                if let Some(audio_queue) = self.audio_queue.as_mut() {
+                    sound::SDLUtility::top_up_audio_queue(&mut **audio_queue, |fill_size| self.emulator.next_audio_chunk(fill_size));
                 }
-                display_refreshes += 1;
             }
-            true 
-        } else {
-            while display_refreshes < iterations {
-                if self.stop_clock > 0 && self.core.clock.ticks > self.stop_clock {
-                    return false;
-                }
-                self.core.step(self.debug, self.realtime);
-                self.core.memory.stella.step_tia_sound(&self.core.clock);
-
-                if 0 == audio_steps % Atari2600::CPU_STEPS_PER_AUDIO_UPDATE {
-                    // Top-up the audio queue
-                    // TODO: Change this thing of beauty to something even better. 
-                    if self.audio_queue.is_some() {
-                        let audio_queue = self.audio_queue.as_mut().expect("Optional audio not set");
-                        sound::SDLUtility::top_up_audio_queue(&mut **audio_queue, |fill_size| self.core.memory.stella.get_next_audio_chunk(fill_size));
-                    }
-                }
-                audio_steps += 1;
-
-                display_refreshes += 1;
+            audio_steps += 1;
+
+            if let (true, Some(canvas), Some(texture)) = (new_frame, self.canvas.as_mut(), texture.as_mut()) {
+                let framebuffer = self.emulator.framebuffer();
+                texture.with_lock(None, |buffer: &mut [u8], _pitch: usize| buffer[..framebuffer.len()].copy_from_slice(framebuffer)).unwrap();
+
+                canvas.clear();
+                canvas
+                        .copy(
+                            texture,
+                            None,
+                            Some(rect::Rect::new(0, 0, graphics::stella::Constants::PIXEL_WIDTH_STRETCH as u32 * console_size.console_width as u32, console_size.console_height as u32)),
+                            )
+                        .unwrap();
+                canvas.present();
             }
-            true
+            display_refreshes += 1;
         }
+        true
     }
 
-    pub fn configure_sdl(&mut self, window_size: graphics::display::WindowSize, pixel_format: pixels::PixelFormatEnum) {
+    #[cfg(feature = "sdl")]
+    pub fn configure_sdl(&mut self, window_size: graphics::display::WindowSize, _pixel_format: pixels::PixelFormatEnum) {
 
         let mut sdl_context = sdl2::init().unwrap();
 
         self.canvas = graphics::display::SDLUtility::create_canvas(&mut sdl_context, "rust-atari2600 emulator", window_size.frame_width, window_size.frame_height, window_size.fullscreen);
 
-        match self.canvas {
-            Some(ref mut v) => { v.set_logical_size(graphics::stella::Constants::PIXEL_WIDTH_STRETCH as u32 * window_size.console_size.console_width as u32, window_size.console_size.console_height as u32).unwrap();
-            },
-            None => {}
+        if let Some(ref mut v) = self.canvas {
+            v.set_logical_size(graphics::stella::Constants::PIXEL_WIDTH_STRETCH as u32 * window_size.console_size.console_width as u32, window_size.console_size.console_height as u32).unwrap();
         }
 
         // Set members once update/modifications have been done.
@@ -215,6 +312,9 @@ impl Atari2600 {
 
 impl Drop for Atari2600 {
     fn drop(&mut self) {
+        if let Err(e) = self.emulator.finish_replay() {
+            println!("Error saving replay. {}", e);
+        }
         println!("Done.");
     }
 }
@@ -1,5 +1,6 @@
 use super::super::audio::tiasound;
 use super::soundchannel;
+#[cfg(feature = "sdl")]
 use sdl2::audio;
 
 pub trait SoundQueue {
@@ -7,6 +8,7 @@ pub trait SoundQueue {
     fn size(&self) -> usize;
 }
 
+#[cfg(feature = "sdl")]
 impl SoundQueue for audio::AudioQueue<soundchannel::PlaybackType> {
     fn add_audio(&mut self, new_audio_data: &[soundchannel::PlaybackType]) {
         self.queue_audio(new_audio_data).unwrap();
@@ -17,10 +19,10 @@ impl SoundQueue for audio::AudioQueue<soundchannel::PlaybackType> {
     }
 }
 
+#[cfg(feature = "sdl")]
 pub type SoundQueueType = audio::AudioQueue<soundchannel::PlaybackType>;
 
 pub struct HoundOutput {
-    spec: hound::WavSpec,
     out_file: hound::WavWriter<std::io::BufWriter<std::fs::File>>,
 }
 
@@ -33,7 +35,6 @@ impl HoundOutput {
             sample_format: hound::SampleFormat::Int,
         };
         Self {
-            spec: wav_spec,
             out_file: hound::WavWriter::create(std::path::Path::new(&filename), wav_spec).unwrap(),
         }
     }
@@ -62,11 +63,13 @@ impl SDLUtility {
     // TODO: Fix up values, make them more dynamic, do better comparisons
     // Not sure how they compare on different PCs
     const TARGET_QUEUE_LENGTH: u32 = 4096; // This drives the 'delay' in audio, but too small for the speed and they aren't filled fast enough
+    #[cfg(feature = "sdl")]
     const AUDIO_SAMPLE_SIZE: u16 = 1024; // 'Desired' sample size, too small and SDL buffer doesn't stay filled (pops/crackles).
     const FRACTION_FILL: f32 = 0.05; // TODO: FUDGE FACTOR.  Don't completely fill, samples a removed 1 at a time, don't fill them immediately.
 
     pub const MONO_STERO_FLAG: u8 = 2; // TODO: Make this configurable 1 - mono, 2 - stereo
 
+    #[cfg(feature = "sdl")]
     pub fn get_audio_queue(sdl_context: &mut sdl2::Sdl) -> Option<Box<dyn SoundQueue>> {
         let audio_subsystem = sdl_context.audio().unwrap();
 
@@ -105,9 +108,10 @@ impl SDLUtility {
     }
 }
 
+#[cfg(feature = "sdl")]
 pub struct Sound {}
 
+#[cfg(feature = "sdl")]
 impl Sound {
     const SAMPLERATE: u32 = 32050;
-    const BITS: u8 = 8;
 }
@@ -1,5 +1,6 @@
 use super::super::clocks;
 use super::super::cpu::core;
+use super::super::state;
 use super::sound;
 use super::soundchannel;
 use std::thread;
@@ -63,9 +64,9 @@ impl TiaSound {
         }
 
         if length > 0 {
-            for i in 0..(length * (sound::SDLUtility::MONO_STERO_FLAG as u32)) {
+            for _ in 0..(length * (sound::SDLUtility::MONO_STERO_FLAG as u32)) {
                 if !self.working_stream.is_empty() {
-                    for j in 0..sound::SDLUtility::MONO_STERO_FLAG {
+                    for _ in 0..sound::SDLUtility::MONO_STERO_FLAG {
                         stream.push(self.working_stream.remove(0)); // Neutral volume
                     }
                 }
@@ -87,6 +88,11 @@ impl TiaSound {
         stream
     }
 
+    /// Take all of the generated audio (without pacing), for when there's no audio device to feed.
+    pub fn take_audio_samples(&mut self) -> Vec<soundchannel::PlaybackType> {
+        std::mem::take(&mut self.working_stream)
+    }
+
     // Clock poly 4, return new poly4 state
     // @staticmethod
     pub fn poly4(audio_ctrl: u8, poly5_state: u8, poly4_state: u8) -> u8 {
@@ -119,7 +125,7 @@ impl TiaSound {
         // stream.
         let mut stream = vec![0; length as usize];
         for i in 0..length {
-            if 0 == self.freq_pos[channel as usize] % (self.freq[channel as usize] as u32 + 1) {
+            if self.freq_pos[channel as usize].is_multiple_of(self.freq[channel as usize] as u32 + 1) {
                 let next_poly5 = TiaSound::poly5(self.wave_form[channel as usize], self.poly5state[channel as usize], self.poly4state[channel as usize]);
 
                 if TiaSound::poly5clk(self.wave_form[channel as usize], self.poly5state[channel as usize]) {
@@ -143,33 +149,33 @@ impl TiaSound {
     // change, so previous wave form can be stopped at correct time before
     // control change.
 
-    pub fn write_audio_ctrl_0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    pub fn write_audio_ctrl_0(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.pre_write_generate_sound(clock);
         self.wave_form[0] = data;
     }
 
-    pub fn write_audio_ctrl_1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    pub fn write_audio_ctrl_1(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.pre_write_generate_sound(clock);
         self.wave_form[1] = data;
     }
 
-    pub fn write_audio_freq_0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    pub fn write_audio_freq_0(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.pre_write_generate_sound(clock);
         self.freq[0] = data & TiaSound::FREQ_DATA_MASK;
     }
 
-    pub fn write_audio_freq_1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    pub fn write_audio_freq_1(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.pre_write_generate_sound(clock);
         self.freq[1] = data & TiaSound::FREQ_DATA_MASK;
     }
 
-    pub fn write_audio_vol_0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    pub fn write_audio_vol_0(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.pre_write_generate_sound(clock);
         self.volume[0] = data;
         self.post_write_generate_sound();
     }
 
-    pub fn write_audio_vol_1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    pub fn write_audio_vol_1(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.pre_write_generate_sound(clock);
         self.volume[1] = data;
         self.post_write_generate_sound();
@@ -203,3 +209,32 @@ impl TiaSound {
 
     fn post_write_generate_sound(&mut self) {}
 }
+
+impl state::Snapshot for TiaSound {
+    // The generated (but not yet played) audio stream isn't saved.
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_bytes(&self.volume);
+        state.put_bytes(&self.freq);
+        state.put_bytes(&self.poly4state);
+        state.put_bytes(&self.poly5state);
+        state.put_bytes(&self.wave_form);
+        for freq_pos in &self.freq_pos {
+            state.put_u32(*freq_pos);
+        }
+        state.put_u64(self.last_update_time);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        state.get_bytes_into(&mut self.volume)?;
+        state.get_bytes_into(&mut self.freq)?;
+        state.get_bytes_into(&mut self.poly4state)?;
+        state.get_bytes_into(&mut self.poly5state)?;
+        state.get_bytes_into(&mut self.wave_form)?;
+        for freq_pos in self.freq_pos.iter_mut() {
+            *freq_pos = state.get_u32()?;
+        }
+        self.last_update_time = state.get_u64()?;
+        self.working_stream.clear();
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use super::state;
+
 pub type ClockType = u64;
 
 //#[derive(Copy)]
@@ -5,6 +7,12 @@ pub struct Clock {
     pub ticks: ClockType,
 }
 
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Clock {
     pub fn new() -> Self {
         Self { ticks: 0 }
@@ -14,3 +22,14 @@ impl Clock {
         self.ticks = self.ticks.wrapping_add(inc as u64);
     }
 }
+
+impl state::Snapshot for Clock {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u64(self.ticks);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.ticks = state.get_u64()?;
+        Ok(())
+    }
+}
@@ -2,6 +2,10 @@ use super::super::clocks;
 use super::super::graphics;
 use super::super::memory::memory;
 use super::super::ports;
+use super::super::state;
+use super::super::symbols;
+use super::super::trace;
+use super::disassembler;
 use super::instructions;
 use super::pc_state;
 use std::thread;
@@ -31,7 +35,7 @@ impl Core {
             memory,
             pc_state,
             ports,
-            raw_display: vec![0; (graphics::stella::Constants::ATARI2600_WIDTH as usize) * (graphics::stella::Constants::ATARI2600_HEIGHT as usize) * (graphics::display::SDLUtility::bytes_per_pixel() as usize)],
+            raw_display: vec![0; (graphics::stella::Constants::ATARI2600_WIDTH as usize) * (graphics::stella::Constants::ATARI2600_HEIGHT as usize) * (graphics::display::BYTES_PER_PIXEL as usize)],
             start_time: time::SystemTime::now(),
         }
     }
@@ -41,7 +45,22 @@ impl Core {
         self.pc_state.set_pc(self.memory.read16(&self.clock, Core::PROGRAM_ENTRY_ADDR));
     }
 
-    pub fn step(&mut self, debug: bool, realtime: bool) {
+    pub fn pc_state(&self) -> &pc_state::PcState {
+        &self.pc_state
+    }
+
+    pub fn pc_state_mut(&mut self) -> &mut pc_state::PcState {
+        &mut self.pc_state
+    }
+
+    /// The TIA beam position, (scanline, colour clock), counted from the start of the frame.
+    pub fn beam_position(&mut self) -> (clocks::ClockType, clocks::ClockType) {
+        let frame_clocks = self.clock.ticks.wrapping_sub(self.memory.stella.debug_clock());
+        (frame_clocks / graphics::stella::Stella::HORIZONTAL_TICKS, frame_clocks % graphics::stella::Stella::HORIZONTAL_TICKS)
+    }
+
+    /// Run an instruction, writing it to the 'trace' first (with its label and source line when there are 'symbols').
+    pub fn step(&mut self, realtime: bool, trace: Option<&mut trace::Trace>, symbols: Option<&symbols::Symbols>) {
         if realtime {
             let in_ms: u64 = self.start_time.elapsed().expect("Error getting eplapsed").as_millis() as u64;
             if 1000 * self.clock.ticks / Constants::CLOCK_HZ as u64 > in_ms {
@@ -50,20 +69,44 @@ impl Core {
             }
         }
 
-        let op_code = self.memory.read(&self.clock, self.pc_state.get_pc());
-
-        if debug {
-            print!(
-                "cycles:{} 0x{:X} {:X} (0x{:X}) ",
-                (self.clock.ticks.wrapping_sub(self.memory.stella.debug_clock())) / pc_state::PcState::CYCLES_TO_CLOCK as u64,
-                op_code,
-                self.pc_state.get_pc(),
-                self.memory.read(&self.clock, self.pc_state.get_pc().wrapping_add(1))
-            );
-            println!("{}", self.pc_state);
+        if let Some(trace) = trace {
+            if trace.is_active(self.pc_state.get_pc()) {
+                self.trace(trace, symbols);
+            }
         }
 
-        instructions::Instruction::execute(op_code, &mut self.clock, &mut self.memory, &mut self.pc_state, &mut self.ports);
+        instructions::Instruction::step(&mut self.clock, &mut self.memory, &mut self.pc_state, &mut self.ports);
+    }
+
+    fn trace(&mut self, trace: &mut trace::Trace, symbols: Option<&symbols::Symbols>) {
+        // The instruction's bytes are peeked, so tracing doesn't switch banks (or have any other side effects).
+        let pc = self.pc_state.get_pc();
+        let bank = self.memory.cartridge_bank();
+        let op_code = self.memory.peek(&self.clock, pc);
+        let (op_name, address_mode) = instructions::Instruction::decode(op_code);
+        let mut bytes = vec![op_code];
+        for offset in 1..disassembler::size(op_name, address_mode) {
+            bytes.push(self.memory.peek(&self.clock, pc.wrapping_add(offset)));
+        }
+
+        let disassembly = disassembler::disassemble_instruction(&bytes, pc, symbols.map(|symbols| (symbols, bank)));
+        let (scanline, colour_clock) = self.beam_position();
+        trace.write_line(&trace::TraceLine {
+            pc,
+            bank,
+            bytes: &bytes,
+            text: &disassembly.text,
+            a: self.pc_state.get_a(),
+            x: self.pc_state.get_x(),
+            y: self.pc_state.get_y(),
+            s: self.pc_state.get_s(),
+            p: self.pc_state.get_p(),
+            cycle: self.clock.ticks / pc_state::PcState::CYCLES_TO_CLOCK as u64,
+            scanline,
+            colour_clock,
+            label: symbols.and_then(|symbols| symbols.label(bank, pc)),
+            source: symbols.and_then(|symbols| symbols.source_line(bank, pc)),
+        });
     }
 
     pub fn export(&mut self) -> bool {
@@ -77,3 +120,21 @@ impl Core {
         buffer.clone_from_slice(self.raw_display.as_slice());
     }
 }
+
+impl state::Snapshot for Core {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        self.clock.save_state(state);
+        self.pc_state.save_state(state);
+        self.memory.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.clock.load_state(state)?;
+        self.pc_state.load_state(state)?;
+        self.memory.load_state(state)?;
+
+        // Restart the real-time reference, so there's no 'catch-up' after loading.
+        self.start_time = time::SystemTime::now() - time::Duration::from_millis(1000 * self.clock.ticks / Constants::CLOCK_HZ as u64);
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+//! 6507 disassembler.
+//!
+//! Decodes with the op-code table used by 'Instruction::execute' ('Instruction::decode'), so the two agree.  Renders
+//! the mnemonic, operand (with the TIA/RIOT register names) and cycles, '*' for an extra cycle if a page is crossed,
+//! '**' for branches (an extra cycle if taken, another if that crosses a page).  With debug symbols (and the bank)
+//! addresses are shown as labels.
+
+use super::super::memory::cartridge::CartridgeType;
+use super::super::symbols::Symbols;
+use super::instructions::{AddressMode, AddressMode::*, Instruction, OpName, OpName::*, RegisterName};
+use std::fmt;
+
+const TIA_WRITE: [&str; 0x2D] = [
+    "VSYNC", "VBLANK", "WSYNC", "RSYNC", "NUSIZ0", "NUSIZ1", "COLUP0", "COLUP1", "COLUPF", "COLUBK", "CTRLPF", "REFP0", "REFP1", "PF0", "PF1", "PF2", "RESP0", "RESP1", "RESM0", "RESM1", "RESBL", "AUDC0", "AUDC1", "AUDF0", "AUDF1", "AUDV0", "AUDV1",
+    "GRP0", "GRP1", "ENAM0", "ENAM1", "ENABL", "HMP0", "HMP1", "HMM0", "HMM1", "HMBL", "VDELP0", "VDELP1", "VDELBL", "RESMP0", "RESMP1", "HMOVE", "HMCLR", "CXCLR",
+];
+
+const TIA_READ: [&str; 0x0E] = ["CXM0P", "CXM1P", "CXP0FB", "CXP1FB", "CXM0FB", "CXM1FB", "CXBLPF", "CXPPMM", "INPT0", "INPT1", "INPT2", "INPT3", "INPT4", "INPT5"];
+
+const RIOT_READ: [&str; 6] = ["SWCHA", "SWACNT", "SWCHB", "SWBCNT", "INTIM", "TIMINT"];
+
+const RIOT_WRITE: [(u16, &str); 8] = [(0x00, "SWCHA"), (0x01, "SWACNT"), (0x02, "SWCHB"), (0x03, "SWBCNT"), (0x14, "TIM1T"), (0x15, "TIM8T"), (0x16, "TIM64T"), (0x17, "T1024T")];
+
+/// Name of the TIA/RIOT register at the address (as seen by a read or write).
+pub fn register_name(address: u16, write: bool) -> Option<&'static str> {
+    if 0 == address & 0x1080 {
+        match write {
+            true => TIA_WRITE.get((address & 0x3F) as usize).copied(),
+            false => TIA_READ.get((address & 0x0F) as usize).copied(),
+        }
+    } else if 0x0280 == address & 0x1280 {
+        match write {
+            true => RIOT_WRITE.iter().find(|(register, _)| *register == address & 0x1F).map(|(_, name)| *name),
+            false => RIOT_READ.get((address & 0x07) as usize).copied(),
+        }
+    } else {
+        Option::None // ('None' is the addressing mode)
+    }
+}
+
+pub struct Disassembly {
+    pub address: u16,
+    pub label: Option<String>,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    pub cycles: String,
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(dest, "{:04X}  {:<9} {:<20} {}", self.address, bytes.join(" "), self.text, self.cycles)
+    }
+}
+
+fn mnemonic(op_name: OpName, address_mode: AddressMode) -> String {
+    match (op_name, address_mode) {
+        (TStat | TNoStat, Imp(read, write)) => format!("T{:?}{:?}", read, write),
+        (Inc, Imp(register, _)) => format!("IN{:?}", register),
+        (Dec, Imp(register, _)) => format!("DE{:?}", register),
+        (Br(flag, set), _) => {
+            let names = match flag {
+                7 => ("BPL", "BMI"),
+                6 => ("BVC", "BVS"),
+                0 => ("BCC", "BCS"),
+                _ => ("BNE", "BEQ"),
+            };
+            if set { names.1 } else { names.0 }.to_string()
+        }
+        (Or, _) => "ORA".to_string(),
+        (JmpAbs | JmpInd, _) => "JMP".to_string(),
+        (op_name, _) => format!("{:?}", op_name).to_uppercase(),
+    }
+}
+
+/// Bytes of the instruction, including the op-code.
+pub fn size(op_name: OpName, address_mode: AddressMode) -> u16 {
+    match address_mode {
+        Imp(_, _) | Acc => 1,
+        ImmR | ZpR | ZpxR | ZpyR | IzxR | IzyRD | ZpW | ZpxW | IzxW | IzyWD | ZpRegW | ZpxRegW | ZpyRegW | IzxRegW | IzyRegWD => 2,
+        AbsR | AbxRD | AbyRD | AbsW | AbxWD | AbyWD | AbsRegW | AbxRegWD | AbyRegWD => 3,
+        None => match op_name {
+            Jsr | JmpAbs | JmpInd => 3,
+            Br(_, _) => 2,
+            _ => 1,
+        },
+    }
+}
+
+/// Cycles taken (without a page cross or branch), and the marker for the extra cycles.
+pub fn cycles(op_name: OpName, address_mode: AddressMode) -> (u8, &'static str) {
+    match address_mode {
+        Imp(_, _) | Acc | ImmR => (2, ""),
+        ZpR | ZpRegW => (3, ""),
+        ZpxR | ZpyR | AbsR | ZpxRegW | ZpyRegW | AbsRegW => (4, ""),
+        AbxRD | AbyRD => (4, "*"),
+        IzyRD => (5, "*"),
+        ZpW | AbxRegWD | AbyRegWD => (5, ""),
+        ZpxW | AbsW | IzxR | IzxRegW | IzyRegWD => (6, ""),
+        AbxWD | AbyWD => (7, ""),
+        IzxW | IzyWD => (8, ""),
+        None => match op_name {
+            Brk => (7, ""),
+            Jsr | Rti | Rts => (6, ""),
+            JmpAbs | Php | Pha => (3, ""),
+            JmpInd => (5, ""),
+            Plp | Pla => (4, ""),
+            Br(_, _) => (2, "**"),
+            _ => (0, "-"), // 'JAM', the CPU stops.
+        },
+    }
+}
+
+/// Disassemble the instruction at the start of 'data' (at 'address').  If 'data' is too short for the instruction
+/// the op-code is shown as a data byte.  'symbols' are the debug symbols and the bank holding 'data'.
+pub fn disassemble_instruction(data: &[u8], address: u16, symbols: Option<(&Symbols, usize)>) -> Disassembly {
+    let label_of = |address: u16| symbols.and_then(|(symbols, bank)| symbols.label(bank, address)).map(|label| label.to_string());
+    let (op_name, address_mode) = Instruction::decode(data[0]);
+    let size = size(op_name, address_mode) as usize;
+    if data.len() < size {
+        return Disassembly {
+            address,
+            label: label_of(address),
+            bytes: data[..1].to_vec(),
+            text: format!(".byte ${:02X}", data[0]),
+            cycles: String::new(),
+        };
+    }
+
+    let byte = data.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, data.get(2).copied().unwrap_or(0)]);
+    let write = matches!(address_mode, ZpW | ZpxW | AbsW | AbxWD | AbyWD | IzxW | IzyWD | ZpRegW | ZpxRegW | ZpyRegW | AbsRegW | AbxRegWD | AbyRegWD | IzxRegW | IzyRegWD);
+    let zero_page = label_of(byte as u16).or_else(|| register_name(byte as u16, write).map(|name| name.to_string())).unwrap_or(format!("${:02X}", byte));
+    let absolute = label_of(word).or_else(|| register_name(word, write).map(|name| name.to_string())).unwrap_or(format!("${:04X}", word));
+    let target = address.wrapping_add(2).wrapping_add(byte as i8 as u16);
+
+    let operand = match address_mode {
+        Imp(RegisterName::A, RegisterName::A) if matches!(op_name, Asl | Lsr | Rol | Ror) => "A".to_string(),
+        Acc => "A".to_string(),
+        Imp(_, _) => String::new(),
+        ImmR => format!("#${:02X}", byte),
+        ZpR | ZpW | ZpRegW => zero_page,
+        ZpxR | ZpxW | ZpxRegW => format!("{},X", zero_page),
+        ZpyR | ZpyRegW => format!("{},Y", zero_page),
+        IzxR | IzxW | IzxRegW => format!("({},X)", label_of(byte as u16).unwrap_or(format!("${:02X}", byte))),
+        IzyRD | IzyWD | IzyRegWD => format!("({}),Y", label_of(byte as u16).unwrap_or(format!("${:02X}", byte))),
+        AbsR | AbsW | AbsRegW => absolute,
+        AbxRD | AbxWD | AbxRegWD => format!("{},X", absolute),
+        AbyRD | AbyWD | AbyRegWD => format!("{},Y", absolute),
+        None => match op_name {
+            Jsr | JmpAbs => label_of(word).unwrap_or(format!("${:04X}", word)),
+            JmpInd => format!("({})", label_of(word).unwrap_or(format!("${:04X}", word))),
+            Br(_, _) => label_of(target).unwrap_or(format!("${:04X}", target)),
+            _ => String::new(),
+        },
+    };
+
+    let (cycles, extra) = cycles(op_name, address_mode);
+    Disassembly {
+        address,
+        label: label_of(address),
+        bytes: data[..size].to_vec(),
+        text: format!("{} {}", mnemonic(op_name, address_mode), operand).trim_end().to_string(),
+        cycles: if 0 == cycles { extra.to_string() } else { format!("{}{}", cycles, extra) },
+    }
+}
+
+/// Disassemble all of 'data', loaded at 'origin'.
+pub fn disassemble(data: &[u8], origin: u16, symbols: Option<(&Symbols, usize)>) -> Vec<Disassembly> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let line = disassemble_instruction(&data[offset..], origin.wrapping_add(offset as u16), symbols);
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+    lines
+}
+
+// Size of the banks/slices the scheme switches, the disassembly is per bank.
+fn bank_size(cartridge_type: CartridgeType) -> usize {
+    match cartridge_type {
+        CartridgeType::E0 => 0x400,
+        CartridgeType::E7 | CartridgeType::C3F | CartridgeType::C3E | CartridgeType::Supercharger => 0x800,
+        _ => 0x1000,
+    }
+}
+
+/// Disassemble each bank of the cartridge, as '(bank number, origin, disassembly)'.  Banks are shown at $F000, except
+/// a smaller last bank, at the top of memory (where the schemes with slices fix it).  The symbols' banks are 4K.
+pub fn disassemble_banks(rom: &[u8], cartridge_type: CartridgeType, symbols: Option<&Symbols>) -> Vec<(usize, u16, Vec<Disassembly>)> {
+    let bank_size = bank_size(cartridge_type).min(rom.len().max(1));
+    let banks = rom.len().div_ceil(bank_size);
+    rom.chunks(bank_size)
+        .enumerate()
+        .map(|(bank, data)| {
+            let origin = if bank + 1 == banks && bank_size < 0x1000 { (0x10000 - bank_size) as u16 } else { 0xF000 };
+            (bank, origin, disassemble(data, origin, symbols.map(|symbols| (symbols, bank * bank_size / 0x1000))))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::cpu::disassembler::{cycles, disassemble, disassemble_banks, disassemble_instruction};
+    use crate::atari2600::cpu::instructions::{Instruction, OpName};
+    use crate::atari2600::cpu::pc_state::PcState;
+    use crate::atari2600::memory::cartridge::CartridgeType;
+    use crate::atari2600::memory::memory::Bus;
+    use crate::atari2600::ports::Ports;
+    use crate::atari2600::symbols::Symbols;
+
+    struct FlatBus {
+        ram: Vec<u8>,
+    }
+
+    impl Bus for FlatBus {
+        fn read(&mut self, _clock: &Clock, address: u16) -> u8 {
+            self.ram[address as usize]
+        }
+
+        fn write(&mut self, _clock: &mut Clock, address: u16, data: u8) {
+            self.ram[address as usize] = data;
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let code = [0xA9, 0x02, 0x85, 0x02, 0x8D, 0x96, 0x02, 0xA5, 0x02, 0xBD, 0xF8, 0x1F, 0x0A, 0xD0, 0xFB, 0x6C, 0xFE, 0xFF, 0xCA, 0x9A, 0x33, 0x80, 0x02, 0x4C];
+        let text: Vec<String> = disassemble(&code, 0xF000, None).iter().map(|line| line.to_string()).collect();
+        assert_eq!(
+            text,
+            [
+                "F000  A9 02     LDA #$02             2",
+                "F002  85 02     STA WSYNC            3",
+                "F004  8D 96 02  STA TIM64T           4",
+                "F007  A5 02     LDA CXP0FB           3",
+                "F009  BD F8 1F  LDA $1FF8,X          4*",
+                "F00C  0A        ASL A                2",
+                "F00D  D0 FB     BNE $F00A            2**",
+                "F00F  6C FE FF  JMP ($FFFE)          5",
+                "F012  CA        DEX                  2",
+                "F013  9A        TXS                  2",
+                "F014  33 80     RLA ($80),Y          8",
+                "F016  02        JAM                  -",
+                "F017  4C        .byte $4C            ",
+            ]
+        );
+        assert_eq!(disassemble_instruction(&[0xEA], 0x1000, None).text, "NOP");
+
+        // Labels for the operands and the line, zero page labels only for RAM.
+        let mut symbols = Symbols::new();
+        symbols.parse_sym("Start  f000\nLoop  f00a\nCounter  0080\nLIVES  0002\n");
+        let labelled = disassemble(&code, 0xF000, Some((&symbols, 0)));
+        assert_eq!(labelled[0].label.as_deref(), Some("Start"));
+        assert_eq!(labelled[1].text, "STA WSYNC");
+        assert_eq!(labelled[6].text, "BNE Loop");
+        assert_eq!(labelled[10].text, "RLA (Counter),Y");
+    }
+
+    #[test]
+    fn test_cycles_match_execution() {
+        // Without page crosses (or branches), the CPU takes the disassembler's cycles.
+        for op_code in 0..=0xFF_u8 {
+            let (op_name, address_mode) = Instruction::decode(op_code);
+            if matches!(op_name, OpName::Br(_, _) | OpName::Jam) {
+                continue;
+            }
+            let mut bus = FlatBus { ram: vec![0; 0x10000] };
+            bus.ram[0x0200] = op_code;
+            let mut pc_state = PcState::new();
+            pc_state.set_pc(0x0200);
+            pc_state.set_s(0xFF);
+            let mut clock = Clock::new();
+            Instruction::step(&mut clock, &mut bus, &mut pc_state, &mut Ports::new());
+            let taken = clock.ticks / PcState::CYCLES_TO_CLOCK as u64;
+            assert_eq!(taken, cycles(op_name, address_mode).0 as u64, "0x{:02X}", op_code);
+        }
+    }
+
+    #[test]
+    fn test_disassemble_banks() {
+        let banks = disassemble_banks(&vec![0xEA; 0x2000], CartridgeType::F8, None);
+        assert_eq!(banks.iter().map(|(bank, origin, lines)| (*bank, *origin, lines.len())).collect::<Vec<_>>(), [(0, 0xF000, 0x1000), (1, 0xF000, 0x1000)]);
+
+        let banks = disassemble_banks(&vec![0xEA; 0x2000], CartridgeType::E0, None);
+        assert_eq!(banks.len(), 8);
+        assert_eq!(banks[7].1, 0xFC00);
+        assert_eq!(disassemble_banks(&[0xEA; 0x800], CartridgeType::Default, None)[0].1, 0xF800);
+    }
+}
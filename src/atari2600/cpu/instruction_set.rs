@@ -4,334 +4,322 @@ use super::super::memory::addressing::Address16;
 use super::super::memory::memory;
 use super::pc_state;
 
-pub fn nop(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+// Each instruction runs a bus cycle at a time (including the 6502's dummy reads/writes).  The access is at the end of
+// the cycle, when the TIA/RIOT/cartridge see it, the op-code fetch is the first cycle (see 'Instruction::step').
+
+pub fn read_cycle(clock: &mut clocks::Clock, memory: &mut dyn memory::Bus, address: u16) -> u8 {
+    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    memory.read(clock, address)
+}
+
+pub fn write_cycle(clock: &mut clocks::Clock, memory: &mut dyn memory::Bus, address: u16, data: u8) {
+    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    memory.write(clock, address, data);
+}
+
+// Read the byte at the PC, and advance it.
+pub fn fetch_cycle(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) -> u8 {
+    let data = read_cycle(clock, memory, pc_state.get_pc());
+    pc_state.increment_pc(1);
+    data
+}
+
+fn push_cycle(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) {
+    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    memory.write_sp(clock, pc_state.get_s(), data);
+    pc_state.increment_s(-1);
+}
+
+fn pull_cycle(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) -> u8 {
+    pc_state.increment_s(1);
+    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    memory.read_sp(clock, pc_state.get_s())
+}
+
+// Dummy read of the stack, while the stack pointer is incremented.
+fn stack_dummy_cycle(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    memory.read_sp(clock, pc_state.get_s());
+}
+
+pub fn nop(_clock: &mut clocks::Clock, _pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     data
 }
 
-pub fn single_byte_instruction<R, W, I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut memory::Memory, u8) -> u8>(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, read: R, write: W, instruction: I)
+pub fn single_byte_instruction<R, W, I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut dyn memory::Bus, u8) -> u8>(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, read: R, write: W, instruction: I)
 where
     R: Fn(&pc_state::PcState) -> u8,
     W: Fn(&mut pc_state::PcState, u8),
 {
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    // Dummy read of the next byte.
+    read_cycle(clock, memory, pc_state.get_pc());
 
     let data = read(pc_state);
     let result = instruction(clock, pc_state, memory, data);
     write(pc_state, result);
+}
 
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-
-    pc_state.increment_pc(1);
+pub fn read_instruction<I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut dyn memory::Bus, u8) -> u8>(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, address: &addressing::Addressing, instruction: I) {
+    let addr = address.address16(clock, pc_state, memory, false);
+    let value = read_cycle(clock, memory, addr);
+    instruction(clock, pc_state, memory, value);
 }
 
-pub fn read_write_instruction<R, W, I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut memory::Memory, u8) -> u8>(
+pub fn read_modify_write_instruction<I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut dyn memory::Bus, u8) -> u8>(
     clock: &mut clocks::Clock,
     pc_state: &mut pc_state::PcState,
-    memory: &mut memory::Memory,
+    memory: &mut dyn memory::Bus,
     address: &addressing::Addressing,
-    read: R,
-    write: W,
     instruction: I,
-) where
-    R: addressing::ReadData,
-    W: addressing::WriteData,
-{
-    read_write_instruction_additional_delay(clock, pc_state, memory, address, read, write, instruction, 0);
+) {
+    let addr = address.address16(clock, pc_state, memory, true);
+    let value = read_cycle(clock, memory, addr);
+
+    // The unmodified value is written back, while the operation happens.
+    write_cycle(clock, memory, addr, value);
+    let result = instruction(clock, pc_state, memory, value);
+    write_cycle(clock, memory, addr, result);
+}
+
+pub fn write_instruction<I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut dyn memory::Bus, u8) -> u8>(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, address: &addressing::Addressing, instruction: I) {
+    let addr = address.address16(clock, pc_state, memory, true);
+    let data = instruction(clock, pc_state, memory, 0);
+    write_cycle(clock, memory, addr, data);
 }
 
-pub fn read_write_instruction_additional_delay<R, W, I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut memory::Memory, u8) -> u8>(
+// Undocumented 'SHA'/'SHX'/'SHY'/'TAS', store the value AND (the high byte of the base address + 1).
+// If indexing crossed a page, the stored value also replaces the high byte of the address.
+pub fn store_high_instruction<R, I: Fn(&mut clocks::Clock, &mut pc_state::PcState, &mut dyn memory::Bus, u8) -> u8>(
     clock: &mut clocks::Clock,
     pc_state: &mut pc_state::PcState,
-    memory: &mut memory::Memory,
+    memory: &mut dyn memory::Bus,
     address: &addressing::Addressing,
-    read: R,
-    write: W,
+    index: R,
     instruction: I,
-    additional_delay: u8,
 ) where
-    R: addressing::ReadData,
-    W: addressing::WriteData,
+    R: Fn(&pc_state::PcState) -> u8,
 {
-    let addr = address.address16(clock, pc_state, memory);
-    let mut execute_time = address.get_addressing_time();
-
-    let value = read.read(clock, pc_state, memory, addr);
-    execute_time += read.get_reading_time();
-
-    execute_time += write.get_writing_time();
-
-    let data = instruction(clock, pc_state, memory, value);
-
-    clock.increment(execute_time as u32);
-    clock.increment(additional_delay as u32);
-
-    write.write(clock, pc_state, memory, addr, data);
-
-    pc_state.increment_pc((address.get_addressing_size() + 1) as i16);
+    let addr = address.address16(clock, pc_state, memory, true);
+    let base = addr.wrapping_sub(index(pc_state) as u16);
+    let data = instruction(clock, pc_state, memory, 0) & ((base >> 8) as u8).wrapping_add(1);
+    let addr = if (base & 0xFF00) != (addr & 0xFF00) { ((data as u16) << 8) | (addr & 0xFF) } else { addr };
+    write_cycle(clock, memory, addr, data);
 }
 
-pub fn break_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    let adl = memory.read(clock, 0xFFFE);
-
+// Undocumented 'JAM' (aka 'KIL'), the CPU locks up until reset.  The PC is left on the op-code, so it's 'executed'
+// repeatedly, but time passes so the rest of the machine (ie the TIA) continues.
+pub fn jam_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus) {
+    pc_state.increment_pc(-1);
     clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.write(clock, pc_state.get_s() as u16, pc_state.get_pch());
-    pc_state.increment_s(-1);
+}
 
-    pc_state.increment_pc(1);
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.write(clock, pc_state.get_s() as u16, pc_state.get_pcl());
-    pc_state.increment_s(-1);
+pub fn break_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    // The 'operand' is skipped (so the return address is the op-code + 2).
+    fetch_cycle(clock, pc_state, memory);
+    push_cycle(clock, pc_state, memory, pc_state.get_pch());
+    push_cycle(clock, pc_state, memory, pc_state.get_pcl());
 
     // The 'B' flag, only alters the value on the stack, not ongoing status.
     pc_state.set_flag_b(true);
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.write(clock, pc_state.get_s() as u16, pc_state.get_p());
-    pc_state.increment_s(-1);
+    pc_state.set_flag_x1(true);
+    push_cycle(clock, pc_state, memory, pc_state.get_p());
     pc_state.set_flag_b(false);
+    pc_state.set_flag_i(true);
 
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    let adh = memory.read(clock, 0xFFFF);
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    let adl = read_cycle(clock, memory, 0xFFFE);
+    let adh = read_cycle(clock, memory, 0xFFFF);
     pc_state.set_pc(adl as u16 + ((adh as u16) << 8));
 }
 
-pub fn jump_sub_routine_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    let adl = memory.read(clock, pc_state.get_pc());
+pub fn jump_sub_routine_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    let adl = fetch_cycle(clock, pc_state, memory);
+    stack_dummy_cycle(clock, pc_state, memory);
 
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    // The PC is on the last byte of the instruction (the high byte of the address), as pushed.
+    push_cycle(clock, pc_state, memory, pc_state.get_pch());
+    push_cycle(clock, pc_state, memory, pc_state.get_pcl());
 
-    // Increment before store, to catch low to high carry.
-    pc_state.increment_pc(1);
-    memory.write(clock, pc_state.get_s() as u16, pc_state.get_pch());
-    pc_state.increment_s(-1);
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.write(clock, pc_state.get_s() as u16, pc_state.get_pcl());
-    pc_state.increment_s(-1);
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    let adh = memory.read(clock, pc_state.get_pc());
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    let adh = read_cycle(clock, memory, pc_state.get_pc());
     pc_state.set_pc(adl as u16 + ((adh as u16) << 8));
 }
 
-pub fn return_from_interrupt(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_s(1);
-    pc_state.set_p(memory.read(clock, pc_state.get_s() as u16));
-
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_s(1);
-    pc_state.set_pcl(memory.read(clock, pc_state.get_s() as u16));
+pub fn return_from_interrupt(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    read_cycle(clock, memory, pc_state.get_pc());
+    stack_dummy_cycle(clock, pc_state, memory);
 
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_s(1);
-    pc_state.set_pch(memory.read(clock, pc_state.get_s() as u16));
+    let p = pull_cycle(clock, pc_state, memory);
+    pc_state.set_p(p);
+    let pcl = pull_cycle(clock, pc_state, memory);
+    pc_state.set_pcl(pcl);
+    let pch = pull_cycle(clock, pc_state, memory);
+    pc_state.set_pch(pch);
+}
 
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.read(clock, pc_state.get_pc());
+pub fn return_from_sub_routine_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    read_cycle(clock, memory, pc_state.get_pc());
+    stack_dummy_cycle(clock, pc_state, memory);
 
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-}
+    let pcl = pull_cycle(clock, pc_state, memory);
+    pc_state.set_pcl(pcl);
+    let pch = pull_cycle(clock, pc_state, memory);
+    pc_state.set_pch(pch);
 
-pub fn return_from_sub_routine_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    // T1 - PC + 1
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-    // T2 - Stack Ptr
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    // T3 - Stack Ptr + 1 -> PCL
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_s(1);
-    pc_state.set_pcl(memory.read(clock, pc_state.get_s() as u16));
-    // T4 - Stack Ptr + 1 -> PCL
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_s(1);
-    pc_state.set_pch(memory.read(clock, pc_state.get_s() as u16));
-    // T5 - discarded
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.read(clock, pc_state.get_pc());
-    // T0 - Next instruction
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
+    // The pulled address is the last byte of the 'JSR', the PC is incremented (with a dummy read) to the next instruction.
+    fetch_cycle(clock, pc_state, memory);
 }
 
-pub fn jump_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, address: &addressing::Addressing)
+pub fn jump_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, address: &addressing::Addressing)
 {
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    let addr = address.address16(clock, pc_state, memory);
-    let execute_time = address.get_addressing_time();
-    clock.increment(execute_time as u32);
+    let addr = address.address16(clock, pc_state, memory, false);
     pc_state.set_pc(addr);
 }
 
-pub fn branch_page_delay(clock: &mut clocks::Clock, start_address: u16, end_address: u16) {
-    // If pages don't match, add a cycle.
-    if (start_address & 0xF00) != (end_address & 0xF00) {
-        clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    }
-}
-
-pub fn branch_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, condition_mask: u8, condition: u8) {
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+pub fn branch_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, condition_mask: u8, condition: u8) {
+    let delta = fetch_cycle(clock, pc_state, memory);
 
     if (pc_state.get_p() & condition_mask) == condition {
+        // Dummy read of the next op-code, while the offset is added to the low byte.
         let initial_pc = pc_state.get_pc();
-        let delta = memory.read(clock, pc_state.get_pc().wrapping_add(1));
-        if delta & 0x80 != 0 {
-            pc_state.increment_pc((delta as i8) as i16);
-        } else {
-            pc_state.increment_pc(delta as i16);
-        }
-        // If branch to same page add 1, else add 2
-        // TODO: Confirm if starting address is 'tmp16' or 'tmp16+2'
-        branch_page_delay(clock, initial_pc.wrapping_add(2), pc_state.get_pc().wrapping_add(2));
+        read_cycle(clock, memory, initial_pc);
+        let target_pc = initial_pc.wrapping_add(delta as i8 as u16);
 
-        clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+        // If the page changed, a dummy read of the un-fixed address.
+        if (initial_pc & 0xFF00) != (target_pc & 0xFF00) {
+            read_cycle(clock, memory, (initial_pc & 0xFF00) | (target_pc & 0x00FF));
+        }
+        pc_state.set_pc(target_pc);
     }
-
-    pc_state.increment_pc(2);
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
 }
 
-pub fn asl(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn asl(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_flag_c(0 != (data >> 7) & 0x1);
     let left_shift = data << 1;
     pc_state::set_status_nz(pc_state, left_shift);
     left_shift
 }
 
-pub fn lsr(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn lsr(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_flag_c(0 != data & 0x1);
     let right_shift = data >> 1;
     pc_state::set_status_nz(pc_state, right_shift);
     right_shift
 }
 
-pub fn rol(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn rol(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let t8 = (data << 1) | pc_state.get_flag_c() as u8;
     pc_state.set_flag_c(1 == (data >> 7) & 1);
     pc_state::set_status_nz(pc_state, t8);
     t8
 }
 
-pub fn ror(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn ror(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let t8 = (data >> 1) | ((pc_state.get_flag_c() as u8) << 7);
     pc_state.set_flag_c(1 == data & 1);
     pc_state::set_status_nz(pc_state, t8);
     t8
 }
 
-pub fn ldx(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn ldx(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_x(data);
     pc_state::set_status_nz(pc_state, pc_state.get_x());
     0
 }
 
-pub fn ldy(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn ldy(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_y(data);
     pc_state::set_status_nz(pc_state, pc_state.get_y());
     0
 }
 
-pub fn lda(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn lda(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_a(data);
     pc_state::set_status_nz(pc_state, data);
     0
 }
 
-pub fn and(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn and(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_a(pc_state.get_a() & data);
     pc_state::set_status_nz(pc_state, pc_state.get_a());
     0
 }
 
-pub fn eor(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn eor(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_a(pc_state.get_a() ^ data);
     pc_state::set_status_nz(pc_state, pc_state.get_a());
     0
 }
 
-pub fn or(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn or(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_a(pc_state.get_a() | data);
     pc_state::set_status_nz(pc_state, pc_state.get_a());
     0
 }
 
 pub fn add_carry(pc_state: &mut pc_state::PcState, a: u8, b: u8, c: u8) -> u8 {
+    let binary = a.wrapping_add(b).wrapping_add(c);
+    let r = a as u16 + b as u16 + c as u16;
+    // Z is always from the binary result (even in decimal mode on the NMOS 6502).
+    pc_state.set_flag_z(binary == 0x0);
+
     if !pc_state.get_flag_d() {
-        let r = (a as u16).wrapping_add(b as u16) + c as u16;
-        let rc = a.wrapping_add(b).wrapping_add(c);
-        pc_state.set_flag_n(0 != rc & 0x80);
-        pc_state.set_flag_z(rc == 0x0);
-        pc_state.set_flag_v(rc as i8 as u16 != r); // Overflow
+        pc_state.set_flag_n(0 != binary & 0x80);
+        pc_state.set_flag_v(0 != !(a ^ b) & (a ^ binary) & 0x80); // Overflow
         pc_state.set_flag_c(0x100 == (r & 0x100));
-
-        a.wrapping_add(b).wrapping_add(c)
+        binary
     } else {
-        // Decimal Addition
-        // FIXME need to fix flags
-        let r = (((a >> 4) & 0xF) * 10 + ((a & 0xF) % 10) + ((b >> 4) & 0xF) * 10 + ((b & 0xF) % 10) + c) as u16;
-        let rc = a.wrapping_add(b).wrapping_add(c) as u16; // ???? TODO
-        pc_state.set_flag_n(false);
-        pc_state.set_flag_z(rc == 0x0);
-        // TODO: Check not needed        pc_state.set_flag_v(rc != r); // Overflow
-        pc_state.set_flag_c(r > 99);
-        ((((r / 10 % 10) << 4) & 0xf0) + (r % 10)) as u8
+        // Decimal addition, a nybble at a time (invalid BCD digits included).
+        // The low nybble is adjusted (and carries) if > 9, N/V are from the high nybble before its adjustment.
+        let mut low = (a & 0xF) + (b & 0xF) + c;
+        if low > 0x9 {
+            low += 0x6;
+        }
+        let mut high = (a >> 4) + (b >> 4) + (low > 0xF) as u8;
+        let intermediate = (high << 4) | (low & 0xF);
+        pc_state.set_flag_n(0 != intermediate & 0x80);
+        pc_state.set_flag_v(0 != !(a ^ b) & (a ^ intermediate) & 0x80); // Overflow
+
+        if high > 0x9 {
+            high += 0x6;
+        }
+        pc_state.set_flag_c(high > 0xF);
+        (high << 4) | (low & 0xF)
     }
 }
 
-pub fn adc(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn adc(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let result = add_carry(pc_state, pc_state.get_a(), data, pc_state.get_flag_c() as u8);
     pc_state.set_a(result);
     0
 }
 
-pub fn sbc(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn sbc(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let result = sub_carry(pc_state, pc_state.get_a(), data, !pc_state.get_flag_c() as u8);
     pc_state.set_a(result);
     0
 }
 
 pub fn sub_carry(pc_state: &mut pc_state::PcState, a: u8, b: u8, c: u8) -> u8 {
+    // The flags are from the binary result (even in decimal mode on the NMOS 6502).
+    let r = (a as i8 as i16) - (b as i8 as i16) - (c as i16);
+    let rs = a.wrapping_sub(b).wrapping_sub(c);
+    pc_state.set_flag_n(0 != rs & 0x80); // Negative
+    pc_state.set_flag_z(rs == 0); // Zero
+    pc_state.set_flag_v(r != rs as i8 as i16); // Overflow
+    pc_state.set_flag_c((a as i16) - (b as i16) - (c as i16) >= 0); // Carry (not borrow)
+
     if !pc_state.get_flag_d() {
-        let mut r = (a as i8 as i16) - (b as i8 as i16) - (c as i8 as i16);
-        let rs = a.wrapping_sub(b).wrapping_sub(c) as i8;
-        pc_state.set_flag_n(rs < 0); // Negative
-        pc_state.set_flag_z(rs == 0); // Zero
-        pc_state.set_flag_v(r != rs as i16); // Overflow
-
-        r = (a as i16) - (b as i16) - (c as i16);
-        pc_state.set_flag_c(0x100 != (r as u16 & 0x100)); // Carry (not borrow
-        a.wrapping_sub(b).wrapping_sub(c)
+        rs
     } else {
-        // Decimal subtraction
-        // FIXME need to fix flags
-
-        let r = (((a >> 4) & 0xF) * 10 + ((a & 0xF) % 10)) as i16 - (((b >> 4) & 0xF) * 10 + ((b & 0xF) % 10)) as i16 - c as i16;
-
-        // rc = a + b + c
-        pc_state.set_flag_n(r < 0);
-        pc_state.set_flag_z(r == 0x0);
-        //  Need to check/fix conditions for V
-        // self.pc_state.P.V = (rc != r) ? 1:0;   # Overflow
-        pc_state.set_flag_v(true); // Overflow
-
-        pc_state.set_flag_c((0..=99).contains(&r));
-        (((((r / 10) % 10) << 4) & 0xf0) + (r % 10)) as u8
+        // Decimal subtraction, a nybble at a time (invalid BCD digits included), each nybble is adjusted if it borrowed.
+        let mut low = (a & 0xF) as i8 - (b & 0xF) as i8 - c as i8;
+        let mut high = (a >> 4) as i8 - (b >> 4) as i8 - (low < 0) as i8;
+        if low < 0 {
+            low -= 0x6;
+        }
+        if high < 0 {
+            high -= 0x6;
+        }
+        ((high << 4) as u8) | (low as u8 & 0xF)
     }
 }
 
@@ -344,177 +332,313 @@ pub fn compare(pc_state: &mut pc_state::PcState, a: u8, b: u8) {
     pc_state.set_flag_c(0x100 != (r & 0x100)); // Carry (not borrow)
 }
 
-pub fn cpx(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn cpx(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     compare(pc_state, pc_state.get_x(), data);
     0
 }
 
-pub fn cpy(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn cpy(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     compare(pc_state, pc_state.get_y(), data);
     0
 }
 
-pub fn cmp(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn cmp(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     compare(pc_state, pc_state.get_a(), data);
     0
 }
-pub fn bit(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn bit(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state.set_flag_n(0x80 == (data & 0x80));
     pc_state.set_flag_v(0x40 == (data & 0x40));
     pc_state.set_flag_z((pc_state.get_a() & data) == 0x0);
     0
 }
 
-pub fn sta(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn sta(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.get_a()
 }
 
-pub fn dcp(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn dcp(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let decremented = data.wrapping_sub(1);
     pc_state::set_status_nz(pc_state, decremented);
     compare(pc_state, pc_state.get_a(), decremented);
     decremented
 }
 
-pub fn sty(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn sty(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.get_y()
 }
 
-pub fn stx(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn stx(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.get_x()
 }
 
-pub fn sax(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn sax(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.get_a() & pc_state.get_x()
 }
 
-pub fn dec(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn dec(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let incremented = data.wrapping_sub(1);
     pc_state::set_status_nz(pc_state, incremented);
     incremented
 }
 
-pub fn clc(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn clc(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.set_flag_c(false);
     0
 }
 
-pub fn cld(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn cld(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.set_flag_d(false);
     0
 }
 
-pub fn cli(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn cli(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.set_flag_i(false);
     0
 }
 
-pub fn clv(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn clv(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.set_flag_v(false);
     0
 }
 
-pub fn sec(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn sec(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.set_flag_c(true);
     0
 }
 
-pub fn sei(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn sei(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.set_flag_i(true);
     0
 }
 
-pub fn sed(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn sed(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
     pc_state.set_flag_d(true);
     0
 }
 
-pub fn inc(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn inc(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let incremented = data.wrapping_add(1);
     pc_state::set_status_nz(pc_state, incremented);
     incremented
 }
 
 // Undocumented instruction
-pub fn isc(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn isc(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let increment = inc(clock, pc_state, memory, data);
     sbc(clock, pc_state, memory, increment);
     increment
 }
 
-pub fn slo(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn slo(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
     let shift = asl(clock, pc_state, memory, data);
     or(clock, pc_state, memory, shift);
     shift
 }
 
-pub fn lax(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn lax(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
      lda(clock, pc_state, memory, data);
      ldx(clock, pc_state, memory, data)
 }
 
-pub fn t_no_status(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn rla(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    let rotate = rol(clock, pc_state, memory, data);
+    and(clock, pc_state, memory, rotate);
+    rotate
+}
+
+pub fn sre(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    let shift = lsr(clock, pc_state, memory, data);
+    eor(clock, pc_state, memory, shift);
+    shift
+}
+
+pub fn rra(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    let rotate = ror(clock, pc_state, memory, data);
+    adc(clock, pc_state, memory, rotate);
+    rotate
+}
+
+pub fn anc(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    and(clock, pc_state, memory, data);
+    pc_state.set_flag_c(pc_state.get_flag_n());
+    0
+}
+
+pub fn alr(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    let shift = lsr(clock, pc_state, memory, pc_state.get_a() & data);
+    pc_state.set_a(shift);
+    0
+}
+
+pub fn arr(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    let t8 = pc_state.get_a() & data;
+    let mut result = (t8 >> 1) | ((pc_state.get_flag_c() as u8) << 7);
+    pc_state::set_status_nz(pc_state, result);
+    if !pc_state.get_flag_d() {
+        pc_state.set_flag_c(0 != result & 0x40);
+        pc_state.set_flag_v(0 != (result ^ (result << 1)) & 0x40); // Bit 6 xor bit 5
+    } else {
+        // NMOS decimal mode, each nybble of the rotated value is 'fixed' based on the un-rotated value.
+        pc_state.set_flag_v(0 != (t8 ^ result) & 0x40);
+        if (t8 & 0x0F) + (t8 & 0x01) > 0x05 {
+            result = (result & 0xF0) | (result.wrapping_add(0x06) & 0x0F);
+        }
+        let carry = (t8 as u16 & 0xF0) + (t8 as u16 & 0x10) > 0x50;
+        if carry {
+            result = result.wrapping_add(0x60);
+        }
+        pc_state.set_flag_c(carry);
+    }
+    pc_state.set_a(result);
+    0
+}
+
+pub fn sbx(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    let and_x = pc_state.get_a() & pc_state.get_x();
+    compare(pc_state, and_x, data);
+    pc_state.set_x(and_x.wrapping_sub(data));
+    0
+}
+
+// 'ANE'/'LXA' are unstable, the 'magic' constant varies between chips (and temperature), use the common value.
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+pub fn ane(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    pc_state.set_a((pc_state.get_a() | UNSTABLE_MAGIC) & pc_state.get_x() & data);
+    pc_state::set_status_nz(pc_state, pc_state.get_a());
+    0
+}
+
+pub fn lxa(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    lax(clock, pc_state, memory, (pc_state.get_a() | UNSTABLE_MAGIC) & data)
+}
+
+pub fn las(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus, data: u8) -> u8 {
+    pc_state.set_s(data & pc_state.get_s());
+    lax(clock, pc_state, memory, pc_state.get_s())
+}
+
+// Store high instructions (see 'store_high_instruction'), return the value before the address 'AND'.
+pub fn sha(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
+    pc_state.get_a() & pc_state.get_x()
+}
+
+pub fn shx(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
+    pc_state.get_x()
+}
+
+pub fn shy(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
+    pc_state.get_y()
+}
+
+pub fn tas(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, _data: u8) -> u8 {
+    pc_state.set_s(pc_state.get_a() & pc_state.get_x());
+    pc_state.get_s()
+}
+
+pub fn t_no_status(_clock: &mut clocks::Clock, _pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     data
 }
 
-pub fn t_status(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory, data: u8) -> u8 {
+pub fn t_status(_clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, _memory: &mut dyn memory::Bus, data: u8) -> u8 {
     pc_state::set_status_nz(pc_state, data);
     data
 }
 
-pub fn php_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    // T1 - PC + 1
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-    // T2 - PC + 1
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+pub fn php_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    read_cycle(clock, memory, pc_state.get_pc());
     pc_state.set_flag_b(true);
     pc_state.set_flag_x1(true);
-    memory.write_sp(clock, pc_state.get_s(), pc_state.get_p());
-    pc_state.increment_s(-1);
-    // T0 - Next kid
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+    push_cycle(clock, pc_state, memory, pc_state.get_p());
 }
 
-pub fn plp_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    // T1 - PC + 1
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-    // T2 Stack Ptr. (Discard data)
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.read_sp(clock, pc_state.get_s());
-    // T3 Stack Ptr + 1.
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_s(1);
-    pc_state.set_p(memory.read_sp(clock, pc_state.get_s()));
-    // T0 - Next instruction
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+pub fn plp_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    read_cycle(clock, memory, pc_state.get_pc());
+    stack_dummy_cycle(clock, pc_state, memory);
+    let p = pull_cycle(clock, pc_state, memory);
+    pc_state.set_p(p);
 }
 
-pub fn pha_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    // T1 - PC + 1
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-    // T2 - PC + 1
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.write_sp(clock, pc_state.get_s(), pc_state.get_a());
-    pc_state.increment_s(-1);
-    // T0 - Next kid
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+pub fn pha_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    read_cycle(clock, memory, pc_state.get_pc());
+    push_cycle(clock, pc_state, memory, pc_state.get_a());
 }
 
-pub fn pla_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut memory::Memory) {
-    // T1 - PC + 1
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_pc(1);
-    // T2 Stack Ptr. (Discard data)
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    memory.read_sp(clock, pc_state.get_s());
-    // T3 Stack Ptr + 1.
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
-    pc_state.increment_s(1);
-    pc_state.set_a(memory.read_sp(clock, pc_state.get_s()));
+pub fn pla_instruction(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, memory: &mut dyn memory::Bus) {
+    read_cycle(clock, memory, pc_state.get_pc());
+    stack_dummy_cycle(clock, pc_state, memory);
+    let a = pull_cycle(clock, pc_state, memory);
+    pc_state.set_a(a);
     pc_state::set_status_nz(pc_state, pc_state.get_a());
-    // T0 - Next instruction
-    clock.increment(pc_state::PcState::CYCLES_TO_CLOCK as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::cpu::instruction_set::{add_carry, sub_carry};
+    use crate::atari2600::cpu::pc_state::PcState;
+
+    // Reference results (A, N, V, Z, C), following the NMOS 6502 decimal mode description (Bruce Clark, 6502.org).
+    fn reference_adc(a: u8, b: u8, c: u8, decimal: bool) -> (u8, bool, bool, bool, bool) {
+        let binary = a as i32 + b as i32 + c as i32;
+        let signed = a as i8 as i32 + b as i8 as i32 + c as i32;
+        let zero = 0 == binary & 0xFF;
+        if !decimal {
+            return (binary as u8, 0 != binary & 0x80, !(-128..=127).contains(&signed), zero, binary > 0xFF);
+        }
+
+        let low = |a: u8, b: u8| {
+            let al = (a & 0x0F) as i32 + (b & 0x0F) as i32 + c as i32;
+            if al >= 0x0A { ((al + 0x06) & 0x0F) + 0x10 } else { al }
+        };
+        let mut result = (a & 0xF0) as i32 + (b & 0xF0) as i32 + low(a, b);
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+        let signed = (a & 0xF0) as i8 as i32 + (b & 0xF0) as i8 as i32 + low(a, b);
+        (result as u8, 0 != signed & 0x80, !(-128..=127).contains(&signed), zero, result >= 0x100)
+    }
+
+    fn reference_sbc(a: u8, b: u8, c: u8, decimal: bool) -> (u8, bool, bool, bool, bool) {
+        let binary = a as i32 - b as i32 - c as i32;
+        let signed = a as i8 as i32 - b as i8 as i32 - c as i32;
+        let flags = (0 != binary & 0x80, !(-128..=127).contains(&signed), 0 == binary & 0xFF, binary >= 0);
+        if !decimal {
+            return (binary as u8, flags.0, flags.1, flags.2, flags.3);
+        }
+
+        let mut al = (a & 0x0F) as i32 - (b & 0x0F) as i32 - c as i32;
+        if al < 0 {
+            al = ((al - 0x06) & 0x0F) - 0x10;
+        }
+        let mut result = (a & 0xF0) as i32 - (b & 0xF0) as i32 + al;
+        if result < 0 {
+            result -= 0x60;
+        }
+        (result as u8, flags.0, flags.1, flags.2, flags.3)
+    }
+
+    #[test]
+    fn test_add_subtract_exhaustive() {
+        let mut pc_state = PcState::new();
+        for decimal in [false, true] {
+            pc_state.set_flag_d(decimal);
+            for a in 0..=0xFF {
+                for b in 0..=0xFF {
+                    for c in 0..=1 {
+                        let flags = |pc_state: &PcState| (pc_state.get_flag_n(), pc_state.get_flag_v(), pc_state.get_flag_z(), pc_state.get_flag_c());
+
+                        let result = add_carry(&mut pc_state, a, b, c);
+                        let (expected, n, v, z, carry) = reference_adc(a, b, c, decimal);
+                        assert_eq!((result, flags(&pc_state)), (expected, (n, v, z, carry)), "ADC {:02X} {:02X} {} decimal {}", a, b, c, decimal);
+
+                        // 'sub_carry' takes the borrow (not carry).
+                        let result = sub_carry(&mut pc_state, a, b, c);
+                        let (expected, n, v, z, carry) = reference_sbc(a, b, c, decimal);
+                        assert_eq!((result, flags(&pc_state)), (expected, (n, v, z, carry)), "SBC {:02X} {:02X} {} decimal {}", a, b, c, decimal);
+                    }
+                }
+            }
+        }
+    }
 }
@@ -1,6 +1,5 @@
 use super::super::clocks;
 use super::super::memory::memory;
-use super::super::memory::addressing;
 use super::super::memory::addressing::Addressing;
 use super::super::ports;
 use super::pc_state;
@@ -8,55 +7,61 @@ use super::instruction_set;
 
 pub struct Instruction {}
 
-// Page Delay version of addressing modes (only applicable to some indexed modes, that can carry).)
-const NULL_READ:addressing::NullRead = addressing::NullRead::new();
-const MEMORY_READ:addressing::MemoryRead = addressing::MemoryRead::new();
-const ACCUMULATOR_READ:addressing::AccumulatorRead = addressing::AccumulatorRead::new();
-const MEMORY_WRITE:addressing::MemoryWrite = addressing::MemoryWrite::new();
-const ACCUMULATOR_WRITE:addressing::AccumulatorWrite = addressing::AccumulatorWrite::new();
-const REG_WRITE:addressing::RegisterWrite = addressing::RegisterWrite::new();
-const MEMORY_NULL:addressing::MemoryNull = addressing::MemoryNull::new();
-
 use OpName::*;
 use AddressMode::*;
 use RegisterName::*;
 
-#[derive(Debug, Copy, Clone)]
-enum RegisterName { N, X, Y, A, S} // 'N - Null/No register
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RegisterName { N, X, Y, A, S} // 'N - Null/No register
 
-#[derive(Debug, Copy, Clone)]
-enum OpName {
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OpName {
     Adc, And, Asl, Bit, Clc, Cld, Cli, Clv, Cmp, Cpx, Cpy, Dcp, Dec, Eor, Inc,
     Lda, Ldx, Ldy, Lsr, Nop, Or, Rol, Ror, Sax, Sbc, Sec, Sed, Sei, Sta, Stx,
     Sty, TNoStat, TStat,
     Jsr, Brk, Rti, Rts, JmpAbs, JmpInd, Php, Plp, Pha, Pla, Br(u8, bool),
-    Isc, Slo, Lax, // Undocumented op-codes
-    NoOP
+    Isc, Slo, Lax, Rla, Sre, Rra, Anc, Alr, Arr, Sbx, Ane, Lxa, Las, Sha, Shx, Shy, Tas, Jam, // Undocumented op-codes
 }
 
-#[derive(Debug, Copy, Clone)]
-enum AddressMode {
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AddressMode {
     Imp(RegisterName, RegisterName), // (Read, Write)
-    IzxR, IzyR,ImmR, ZpR, ZpyR, ZpxR, IzyRD, AbsR, AbxR, AbyR, AbxRD, AbyRD,
-    IzxW, IzyW, ZpW, ZpxW, AbsW, AbxW, AbxWD, AbyW, Acc, IzxRegW, ZpRegW, ZpxRegW, ZpyRegW, IzyRegW, AbsRegW,
-    None, AbxRegWD, AbyRegWD,
-    NoA
+    IzxR, ImmR, ZpR, ZpyR, ZpxR, IzyRD, AbsR, AbxRD, AbyRD, // 'D' indexed, the extra cycle only if a page is crossed.
+    IzxW, ZpW, ZpxW, AbsW, Acc, IzxRegW, ZpRegW, ZpxRegW, ZpyRegW, AbsRegW,
+    IzyWD, AbxWD, AbyWD, // Indexed read-modify-write, always take the extra cycle.
+    None, IzyRegWD, AbxRegWD, AbyRegWD, // Indexed writes, always take the extra cycle.
 }
 
 impl Instruction {
 
+    // Run a complete instruction, starting with the op-code fetch.
+    pub fn step(
+        clock: &mut clocks::Clock,
+        memory: &mut dyn memory::Bus,
+        pc_state: &mut pc_state::PcState,
+        ports: &mut ports::Ports) {
+
+        let op_code = instruction_set::fetch_cycle(clock, pc_state, memory);
+        Instruction::execute(op_code, clock, memory, pc_state, ports);
+    }
+
+    // Run the remaining cycles of the instruction, the op-code has already been fetched (and the PC is past it).
     pub fn execute(
         op_code: u8,
         clock: &mut clocks::Clock,
-        memory: &mut memory::Memory,
+        memory: &mut dyn memory::Bus,
         pc_state: &mut pc_state::PcState,
-        ports: &mut ports::Ports) {
+        _ports: &mut ports::Ports) {
 
         use instruction_set::*;
 
         let op_fn = |op| {match op {
             Adc => adc,
+            Alr => alr,
+            Anc => anc,
             And => and,
+            Ane => ane,
+            Arr => arr,
             Asl => asl,
             Bit => bit,
             Clc => clc,
@@ -71,24 +76,34 @@ impl Instruction {
             Eor => eor,
             Inc => inc,
             Isc => isc,
+            Las => las,
             Lax => lax,
             Lda => lda,
             Ldx => ldx,
             Ldy => ldy,
             Lsr => lsr,
+            Lxa => lxa,
             Nop => nop,
             Or => or,
             Rol => rol,
             Ror => ror,
+            Rla => rla,
+            Rra => rra,
             Sax => sax,
             Sbc => sbc,
+            Sbx => sbx,
             Sec => sec,
             Sed => sed,
             Sei => sei,
+            Sha => sha,
+            Shx => shx,
+            Shy => shy,
             Slo => slo,
+            Sre => sre,
             Sta => sta,
             Stx => stx,
             Sty => sty,
+            Tas => tas,
             TNoStat => t_no_status,
             TStat => t_status,
             _ => {panic!("Unexpected operator {:?}", op);}
@@ -96,18 +111,14 @@ impl Instruction {
 
         let addressing_fn = |addr| match addr {
                 IzxR | IzxRegW | IzxW =>  &Addressing::Izx,
-                IzyR | IzyRegW | IzyW =>  &Addressing::Izy,
+                IzyRD | IzyWD | IzyRegWD =>  &Addressing::Izy,
                 ImmR =>  &Addressing::Imm,
                 ZpR | ZpW | ZpRegW =>  &Addressing::Zp,
                 ZpxR | ZpxW | ZpxRegW =>  &Addressing::Zpx,
                 ZpyR | ZpyRegW =>  &Addressing::Zpy,
-                IzyRD =>  &Addressing::IZYPageDelay,
                 AbsR | AbsW | AbsRegW =>  &Addressing::Abs,
-                AbxR | AbxW | AbyW =>  &Addressing::Abx,
-                AbyR =>  &Addressing::Aby,
-                AbxRD | AbxWD =>  &Addressing::AbxPageDelay,
-                AbyRD => &Addressing::AbyPageDelay,
-                Acc =>  &Addressing::Accumulator,
+                AbxRD | AbxWD | AbxRegWD =>  &Addressing::Abx,
+                AbyRD | AbyWD | AbyRegWD =>  &Addressing::Aby,
 
                 _ => {panic!("Unexpected addressing mode {:?}", addr);}
         };
@@ -130,43 +141,47 @@ impl Instruction {
 
         let mut op = |op_arg, addr| {
             match (addr, op_arg) {
-                (Imp(r, w), o) => Ok(instruction_set::single_byte_instruction(clock, pc_state, memory, read_fn(r), write_fn(w), op_fn(o))),
+                (Imp(r, w), o) => instruction_set::single_byte_instruction(clock, pc_state, memory, read_fn(r), write_fn(w), op_fn(o)),
 
-                (read_null@(IzxR|IzyR|ImmR|ZpR|ZpxR|ZpyR|IzyRD|AbsR|AbxR|AbyR|AbxRD|AbyRD), o) => {
-                    Ok(instruction_set::read_write_instruction(clock, pc_state, memory, addressing_fn(read_null), MEMORY_READ, MEMORY_NULL, op_fn(o)))
-                },
-                (read_write@(IzxW|IzyW|ZpW|ZpxW|AbsW|AbxWD|AbxW|AbyW), o) => {
-                    Ok(instruction_set::read_write_instruction(clock, pc_state, memory, addressing_fn(read_write), MEMORY_READ, MEMORY_WRITE, op_fn(o)))
+                (read@(IzxR|ImmR|ZpR|ZpxR|ZpyR|IzyRD|AbsR|AbxRD|AbyRD), o) => {
+                    instruction_set::read_instruction(clock, pc_state, memory, addressing_fn(read), op_fn(o))
                 },
-                (Acc, o) => {
-                    Ok(instruction_set::read_write_instruction(clock, pc_state, memory, &Addressing::Accumulator, ACCUMULATOR_READ, ACCUMULATOR_WRITE, op_fn(o)))
+                (read_write@(IzxW|ZpW|ZpxW|AbsW|IzyWD|AbxWD|AbyWD), o) => {
+                    instruction_set::read_modify_write_instruction(clock, pc_state, memory, addressing_fn(read_write), op_fn(o))
                 },
-                (reg_write@(IzxRegW|ZpRegW|ZpxRegW|IzyRegW|AbsRegW|ZpyRegW), o) => {
-                    Ok(instruction_set::read_write_instruction(clock, pc_state, memory, addressing_fn(reg_write),  NULL_READ, REG_WRITE, op_fn(o)))
+                (Acc, o) => instruction_set::single_byte_instruction(clock, pc_state, memory, pc_state::read_a, pc_state::write_a, op_fn(o)),
+
+                (AbxRegWD, o@Shy) => instruction_set::store_high_instruction(clock, pc_state, memory, &Addressing::Abx, pc_state::read_x, op_fn(o)),
+                (store_high@(IzyRegWD|AbyRegWD), o@(Sha|Shx|Tas)) => instruction_set::store_high_instruction(clock, pc_state, memory, addressing_fn(store_high), pc_state::read_y, op_fn(o)),
+                (reg_write@(IzxRegW|ZpRegW|ZpxRegW|ZpyRegW|AbsRegW|IzyRegWD|AbxRegWD|AbyRegWD), o) => {
+                    instruction_set::write_instruction(clock, pc_state, memory, addressing_fn(reg_write), op_fn(o))
                 },
 
-                (AbxRegWD, o) => Ok(instruction_set::read_write_instruction_additional_delay(clock, pc_state, memory, &Addressing::Abx, NULL_READ, REG_WRITE, op_fn(o), pc_state::PcState::CYCLES_TO_CLOCK)),
-                (AbyRegWD, o) => Ok(instruction_set::read_write_instruction_additional_delay(clock, pc_state, memory, &Addressing::Aby, NULL_READ, REG_WRITE, op_fn(o), pc_state::PcState::CYCLES_TO_CLOCK)),
-
-                (None, Jsr) => Ok(instruction_set::jump_sub_routine_instruction(clock, pc_state, memory)),
-                (None, Brk) => Ok(instruction_set::break_instruction(clock, pc_state, memory)),
-                (None, Rti) => Ok(instruction_set::return_from_interrupt(clock, pc_state, memory)),
-                (None, Rts) => Ok(instruction_set::return_from_sub_routine_instruction(clock, pc_state, memory)),
-                (None, JmpAbs) => Ok(instruction_set::jump_instruction(clock, pc_state, memory, &Addressing::Abs)),
-                (None, JmpInd) => Ok(instruction_set::jump_instruction(clock, pc_state, memory, &Addressing::Indirect)),
-                (None, Php) => Ok(instruction_set::php_instruction(clock, pc_state, memory)),
-                (None, Plp) => Ok(instruction_set::plp_instruction(clock, pc_state, memory)),
-                (None, Pha) => Ok(instruction_set::pha_instruction(clock, pc_state, memory)),
-                (None, Pla) => Ok(instruction_set::pla_instruction(clock, pc_state, memory)),
-
-                (None, Br(m,v)) => Ok(instruction_set::branch_instruction(clock, pc_state, memory, 1 << m, (1 << m) * (v as u8))), // N == 1
-                _ => { Err(format!("Unexpected address operator combination {:?} {:?}", addr, op_arg))}
+                (None, Jsr) => instruction_set::jump_sub_routine_instruction(clock, pc_state, memory),
+                (None, Brk) => instruction_set::break_instruction(clock, pc_state, memory),
+                (None, Rti) => instruction_set::return_from_interrupt(clock, pc_state, memory),
+                (None, Rts) => instruction_set::return_from_sub_routine_instruction(clock, pc_state, memory),
+                (None, JmpAbs) => instruction_set::jump_instruction(clock, pc_state, memory, &Addressing::Abs),
+                (None, JmpInd) => instruction_set::jump_instruction(clock, pc_state, memory, &Addressing::Indirect),
+                (None, Php) => instruction_set::php_instruction(clock, pc_state, memory),
+                (None, Plp) => instruction_set::plp_instruction(clock, pc_state, memory),
+                (None, Pha) => instruction_set::pha_instruction(clock, pc_state, memory),
+                (None, Pla) => instruction_set::pla_instruction(clock, pc_state, memory),
+                (None, Jam) => instruction_set::jam_instruction(clock, pc_state, memory),
+
+                (None, Br(m,v)) => instruction_set::branch_instruction(clock, pc_state, memory, 1 << m, (1 << m) * (v as u8)), // N == 1
+                _ => return Err(format!("Unexpected address operator combination {:?} {:?}", addr, op_arg)),
             }
+            Ok(())
         };
 
-        // Mnemonic simplifications 
-        const NA:(OpName, AddressMode) = (NoOP,  NoA); // Not applicable/operation not implemented.
+        let (op_name, address_mode) = Instruction::decode(op_code);
+        if let Err(err) = op(op_name, address_mode) {panic!("Ocode not implemented: 0x{:x}. ({})", op_code, err)};
+    }
 
+    // The op-code table, the operation and addressing mode (shared with the disassembler).
+    pub fn decode(op_code: u8) -> (OpName, AddressMode) {
+        // Mnemonic simplifications 
         // Bpl: 0x80, 0x00, N == 0, Bmi: 0x80, 0x80, N == 1, Bvc: 0x40, 0x00, V == 0, Bvs: 0x40, 0x40, V == 1,
         // Bcc: 0x01, 0x00, C == 0, Bcs: 0x01, 0x01, C == 1, Bne: 0x02, 0x00, Z == 0, Beo: 0x02, 0x02, Z == 1,
         let (bpl, bmi, bvc, bvs, bcc, bcs, bne, beo) = ((Br(7, false), None),
@@ -178,63 +193,268 @@ impl Instruction {
                                                         (Br(1, false), None),
                                                         (Br(1, true), None));
 
-        let mut low =  |(op0, a0), (op1, a1), (op2, a2), (op3, a3), (op4, a4), (op5, a5), (op6, a6), (op7, a7)| { 
+        let low =  |(op0, a0), (op1, a1), (op2, a2), (op3, a3), (op4, a4), (op5, a5), (op6, a6), (op7, a7)| { 
             match op_code & 0x7 {
-                0 => op(op0, a0), 
-                1 => op(op1, a1), 
-                2 => op(op2, a2),
-                3 => op(op3, a3),
-                4 => op(op4, a4),
-                5 => op(op5, a5),
-                6 => op(op6, a6),
-                7 => op(op7, a7), 
+                0 => (op0, a0), 
+                1 => (op1, a1), 
+                2 => (op2, a2),
+                3 => (op3, a3),
+                4 => (op4, a4),
+                5 => (op5, a5),
+                6 => (op6, a6),
+                7 => (op7, a7), 
                 _ => unreachable!("Me panic? Not Possible.")
             }
 
         };
 
-        if let Err(err) = match op_code & 0xF8 {
-
-            0x00 => low((Brk, None),       (Or, IzxR),      NA,                   (Slo, IzxW),    (Nop, ZpR),     (Or, ZpR),       (Asl, ZpW),      (Slo, ZpW)),
-            0x08 => low((Php, None),       (Or, ImmR),      (Asl, Imp(A,A)),      NA,             (Nop, AbsR),    (Or, AbsR),      (Asl, AbsW),     (Slo, AbsW)),
-            0x10 => low(bpl,               (Or, IzyRD),     NA,                   (Slo, IzyW),    (Nop, ZpxR),    (Or, ZpxR),      (Asl, ZpxW),     (Slo, ZpxW)),
-            0x18 => low((Clc, Imp(N,N)),   (Or, AbyRD),     (Nop, Imp(N,N)),      (Slo, AbyW),    (Nop, AbxR),    (Or, AbxRD),     (Asl, AbxWD),    (Slo, AbxW)),
-            0x20 => low((Jsr, None),       (And, IzxR),     NA,                   NA,             (Bit, ZpR),     (And, ZpR),      (Rol, ZpW),      NA),
-            0x28 => low((Plp, None),       (And, ImmR),     (Rol, Acc),           NA,             (Bit, AbsR),    (And, AbsR),     (Rol, AbsW),     NA),
-            0x30 => low(bmi,               (And, IzyRD),    NA,                   NA,             (Nop, ZpxR),    (And, ZpxR),     (Rol, ZpxW),     NA),
-            0x38 => low((Sec, Imp(N,N)),   (And, AbyRD),    (Nop, Imp(N,N)),      NA,             (Nop, AbxR),    (And, AbxRD),    (Rol, AbxW),     NA),
-            0x40 => low((Rti, None),       (Eor, IzxR),     NA,                   NA,             (Nop, ZpR),     (Eor, ZpR),      (Lsr, ZpW),      NA),
-            0x48 => low((Pha, None),       (Eor, ImmR),     (Lsr, Imp(A,A)),      NA,             (JmpAbs, None), (Eor, AbsR),     (Lsr, AbsW),     NA),
-            0x50 => low(bvc,               (Eor, IzyR),     NA,                   NA,             (Nop, ZpxR),    (Eor, ZpxR),     (Lsr, ZpxW),     NA),
-            0x58 => low((Cli, Imp(N,N)),   (Eor, AbyR),     (Nop, Imp(N,N)),      NA,             (Nop, AbxR),    (Eor, AbxR),     (Lsr, AbxWD),    NA),
-            0x60 => low((Rts, None),       (Adc, IzxR),     NA,                   NA,             (Nop, ZpR),     (Adc, ZpR),      (Ror, ZpW),      NA),
-            0x68 => low((Pla, None),       (Adc, ImmR),     (Ror, Acc),           NA,             (JmpInd, None), (Adc, AbsR),     (Ror, AbsW),     NA),
-            0x70 => low(bvs,               (Adc, IzyRD),    NA,                   NA,             (Nop, ZpxR),    (Adc, ZpxR),     (Ror, ZpxW),     NA),
-            0x78 => low((Sei, Imp(N,N)),   (Adc, AbyRD),    (Nop, Imp(N,N)),      NA,             (Nop, AbxR),    (Adc, AbxRD),    (Ror, AbxW),     NA),
-            0x80 => low((Nop, ImmR),       (Sta, IzxRegW),  (Nop, ImmR),          (Sax, IzxRegW), (Sty, ZpRegW),  (Sta, ZpRegW),   (Stx, ZpRegW),   (Sax, ZpRegW)),
-            0x88 => low((Dec, Imp(Y,Y)),   (Nop, ImmR),     (TStat, Imp(X,A)),    NA,             (Sty, AbsRegW), (Sta, AbsRegW),  (Stx, AbsRegW),  (Sax, AbsRegW)),
-            0x90 => low(bcc,               (Sta, IzyRegW),  NA,                   NA,             (Sty, ZpxRegW), (Sta, ZpxRegW),  (Stx, ZpyRegW),  (Sax, ZpyRegW)),
-            0x98 => low((TStat, Imp(Y,A)), (Sta, AbyRegWD), (TNoStat, Imp(X,S)),  NA,             NA,             (Sta, AbxRegWD), NA,              NA),
-            0xA0 => low((Ldy, ImmR),       (Lda, IzxR),     (Ldx, ImmR),          (Lax, IzxR),    (Ldy, ZpR),     (Lda, ZpR),      (Ldx, ZpR),      (Lax, ZpR)),
-            0xA8 => low((TStat, Imp(A,Y)), (Lda, ImmR),     (TStat, Imp(A,X)),    NA,             (Ldy, AbsR),    (Lda, AbsR),     (Ldx, AbsR),     (Lax, AbsR)),
-            0xB0 => low(bcs,               (Lda, IzyRD),    NA,                   (Lax, IzyR),    (Ldy, ZpxR),    (Lda, ZpxR),     (Ldx, ZpyR),     (Lax, ZpyR)),
-            0xB8 => low((Clv, Imp(N,N)),   (Lda, AbyRD),    (TNoStat, Imp(S, X)), NA,             (Ldy, AbxRD),   (Lda, AbxRD),    (Ldx, AbyRD),    (Lax, AbyR)),
-            0xC0 => low((Cpy, ImmR),       (Cmp, IzxR),     (Nop, ImmR),          (Dcp, IzxR),    (Cpy, ZpR),     (Cmp, ZpR),      (Dec, ZpW),      (Dcp, ZpR)),
-            0xC8 => low((Inc, Imp(Y,Y)),   (Cmp, ImmR),     (Dec, Imp(X,X)),      NA,             (Cpy, AbsR),    (Cmp, AbsR),     (Dec, AbsW),     (Dcp, AbsR)),
-            0xD0 => low(bne,               (Cmp, IzyRD),    NA,                   (Dcp, IzyRD),   (Nop, ZpxR),    (Cmp, ZpxR),     (Dec, ZpxW),     (Dcp, ZpxR)),
-            0xD8 => low((Cld, Imp(N,N)),   (Cmp, AbyRD),    (Nop, Imp(N,N)),      (Dcp, AbyRD),   (Nop, AbxR),    (Cmp, AbxRD),    (Dec, AbxWD),    (Dcp, AbxRD)),
-            0xE0 => low((Cpx, ImmR),       (Sbc, IzxR),     (Nop, ImmR),          (Isc, IzxW),    (Cpx, ZpR),     (Sbc, ZpR),      (Inc, ZpW),      (Isc, ZpW)),
-            0xE8 => low((Inc, Imp(X,X)),   (Sbc, ImmR),     (Nop, Imp(A,A)),      NA,             (Cpx, AbsR),    (Sbc, AbsR),     (Inc, AbsW),     (Isc, AbsW)),
-            0xF0 => low(beo,               (Sbc, IzyRD),    NA,                   (Isc, IzyW),    (Nop, ZpxR),    (Sbc, ZpxR),     (Inc, ZpxW),     (Isc, ZpxW)),
-            0xF8 => low((Sed, Imp(N,N)),   (Sbc, AbyRD),    (Nop, Imp(N,N)),      (Isc, AbyW),    (Nop, AbxR),    (Sbc, AbxRD),    (Inc, AbxWD),    (Isc, AbxW)),
-
-            _ => {
-                Err(format!("Ocode not implemented: 0x{:x}", op_code))
-            }
-        } {panic!("Ocode not implemented: 0x{:x}. ({})", op_code, err)};
+        match op_code & 0xF8 {
+
+            0x00 => low((Brk, None),       (Or, IzxR),      (Jam, None),          (Slo, IzxW),     (Nop, ZpR),      (Or, ZpR),       (Asl, ZpW),      (Slo, ZpW)),
+            0x08 => low((Php, None),       (Or, ImmR),      (Asl, Imp(A,A)),      (Anc, ImmR),     (Nop, AbsR),     (Or, AbsR),      (Asl, AbsW),     (Slo, AbsW)),
+            0x10 => low(bpl,               (Or, IzyRD),     (Jam, None),          (Slo, IzyWD),    (Nop, ZpxR),     (Or, ZpxR),      (Asl, ZpxW),     (Slo, ZpxW)),
+            0x18 => low((Clc, Imp(N,N)),   (Or, AbyRD),     (Nop, Imp(N,N)),      (Slo, AbyWD),    (Nop, AbxRD),    (Or, AbxRD),     (Asl, AbxWD),    (Slo, AbxWD)),
+            0x20 => low((Jsr, None),       (And, IzxR),     (Jam, None),          (Rla, IzxW),     (Bit, ZpR),      (And, ZpR),      (Rol, ZpW),      (Rla, ZpW)),
+            0x28 => low((Plp, None),       (And, ImmR),     (Rol, Acc),           (Anc, ImmR),     (Bit, AbsR),     (And, AbsR),     (Rol, AbsW),     (Rla, AbsW)),
+            0x30 => low(bmi,               (And, IzyRD),    (Jam, None),          (Rla, IzyWD),    (Nop, ZpxR),     (And, ZpxR),     (Rol, ZpxW),     (Rla, ZpxW)),
+            0x38 => low((Sec, Imp(N,N)),   (And, AbyRD),    (Nop, Imp(N,N)),      (Rla, AbyWD),    (Nop, AbxRD),    (And, AbxRD),    (Rol, AbxWD),    (Rla, AbxWD)),
+            0x40 => low((Rti, None),       (Eor, IzxR),     (Jam, None),          (Sre, IzxW),     (Nop, ZpR),      (Eor, ZpR),      (Lsr, ZpW),      (Sre, ZpW)),
+            0x48 => low((Pha, None),       (Eor, ImmR),     (Lsr, Imp(A,A)),      (Alr, ImmR),     (JmpAbs, None),  (Eor, AbsR),     (Lsr, AbsW),     (Sre, AbsW)),
+            0x50 => low(bvc,               (Eor, IzyRD),    (Jam, None),          (Sre, IzyWD),    (Nop, ZpxR),     (Eor, ZpxR),     (Lsr, ZpxW),     (Sre, ZpxW)),
+            0x58 => low((Cli, Imp(N,N)),   (Eor, AbyRD),    (Nop, Imp(N,N)),      (Sre, AbyWD),    (Nop, AbxRD),    (Eor, AbxRD),    (Lsr, AbxWD),    (Sre, AbxWD)),
+            0x60 => low((Rts, None),       (Adc, IzxR),     (Jam, None),          (Rra, IzxW),     (Nop, ZpR),      (Adc, ZpR),      (Ror, ZpW),      (Rra, ZpW)),
+            0x68 => low((Pla, None),       (Adc, ImmR),     (Ror, Acc),           (Arr, ImmR),     (JmpInd, None),  (Adc, AbsR),     (Ror, AbsW),     (Rra, AbsW)),
+            0x70 => low(bvs,               (Adc, IzyRD),    (Jam, None),          (Rra, IzyWD),    (Nop, ZpxR),     (Adc, ZpxR),     (Ror, ZpxW),     (Rra, ZpxW)),
+            0x78 => low((Sei, Imp(N,N)),   (Adc, AbyRD),    (Nop, Imp(N,N)),      (Rra, AbyWD),    (Nop, AbxRD),    (Adc, AbxRD),    (Ror, AbxWD),    (Rra, AbxWD)),
+            0x80 => low((Nop, ImmR),       (Sta, IzxRegW),  (Nop, ImmR),          (Sax, IzxRegW),  (Sty, ZpRegW),   (Sta, ZpRegW),   (Stx, ZpRegW),   (Sax, ZpRegW)),
+            0x88 => low((Dec, Imp(Y,Y)),   (Nop, ImmR),     (TStat, Imp(X,A)),    (Ane, ImmR),     (Sty, AbsRegW),  (Sta, AbsRegW),  (Stx, AbsRegW),  (Sax, AbsRegW)),
+            0x90 => low(bcc,               (Sta, IzyRegWD), (Jam, None),          (Sha, IzyRegWD), (Sty, ZpxRegW),  (Sta, ZpxRegW),  (Stx, ZpyRegW),  (Sax, ZpyRegW)),
+            0x98 => low((TStat, Imp(Y,A)), (Sta, AbyRegWD), (TNoStat, Imp(X,S)),  (Tas, AbyRegWD), (Shy, AbxRegWD), (Sta, AbxRegWD), (Shx, AbyRegWD), (Sha, AbyRegWD)),
+            0xA0 => low((Ldy, ImmR),       (Lda, IzxR),     (Ldx, ImmR),          (Lax, IzxR),     (Ldy, ZpR),      (Lda, ZpR),      (Ldx, ZpR),      (Lax, ZpR)),
+            0xA8 => low((TStat, Imp(A,Y)), (Lda, ImmR),     (TStat, Imp(A,X)),    (Lxa, ImmR),     (Ldy, AbsR),     (Lda, AbsR),     (Ldx, AbsR),     (Lax, AbsR)),
+            0xB0 => low(bcs,               (Lda, IzyRD),    (Jam, None),          (Lax, IzyRD),    (Ldy, ZpxR),     (Lda, ZpxR),     (Ldx, ZpyR),     (Lax, ZpyR)),
+            0xB8 => low((Clv, Imp(N,N)),   (Lda, AbyRD),    (TStat, Imp(S,X)),    (Las, AbyRD),    (Ldy, AbxRD),    (Lda, AbxRD),    (Ldx, AbyRD),    (Lax, AbyRD)),
+            0xC0 => low((Cpy, ImmR),       (Cmp, IzxR),     (Nop, ImmR),          (Dcp, IzxW),     (Cpy, ZpR),      (Cmp, ZpR),      (Dec, ZpW),      (Dcp, ZpW)),
+            0xC8 => low((Inc, Imp(Y,Y)),   (Cmp, ImmR),     (Dec, Imp(X,X)),      (Sbx, ImmR),     (Cpy, AbsR),     (Cmp, AbsR),     (Dec, AbsW),     (Dcp, AbsW)),
+            0xD0 => low(bne,               (Cmp, IzyRD),    (Jam, None),          (Dcp, IzyWD),    (Nop, ZpxR),     (Cmp, ZpxR),     (Dec, ZpxW),     (Dcp, ZpxW)),
+            0xD8 => low((Cld, Imp(N,N)),   (Cmp, AbyRD),    (Nop, Imp(N,N)),      (Dcp, AbyWD),    (Nop, AbxRD),    (Cmp, AbxRD),    (Dec, AbxWD),    (Dcp, AbxWD)),
+            0xE0 => low((Cpx, ImmR),       (Sbc, IzxR),     (Nop, ImmR),          (Isc, IzxW),     (Cpx, ZpR),      (Sbc, ZpR),      (Inc, ZpW),      (Isc, ZpW)),
+            0xE8 => low((Inc, Imp(X,X)),   (Sbc, ImmR),     (Nop, Imp(A,A)),      (Sbc, ImmR),     (Cpx, AbsR),     (Sbc, AbsR),     (Inc, AbsW),     (Isc, AbsW)),
+            0xF0 => low(beo,               (Sbc, IzyRD),    (Jam, None),          (Isc, IzyWD),    (Nop, ZpxR),     (Sbc, ZpxR),     (Inc, ZpxW),     (Isc, ZpxW)),
+            0xF8 => low((Sed, Imp(N,N)),   (Sbc, AbyRD),    (Nop, Imp(N,N)),      (Isc, AbyWD),    (Nop, AbxRD),    (Sbc, AbxRD),    (Inc, AbxWD),    (Isc, AbxWD)),
+
+            _ => unreachable!("All op-codes are in the table.")
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::cpu::instructions::Instruction;
+    use crate::atari2600::cpu::pc_state::PcState;
+    use crate::atari2600::graphics::stella::Stella;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+    use crate::atari2600::memory::memory::{Bus, Memory};
+    use crate::atari2600::memory::riot::Riot;
+    use crate::atari2600::ports::Ports;
+
+    const C: u8 = 0x01;
+    const Z: u8 = 0x02;
+    const D: u8 = 0x08;
+    const V: u8 = 0x40;
+    const N: u8 = 0x80;
+
+    // The operand is always at $0084 (in the RIOT RAM), the indirect modes use a pointer at $E0.
+    const OPERAND: u16 = 0x0084;
+    const POINTER: u8 = 0xE0;
+
+    #[derive(Clone, Copy, Debug)]
+    enum Mode { Imp, Imm, Zp, Zpx, Zpy, Abs, Abx, Aby, Izx, Izy }
+
+    // (A, X, Y, P)
+    type Registers = (u8, u8, u8, u8);
+
+    // Execute a single instruction (at $F000), returning the state and the number of cycles taken.
+    fn execute(code: &[u8], registers: Registers, memory_setup: &[(u16, u8)]) -> (PcState, Memory, u64) {
+        let mut rom = vec![0xEA_u8; 0x1000];
+        rom[..code.len()].copy_from_slice(code);
+        let mut memory = Memory::new(get_new_carterage("test", &rom, &CartridgeType::Default), Box::new(Stella::new(false, false, false)), Box::new(Riot::new()));
+        let mut clock = Clock::new();
+        for (address, data) in memory_setup {
+            memory.write(&mut clock, *address, *data);
+        }
+
+        let mut pc_state = PcState::new();
+        pc_state.set_pc(0xF000);
+        pc_state.set_s(0xFF);
+        let (a, x, y, p) = registers;
+        pc_state.set_a(a);
+        pc_state.set_x(x);
+        pc_state.set_y(y);
+        pc_state.set_p(p);
+
+        let start = clock.ticks;
+        Instruction::step(&mut clock, &mut memory, &mut pc_state, &mut Ports::new());
+        (pc_state, memory, (clock.ticks - start) / PcState::CYCLES_TO_CLOCK as u64)
+    }
+
+    // Run the op-code with the operand at 'OPERAND' (or the immediate value), check the registers, the operand after
+    // and the cycles.
+    fn check(op_code: u8, mode: Mode, registers: Registers, data: u8, expected: Registers, expected_data: u8, cycles: u64) -> PcState {
+        let (_, x, y, _) = registers;
+        let pointer = |address: u16| [(POINTER as u16, address as u8), (POINTER as u16 + 1, (address >> 8) as u8)];
+        let (code, mut setup) = match mode {
+            Mode::Imp => (vec![op_code], vec![]),
+            Mode::Imm => (vec![op_code, data], vec![]),
+            Mode::Zp => (vec![op_code, OPERAND as u8], vec![]),
+            Mode::Zpx => (vec![op_code, (OPERAND as u8).wrapping_sub(x)], vec![]),
+            Mode::Zpy => (vec![op_code, (OPERAND as u8).wrapping_sub(y)], vec![]),
+            Mode::Abs => (vec![op_code, OPERAND as u8, 0x00], vec![]),
+            Mode::Abx => (vec![op_code, OPERAND as u8 - x, 0x00], vec![]),
+            Mode::Aby => (vec![op_code, OPERAND as u8 - y, 0x00], vec![]),
+            Mode::Izx => (vec![op_code, POINTER.wrapping_sub(x)], pointer(OPERAND).to_vec()),
+            Mode::Izy => (vec![op_code, POINTER], pointer(OPERAND - y as u16).to_vec()),
+        };
+        setup.push((OPERAND, data));
+
+        let (pc_state, mut memory, taken) = execute(&code, registers, &setup);
+        let name = format!("0x{:02X} {:?}", op_code, mode);
+        let (a, x, y, p) = expected;
+        assert_eq!((pc_state.get_a(), pc_state.get_x(), pc_state.get_y()), (a, x, y), "{} registers", name);
+        assert_eq!(pc_state.get_p(), p, "{} flags {}", name, pc_state.get_flags());
+        if !matches!(mode, Mode::Imp | Mode::Imm) {
+            assert_eq!(memory.read(&Clock::new(), OPERAND), expected_data, "{} data", name);
+        }
+        assert_eq!(taken, cycles, "{} cycles", name);
+        assert_eq!(pc_state.get_pc(), 0xF000 + code.len() as u16, "{} PC", name);
+        pc_state
+    }
+
+    // The read-modify-write addressing modes and their cycles.
+    const READ_MODIFY_WRITE: [(u8, Mode, u64); 7] = [(0x07, Mode::Zp, 5), (0x17, Mode::Zpx, 6), (0x0F, Mode::Abs, 6), (0x1F, Mode::Abx, 7), (0x1B, Mode::Aby, 7), (0x03, Mode::Izx, 8), (0x13, Mode::Izy, 8)];
+
+    #[test]
+    fn test_read_modify_write_opcodes() {
+        for (base, mode, cycles) in READ_MODIFY_WRITE {
+            check(base, mode, (0x10, 4, 4, 0), 0x81, (0x12, 4, 4, C), 0x02, cycles); // SLO
+            check(base | 0x20, mode, (0x0F, 4, 4, C), 0x81, (0x03, 4, 4, C), 0x03, cycles); // RLA
+            check(base | 0x40, mode, (0xC0, 4, 4, 0), 0x81, (0x80, 4, 4, N | C), 0x40, cycles); // SRE
+            check(base | 0x60, mode, (0x10, 4, 4, 0), 0x03, (0x12, 4, 4, 0), 0x01, cycles); // RRA
+            check(base | 0xC0, mode, (0x10, 4, 4, 0), 0x11, (0x10, 4, 4, Z | C), 0x10, cycles); // DCP
+            check(base | 0xE0, mode, (0x10, 4, 4, C), 0x0F, (0x00, 4, 4, Z | C), 0x10, cycles); // ISC
+        }
+
+        // Documented indexed read-modify-write always take the extra cycle.
+        check(0x1E, Mode::Abx, (0, 4, 4, 0), 0x81, (0, 4, 4, C), 0x02, 7); // ASL
+        check(0x3E, Mode::Abx, (0, 4, 4, C), 0x40, (0, 4, 4, N), 0x81, 7); // ROL
+        check(0x5E, Mode::Abx, (0, 4, 4, 0), 0x01, (0, 4, 4, Z | C), 0x00, 7); // LSR
+        check(0x7E, Mode::Abx, (0, 4, 4, C), 0x02, (0, 4, 4, N), 0x81, 7); // ROR
+        check(0xDE, Mode::Abx, (0, 4, 4, 0), 0x01, (0, 4, 4, Z), 0x00, 7); // DEC
+        check(0xFE, Mode::Abx, (0, 4, 4, 0), 0xFF, (0, 4, 4, Z), 0x00, 7); // INC
+        check(0x26, Mode::Zp, (0, 4, 4, 0), 0x80, (0, 4, 4, Z | C), 0x00, 5); // ROL
+        check(0x6E, Mode::Abs, (0, 4, 4, 0), 0x01, (0, 4, 4, Z | C), 0x00, 6); // ROR
+    }
+
+    #[test]
+    fn test_load_store_opcodes() {
+        for (op_code, mode, cycles) in [(0xA7, Mode::Zp, 3), (0xB7, Mode::Zpy, 4), (0xAF, Mode::Abs, 4), (0xBF, Mode::Aby, 4), (0xA3, Mode::Izx, 6), (0xB3, Mode::Izy, 5)] {
+            check(op_code, mode, (0, 4, 4, 0), 0x84, (0x84, 0x84, 4, N), 0x84, cycles); // LAX
+        }
+        for (op_code, mode, cycles) in [(0x87, Mode::Zp, 3), (0x97, Mode::Zpy, 4), (0x8F, Mode::Abs, 4), (0x83, Mode::Izx, 6)] {
+            check(op_code, mode, (0xF0, 0x3C, 4, 0), 0x00, (0xF0, 0x3C, 4, 0), 0x30, cycles); // SAX
+        }
+        check(0x91, Mode::Izy, (0x42, 4, 4, 0), 0x00, (0x42, 4, 4, 0), 0x42, 6); // STA (zp),Y
+        check(0x51, Mode::Izy, (0x0F, 4, 4, 0), 0xFF, (0xF0, 4, 4, N), 0xFF, 5); // EOR (zp),Y
+
+        let pc_state = check(0xBB, Mode::Aby, (0, 4, 4, 0), 0x7C, (0x7C, 0x7C, 4, 0), 0x7C, 4); // LAS
+        assert_eq!(pc_state.get_s(), 0x7C);
+
+        // The store high instructions, AND with the high byte of the base address + 1.
+        let pc_state = check(0x9B, Mode::Aby, (0xFF, 0x0F, 4, 0), 0x00, (0xFF, 0x0F, 4, 0), 0x01, 5); // TAS
+        assert_eq!(pc_state.get_s(), 0x0F);
+        check(0x9F, Mode::Aby, (0xF3, 0xFF, 4, 0), 0x00, (0xF3, 0xFF, 4, 0), 0x01, 5); // SHA
+        check(0x93, Mode::Izy, (0xF3, 0xFF, 4, 0), 0x00, (0xF3, 0xFF, 4, 0), 0x01, 6); // SHA
+        check(0x9E, Mode::Aby, (0, 0xFF, 4, 0), 0x00, (0, 0xFF, 4, 0), 0x01, 5); // SHX
+        check(0x9C, Mode::Abx, (0, 4, 0xFF, 0), 0x00, (0, 4, 0xFF, 0), 0x01, 5); // SHY
+
+        // SHX $0180,Y (page 1, so AND $02)
+        let (_, mut memory, _) = execute(&[0x9E, 0x80, 0x01], (0, 0x03, 1, 0), &[]);
+        assert_eq!(memory.read(&Clock::new(), 0x0181), 0x02);
+        // SHX $01F0,Y to $0280 (RIOT I/O) crosses a page, the value (0) replaces the high byte, so writes RAM $0080.
+        let (_, mut memory, _) = execute(&[0x9E, 0xF0, 0x01], (0, 0xFD, 0x90, 0), &[(0x0080, 0x55)]);
+        assert_eq!(memory.read(&Clock::new(), 0x0080), 0x00);
+    }
+
+    #[test]
+    fn test_immediate_opcodes() {
+        check(0x0B, Mode::Imm, (0x81, 0, 0, 0), 0xF0, (0x80, 0, 0, N | C), 0, 2); // ANC
+        check(0x2B, Mode::Imm, (0x81, 0, 0, C), 0x0F, (0x01, 0, 0, 0), 0, 2); // ANC
+        check(0x4B, Mode::Imm, (0x0F, 0, 0, 0), 0x03, (0x01, 0, 0, C), 0, 2); // ALR
+        check(0x6B, Mode::Imm, (0xFF, 0, 0, C), 0xFF, (0xFF, 0, 0, N | C), 0, 2); // ARR
+        check(0x6B, Mode::Imm, (0x80, 0, 0, 0), 0xFF, (0x40, 0, 0, V | C), 0, 2); // ARR
+        check(0x6B, Mode::Imm, (0xFF, 0, 0, D), 0x0F, (0x0D, 0, 0, D), 0, 2); // ARR (decimal)
+        check(0x6B, Mode::Imm, (0xFF, 0, 0, D | C), 0xF0, (0x58, 0, 0, N | D | C), 0, 2); // ARR (decimal)
+        check(0x8B, Mode::Imm, (0x00, 0xFF, 0, 0), 0xFF, (0xEE, 0xFF, 0, N), 0, 2); // ANE
+        check(0xAB, Mode::Imm, (0x01, 0, 0, 0), 0x0F, (0x0F, 0x0F, 0, 0), 0, 2); // LXA
+        check(0xCB, Mode::Imm, (0xF0, 0x3C, 0, 0), 0x10, (0xF0, 0x20, 0, C), 0, 2); // SBX
+        check(0xCB, Mode::Imm, (0xF0, 0x3C, 0, 0), 0x31, (0xF0, 0xFF, 0, N), 0, 2); // SBX
+        check(0xEB, Mode::Imm, (0x10, 0, 0, C), 0x01, (0x0F, 0, 0, C), 0, 2); // SBC
+        for op_code in [0x80, 0x82, 0x89, 0xC2, 0xE2] {
+            check(op_code, Mode::Imm, (0x10, 0, 0, 0), 0xFF, (0x10, 0, 0, 0), 0, 2); // NOP
+        }
+    }
+
+    #[test]
+    fn test_implied_and_nop_opcodes() {
+        check(0xBA, Mode::Imp, (0, 0, 0, 0), 0, (0, 0xFF, 0, N), 0, 2); // TSX
+        check(0x9A, Mode::Imp, (0, 0x80, 0, 0), 0, (0, 0x80, 0, 0), 0, 2); // TXS (no flags)
+        for op_code in [0x1A, 0x3A, 0x5A, 0x7A, 0xDA, 0xFA, 0xEA] {
+            check(op_code, Mode::Imp, (0x10, 0, 0, 0), 0, (0x10, 0, 0, 0), 0, 2);
+        }
+        for op_code in [0x04, 0x44, 0x64] {
+            check(op_code, Mode::Zp, (0, 4, 4, 0), 0x55, (0, 4, 4, 0), 0x55, 3);
+        }
+        for op_code in [0x14, 0x34, 0x54, 0x74, 0xD4, 0xF4] {
+            check(op_code, Mode::Zpx, (0, 4, 4, 0), 0x55, (0, 4, 4, 0), 0x55, 4);
+        }
+        check(0x0C, Mode::Abs, (0, 4, 4, 0), 0x55, (0, 4, 4, 0), 0x55, 4);
+        for op_code in [0x1C, 0x3C, 0x5C, 0x7C, 0xDC, 0xFC] {
+            check(op_code, Mode::Abx, (0, 4, 4, 0), 0x55, (0, 4, 4, 0), 0x55, 4);
+        }
+    }
+
+    #[test]
+    fn test_jam_opcodes() {
+        for op_code in [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2] {
+            let (pc_state, _, cycles) = execute(&[op_code], (0, 0, 0, 0), &[]);
+            assert_eq!(pc_state.get_pc(), 0xF000, "0x{:02X}", op_code);
+            assert!(cycles > 0);
+        }
+    }
+
+    #[test]
+    fn test_page_cross_dummy_read() {
+        // 'LDA $1FF9,X' in the last F8 bank, crossing a page the dummy read is at $1FF8 (switching to the first bank).
+        for (x, expected) in [(0xFF, 0xA0), (0x0F, 0xBD)] {
+            let mut rom = vec![0xA0_u8; 0x2000];
+            rom[0x1000..0x1003].copy_from_slice(&[0xBD, 0xF9, 0x1F]);
+            let mut memory = Memory::new(get_new_carterage("test", &rom, &CartridgeType::F8), Box::new(Stella::new(false, false, false)), Box::new(Riot::new()));
+            let mut clock = Clock::new();
+            let mut pc_state = PcState::new();
+            pc_state.set_pc(0xF000);
+            pc_state.set_x(x);
+
+            Instruction::step(&mut clock, &mut memory, &mut pc_state, &mut Ports::new());
+            assert_eq!(clock.ticks, 5 * PcState::CYCLES_TO_CLOCK as u64);
+            assert_eq!(memory.read(&clock, 0xF000), expected, "X=0x{:02X}", x);
+        }
+    }
+
+    #[test]
+    fn test_all_opcodes_execute() {
+        for op_code in 0..=0xFF {
+            execute(&[op_code, 0x80, 0x00], (0, 0, 0, 0), &[]);
+        }
+    }
 }
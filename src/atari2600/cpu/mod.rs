@@ -1,4 +1,7 @@
 pub mod core;
+pub mod disassembler;
 pub mod instruction_set;
 pub mod instructions;
 pub mod pc_state;
+#[cfg(test)]
+mod single_step;
@@ -1,3 +1,4 @@
+use super::super::state;
 use bitfield::bitfield;
 use std::fmt;
 
@@ -51,6 +52,12 @@ impl fmt::Display for PcState {
     }
 }
 
+impl Default for PcState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PcState {
     pub const CYCLES_TO_CLOCK: u8 = 3;
 
@@ -210,22 +217,39 @@ impl PcState {
     }
 }
 
+impl state::Snapshot for PcState {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.a_reg);
+        state.put_u8(self.x_reg);
+        state.put_u8(self.y_reg);
+        state.put_u16(self.pc_reg);
+        state.put_u8(self.s_reg);
+        state.put_u8(self.p_reg.0);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.a_reg = state.get_u8()?;
+        self.x_reg = state.get_u8()?;
+        self.y_reg = state.get_u8()?;
+        self.pc_reg = state.get_u16()?;
+        self.s_reg = state.get_u8()?;
+        self.p_reg.0 = state.get_u8()?;
+        Ok(())
+    }
+}
+
 pub fn set_status_nz(pc_state: &mut PcState, value: u8) {
     pc_state.set_flag_n(0x80 == 0x80 & value);
     pc_state.set_flag_z(0x00 == value);
 }
 
-type ReadFunc = fn(&PcState) -> u8; // Unused: Switch to 'trait alias' when available
-
-pub fn read_null(pc_state: &PcState) -> u8 { 0 }
+pub fn read_null(_pc_state: &PcState) -> u8 { 0 }
 pub fn read_x(pc_state: &PcState) -> u8 { pc_state.get_x() }
 pub fn read_y(pc_state: &PcState) -> u8 { pc_state.get_y() }
 pub fn read_a(pc_state: &PcState) -> u8 { pc_state.get_a() }
 pub fn read_s(pc_state: &PcState) -> u8 { pc_state.get_s() }
 
-type WriteFunc = fn(&mut PcState, u8); // Unused: Switch to 'trait alias' when available
-
-pub fn write_null(pc_state: &mut PcState, input: u8) { }
+pub fn write_null(_pc_state: &mut PcState, _input: u8) { }
 pub fn write_x(pc_state: &mut PcState, input: u8) { pc_state.set_x(input); }
 pub fn write_y(pc_state: &mut PcState, input: u8) { pc_state.set_y(input); }
 pub fn write_a(pc_state: &mut PcState, input: u8) { pc_state.set_a(input); }
@@ -0,0 +1,203 @@
+//! CPU tests against the 'single step' JSON test vectors (https://github.com/SingleStepTests/65x02, the '6502' set).
+//!
+//! There's a file per op-code ('a9.json' etc), each an array of tests giving the initial and final registers/RAM and
+//! every bus cycle of the instruction.  The vectors aren't part of the repo, so the test is ignored by default, set
+//! 'SINGLE_STEP_TESTS' to the directory holding them and run with '--ignored'.  The CPU runs on a
+//! flat 64K bus (no TIA/RIOT memory map), so the results are the 6502's.
+
+use super::super::clocks;
+use super::super::memory::memory::Bus;
+use super::super::ports::Ports;
+use super::instructions::Instruction;
+use super::pc_state::PcState;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// The B and bit 5 'flags' aren't stored in the status register, only in the copies pushed to the stack.
+const STATUS_MASK: u8 = 0xCF;
+
+// The 'JAM' op-codes halt the CPU, the vectors record the internal state of the stopped CPU rather than an instruction.
+const JAM_OP_CODES: [u8; 12] = [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2];
+
+// Show a few failures per op-code, more aren't useful.
+const MAX_REPORTED_FAILURES: usize = 5;
+
+#[derive(Debug, PartialEq)]
+enum Cycle {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+// All 64K is RAM, recording every access.
+struct TestBus {
+    ram: Vec<u8>,
+    cycles: Vec<Cycle>,
+}
+
+impl TestBus {
+    fn new() -> Self {
+        Self { ram: vec![0; 0x10000], cycles: Vec::new() }
+    }
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        let data = self.ram[address as usize];
+        self.cycles.push(Cycle::Read(address, data));
+        data
+    }
+
+    fn write(&mut self, _clock: &mut clocks::Clock, address: u16, data: u8) {
+        self.ram[address as usize] = data;
+        self.cycles.push(Cycle::Write(address, data));
+    }
+}
+
+fn number(value: &Value, key: &str) -> Result<u64, String> {
+    value[key].as_u64().ok_or(format!("Missing '{}'", key))
+}
+
+fn ram(state: &Value) -> Result<Vec<(u16, u8)>, String> {
+    let entries = state["ram"].as_array().ok_or("Missing 'ram'")?;
+    entries
+        .iter()
+        .map(|entry| match (entry[0].as_u64(), entry[1].as_u64()) {
+            (Some(address), Some(data)) => Ok((address as u16, data as u8)),
+            _ => Err(format!("Invalid ram entry {}", entry)),
+        })
+        .collect()
+}
+
+fn cycles(test: &Value) -> Result<Vec<Cycle>, String> {
+    let entries = test["cycles"].as_array().ok_or("Missing 'cycles'")?;
+    entries
+        .iter()
+        .map(|entry| match (entry[0].as_u64(), entry[1].as_u64(), entry[2].as_str()) {
+            (Some(address), Some(data), Some("read")) => Ok(Cycle::Read(address as u16, data as u8)),
+            (Some(address), Some(data), Some("write")) => Ok(Cycle::Write(address as u16, data as u8)),
+            _ => Err(format!("Invalid cycle {}", entry)),
+        })
+        .collect()
+}
+
+// (PC, S, A, X, Y, P)
+fn registers(state: &Value) -> Result<(u16, u8, u8, u8, u8, u8), String> {
+    Ok((
+        number(state, "pc")? as u16,
+        number(state, "s")? as u8,
+        number(state, "a")? as u8,
+        number(state, "x")? as u8,
+        number(state, "y")? as u8,
+        number(state, "p")? as u8 & STATUS_MASK,
+    ))
+}
+
+// Run a single test, returning a description of the first difference.
+fn run_test(test: &Value) -> Result<(), String> {
+    let initial = &test["initial"];
+    let expected = &test["final"];
+
+    let mut bus = TestBus::new();
+    for (address, data) in ram(initial)? {
+        bus.ram[address as usize] = data;
+    }
+
+    let mut pc_state = PcState::new();
+    pc_state.set_pc(number(initial, "pc")? as u16);
+    pc_state.set_s(number(initial, "s")? as u8);
+    pc_state.set_a(number(initial, "a")? as u8);
+    pc_state.set_x(number(initial, "x")? as u8);
+    pc_state.set_y(number(initial, "y")? as u8);
+    pc_state.set_p(number(initial, "p")? as u8);
+
+    let mut clock = clocks::Clock::new();
+    Instruction::step(&mut clock, &mut bus, &mut pc_state, &mut Ports::new());
+
+    let result = (pc_state.get_pc(), pc_state.get_s(), pc_state.get_a(), pc_state.get_x(), pc_state.get_y(), pc_state.get_p() & STATUS_MASK);
+    let expected_registers = registers(expected)?;
+    if result != expected_registers {
+        return Err(format!("(PC, S, A, X, Y, P) {:X?}, expected {:X?}", result, expected_registers));
+    }
+
+    for (address, data) in ram(expected)? {
+        if bus.ram[address as usize] != data {
+            return Err(format!("ram 0x{:04X} = 0x{:02X}, expected 0x{:02X}", address, bus.ram[address as usize], data));
+        }
+    }
+
+    let expected_cycles = cycles(test)?;
+    if bus.cycles != expected_cycles {
+        return Err(format!("cycles {:X?}, expected {:X?}", bus.cycles, expected_cycles));
+    }
+
+    let taken = clock.ticks / PcState::CYCLES_TO_CLOCK as u64;
+    if taken != expected_cycles.len() as u64 {
+        return Err(format!("{} cycles, expected {}", taken, expected_cycles.len()));
+    }
+    Ok(())
+}
+
+// Run all the tests in the JSON text, returning the failures.
+fn run_tests(json: &str) -> Vec<String> {
+    let tests: Value = match serde_json::from_str(json) {
+        Ok(tests) => tests,
+        Err(err) => return vec![format!("Invalid JSON: {}", err)],
+    };
+    let Some(tests) = tests.as_array() else {
+        return vec!["Expected an array of tests".to_string()];
+    };
+
+    tests.iter().filter_map(|test| run_test(test).err().map(|err| format!("'{}': {}", test["name"].as_str().unwrap_or("?"), err))).collect()
+}
+
+#[test]
+#[ignore = "needs the test vectors, set SINGLE_STEP_TESTS to their directory"]
+fn test_single_step_vectors() {
+    let directory = env::var("SINGLE_STEP_TESTS").expect("SINGLE_STEP_TESTS should be set to the test vector directory");
+
+    let mut failures = Vec::new();
+    let mut files = 0;
+    for op_code in (0..=0xFF_u8).filter(|op_code| !JAM_OP_CODES.contains(op_code)) {
+        let path = Path::new(&directory).join(format!("{:02x}.json", op_code));
+        let Ok(json) = fs::read_to_string(&path) else {
+            println!("No test vectors for 0x{:02X} ({})", op_code, path.display());
+            continue;
+        };
+
+        files += 1;
+        let op_failures = run_tests(&json);
+        if !op_failures.is_empty() {
+            failures.push(format!("0x{:02X}: {} failed", op_code, op_failures.len()));
+            failures.extend(op_failures.into_iter().take(MAX_REPORTED_FAILURES).map(|failure| format!("    {}", failure)));
+        }
+    }
+    assert!(files > 0, "No test vectors in '{}'", directory);
+    assert!(failures.is_empty(), "Single step failures:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_single_step_format() {
+    // 'STA ($10),Y' with a page cross, a dummy read of the un-fixed address before the write.
+    let sta_izy = r#"[{"name": "91 10 00",
+        "initial": {"pc": 512, "s": 253, "a": 66, "x": 0, "y": 1, "p": 36,
+                    "ram": [[512, 145], [513, 16], [16, 255], [17, 2], [768, 0]]},
+        "final": {"pc": 514, "s": 253, "a": 66, "x": 0, "y": 1, "p": 36,
+                  "ram": [[768, 66]]},
+        "cycles": [[512, 145, "read"], [513, 16, "read"], [16, 255, "read"], [17, 2, "read"], [512, 145, "read"], [768, 66, "write"]]}]"#;
+    assert_eq!(run_tests(sta_izy), Vec::<String>::new());
+
+    // 'INC $0300', the unmodified value is written back before the result.
+    let inc_abs = r#"[{"name": "ee 00 03",
+        "initial": {"pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                    "ram": [[4096, 238], [4097, 0], [4098, 3], [768, 127]]},
+        "final": {"pc": 4099, "s": 253, "a": 0, "x": 0, "y": 0, "p": 164,
+                  "ram": [[768, 128]]},
+        "cycles": [[4096, 238, "read"], [4097, 0, "read"], [4098, 3, "read"], [768, 127, "read"], [768, 127, "write"], [768, 128, "write"]]}]"#;
+    assert_eq!(run_tests(inc_abs), Vec::<String>::new());
+
+    // A wrong result is reported.
+    let wrong = inc_abs.replace("[[768, 128]]", "[[768, 129]]");
+    assert_eq!(run_tests(&wrong).len(), 1);
+}
@@ -0,0 +1,537 @@
+//! Interactive debugger, a command console (see 'Debugger::run_console') driving the emulator.
+//!
+//! Runs an instruction at a time, stopping on breakpoints (PC), watchpoints (CPU reads/writes of an address) and
+//! conditions on the registers.  Can step by instruction, scanline or frame, run to a PC and inspect/edit the
+//! registers and RAM.  Addresses and values are hex (with an optional '$' or '0x'), counts are decimal.  With DASM
+//! symbols loaded addresses can also be labels, a '$' or '0x' prefix forces hex (for labels like 'BEEF').
+
+use super::cpu::pc_state::PcState;
+use super::emulator::Emulator;
+use super::memory::memory::{Access, WatchHit};
+use super::symbols::Symbols;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "Commands:
+  step|s [n]            run n instructions (default 1)
+  line|l [n]            run n scanlines
+  frame|f [n]           run n frames
+  until|u <addr>        run until the PC reaches the address
+  continue|c            run until a breakpoint/watchpoint/condition
+  break|b <addr>        breakpoint on the PC
+  delete|d <addr>       remove a breakpoint
+  watch|w <addr> [r|w]  watch reads and/or writes of the address (default both)
+  unwatch <addr>        remove the watchpoints on the address
+  cond <reg> <op> <val> break when the condition becomes true (reg: a x y s p pc, op: == != < <= > >=)
+  uncond <n>            remove condition n
+  list                  list the breakpoints, watchpoints and conditions
+  clear                 remove all breakpoints, watchpoints and conditions
+  regs|r                show the registers
+  set <reg> <val>       set a register
+  mem|m [addr] [len]    show RAM ($80-$FF)
+  poke <addr> <val>..   write to RAM
+  help|h                this help
+  quit|q                leave the debugger
+An empty line repeats the last command.  Addresses can be labels when there are symbols.";
+
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid hex value '{}'", text))
+}
+
+// A label (when there are symbols) or a hex address.
+fn parse_address(symbols: Option<&Symbols>, text: &str) -> Result<u16, String> {
+    let is_hex = text.starts_with('$') || text.starts_with("0x");
+    match symbols.and_then(|symbols| symbols.address_of(text)) {
+        Some(address) if !is_hex => Ok(address),
+        _ => parse_hex(text),
+    }
+}
+
+fn parse_count(text: Option<&&str>) -> Result<u32, String> {
+    match text {
+        Some(text) => text.parse::<u32>().map_err(|_| format!("Invalid count '{}'", text)),
+        None => Ok(1),
+    }
+}
+
+fn parse_ram_address(symbols: Option<&Symbols>, text: &str) -> Result<u8, String> {
+    match parse_address(symbols, text)? {
+        address @ 0x80..=0xFF => Ok(address as u8),
+        address => Err(format!("${:04X} isn't RAM ($80-$FF)", address)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    Pc,
+}
+
+impl Register {
+    fn parse(name: &str) -> Result<Register, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "a" => Ok(Register::A),
+            "x" => Ok(Register::X),
+            "y" => Ok(Register::Y),
+            "s" => Ok(Register::S),
+            "p" => Ok(Register::P),
+            "pc" => Ok(Register::Pc),
+            _ => Err(format!("Unknown register '{}'", name)),
+        }
+    }
+
+    pub fn get(&self, pc_state: &PcState) -> u16 {
+        match self {
+            Register::A => pc_state.get_a() as u16,
+            Register::X => pc_state.get_x() as u16,
+            Register::Y => pc_state.get_y() as u16,
+            Register::S => pc_state.get_s() as u16,
+            Register::P => pc_state.get_p() as u16,
+            Register::Pc => pc_state.get_pc(),
+        }
+    }
+
+    pub fn set(&self, pc_state: &mut PcState, value: u16) {
+        match self {
+            Register::A => pc_state.set_a(value as u8),
+            Register::X => pc_state.set_x(value as u8),
+            Register::Y => pc_state.set_y(value as u8),
+            Register::S => pc_state.set_s(value as u8),
+            Register::P => pc_state.set_p(value as u8),
+            Register::Pc => pc_state.set_pc(value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+// The two character symbols first, so '<=' isn't taken as '<'.
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessEqual),
+    (">=", Comparison::GreaterEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    /// Parse '<register> <comparison> <value>', ie 'a == 10' or 'x>=$80'.
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let text: String = text.split_whitespace().collect();
+        for (symbol, comparison) in COMPARISONS {
+            if let Some((register, value)) = text.split_once(symbol) {
+                return Ok(Condition {
+                    register: Register::parse(register)?,
+                    comparison,
+                    value: parse_hex(value)?,
+                });
+            }
+        }
+        Err(format!("Expected '<register> <comparison> <value>', got '{}'", text))
+    }
+
+    pub fn is_true(&self, pc_state: &PcState) -> bool {
+        let register = self.register.get(pc_state);
+        match self.comparison {
+            Comparison::Equal => register == self.value,
+            Comparison::NotEqual => register != self.value,
+            Comparison::Less => register < self.value,
+            Comparison::LessEqual => register <= self.value,
+            Comparison::Greater => register > self.value,
+            Comparison::GreaterEqual => register >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = COMPARISONS.iter().find(|(_, comparison)| *comparison == self.comparison).map_or("?", |(symbol, _)| symbol);
+        write!(dest, "{:?} {} ${:X}", self.register, symbol, self.value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Done,
+    Breakpoint(u16),
+    Watchpoint(WatchHit),
+    Condition(Condition),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Done => Ok(()),
+            StopReason::Breakpoint(address) => write!(dest, "Breakpoint at ${:04X}", address),
+            StopReason::Watchpoint(hit) => write!(dest, "Watchpoint: {:?} ${:04X} = ${:02X}", hit.access, hit.address, hit.data),
+            StopReason::Condition(condition) => write!(dest, "Condition: {}", condition),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunUntil {
+    Instructions(u32),
+    Scanlines(u32),
+    Frames(u32),
+    Pc(u16),
+    Break, // Only a breakpoint/watchpoint/condition stops it.
+}
+
+pub struct Debugger {
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<(u16, Access)>,
+    conditions: Vec<Condition>,
+    last_command: String,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            conditions: Vec::new(),
+            last_command: String::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| *breakpoint != address);
+        count != self.breakpoints.len()
+    }
+
+    pub fn add_watchpoint(&mut self, address: u16, access: Access) {
+        if !self.watchpoints.contains(&(address, access)) {
+            self.watchpoints.push((address, access));
+        }
+    }
+
+    pub fn remove_watchpoints(&mut self, address: u16) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|(watch_address, _)| *watch_address != address);
+        count != self.watchpoints.len()
+    }
+
+    pub fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push(condition);
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.conditions.clear();
+    }
+
+    /// Run the emulator (at least one instruction) until 'until' is reached or a breakpoint, watchpoint or condition
+    /// stops it.  Conditions stop when they become true (not while they stay true).
+    pub fn run(&mut self, emulator: &mut Emulator, until: RunUntil) -> StopReason {
+        emulator.set_watchpoints(&self.watchpoints);
+        let mut conditions_true: Vec<bool> = self.conditions.iter().map(|condition| condition.is_true(emulator.pc_state())).collect();
+        let (mut scanline, _) = emulator.beam_position();
+        let mut count = 0;
+
+        loop {
+            let new_frame = emulator.step();
+
+            if let Some(hit) = emulator.take_watch_hit() {
+                return StopReason::Watchpoint(hit);
+            }
+            let pc = emulator.pc_state().get_pc();
+            if self.breakpoints.contains(&pc) {
+                return StopReason::Breakpoint(pc);
+            }
+            for (condition, was_true) in self.conditions.iter().zip(conditions_true.iter_mut()) {
+                let is_true = condition.is_true(emulator.pc_state());
+                if is_true && !*was_true {
+                    return StopReason::Condition(*condition);
+                }
+                *was_true = is_true;
+            }
+
+            let done = match until {
+                RunUntil::Instructions(instructions) => {
+                    count += 1;
+                    count >= instructions
+                }
+                RunUntil::Scanlines(scanlines) => {
+                    let (line, _) = emulator.beam_position();
+                    if line != scanline {
+                        scanline = line;
+                        count += 1;
+                    }
+                    count >= scanlines
+                }
+                RunUntil::Frames(frames) => {
+                    count += new_frame as u32;
+                    count >= frames
+                }
+                RunUntil::Pc(address) => pc == address,
+                RunUntil::Break => false,
+            };
+            if done {
+                return StopReason::Done;
+            }
+        }
+    }
+
+    pub fn state(emulator: &mut Emulator) -> String {
+        let (scanline, colour_clock) = emulator.beam_position();
+        let pc_state = emulator.pc_state();
+        let state = format!("{} S:{:X}  scanline:{} clock:{} frame:{}", pc_state, pc_state.get_s(), scanline, colour_clock, emulator.frame_number());
+
+        let (bank, pc) = (emulator.cartridge_bank(), pc_state.get_pc());
+        match emulator.symbols().map(|symbols| (symbols.label(bank, pc), symbols.source_line(bank, pc))) {
+            Some((Some(label), source)) => format!("{}\n{}: {}", state, label, source.unwrap_or("")).trim_end().to_string(),
+            Some((None, Some(source))) => format!("{}\n    {}", state, source),
+            _ => state,
+        }
+    }
+
+    fn list(&self) -> String {
+        let mut lines: Vec<String> = self.breakpoints.iter().map(|address| format!("break ${:04X}", address)).collect();
+        lines.extend(self.watchpoints.iter().map(|(address, access)| format!("watch ${:04X} {:?}", address, access)));
+        lines.extend(self.conditions.iter().enumerate().map(|(i, condition)| format!("cond {}: {}", i, condition)));
+        if lines.is_empty() {
+            "Nothing set".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    fn memory(emulator: &mut Emulator, start: u8, length: u16) -> String {
+        let end = (start as u16 + length).min(0x100);
+        (start as u16..end)
+            .collect::<Vec<u16>>()
+            .chunks(16)
+            .map(|row| format!("${:02X}:{}", row[0], row.iter().map(|address| format!(" {:02X}", emulator.peek_ram(*address as u8))).collect::<String>()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn run_command(&mut self, emulator: &mut Emulator, until: RunUntil) -> String {
+        let reason = self.run(emulator, until);
+        match reason {
+            StopReason::Done => Debugger::state(emulator),
+            reason => format!("{}\n{}", reason, Debugger::state(emulator)),
+        }
+    }
+
+    /// Run a single command line, returning the output ('None' to quit).
+    pub fn command(&mut self, emulator: &mut Emulator, line: &str) -> Result<Option<String>, String> {
+        let line = if line.trim().is_empty() { self.last_command.clone() } else { line.trim().to_string() };
+        self.last_command = line.clone();
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((command, args)) = words.split_first() else {
+            return Ok(Some(String::new()));
+        };
+        let addresses: Vec<Result<u16, String>> = args.iter().map(|text| parse_address(emulator.symbols(), text)).collect();
+        let address = |index: usize| addresses.get(index).cloned().unwrap_or(Err(format!("'{}' needs an address", command)));
+
+        let output = match *command {
+            "step" | "s" => self.run_command(emulator, RunUntil::Instructions(parse_count(args.first())?)),
+            "line" | "l" => self.run_command(emulator, RunUntil::Scanlines(parse_count(args.first())?)),
+            "frame" | "f" => self.run_command(emulator, RunUntil::Frames(parse_count(args.first())?)),
+            "until" | "u" => self.run_command(emulator, RunUntil::Pc(address(0)?)),
+            "continue" | "c" => self.run_command(emulator, RunUntil::Break),
+            "break" | "b" => {
+                self.add_breakpoint(address(0)?);
+                self.list()
+            }
+            "delete" | "d" => match self.remove_breakpoint(address(0)?) {
+                true => self.list(),
+                false => return Err(format!("No breakpoint at {}", args[0])),
+            },
+            "watch" | "w" => {
+                let watch_address = address(0)?;
+                let accesses = match args.get(1).copied() {
+                    None | Some("rw") => vec![Access::Read, Access::Write],
+                    Some("r") => vec![Access::Read],
+                    Some("w") => vec![Access::Write],
+                    Some(access) => return Err(format!("Unknown access '{}', expected 'r', 'w' or 'rw'", access)),
+                };
+                for access in accesses {
+                    self.add_watchpoint(watch_address, access);
+                }
+                self.list()
+            }
+            "unwatch" => match self.remove_watchpoints(address(0)?) {
+                true => self.list(),
+                false => return Err(format!("No watchpoint at {}", args[0])),
+            },
+            "cond" => {
+                self.add_condition(Condition::parse(&args.join(" "))?);
+                self.list()
+            }
+            "uncond" => {
+                let index = args.first().and_then(|text| text.parse::<usize>().ok()).filter(|index| *index < self.conditions.len());
+                let index = index.ok_or("'uncond' needs a condition number (see 'list')")?;
+                self.conditions.remove(index);
+                self.list()
+            }
+            "list" => self.list(),
+            "clear" => {
+                self.clear();
+                self.list()
+            }
+            "regs" | "r" => Debugger::state(emulator),
+            "set" => {
+                let register = Register::parse(args.first().ok_or("'set' needs a register")?)?;
+                register.set(emulator.pc_state_mut(), address(1)?);
+                Debugger::state(emulator)
+            }
+            "mem" | "m" => {
+                let start = args.first().map_or(Ok(0x80), |text| parse_ram_address(emulator.symbols(), text))?;
+                let length = args.get(1).map_or(Ok(0x80), |text| parse_hex(text))?;
+                Debugger::memory(emulator, start, length)
+            }
+            "poke" => {
+                let start = parse_ram_address(emulator.symbols(), args.first().ok_or("'poke' needs an address")?)?;
+                if args.len() < 2 {
+                    return Err("'poke' needs at least one value".to_string());
+                }
+                for (offset, value) in args[1..].iter().enumerate() {
+                    emulator.poke_ram(start.wrapping_add(offset as u8), parse_hex(value)? as u8);
+                }
+                Debugger::memory(emulator, start, args.len() as u16 - 1)
+            }
+            "help" | "h" => HELP.to_string(),
+            "quit" | "q" => return Ok(None),
+            _ => return Err(format!("Unknown command '{}', 'help' for the commands", command)),
+        };
+        Ok(Some(output))
+    }
+
+    /// Read commands from 'input' until 'quit' (or the end of the input).
+    pub fn run_console(&mut self, emulator: &mut Emulator, input: &mut dyn BufRead, output: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(output, "Debugger, 'help' for the commands.\n{}", Debugger::state(emulator))?;
+        let mut line = String::new();
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+            line.clear();
+            if 0 == input.read_line(&mut line)? {
+                return Ok(());
+            }
+            match self.command(emulator, &line) {
+                Ok(Some(text)) => writeln!(output, "{}", text)?,
+                Ok(None) => return Ok(()),
+                Err(e) => writeln!(output, "{}", e)?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::debugger::{Condition, Debugger, RunUntil, StopReason};
+    use crate::atari2600::emulator::Emulator;
+    use crate::atari2600::memory::cartridge::CartridgeType;
+    use crate::atari2600::memory::memory::{Access, WatchHit};
+
+    // 4K ROM: loop on 'VSYNC on, 3 lines, VSYNC off, INC $80, 255 lines'.
+    fn frame_emulator() -> Emulator {
+        let mut rom = vec![0xEA; 4096];
+        let code = [
+            0xA9, 0x02, 0x85, 0x00, 0x85, 0x02, 0x85, 0x02, 0x85, 0x02, // $F000: VSYNC on, 3 x WSYNC
+            0xA9, 0x00, 0x85, 0x00, // $F00A: VSYNC off
+            0xE6, 0x80, // $F00E: INC $80
+            0xA2, 0xFF, 0x85, 0x02, 0xCA, 0xD0, 0xFB, // $F010: 255 x WSYNC
+            0x4C, 0x00, 0xF0, // $F017: JMP
+        ];
+        rom[..code.len()].copy_from_slice(&code);
+        rom[0xFFC..].copy_from_slice(&[0x00, 0xF0, 0x00, 0xF0]);
+
+        let mut emulator = Emulator::new(false, false, false);
+        emulator.load_rom(&rom, CartridgeType::Default);
+        emulator
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let mut emulator = frame_emulator();
+        let mut debugger = Debugger::new();
+
+        assert_eq!(debugger.run(&mut emulator, RunUntil::Instructions(2)), StopReason::Done);
+        assert_eq!(emulator.pc_state().get_pc(), 0xF004);
+        assert_eq!(debugger.run(&mut emulator, RunUntil::Pc(0xF00E)), StopReason::Done);
+        let (scanline, _) = emulator.beam_position();
+        assert_eq!(debugger.run(&mut emulator, RunUntil::Scanlines(3)), StopReason::Done);
+        assert_eq!(emulator.beam_position().0, scanline + 3);
+
+        debugger.add_breakpoint(0xF017);
+        assert_eq!(debugger.run(&mut emulator, RunUntil::Break), StopReason::Breakpoint(0xF017));
+        assert_eq!(emulator.peek_ram(0x80), 1);
+        assert!(debugger.remove_breakpoint(0xF017));
+
+        // The read-modify-write 'INC' reads, then writes (twice).
+        debugger.add_watchpoint(0x80, Access::Write);
+        assert_eq!(debugger.run(&mut emulator, RunUntil::Break), StopReason::Watchpoint(WatchHit { address: 0x80, data: 1, access: Access::Write }));
+        assert!(debugger.remove_watchpoints(0x80));
+
+        debugger.add_condition(Condition::parse("x < $80").unwrap());
+        assert_eq!(debugger.run(&mut emulator, RunUntil::Frames(1)), StopReason::Condition(Condition::parse("x<80").unwrap()));
+        assert_eq!(emulator.pc_state().get_x(), 0x7F);
+        debugger.clear();
+
+        let frame = emulator.frame_number();
+        assert_eq!(debugger.run(&mut emulator, RunUntil::Frames(2)), StopReason::Done);
+        assert_eq!(emulator.frame_number(), frame + 2);
+    }
+
+    #[test]
+    fn test_console_commands() {
+        let mut emulator = frame_emulator();
+        let mut debugger = Debugger::new();
+        let script = "break f00e\ncontinue\nset a 42\nset pc f010\npoke 90 12 34\nm 90 2\nfoo\nstep 3\n\nquit\nstep\n";
+        let mut output = Vec::new();
+        debugger.run_console(&mut emulator, &mut script.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("Breakpoint at $F00E"), "{}", output);
+        assert!(output.contains("$90: 12 34"), "{}", output);
+        assert!(output.contains("Unknown command 'foo'"), "{}", output);
+        assert_eq!(emulator.pc_state().get_a(), 0x42);
+        assert_eq!(emulator.peek_ram(0x91), 0x34);
+        // 'LDX', 'STA WSYNC', 'DEX' and (repeated) 'BNE', 'STA WSYNC', 'DEX', then quit.
+        assert_eq!(emulator.pc_state().get_pc(), 0xF015);
+        assert_eq!(emulator.pc_state().get_x(), 0xFD);
+
+        assert_eq!(debugger.command(&mut emulator, "mem 10"), Err("$0010 isn't RAM ($80-$FF)".to_string()));
+        assert_eq!(debugger.command(&mut emulator, "cond a ~ 1"), Err("Expected '<register> <comparison> <value>', got 'a~1'".to_string()));
+    }
+}
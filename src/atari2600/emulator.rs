@@ -0,0 +1,553 @@
+//! Frontend agnostic emulator.
+//!
+//! Owns the emulated machine (CPU, memory, TIA, RIOT and cartridge), the ROM properties database
+//! and the per-frame features (frame counting, replays, rewind and save states), but no display,
+//! audio or input devices.
+//! A frontend feeds it inputs, runs it a frame at a time and collects the display/audio:
+//!
+//!   `let mut emulator = Emulator::new(false, false, false);`
+//!   `emulator.load_rom(&rom_data, CartridgeType::Default);`
+//!   `emulator.set_input(input);`
+//!   `emulator.run_frame();`
+//!   `draw(emulator.framebuffer()); play(&emulator.audio_samples());`
+//!
+//! The SDL (and headless) frontend in 'atari2600' is built on top of this.
+
+use super::audio::soundchannel;
+use super::clocks;
+use super::cpu;
+use super::graphics;
+use super::inputs;
+use super::memory;
+use super::memory::memory::Bus;
+use super::ports;
+use super::properties;
+use super::replay;
+use super::rewind;
+use super::state;
+use super::state::Snapshot;
+use super::symbols;
+use super::trace;
+
+pub struct Emulator {
+    core: Option<cpu::core::Core>,
+    debug: bool,
+    realtime: bool,
+    pal_palette: bool,
+
+    rom: Vec<u8>,
+    cartridge_type: memory::cartridge::CartridgeType,
+    properties: properties::PropertiesDatabase,
+    rom_properties: properties::RomProperties,
+
+    frame_number: u32,
+    framebuffer: Vec<u8>,
+
+    replay: Option<replay::Replay>,
+    rewind: Option<rewind::Rewind>,
+    rewinding: bool,
+
+    symbols: Option<symbols::Symbols>,
+    trace: Option<trace::Trace>,
+}
+
+impl Emulator {
+    pub const WIDTH: u16 = graphics::stella::Constants::ATARI2600_WIDTH;
+    pub const HEIGHT: u16 = graphics::stella::Constants::ATARI2600_HEIGHT;
+    pub const AUDIO_SAMPLE_RATE: u16 = super::audio::tiasound::TiaSound::SAMPLERATE;
+
+    // Limit for 'run_frame', in case the ROM never signals a new frame (VSYNC). Roughly 4 NTSC frames.
+    const MAX_CLOCKS_PER_FRAME: clocks::ClockType = 4 * 262 * graphics::stella::Stella::HORIZONTAL_TICKS;
+
+    /// 'realtime' paces the emulation to the console speed (leave it off when embedding).  'debug' traces every
+    /// instruction to stdout (see 'set_trace').
+    pub fn new(debug: bool, realtime: bool, pal_palette: bool) -> Self {
+        Self {
+            core: None,
+            debug,
+            realtime,
+            pal_palette,
+            rom: Vec::new(),
+            cartridge_type: memory::cartridge::CartridgeType::Auto,
+            properties: properties::PropertiesDatabase::built_in(),
+            rom_properties: properties::RomProperties::default(),
+            frame_number: 0,
+            framebuffer: vec![0; Emulator::WIDTH as usize * Emulator::HEIGHT as usize * graphics::display::BYTES_PER_PIXEL as usize],
+            replay: None,
+            rewind: None,
+            rewinding: false,
+            symbols: None,
+            trace: debug.then(|| trace::Trace::new(Box::new(std::io::stdout()), trace::TraceFormat::Default)),
+        }
+    }
+
+    fn build_core(rom: &[u8], cartridge_type: &memory::cartridge::CartridgeType, debug: bool, realtime: bool, pal_palette: bool) -> cpu::core::Core {
+        let clock = clocks::Clock::new();
+        let pc_state = cpu::pc_state::PcState::new();
+        let stella = graphics::stella::Stella::new(debug, realtime, pal_palette);
+        let riot = memory::riot::Riot::new();
+        let cartridge = memory::cartridge::get_new_carterage("rom", rom, cartridge_type);
+        let memory = memory::memory::Memory::new(cartridge, Box::new(stella), Box::new(riot));
+        let ports = ports::Ports::new();
+
+        let mut core = cpu::core::Core::new(clock, memory, pc_state, ports);
+        core.reset();
+
+        core
+    }
+
+    /// Add ROM properties from a user file (overriding the built-in database), used by later 'load_rom' calls.
+    pub fn load_properties_file(&mut self, filename: &str) -> std::io::Result<()> {
+        self.properties.load_file(filename)
+    }
+
+    /// Properties of the loaded ROM, from the database.
+    pub fn rom_properties(&self) -> &properties::RomProperties {
+        &self.rom_properties
+    }
+
+    /// Insert a cartridge (from the raw ROM data) and power on.
+    /// With 'CartridgeType::Auto' the type comes from the properties database, or is detected from the data.
+    pub fn load_rom(&mut self, rom: &[u8], cartridge_type: memory::cartridge::CartridgeType) {
+        self.rom = rom.to_vec();
+        self.cartridge_type = cartridge_type;
+        self.symbols = None;
+        self.rom_properties = self.properties.lookup(rom);
+        if let Some(name) = &self.rom_properties.name {
+            println!("ROM properties: {}", name);
+        }
+
+        let mut input = inputs::Input::new();
+        self.rom_properties.apply_input_defaults(&mut input);
+        self.reset();
+        self.set_input(input);
+    }
+
+    /// Power cycle the console, with the same cartridge.  The live input is kept, an active replay restarts.
+    pub fn reset(&mut self) {
+        let input = self.input();
+        let cartridge_type = match (self.cartridge_type, self.rom_properties.cartridge_type) {
+            (memory::cartridge::CartridgeType::Auto, Some(cartridge_type)) => cartridge_type,
+            (cartridge_type, _) => cartridge_type,
+        };
+        let pal_palette = self.pal_palette || Some(properties::TvFormat::Pal) == self.rom_properties.tv_format;
+
+        let mut core = Emulator::build_core(&self.rom, &cartridge_type, self.debug, self.realtime, pal_palette);
+        core.memory.stella.set_vertical_offset(self.rom_properties.vertical_offset.unwrap_or(0));
+        core.ports.joysticks.input = input;
+        core.memory.riot.set_inputs(input);
+        core.memory.stella.set_inputs(input);
+
+        self.core = Some(core);
+        self.frame_number = 0;
+        self.framebuffer.fill(0);
+
+        // The snapshots are from before the reset (possibly another ROM), and numbered from the old frame count.
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        self.rewinding = false;
+
+        if let Some(replay) = self.replay.as_mut() {
+            println!("Replay restarted (console reset)");
+            replay.restart();
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.core.is_some()
+    }
+
+    fn core(&self) -> &cpu::core::Core {
+        self.core.as_ref().expect("No cartridge loaded")
+    }
+
+    fn core_mut(&mut self) -> &mut cpu::core::Core {
+        self.core.as_mut().expect("No cartridge loaded")
+    }
+
+    /// The type of the loaded cartridge (as detected, if loaded as 'CartridgeType::Auto').
+    pub fn cartridge_type(&self) -> memory::cartridge::CartridgeType {
+        self.core().memory.cartridge_type()
+    }
+
+    pub fn cartridge_bank(&self) -> usize {
+        self.core().memory.cartridge_bank()
+    }
+
+    /// Debug symbols for the ROM, used by the trace output and the debugger.
+    pub fn set_symbols(&mut self, symbols: Option<symbols::Symbols>) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> Option<&symbols::Symbols> {
+        self.symbols.as_ref()
+    }
+
+    /// Write an execution trace, replacing any previous trace ('None' to stop tracing).
+    pub fn set_trace(&mut self, trace: Option<trace::Trace>) {
+        self.trace = trace;
+    }
+
+    pub fn frame_number(&self) -> u32 {
+        self.frame_number
+    }
+
+    pub fn clock_ticks(&self) -> clocks::ClockType {
+        self.core().clock.ticks
+    }
+
+    pub fn input(&self) -> inputs::Input {
+        self.core.as_ref().map_or_else(inputs::Input::new, |core| core.ports.joysticks.input)
+    }
+
+    /// Set the live console inputs.  With a replay active, inputs are only applied at frame boundaries.
+    pub fn set_input(&mut self, input: inputs::Input) {
+        let replay_active = self.replay.is_some();
+        let core = self.core_mut();
+        core.ports.joysticks.input = input;
+        if !replay_active {
+            core.memory.riot.set_inputs(input);
+            core.memory.stella.set_inputs(input);
+        }
+    }
+
+    /// Run a single CPU instruction (and the matching TIA audio), returns 'true' when a new frame starts.
+    pub fn step(&mut self) -> bool {
+        let core = self.core.as_mut().expect("No cartridge loaded");
+        if let Some(trace) = &mut self.trace {
+            trace.set_frame(self.frame_number);
+        }
+        core.step(self.realtime, self.trace.as_mut(), self.symbols.as_ref());
+        core.memory.stella.step_tia_sound(&core.clock);
+
+        if core.memory.stella.export() {
+            self.next_frame();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Run until the start of the next frame (or a frames worth of clocks, if the ROM doesn't signal one).
+    /// Returns 'true' if a complete frame was generated.
+    pub fn run_frame(&mut self) -> bool {
+        let frame_start = self.clock_ticks();
+        while self.clock_ticks() - frame_start < Emulator::MAX_CLOCKS_PER_FRAME {
+            if self.step() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Last complete frame, 'WIDTH' x 'HEIGHT' pixels of 'B, G, R, unused' bytes (RGB888).
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Take the audio generated since the last call, interleaved stereo 'AUDIO_SAMPLE_RATE' samples.
+    pub fn audio_samples(&mut self) -> Vec<soundchannel::PlaybackType> {
+        self.core_mut().memory.stella.take_audio_samples()
+    }
+
+    /// Take up to 'length' (per channel) samples, pacing the emulation when running in real-time.
+    /// For audio devices that are topped up while running (rather than once per frame).
+    pub fn next_audio_chunk(&mut self, length: u32) -> Vec<soundchannel::PlaybackType> {
+        self.core_mut().memory.stella.get_next_audio_chunk(length)
+    }
+
+    /// Read from the CPU address space (as the CPU would, so may have side effects).
+    pub fn read_memory(&mut self, address: u16) -> u8 {
+        let core = self.core_mut();
+        core.memory.read(&core.clock, address)
+    }
+
+    /// The CPU registers, for debugging (the setters edit the live state).
+    pub fn pc_state(&self) -> &cpu::pc_state::PcState {
+        self.core().pc_state()
+    }
+
+    pub fn pc_state_mut(&mut self) -> &mut cpu::pc_state::PcState {
+        self.core_mut().pc_state_mut()
+    }
+
+    /// Read the RIOT RAM ($80-$FF), without any side effects.
+    pub fn peek_ram(&mut self, address: u8) -> u8 {
+        let core = self.core_mut();
+        core.memory.riot.read(&core.clock, 0x80 | address as u16)
+    }
+
+    pub fn poke_ram(&mut self, address: u8, data: u8) {
+        let core = self.core_mut();
+        core.memory.riot.write(&mut core.clock, 0x80 | address as u16, data);
+    }
+
+    /// (Scanline, colour clock) of the beam, from the start of the frame (VSYNC).
+    pub fn beam_position(&mut self) -> (clocks::ClockType, clocks::ClockType) {
+        self.core_mut().beam_position()
+    }
+
+    /// Watch CPU accesses (see 'take_watch_hit'), replacing any previous watchpoints.
+    pub fn set_watchpoints(&mut self, watchpoints: &[(u16, memory::memory::Access)]) {
+        self.core_mut().memory.set_watchpoints(watchpoints);
+    }
+
+    pub fn take_watch_hit(&mut self) -> Option<memory::memory::WatchHit> {
+        self.core_mut().memory.take_watch_hit()
+    }
+
+    pub fn rom_hash(&self) -> String {
+        self.core().memory.cartridge_hash()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        state::save_machine(&self.rom_hash(), self.core())
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let rom_hash = self.rom_hash();
+        state::load_machine(&rom_hash, self.core_mut(), data)
+    }
+
+    /// Start recording to, or playing back from, a replay file.
+    /// Playback fails if the replay was recorded with a different cartridge.
+    pub fn start_replay(&mut self, filename: &str, record: bool) -> std::io::Result<()> {
+        let rom_hash = self.rom_hash();
+        let replay = if record {
+            replay::Replay::new_recording(filename, &rom_hash, &self.cartridge_type())
+        } else {
+            replay::Replay::load_playback(filename, &rom_hash, &self.cartridge_type())?
+        };
+        println!("Replay {:?}: {}", replay.mode(), filename);
+
+        self.replay = Some(replay);
+        Ok(())
+    }
+
+    /// Write out the replay (if recording).
+    pub fn finish_replay(&mut self) -> std::io::Result<()> {
+        match self.replay.as_mut() {
+            Some(replay) => replay.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep snapshots of the last frames (within 'memory_budget' bytes), so the emulation can be rewound.
+    pub fn enable_rewind(&mut self, interval: u32, memory_budget: usize) {
+        self.rewind = Some(rewind::Rewind::new(interval, memory_budget));
+    }
+
+    pub fn rewind(&self) -> Option<&rewind::Rewind> {
+        self.rewind.as_ref()
+    }
+
+    /// While rewinding, each frame restores an earlier snapshot (rather than emulating forward).
+    pub fn set_rewinding(&mut self, rewinding: bool) {
+        self.rewinding = rewinding && self.rewind.is_some();
+    }
+
+    fn next_frame(&mut self) {
+        self.frame_number = self.frame_number.wrapping_add(1);
+        let core = self.core.as_mut().expect("No cartridge loaded");
+
+        // Rewinding isn't supported with replays (the inputs are tied to the frame number).
+        if let (Some(rewind), None) = (self.rewind.as_mut(), self.replay.as_ref()) {
+            if self.rewinding {
+                // The previous frame is on screen, so restore the frame before it (it's re-drawn as it's emulated).
+                if let Some((frame, data)) = self.frame_number.checked_sub(2).and_then(|frame| rewind.pop_before(frame)) {
+                    if let Err(e) = core.load_state(&mut state::StateReader::new(&data)) {
+                        println!("Error restoring rewind snapshot. {}", e);
+                    }
+                    self.frame_number = frame;
+                }
+            } else if rewind.is_snapshot_frame(self.frame_number) {
+                let mut snapshot = state::StateWriter::new();
+                core.save_state(&mut snapshot);
+                rewind.push(self.frame_number, &snapshot.into_inner());
+            }
+        }
+
+        if let Some(replay) = self.replay.as_mut() {
+            let input = replay.next_input(self.frame_number, core.ports.joysticks.input);
+            core.memory.riot.set_inputs(input);
+            core.memory.stella.set_inputs(input);
+        }
+
+        core.memory.stella.generate_display(&mut self.framebuffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::emulator::Emulator;
+    use crate::atari2600::memory::cartridge::CartridgeType;
+    use crate::atari2600::{symbols, trace};
+
+    // 4K ROM: clear VBLANK, then loop on 'VSYNC on, 3 lines, VSYNC off, INC $80, 255 lines'.
+    fn frame_rom() -> Vec<u8> {
+        let mut rom = vec![0xEA; 4096];
+        let code = [
+            0xA9, 0x00, 0x85, 0x01, // LDA #0, STA VBLANK
+            0xA9, 0x02, 0x85, 0x00, 0x85, 0x02, 0x85, 0x02, 0x85, 0x02, // VSYNC on, 3 x WSYNC
+            0xA9, 0x00, 0x85, 0x00, // VSYNC off
+            0xE6, 0x80, // INC $80
+            0xA2, 0xFF, 0x85, 0x02, 0xCA, 0xD0, 0xFB, // 255 x WSYNC
+            0x4C, 0x04, 0xF0, // JMP
+        ];
+        rom[..code.len()].copy_from_slice(&code);
+        rom[0xFFC..].copy_from_slice(&[0x00, 0xF0, 0x00, 0xF0]);
+        rom
+    }
+
+    // 4K ROM: a frame with 'register' (RESP0/RESM0) written every line, 'nops' NOPs after WSYNC, then the single
+    // pixel P0 graphics or M0.
+    fn position_rom(register: u8, nops: usize) -> Vec<u8> {
+        let mut rom = vec![0xEA; 4096];
+        let mut code = vec![
+            0xA9, 0x02, 0x85, 0x00, 0x85, 0x02, 0x85, 0x02, 0x85, 0x02, // VSYNC on, 3 x WSYNC
+            0xA9, 0x00, 0x85, 0x00, 0x85, 0x01, // VSYNC and VBLANK off
+            0xA9, 0x0E, 0x85, 0x06, // COLUP0 white
+            0xA9, if 0x10 == register { 0x80 } else { 0x00 }, 0x85, 0x1B, // GRP0
+            0xA9, if 0x12 == register { 0x02 } else { 0x00 }, 0x85, 0x1D, // ENAM0
+            0xA2, 0xC8, 0x85, 0x02, // 200 x (WSYNC, NOPs, STA register)
+        ];
+        code.extend(vec![0xEA; nops]);
+        code.extend([0x85, register, 0xCA, 0xD0, (0xF9 - nops as u8)]);
+        code.extend([0xA2, 0x3B, 0x85, 0x02, 0xCA, 0xD0, 0xFB, 0x4C, 0x00, 0xF0]); // 59 x WSYNC, JMP
+        rom[..code.len()].copy_from_slice(&code);
+        rom[0xFFC..].copy_from_slice(&[0x00, 0xF0, 0x00, 0xF0]);
+        rom
+    }
+
+    // First lit pixel of a line in the middle of the frame.
+    fn first_pixel(register: u8, nops: usize) -> Option<usize> {
+        let mut emulator = Emulator::new(false, false, false);
+        emulator.load_rom(&position_rom(register, nops), CartridgeType::Default);
+        emulator.run_frame();
+        emulator.run_frame();
+        let line = &emulator.framebuffer()[100 * Emulator::WIDTH as usize * 4..101 * Emulator::WIDTH as usize * 4];
+        line.chunks(4).position(|pixel| pixel[..3] != [0, 0, 0])
+    }
+
+    #[test]
+    fn test_object_reset_positions() {
+        // 'STA RESP0' straight after WSYNC writes at colour clock 9 (horizontal blank), players start at 3 and
+        // missiles at 2.  10 NOPs later the write is at colour clock 69 (pixel 1), +5 for players, +4 for missiles.
+        assert_eq!(first_pixel(0x10, 0), Some(3));
+        assert_eq!(first_pixel(0x12, 0), Some(2));
+        assert_eq!(first_pixel(0x10, 10), Some(6));
+        assert_eq!(first_pixel(0x12, 10), Some(5));
+        assert_eq!(first_pixel(0x10, 20), Some(66));
+    }
+
+    // F0 (64K), every bank the same, executing the bank switch hotspot ($1FF0) in a loop (switching on each 'INC').
+    fn f0_rom() -> Vec<u8> {
+        let mut bank = vec![0xEA; 4096];
+        bank[..3].copy_from_slice(&[0x4C, 0xF0, 0xFF]); // JMP $FFF0
+        bank[0xFF0..0xFF5].copy_from_slice(&[0xE6, 0x80, 0x4C, 0xF0, 0xFF]); // INC $80, JMP $FFF0
+        bank[0xFFC..].copy_from_slice(&[0x00, 0xF0, 0x00, 0xF0]);
+        bank.repeat(16)
+    }
+
+    #[test]
+    fn test_trace_has_no_side_effects() {
+        let rom = f0_rom();
+        let run = |traced: bool| {
+            let mut emulator = Emulator::new(false, false, false);
+            emulator.load_rom(&rom, CartridgeType::F0);
+            emulator.set_trace(traced.then(|| trace::Trace::new(Box::new(std::io::sink()), trace::TraceFormat::Default)));
+            for _ in 0..1000 {
+                emulator.step();
+            }
+            (emulator.cartridge_bank(), emulator.save_state())
+        };
+        let (bank, state) = run(false);
+        assert_ne!(bank, 15);
+        assert_eq!(run(true), (bank, state));
+    }
+
+    #[test]
+    fn test_bank_symbols() {
+        let mut symbols = symbols::Symbols::new();
+        symbols.parse_sym("Loop0  fff0  (R )\nLoop1  fff0  (R )\n");
+        symbols.parse_lst(
+            "      1  1ff0\t\t\t\t\t      org\t$1FF0
+      2  1ff0\t\t\t\t\t      rorg\t$FFF0
+      3  1ff0 fff0\t       e6 80\t   Loop0      inc\t$80
+      4  2ff0\t\t\t\t\t      org\t$2FF0
+      5  2ff0\t\t\t\t\t      rorg\t$FFF0
+      6  2ff0 fff0\t       e6 80\t   Loop1      inc\t$80
+",
+        );
+
+        let mut emulator = Emulator::new(false, false, false);
+        emulator.load_rom(&f0_rom(), CartridgeType::F0);
+        emulator.set_symbols(Some(symbols));
+        let mut label = |bank: usize| {
+            for _ in 0..100 {
+                if emulator.cartridge_bank() == bank {
+                    break;
+                }
+                emulator.step();
+            }
+            emulator.symbols().and_then(|symbols| symbols.label(emulator.cartridge_bank(), 0xFFF0)).map(str::to_string)
+        };
+        assert_eq!(label(0), Some("Loop0".to_string()));
+        assert_eq!(label(1), Some("Loop1".to_string()));
+        assert_eq!(label(2), None);
+    }
+
+    #[test]
+    fn test_load_truncated_state() {
+        let mut emulator = Emulator::new(false, false, false);
+        emulator.load_rom(&frame_rom(), CartridgeType::Default);
+        emulator.run_frame();
+        let saved = emulator.save_state();
+        emulator.run_frame();
+        emulator.run_frame();
+
+        let current = emulator.save_state();
+        assert!(emulator.load_state(&saved[..saved.len() - 1]).is_err());
+        assert_eq!(emulator.save_state(), current);
+
+        emulator.load_state(&saved).unwrap();
+        assert_eq!(emulator.save_state(), saved);
+    }
+
+    #[test]
+    fn test_rewind_after_reset() {
+        let mut emulator = Emulator::new(false, false, false);
+        emulator.load_rom(&frame_rom(), CartridgeType::Default);
+        emulator.enable_rewind(1, 1 << 20);
+        for _ in 0..5 {
+            emulator.run_frame();
+        }
+        assert!(!emulator.rewind().unwrap().is_empty());
+
+        // Nothing from before the reset is restored.
+        emulator.reset();
+        assert!(emulator.rewind().unwrap().is_empty());
+        emulator.set_rewinding(true);
+        emulator.run_frame();
+        assert_eq!(emulator.frame_number(), 1);
+    }
+
+    #[test]
+    fn test_run_frames_and_reset() {
+        let mut emulator = Emulator::new(false, false, false);
+        emulator.load_rom(&frame_rom(), CartridgeType::Default);
+
+        for _ in 0..5 {
+            assert!(emulator.run_frame());
+        }
+        assert_eq!(emulator.frame_number(), 5);
+        assert_eq!(emulator.framebuffer().len(), Emulator::WIDTH as usize * Emulator::HEIGHT as usize * 4);
+        assert!(!emulator.audio_samples().is_empty());
+        assert_eq!(emulator.read_memory(0x80), 4);
+
+        emulator.reset();
+        assert_eq!(emulator.frame_number(), 0);
+        assert_eq!(emulator.clock_ticks(), 0);
+    }
+}
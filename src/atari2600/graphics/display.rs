@@ -1,10 +1,19 @@
+#[cfg(feature = "sdl")]
 use sdl2::event;
+#[cfg(feature = "sdl")]
 use sdl2::pixels;
+#[cfg(feature = "sdl")]
 use sdl2::render;
+#[cfg(feature = "sdl")]
 use sdl2::video;
 
 use std::fmt;
 
+use super::super::state;
+
+// Generated displays are RGB888 (stored as 'B, G, R, unused'), matching the SDL texture format.
+pub const BYTES_PER_PIXEL: u16 = 4;
+
 // Splitting Console Size and windows size, as the console size if 'fixed',
 // only window size changes/is scalable.
 pub struct ConsoleSize {
@@ -33,7 +42,7 @@ impl WindowSize {
         Self {
             frame_width,
             frame_height,
-            console_size: console_size,
+            console_size,
             fullscreen,
         }
     }
@@ -71,14 +80,31 @@ impl Colour {
     }
 }
 
+impl state::Snapshot for Colour {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.r);
+        state.put_u8(self.g);
+        state.put_u8(self.b);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.r = state.get_u8()?;
+        self.g = state.get_u8()?;
+        self.b = state.get_u8()?;
+        Ok(())
+    }
+}
+
 impl fmt::Display for Colour {
     fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
         write!(dest, "R:{} G:{} B:{}", self.r, self.g, self.b)
     }
 }
 
+#[cfg(feature = "sdl")]
 pub struct SDLUtility {}
 
+#[cfg(feature = "sdl")]
 impl SDLUtility {
     pub const PIXEL_FORMAT: pixels::PixelFormatEnum = pixels::PixelFormatEnum::RGB888;
 
@@ -114,14 +140,14 @@ impl SDLUtility {
         canvas.texture_creator()
     }
 
-    pub fn create_texture(texture_creator: &render::TextureCreator<video::WindowContext>, pixel_format: pixels::PixelFormatEnum, frame_width: u16, frame_height: u16) -> render::Texture {
+    pub fn create_texture(texture_creator: &render::TextureCreator<video::WindowContext>, pixel_format: pixels::PixelFormatEnum, frame_width: u16, frame_height: u16) -> render::Texture<'_> {
         texture_creator.create_texture_streaming(pixel_format, frame_width as u32, frame_height as u32).map_err(|e| e.to_string()).unwrap()
     }
 
     pub fn handle_events(event: &event::Event) {
         // Handle window events.
         if let event::Event::Window {
-            win_event: event::WindowEvent::Resized(w, h), ..
+            win_event: event::WindowEvent::Resized(_w, _h), ..
         } = event
         {
         }
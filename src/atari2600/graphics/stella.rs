@@ -1,8 +1,8 @@
 use super::super::audio::tiasound;
 use super::super::clocks;
-use super::super::cpu::pc_state;
 use super::super::inputs;
 use super::super::io;
+use super::super::state;
 use super::display;
 use std;
 
@@ -60,6 +60,12 @@ pub struct PlayfieldState {
     pf2_lookup: Vec<Vec<bool>>,
 }
 
+impl Default for PlayfieldState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PlayfieldState {
     pub const PLAYFIELD_LOOKUP_SIZE: usize = 256;
     pub const PLAYFIELD_LENGTH: usize = 8;
@@ -159,11 +165,56 @@ impl PlayfieldState {
     }
 }
 
+/// An object's horizontal position counter.
+///
+/// The counter is clocked every visible colour clock (not during horizontal blank) and the object starts drawing when
+/// it wraps to 0, so it's held as its value at the start of the visible line.  HMOVE sends extra clock pulses (only
+/// effective during horizontal blank), one every 4 colour clocks, until the HMOVE counter matches the object's motion
+/// register.
+#[derive(Clone, Copy)]
+pub struct PositionCounter {
+    value: u8,
+    moving: bool,
+}
+
+impl PositionCounter {
+    fn new() -> Self {
+        Self { value: 0, moving: false }
+    }
+
+    /// Reset (RESxx) with 'x' the first pixel the counter is clocked for.
+    fn reset(&mut self, x: u16) {
+        self.value = ((Stella::FRAME_WIDTH - x % Stella::FRAME_WIDTH) % Stella::FRAME_WIDTH) as u8;
+    }
+
+    /// Pixel the object starts at.
+    fn start(&self) -> u16 {
+        (Stella::FRAME_WIDTH - self.value as u16) % Stella::FRAME_WIDTH
+    }
+
+    /// Extra clocks, negative for clocks missed (the extended horizontal blank).
+    fn clock(&mut self, clocks: i16) {
+        self.value = (self.value as i16 + clocks).rem_euclid(Stella::FRAME_WIDTH as i16) as u8;
+    }
+
+    /// An HMOVE pulse, 'count' is the HMOVE counter and 'hm' the object's motion register.  Returns 'true' if the
+    /// counter was clocked.
+    fn movement_pulse(&mut self, count: u8, hm: u8, hblank: bool) -> bool {
+        if count == (hm >> 4) ^ 0x8 {
+            self.moving = false;
+        }
+        if self.moving && hblank {
+            self.clock(1);
+        }
+        self.moving && hblank
+    }
+}
+
 pub struct BallState {
     enabl: u8,
     enabl_old: u8,
     vdelbl: u8,
-    resbl: u8,
+    counter: PositionCounter,
     ctrlpf: u8,
 
     x_min: u16,
@@ -180,7 +231,7 @@ impl BallState {
             enabl: 0,
             enabl_old: 0,
             vdelbl: 0,
-            resbl: 0,
+            counter: PositionCounter::new(),
             ctrlpf: 0,
 
             x_min: 0,
@@ -201,14 +252,14 @@ impl BallState {
 
         let width = 1 << ((self.ctrlpf & 0x30) >> 4);
 
-        self.x_min = (self.resbl as u16).wrapping_sub(Stella::HORIZONTAL_BLANK);
-        self.x_max = (self.resbl as u16).wrapping_sub(Stella::HORIZONTAL_BLANK).wrapping_add(width);
+        self.x_min = self.counter.start();
+        self.x_max = self.counter.start() + width;
 
         self.calc_ball_scan()
     }
 
-    fn update_resbl(&mut self, data: u8) {
-        self.resbl = data;
+    fn reset(&mut self, x: u16) {
+        self.counter.reset(x);
         self.update();
     }
 
@@ -253,7 +304,7 @@ impl BallState {
 pub struct MissileState {
     nusiz: u8,
     enam: u8,
-    resm: u8,
+    counter: PositionCounter,
 
     // Derived state data (nominally generated during update)
     number: u8,
@@ -268,7 +319,7 @@ impl MissileState {
         Self {
             nusiz: 0,
             enam: 0,
-            resm: 0,
+            counter: PositionCounter::new(),
 
             // Derived state data (nominally generated during update)
             number: 0,
@@ -281,14 +332,10 @@ impl MissileState {
 
     fn update(&mut self) {
         // Missiles ignore scaling options.
-        let (number, size, gap) = Stella::nusize(self.nusiz);
+        let (number, _size, gap) = Stella::nusize(self.nusiz);
         self.number = number;
         self.gap = gap;
 
-        if self.resm < Stella::HORIZONTAL_BLANK as u8 {
-            self.resm = Stella::HORIZONTAL_BLANK as u8;
-        }
-
         self.calc_missile_scan();
     }
 
@@ -297,8 +344,8 @@ impl MissileState {
         self.update();
     }
 
-    fn update_resm(&mut self, data: u8) {
-        self.resm = data;
+    fn reset(&mut self, x: u16) {
+        self.counter.reset(x);
         self.update();
     }
 
@@ -318,7 +365,7 @@ impl MissileState {
                 let width = 1 << ((self.nusiz & 0x30) >> 4);
                 // Uses similar position to 'player'
                 for i in 0..width as u16 {
-                    let x = ((i + self.resm as u16 + n as u16  * self.gap as u16 * 8 - Stella::HORIZONTAL_BLANK as u16) % Stella::FRAME_WIDTH as u16) as u8;
+                    let x = ((i + self.counter.start() + n as u16  * self.gap as u16 * 8) % Stella::FRAME_WIDTH) as u8;
                     self.scan_line[x as usize] = true;
                 }
             }
@@ -335,7 +382,7 @@ pub struct PlayerState {
     p: u8,
     p_old: u8,
     refp: u8,
-    resp: u8,
+    counter: PositionCounter,
     vdelp: u8,
     reflect: u8,
 
@@ -351,26 +398,13 @@ pub struct PlayerState {
 }
 
 impl PlayerState {
-    // Only 1,2,3 required, but 0..3 calculated
-    const NUMBER_RANGE: usize = 4;
-
-    // Only 1,2,4 required, but 0..4 calculated
-    const SIZE_RANGE: usize = 5;
-
-    // Gaps are 0, 2, 4, 8
-    const GAP_RANGE: usize = 9;
-
-    const REFLECT_RANGE: usize = 2;
-
-    const GRAPHIC_RANGE: usize = 256;
-
     fn new() -> Self {
         Self {
             nusiz: 0,
             p: 0,
             p_old: 0,
             refp: 0,
-            resp: 0,
+            counter: PositionCounter::new(),
             vdelp: 0,
             reflect: 0,
 
@@ -391,8 +425,8 @@ impl PlayerState {
         self.update();
     }
 
-    fn update_resp(&mut self, data: u8) {
-        self.resp = data;
+    fn reset(&mut self, x: u16) {
+        self.counter.reset(x);
         self.update();
     }
 
@@ -463,9 +497,6 @@ impl PlayerState {
             self.size = size;
             self.gap = gap;
 
-            if self.resp < Stella::HORIZONTAL_BLANK as u8 {
-                self.resp = Stella::HORIZONTAL_BLANK as u8;
-            }
             if (self.refp & 0x8) == 0 {
                 self.reflect = 1;
             } else {
@@ -473,7 +504,7 @@ impl PlayerState {
             }
 
             // TODO: Check wrapping of 'start'.
-            self.pos_start = (self.resp as u16).wrapping_sub(Stella::HORIZONTAL_BLANK).wrapping_add(self.size as u16 / 2) % Stella::FRAME_WIDTH;
+            self.pos_start = (self.counter.start() + self.size as u16 / 2) % Stella::FRAME_WIDTH;
             self.calc_player_scan();
         }
     }
@@ -503,6 +534,12 @@ pub struct LineState {
     hmbl: u8,
 }
 
+impl Default for LineState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LineState {
     pub fn new() -> Self {
         Self {
@@ -525,6 +562,12 @@ pub struct CollisionState {
     cxppmm: u8,
 }
 
+impl Default for CollisionState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CollisionState {
     pub fn new() -> Self {
         Self {
@@ -641,6 +684,12 @@ pub struct Colours {
     colours: Vec<display::Colour>,
 }
 
+impl Default for Colours {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Colours {
     pub const NUM_COLOURS: u8 = 128;
 
@@ -674,7 +723,7 @@ impl Colours {
 
         let lines: Vec<String> = std::str::from_utf8(&buffer).unwrap().split('\n').map(|x| x.to_string()).collect();
         for (i, line) in lines.iter().enumerate() {
-            if line.len() > 0 {
+            if !line.is_empty() {
                 let line_without_comments = &line[0..line.find('#').unwrap_or(line.len())].trim_end_matches(' ');
                 let values: Vec<u8> = line_without_comments.split(' ').collect::<Vec<&str>>().iter().map(|x| x.parse::<u8>().unwrap()).collect::<Vec<u8>>();
                 self.colours[i] = display::Colour::new(values[0], values[1], values[2]);
@@ -702,9 +751,17 @@ pub struct Stella {
     is_update_time: bool,
     is_hmove_scan: bool,
 
+    // HMOVE motion, the next pulse (every 4 colour clocks) and the HMOVE counter value for it.
+    is_motion: bool,
+    motion_clock: clocks::ClockType,
+    motion_count: u8,
+    // Start of the line with an extended horizontal blank (from an HMOVE during horizontal blank).
+    hmove_blank_line: Option<clocks::ClockType>,
+
     colours: Colours,
 
     display_lines: Vec<Vec<display::Colour>>,
+    vertical_offset: i16, // Lines to move the generated display window down (from 'START_DRAW_Y').
 
     collision_state: CollisionState,
     playfield_state: PlayfieldState,
@@ -724,6 +781,9 @@ impl Stella {
     pub const FRAME_HEIGHT: u16 = 220; // TODO: Make this 'sensibly' configurable,  was '280' to include overscan/blank. Maybe show overscans in 'Debug'?
     pub const HORIZONTAL_BLANK: u16 = 68;
     pub const LATE_HORIZONTAL_BLANK: u16 = 76;
+    // Colour clocks between HMOVE pulses, the HMOVE counter stops changing after the last (15).
+    pub const MOTION_PULSE_TICKS: clocks::ClockType = 4;
+    pub const MAX_MOTION_COUNT: u8 = 15;
     pub const HORIZONTAL_TICKS: clocks::ClockType = (Stella::FRAME_WIDTH + Stella::HORIZONTAL_BLANK) as clocks::ClockType;
     pub const INPUT_45_LATCH_MASK: u8 = 0x40;
     pub const BLANK_PADDLE_RECHARGE: u8 = 0x80;
@@ -756,8 +816,13 @@ impl Stella {
             is_input_latched: false,
             is_update_time: false,
             is_hmove_scan: false,
+            is_motion: false,
+            motion_clock: 0,
+            motion_count: 0,
+            hmove_blank_line: None,
             colours,
             display_lines: vec![vec![display::Colour::new(0, 0, 0); Stella::FRAME_WIDTH as usize]; (Stella::END_DRAW_Y) as usize],
+            vertical_offset: 0,
             collision_state: CollisionState::new(),
             playfield_state: PlayfieldState::new(),
             p0_state: PlayerState::new(),
@@ -771,6 +836,7 @@ impl Stella {
 
     pub fn write(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
         let future_pixels = self.get_write_delay(address);
+        self.apply_motion(clock.ticks + future_pixels as clocks::ClockType);
         if !self.is_blank {
             self.screen_scan(clock, future_pixels);
         }
@@ -795,13 +861,13 @@ impl Stella {
             0x5 => { self.collision_state.get_cxmfb_1() }
             0x6 => { self.collision_state.get_cxblpf() }
             0x7 => { self.collision_state.get_cxppmm() }
-            0x8 => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, 0.5, &mut self.input.input0);
+            0x8 => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, self.input.paddles[0], &mut self.input.input0);
                      self.input.input0 }
-            0x9 => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, 0.5, &mut self.input.input1);
+            0x9 => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, self.input.paddles[1], &mut self.input.input1);
                      self.input.input1 }
-            0xA => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, 0.5, &mut self.input.input2);
+            0xA => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, self.input.paddles[2], &mut self.input.input2);
                      self.input.input2 }
-            0xB => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, 0.5, &mut self.input.input3);
+            0xB => { Self::get_paddle_inp_value(self.paddle_start_clock, clock, self.input.paddles[3], &mut self.input.input3);
                      self.input.input3 }
             0xC => { self.input.input4 }
             0xD => { self.input.input5 }
@@ -812,54 +878,19 @@ impl Stella {
     }
 
     fn get_write_delay(&mut self, address: u16) -> u8 {
-        let default_future = 1;
-        // Pulling out simulate the 'delay' of the particular command.  Leads to how far in advance the screen is 'written' to, before the register/value is changed.
-        // Currently these are 'fudge' values.  Need to check/confirm each delay (and see how/if they couple with the instruction write timing).
+        // Colour clocks after the write (at the end of the CPU's write cycle) before the TIA acts on it, as in
+        // Stella's TIA ('Delay').  The object resets are the pixels to the object's start (see 'reset_pixel').
         match address & 0x3F {
-            0x00 => default_future, // write_vsync
-            0x01 => default_future, // write_vblank
-            0x02 => default_future, // write_wsync
-            0x03 => default_future, // write_rsync
-            0x04 => default_future, // write_nusiz0
-            0x05 => default_future, // write_nusiz1
-            0x06 => default_future, // write_colump0
-            0x07 => default_future, // write_colump1
-            0x08 => default_future, // write_colupf
-            0x09 => default_future, // write_colubk
-            0x0A => default_future, // write_ctrlpf
-            0x0B => default_future, // write_refp0
-            0x0C => default_future, // write_refp1
-            0x0D => 5, // write_pf0
-            0x0E => 5, // write_pf1
-            0x0F => 5, // write_pf2
-            0x10 => 5, // write_resp0
-            0x11 => 5, // write_resp1
-            0x12 => 4, // write_resm0
-            0x13 => 4, // write_resm1
-            0x14 => 4, // write_resbl
-            0x15 => default_future, // tiasound.write_audio_ctrl_0
-            0x16 => default_future, // tiasound.write_audio_ctrl_1
-            0x17 => default_future, // tiasound.write_audio_freq_0
-            0x18 => default_future, // tiasound.write_audio_freq_1
-            0x19 => default_future, // tiasound.write_audio_vol_0
-            0x1A => default_future, // tiasound.write_audio_vol_1
-            0x1B => default_future, // write_grp0
-            0x1C => default_future, // write_grp1
-            0x1D => default_future, // write_enam0
-            0x1E => default_future, // write_enam1
-            0x1F => default_future, // write_enabl
-            0x20 => default_future, // write_hmp0
-            0x21 => default_future, // write_hmp1
-            0x22 => default_future, // write_hmm0
-            0x23 => default_future, // write_hmm1
-            0x24 => default_future, // write_hmbl
+            0x01 => 1, // write_vblank
+            0x0B | 0x0C => 1, // write_refp0/1
+            0x0D..=0x0F => 2, // write_pf0/1/2
+            0x10 | 0x11 => 5, // write_resp0/1
+            0x12..=0x14 => 4, // write_resm0/1, write_resbl
+            0x1B..=0x1F => 1, // write_grp0/1, write_enam0/1, write_enabl
+            0x20..=0x24 => 2, // write_hmp0/1, write_hmm0/1, write_hmbl
             0x2A => 6, // write_hmove
-            0x2B => default_future, // write_hclr
-            0x25 => default_future, // write_vdelp0
-            0x26 => default_future, // write_vdelp1
-            0x27 => default_future, // write_vdelbl
-            0x2C => default_future, // write_cxclr
-            _ => {default_future}, 
+            0x2B => 2, // write_hclr
+            _ => 0,
         }
     }
 
@@ -903,7 +934,7 @@ impl Stella {
             0x22 => {self.write_hmm0(clock, address, data); }
             0x23 => {self.write_hmm1(clock, address, data); }
             0x24 => {self.write_hmbl(clock, address, data); }
-            0x2A => {self.write_hmove(clock, address, data); }
+            0x2A => {self.write_hmove(clock, address, data, future_pixels); }
             0x2B => {self.write_hclr(clock, address, data); }
             0x25 => {self.write_vdelp0(clock, address, data); }
             0x26 => {self.write_vdelp1(clock, address, data); }
@@ -915,7 +946,7 @@ impl Stella {
         }
     }
 
-    fn write_vsync(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_vsync(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         if !self.is_vsync {
             if Constants::VSYNC_ON == (data & Constants::VSYNC_MASK) {
                 self.is_update_time = true;
@@ -930,7 +961,7 @@ impl Stella {
         }
     }
 
-    fn write_vblank(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_vblank(&mut self, clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.is_input_latched = 0 != data & Stella::INPUT_45_LATCH_MASK;
 
         if (data & Stella::BLANK_PADDLE_RECHARGE) == Stella::BLANK_PADDLE_RECHARGE {
@@ -945,13 +976,13 @@ impl Stella {
         }
     }
 
-    fn write_wsync(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_wsync(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8) {
         if (clock.ticks - self.screen_start_clock) % Stella::HORIZONTAL_TICKS > 3 {
             clock.ticks += Stella::HORIZONTAL_TICKS - (clock.ticks - self.screen_start_clock) % Stella::HORIZONTAL_TICKS;
         }
     }
 
-    fn write_rsync(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_rsync(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8) {
         let fudge = 3;
 
         if (clock.ticks - self.screen_start_clock) > 3 {
@@ -959,127 +990,131 @@ impl Stella {
         }
     }
 
-    fn write_nusiz0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_nusiz0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p0_state.update_nusiz(data);
         self.missile0.update_nusiz(data);
     }
 
-    fn write_nusiz1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_nusiz1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p1_state.update_nusiz(data);
         self.missile1.update_nusiz(data)
     }
 
-    fn write_colump0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_colump0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.p_colour.0 = self.colours.get_colour(data);
     }
 
-    fn write_colump1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_colump1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.p_colour.1 = self.colours.get_colour(data);
     }
 
-    fn write_colupf(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_colupf(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.playfield_colour = self.colours.get_colour(data);
     }
 
-    fn write_colubk(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_colubk(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.background_colour = self.colours.get_colour(data);
     }
 
-    fn write_ctrlpf(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_ctrlpf(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.ctrlpf = data;
         self.playfield_state.update_ctrlpf(data);
         self.ball.update_ctrlpf(data)
     }
 
-    fn write_refp0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_refp0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p0_state.update_refp(data);
     }
 
-    fn write_refp1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_refp1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p1_state.update_refp(data);
     }
 
-    fn write_pf0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_pf0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.playfield_state.update_pf0(data);
     }
 
-    fn write_pf1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_pf1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.playfield_state.update_pf1(data);
     }
 
-    fn write_pf2(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_pf2(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.playfield_state.update_pf2(data);
     }
 
-    fn write_resp0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8, future_pixels: u8) {
-        let resp_value = ((clock.ticks.wrapping_add(future_pixels as u64).wrapping_sub(self.screen_start_clock)) % Stella::HORIZONTAL_TICKS) as u8;
-        self.p0_state.update_resp(resp_value);
+    fn write_resp0(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8, future_pixels: u8) {
+        let x = self.reset_pixel(clock.ticks, future_pixels);
+        self.p0_state.reset(x);
     }
 
-    fn write_resp1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8, future_pixels: u8) {
-        self.p1_state.update_resp(((clock.ticks.wrapping_add(future_pixels as u64).wrapping_sub(self.screen_start_clock)) % Stella::HORIZONTAL_TICKS) as u8);
+    fn write_resp1(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8, future_pixels: u8) {
+        let x = self.reset_pixel(clock.ticks, future_pixels);
+        self.p1_state.reset(x);
     }
 
-    fn write_resm0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8, future_pixels: u8) {
-        self.missile0.update_resm(((clock.ticks.wrapping_add(future_pixels as u64).wrapping_sub(self.screen_start_clock)) % Stella::HORIZONTAL_TICKS) as u8);
+    fn write_resm0(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8, future_pixels: u8) {
+        let x = self.reset_pixel(clock.ticks, future_pixels);
+        self.missile0.reset(x);
     }
 
-    fn write_resm1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8, future_pixels: u8) {
-        self.missile1.update_resm(((clock.ticks.wrapping_add(future_pixels as u64).wrapping_sub(self.screen_start_clock)) % Stella::HORIZONTAL_TICKS) as u8);
+    fn write_resm1(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8, future_pixels: u8) {
+        let x = self.reset_pixel(clock.ticks, future_pixels);
+        self.missile1.reset(x);
     }
 
-    fn write_resbl(&mut self, clock: &mut clocks::Clock, address: u16, data: u8, future_pixels: u8) {
-        self.ball.update_resbl(((clock.ticks.wrapping_add(future_pixels as u64).wrapping_sub(self.screen_start_clock)) % Stella::HORIZONTAL_TICKS) as u8);
+    fn write_resbl(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8, future_pixels: u8) {
+        let x = self.reset_pixel(clock.ticks, future_pixels);
+        self.ball.reset(x);
     }
 
-    fn write_grp0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_grp0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p0_state.update_p(data);
         self.p1_state.update_p_old(self.p1_state.p);
     }
 
-    fn write_grp1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_grp1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p1_state.update_p(data);
         self.p0_state.update_p_old(self.p0_state.p);
         self.ball.update_enabl_old(self.ball.enabl);
     }
 
-    fn write_enam0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_enam0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.missile0.update_enam(data);
     }
 
-    fn write_enam1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_enam1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.missile1.update_enam(data);
     }
 
-    fn write_enabl(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_enabl(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.ball.update_enabl(data);
     }
 
-    fn write_hmp0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_hmp0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.hmp.0 = data;
     }
 
-    fn write_hmp1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_hmp1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.hmp.1 = data;
     }
 
-    fn write_hmm0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_hmm0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.hmm.0 = data;
     }
 
-    fn write_hmm1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_hmm1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.hmm.1 = data;
     }
 
-    fn write_hmbl(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_hmbl(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.next_line.hmbl = data;
     }
 
-    fn write_hmove(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
-        self.hmove(clock);
+    fn write_hmove(&mut self, clock: &mut clocks::Clock, _address: u16, _data: u8, future_pixels: u8) {
+        self.hmove(clock.ticks + future_pixels as clocks::ClockType);
     }
 
-    fn write_hclr(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_hclr(&mut self, _clock: &mut clocks::Clock, _address: u16, _data: u8) {
         self.next_line.hmp.0 = 0;
         self.next_line.hmp.1 = 0;
         self.next_line.hmm.0 = 0;
@@ -1087,26 +1122,30 @@ impl Stella {
         self.next_line.hmbl = 0;
     }
 
-    fn write_vdelp0(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_vdelp0(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p0_state.update_vdelp(data);
     }
 
-    fn write_vdelp1(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_vdelp1(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.p1_state.update_vdelp(data);
     }
 
-    fn write_vdelbl(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_vdelbl(&mut self, _clock: &mut clocks::Clock, _address: u16, data: u8) {
         self.ball.update_vdelbl(data);
     }
 
-    fn write_cxclr(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn write_cxclr(&mut self, _clock: &mut clocks::Clock, _address: u16, _data: u8) {
         self.collision_state.clear();
     }
 
     pub fn screen_scan(&mut self, clock: &mut clocks::Clock, future_pixels: u8) {
+        self.screen_scan_to(clock.ticks + future_pixels as clocks::ClockType);
+    }
 
+    // Draw the display up to 'ticks'.
+    fn screen_scan_to(&mut self, ticks: clocks::ClockType) {
         let last_screen_pos = self.last_screen_update_clock - self.screen_start_clock;
-        let screen_pos = (clock.ticks - self.screen_start_clock).wrapping_add(future_pixels as clocks::ClockType);
+        let screen_pos = ticks - self.screen_start_clock;
 
         let y_start = (last_screen_pos / Stella::HORIZONTAL_TICKS as clocks::ClockType) as i16 - Stella::START_DRAW_Y as i16;
         let y_stop = (screen_pos / Stella::HORIZONTAL_TICKS as clocks::ClockType) as i16 - Stella::START_DRAW_Y as i16;
@@ -1231,7 +1270,7 @@ impl Stella {
             }
         }
 
-        self.last_screen_update_clock = clock.ticks + future_pixels as u64;
+        self.last_screen_update_clock = ticks;
     }
 
     fn nusize(nusiz: u8) -> (u8, u8, u8) {
@@ -1251,38 +1290,312 @@ impl Stella {
         }
     }
 
-    fn hmove(&mut self, clock: &clocks::Clock) {
-        self.is_hmove_scan = true;
+    // Colour clock within the line.
+    fn horizontal_clock(&self, ticks: clocks::ClockType) -> u16 {
+        (ticks.wrapping_sub(self.screen_start_clock) % Stella::HORIZONTAL_TICKS) as u16
+    }
+
+    fn line_start(&self, ticks: clocks::ClockType) -> clocks::ClockType {
+        ticks - self.horizontal_clock(ticks) as clocks::ClockType
+    }
+
+    fn is_extended_hblank(&self, ticks: clocks::ClockType) -> bool {
+        self.hmove_blank_line == Some(self.line_start(ticks))
+    }
+
+    fn is_hblank(&self, ticks: clocks::ClockType) -> bool {
+        let horizontal_clock = self.horizontal_clock(ticks);
+        horizontal_clock < Stella::HORIZONTAL_BLANK || (horizontal_clock < Stella::LATE_HORIZONTAL_BLANK && self.is_extended_hblank(ticks))
+    }
+
+    // The pixel an object reset at 'ticks' starts at, 'delay' pixels after the write (5 for players, 4 for missiles and
+    // the ball, the counter is set to 157 and the graphics follow the counter reaching 0).  The counters aren't clocked
+    // during horizontal blank, a reset there sets 159 and the first clock is the pixel after the blank ends (so the
+    // players start at 3, missiles and the ball at 2, or 8 pixels later with the HMOVE extension).
+    fn reset_pixel(&self, ticks: clocks::ClockType, delay: u8) -> u16 {
+        if self.is_hblank(ticks) {
+            let blank_end = if self.is_extended_hblank(ticks) { Stella::LATE_HORIZONTAL_BLANK } else { Stella::HORIZONTAL_BLANK };
+            blank_end - Stella::HORIZONTAL_BLANK + delay as u16 - 2
+        } else {
+            (self.horizontal_clock(ticks) - Stella::HORIZONTAL_BLANK + delay as u16) % Stella::FRAME_WIDTH
+        }
+    }
+
+    fn hmove(&mut self, ticks: clocks::ClockType) {
+        // Start the pulses, every object moves until the HMOVE counter matches its motion register.
+        self.is_motion = true;
+        self.motion_count = 0;
+        let horizontal_clock = self.horizontal_clock(ticks) as clocks::ClockType;
+        self.motion_clock = ticks + (Stella::MOTION_PULSE_TICKS - horizontal_clock % Stella::MOTION_PULSE_TICKS) % Stella::MOTION_PULSE_TICKS;
+        for counter in self.counters_mut() {
+            counter.moving = true;
+        }
+
+        // During horizontal blank it's extended by 8 pixels (the black 'HMOVE bar'), and the counters miss those
+        // clocks.  The extension is cleared at the start of each line, so an HMOVE at the end of a line (cycle 73/74)
+        // moves the objects the full count, without the bar.
+        let horizontal_clock = horizontal_clock as u16;
+        if 0 < horizontal_clock && horizontal_clock < Stella::HORIZONTAL_BLANK {
+            self.is_hmove_scan = true;
+            self.hmove_blank_line = Some(self.line_start(ticks));
+            for counter in self.counters_mut() {
+                counter.clock(-((Stella::LATE_HORIZONTAL_BLANK - Stella::HORIZONTAL_BLANK) as i16));
+            }
+            self.update_objects();
+        }
+    }
+
+    // Apply the HMOVE pulses up to 'ticks'.  Pulses only move the objects during horizontal blank (an HMOVE late in the
+    // line is mostly lost), and continue until every object's comparator has matched.  Changing a motion register
+    // while the pulses are running can miss the match, then the object keeps moving ('Cosmic Ark' starfield).
+    fn apply_motion(&mut self, ticks: clocks::ClockType) {
+        let mut moved_line = None;
+        while self.is_motion && self.motion_clock <= ticks {
+            let pulse_clock = self.motion_clock;
+            let hblank = self.is_hblank(pulse_clock);
+
+            // Finish drawing the previous lines, before the positions change.
+            if hblank && moved_line != Some(self.line_start(pulse_clock)) {
+                if moved_line.is_some() {
+                    self.update_objects();
+                }
+                if !self.is_blank {
+                    self.screen_scan_to(pulse_clock);
+                }
+                moved_line = Some(self.line_start(pulse_clock));
+            }
+
+            let count = if self.motion_count > Stella::MAX_MOTION_COUNT { 0 } else { self.motion_count };
+            let hm = [self.next_line.hmp.0, self.next_line.hmp.1, self.next_line.hmm.0, self.next_line.hmm.1, self.next_line.hmbl];
+            let mut is_motion = false;
+            for (counter, hm) in self.counters_mut().into_iter().zip(hm) {
+                counter.movement_pulse(count, hm, hblank);
+                is_motion |= counter.moving;
+            }
+
+            self.is_motion = is_motion;
+            self.motion_count = self.motion_count.saturating_add(1).min(Stella::MAX_MOTION_COUNT + 1);
+            self.motion_clock += Stella::MOTION_PULSE_TICKS;
+        }
 
-        let clock_ticks_from_scan = ((clock.ticks - self.screen_start_clock) % Stella::HORIZONTAL_TICKS) as u8;
-        self.p0_state.resp = (self.p0_state.resp.wrapping_sub(Stella::hmove_clocks(self.next_line.hmp.0, clock_ticks_from_scan) as u8)) % Stella::HORIZONTAL_TICKS as u8;
-        self.p1_state.resp = (self.p1_state.resp.wrapping_sub(Stella::hmove_clocks(self.next_line.hmp.1, clock_ticks_from_scan) as u8)) % Stella::HORIZONTAL_TICKS as u8;
-        self.missile0.resm = self.missile0.resm.wrapping_sub(Stella::hmove_clocks(self.next_line.hmm.0, clock_ticks_from_scan) as u8) % Stella::HORIZONTAL_TICKS as u8;
-        self.missile1.resm = self.missile1.resm.wrapping_sub(Stella::hmove_clocks(self.next_line.hmm.1, clock_ticks_from_scan) as u8) % Stella::HORIZONTAL_TICKS as u8;
-        self.ball.resbl = self.ball.resbl.wrapping_sub(Stella::hmove_clocks(self.next_line.hmbl, clock_ticks_from_scan) as u8) % Stella::HORIZONTAL_TICKS as u8;
+        if moved_line.is_some() {
+            self.update_objects();
+        }
+    }
+
+    fn counters_mut(&mut self) -> [&mut PositionCounter; 5] {
+        [&mut self.p0_state.counter, &mut self.p1_state.counter, &mut self.missile0.counter, &mut self.missile1.counter, &mut self.ball.counter]
+    }
 
+    fn update_objects(&mut self) {
         self.p0_state.update();
         self.p1_state.update();
         self.missile0.update();
         self.missile1.update();
         self.ball.update();
     }
+}
 
-    fn hmove_clocks(hm: u8, ticks_since_scan_start: u8) -> i8 {
-        // hm - int8
-        // Need to ensure 'hm' maintains negative when shifted.
-        // 'hm >= 0x80' is negative move.
-        let clock_shift = (hm as i8) >> 4;
-
-        // TODO: Fix 73/74 clock approximation of 'hmove'
-        let horizontal_scan_count = ticks_since_scan_start / pc_state::PcState::CYCLES_TO_CLOCK;
-        match horizontal_scan_count {
-            0..=4 => clock_shift,
-            73 => clock_shift + 8,
-            74 => clock_shift + 8,
-            75 => clock_shift, // Treat as 'zero'
-            _ => 0,            // TODO: Actually emulate the counters, rather than a crude lookup.
-        }
+impl state::Snapshot for PlayfieldState {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.pf0);
+        state.put_u8(self.pf1);
+        state.put_u8(self.pf2);
+        state.put_u8(self.ctrlpf);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.pf0 = state.get_u8()?;
+        self.pf1 = state.get_u8()?;
+        self.pf2 = state.get_u8()?;
+        self.ctrlpf = state.get_u8()?;
+        self.update();
+        Ok(())
+    }
+}
+
+impl state::Snapshot for PositionCounter {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.value);
+        state.put_bool(self.moving);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.value = state.get_u8()?;
+        self.moving = state.get_bool()?;
+        Ok(())
+    }
+}
+
+impl state::Snapshot for BallState {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.enabl);
+        state.put_u8(self.enabl_old);
+        state.put_u8(self.vdelbl);
+        self.counter.save_state(state);
+        state.put_u8(self.ctrlpf);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.enabl = state.get_u8()?;
+        self.enabl_old = state.get_u8()?;
+        self.vdelbl = state.get_u8()?;
+        self.counter.load_state(state)?;
+        self.ctrlpf = state.get_u8()?;
+        self.update();
+        Ok(())
+    }
+}
+
+impl state::Snapshot for MissileState {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.nusiz);
+        state.put_u8(self.enam);
+        self.counter.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.nusiz = state.get_u8()?;
+        self.enam = state.get_u8()?;
+        self.counter.load_state(state)?;
+        self.update();
+        Ok(())
+    }
+}
+
+impl state::Snapshot for PlayerState {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.nusiz);
+        state.put_u8(self.p);
+        state.put_u8(self.p_old);
+        state.put_u8(self.refp);
+        self.counter.save_state(state);
+        state.put_u8(self.vdelp);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.nusiz = state.get_u8()?;
+        self.p = state.get_u8()?;
+        self.p_old = state.get_u8()?;
+        self.refp = state.get_u8()?;
+        self.counter.load_state(state)?;
+        self.vdelp = state.get_u8()?;
+        self.update();
+        Ok(())
+    }
+}
+
+impl state::Snapshot for LineState {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        self.p_colour.0.save_state(state);
+        self.p_colour.1.save_state(state);
+        self.background_colour.save_state(state);
+        self.playfield_colour.save_state(state);
+        state.put_u8(self.ctrlpf);
+        state.put_u8(self.hmp.0);
+        state.put_u8(self.hmp.1);
+        state.put_u8(self.hmm.0);
+        state.put_u8(self.hmm.1);
+        state.put_u8(self.hmbl);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.p_colour.0.load_state(state)?;
+        self.p_colour.1.load_state(state)?;
+        self.background_colour.load_state(state)?;
+        self.playfield_colour.load_state(state)?;
+        self.ctrlpf = state.get_u8()?;
+        self.hmp.0 = state.get_u8()?;
+        self.hmp.1 = state.get_u8()?;
+        self.hmm.0 = state.get_u8()?;
+        self.hmm.1 = state.get_u8()?;
+        self.hmbl = state.get_u8()?;
+        Ok(())
+    }
+}
+
+impl state::Snapshot for CollisionState {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.cxmp.0);
+        state.put_u8(self.cxmp.1);
+        state.put_u8(self.cxpfb.0);
+        state.put_u8(self.cxpfb.1);
+        state.put_u8(self.cxmfb.0);
+        state.put_u8(self.cxmfb.1);
+        state.put_u8(self.cxblpf);
+        state.put_u8(self.cxppmm);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.cxmp.0 = state.get_u8()?;
+        self.cxmp.1 = state.get_u8()?;
+        self.cxpfb.0 = state.get_u8()?;
+        self.cxpfb.1 = state.get_u8()?;
+        self.cxmfb.0 = state.get_u8()?;
+        self.cxmfb.1 = state.get_u8()?;
+        self.cxblpf = state.get_u8()?;
+        self.cxppmm = state.get_u8()?;
+        Ok(())
+    }
+}
+
+impl state::Snapshot for Stella {
+    // The display lines are output (re-drawn each frame), so aren't saved.
+    fn save_state(&self, state: &mut state::StateWriter) {
+        self.tiasound.save_state(state);
+        self.input.save_state(state);
+        state.put_u64(self.vsync_debug_output_clock);
+        state.put_u64(self.screen_start_clock);
+        state.put_u64(self.paddle_start_clock);
+        state.put_u64(self.last_screen_update_clock);
+        self.next_line.save_state(state);
+        state.put_bool(self.is_vsync);
+        state.put_bool(self.is_blank);
+        state.put_bool(self.is_input_latched);
+        state.put_bool(self.is_update_time);
+        state.put_bool(self.is_hmove_scan);
+        state.put_bool(self.is_motion);
+        state.put_u64(self.motion_clock);
+        state.put_u8(self.motion_count);
+        state.put_bool(self.hmove_blank_line.is_some());
+        state.put_u64(self.hmove_blank_line.unwrap_or(0));
+
+        self.collision_state.save_state(state);
+        self.playfield_state.save_state(state);
+        self.p0_state.save_state(state);
+        self.p1_state.save_state(state);
+        self.missile0.save_state(state);
+        self.missile1.save_state(state);
+        self.ball.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.tiasound.load_state(state)?;
+        self.input.load_state(state)?;
+        self.vsync_debug_output_clock = state.get_u64()?;
+        self.screen_start_clock = state.get_u64()?;
+        self.paddle_start_clock = state.get_u64()?;
+        self.last_screen_update_clock = state.get_u64()?;
+        self.next_line.load_state(state)?;
+        self.is_vsync = state.get_bool()?;
+        self.is_blank = state.get_bool()?;
+        self.is_input_latched = state.get_bool()?;
+        self.is_update_time = state.get_bool()?;
+        self.is_hmove_scan = state.get_bool()?;
+        self.is_motion = state.get_bool()?;
+        self.motion_clock = state.get_u64()?;
+        self.motion_count = state.get_u8()?;
+        let is_hmove_blank_line = state.get_bool()?;
+        let hmove_blank_line = state.get_u64()?;
+        self.hmove_blank_line = is_hmove_blank_line.then_some(hmove_blank_line);
+
+        self.collision_state.load_state(state)?;
+        self.playfield_state.load_state(state)?;
+        self.p0_state.load_state(state)?;
+        self.p1_state.load_state(state)?;
+        self.missile0.load_state(state)?;
+        self.missile1.load_state(state)?;
+        self.ball.load_state(state)
     }
 }
 
@@ -1319,6 +1632,10 @@ impl io::StellaIO for Stella {
         self.tiasound.get_next_audio_chunk(length)
     }
 
+    fn take_audio_samples(&mut self) -> Vec<soundchannel::PlaybackType> {
+        self.tiasound.take_audio_samples()
+    }
+
     fn step_tia_sound(&mut self, clock: &clocks::Clock) {
         self.tiasound.step(clock);
     }
@@ -1330,14 +1647,103 @@ impl io::StellaIO for Stella {
         result
     }
 
+    fn set_vertical_offset(&mut self, offset: i16) {
+        self.vertical_offset = offset;
+    }
+
     fn generate_display(&mut self, buffer: &mut [u8]) {
+        // Keep the window within the drawn lines.
+        let start_y = (Stella::START_DRAW_Y as i16 + self.vertical_offset).clamp(0, (Stella::END_DRAW_Y - Stella::START_DRAW_Y - Stella::FRAME_HEIGHT) as i16) as u16;
         let mut index = 0;
         for y in 0..Stella::FRAME_HEIGHT {
-            let display_line = &self.display_lines[(y + Stella::START_DRAW_Y) as usize];
+            let display_line = &self.display_lines[(y + start_y) as usize];
             for x in display_line {
-                x.convert_rgb888(&mut buffer[index..(index + display::SDLUtility::bytes_per_pixel() as usize)]);
-                index += display::SDLUtility::bytes_per_pixel() as usize;
+                x.convert_rgb888(&mut buffer[index..(index + display::BYTES_PER_PIXEL as usize)]);
+                index += display::BYTES_PER_PIXEL as usize;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VSYNC: u16 = 0x00;
+    const COLUBK: u16 = 0x09;
+    const RESP0: u16 = 0x10;
+    const RESM0: u16 = 0x12;
+    const HMP0: u16 = 0x20;
+    const HMM0: u16 = 0x22;
+    const HMOVE: u16 = 0x2A;
+
+    // Lines start at 'Stella::HORIZONTAL_TICKS' after the end of VSYNC.
+    fn write_at(stella: &mut Stella, line: u64, horizontal_clock: u64, address: u16, data: u8) {
+        let mut clock = clocks::Clock::new();
+        clock.ticks = (line + 1) * Stella::HORIZONTAL_TICKS + horizontal_clock;
+        stella.write(&mut clock, address, data);
+    }
+
+    fn new_stella() -> Stella {
+        let mut stella = Stella::new(false, false, false);
+        let mut clock = clocks::Clock::new();
+        stella.write(&mut clock, VSYNC, Constants::VSYNC_ON);
+        clock.ticks = Stella::HORIZONTAL_TICKS;
+        stella.write(&mut clock, VSYNC, Constants::VSYNC_OFF);
+        stella
+    }
+
+    #[test]
+    fn test_hmove() {
+        let mut stella = new_stella();
+        write_at(&mut stella, 0, 100, RESP0, 0);
+        assert_eq!(stella.p0_state.counter.start(), 100 + 5 - Stella::HORIZONTAL_BLANK);
+
+        // Early HMOVE, the move is the motion register (with the HMOVE bar).
+        write_at(&mut stella, 1, 0, HMP0, 0x70);
+        write_at(&mut stella, 1, 3, HMOVE, 0);
+        write_at(&mut stella, 2, 0, COLUBK, 0);
+        assert_eq!(stella.p0_state.counter.start(), 30);
+        assert!(stella.is_hmove_scan);
+
+        write_at(&mut stella, 2, 0, HMP0, 0x80);
+        write_at(&mut stella, 2, 3, HMOVE, 0);
+        write_at(&mut stella, 3, 0, COLUBK, 0);
+        assert_eq!(stella.p0_state.counter.start(), 38);
+
+        // HMOVE in the middle of the line, the pulses are all during the visible line.
+        stella.is_hmove_scan = false;
+        write_at(&mut stella, 3, 0, HMP0, 0x00);
+        write_at(&mut stella, 3, 150, HMOVE, 0);
+        write_at(&mut stella, 4, 100, COLUBK, 0);
+        assert_eq!(stella.p0_state.counter.start(), 38);
+        assert!(!stella.is_hmove_scan);
+
+        // HMOVE at cycle 74, the next line's horizontal blank isn't extended, 8 pixels further left than usual.
+        write_at(&mut stella, 5, 74 * 3, HMOVE, 0);
+        write_at(&mut stella, 7, 0, COLUBK, 0);
+        assert_eq!(stella.p0_state.counter.start(), 30);
+        assert!(!stella.is_hmove_scan);
+    }
+
+    #[test]
+    fn test_hmove_cosmic_ark() {
+        let mut stella = new_stella();
+        write_at(&mut stella, 0, 100, RESM0, 0);
+        let start = stella.missile0.counter.start();
+
+        // Changing HMM0 after its comparator value has passed, the missile keeps moving, 17 pulses every line.
+        write_at(&mut stella, 1, 0, HMM0, 0x70);
+        write_at(&mut stella, 1, 3, HMOVE, 0);
+        write_at(&mut stella, 1, 59, HMM0, 0x00);
+        write_at(&mut stella, 4, 100, COLUBK, 0);
+        assert!(stella.missile0.counter.moving);
+        assert_eq!(stella.missile0.counter.start(), (start + 2 * Stella::FRAME_WIDTH - (16 - 8) - 17 * 3) % Stella::FRAME_WIDTH);
+
+        // Until HMM0 is 0x80, matched once the HMOVE counter stops.
+        write_at(&mut stella, 4, 100, HMM0, 0x80);
+        write_at(&mut stella, 6, 100, COLUBK, 0);
+        assert!(!stella.missile0.counter.moving);
+        assert!(!stella.is_motion);
+    }
+}
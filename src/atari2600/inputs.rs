@@ -1,7 +1,11 @@
+use super::properties::Controller;
+use super::state;
+#[cfg(feature = "sdl")]
 use sdl2::event;
+#[cfg(feature = "sdl")]
 use sdl2::keyboard; // Keycode // Keycode
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Input {
     pub swcha: u8,
     pub swchb: u8,
@@ -11,9 +15,16 @@ pub struct Input {
     pub input3: u8,
     pub input4: u8,
     pub input5: u8,
+    pub paddles: [f32; 4], // Paddle positions (0.0 - 1.0), used to time the 'INP0-INP3' capacitor charge.
     pub quit: u8,
 }
 
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Input {
     const INPUT_MASK: u8 = 0x80; // 'INP0-INP5' set data bit '7' (0-6 are ignored).
                                  // 'I0-I3' can be grouned by software and 'I4-I5' can be configured to latch via software, but this isn't emulated.
@@ -27,19 +38,127 @@ impl Input {
             input3: 0xFF,
             input4: 0xFF,
             input5: 0xFF,
+            paddles: [0.5; 4],
             quit: 0x0,
         }
     }
+
+    // Number of bytes used by 'to_bytes'/'from_bytes'.
+    pub const SERIALISED_SIZE: usize = 8 + 4 * 4;
+
+    // Fixed size encoding of the inputs ('quit' isn't a console input, so isn't included).
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![self.swcha, self.swchb, self.input0, self.input1, self.input2, self.input3, self.input4, self.input5];
+        for paddle in self.paddles {
+            bytes.extend_from_slice(&paddle.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut input = Input::new();
+        input.swcha = bytes[0];
+        input.swchb = bytes[1];
+        input.input0 = bytes[2];
+        input.input1 = bytes[3];
+        input.input2 = bytes[4];
+        input.input3 = bytes[5];
+        input.input4 = bytes[6];
+        input.input5 = bytes[7];
+        for (i, paddle) in input.paddles.iter_mut().enumerate() {
+            *paddle = f32::from_le_bytes(bytes[8 + i * 4..12 + i * 4].try_into().unwrap());
+        }
+        input
+    }
+}
+
+impl state::Snapshot for Input {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_bytes(&self.to_bytes());
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let mut bytes = [0_u8; Input::SERIALISED_SIZE];
+        state.get_bytes_into(&mut bytes)?;
+        *self = Input::from_bytes(&bytes);
+        Ok(())
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct Joystick {
     pub input: Input,
+    controllers: [Controller; 2], // Left and right ports.
+    paddle_motion: [i8; 4],       // Direction each paddle is being turned (by the left/right keys).
+}
+
+impl Default for Joystick {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Joystick {
+    // Paddle triggers are on 'SWCHA' (bits 7 and 6 for the left port's pair, 3 and 2 for the right's).
+    const PADDLE_FIRE_MASKS: [u8; 4] = [0x80, 0x40, 0x08, 0x04];
+    const PADDLE_STEP: f32 = 0.02; // Position change per 'update_paddles' while a key is held.
+
     pub fn new() -> Self {
-        Self { input: Input::new() }
+        Self { input: Input::new(), controllers: [Controller::Joystick; 2], paddle_motion: [0; 4] }
+    }
+
+    /// Set the controllers plugged into the ports (from the ROM properties).  The player keys turn the
+    /// paddles, if there are any, driving and keyboard controllers aren't emulated (a joystick is used).
+    pub fn set_controllers(&mut self, left: Controller, right: Controller) {
+        for controller in [left, right] {
+            if !matches!(controller, Controller::Joystick | Controller::Paddles) {
+                println!("{:?} controllers aren't supported, using a joystick.", controller);
+            }
+        }
+        self.controllers = [left, right];
+        self.paddle_motion = [0; 4];
+    }
+
+    // The paddle a player's keys drive, both players use the left port's pair if it has paddles.
+    fn paddle(&self, player: usize) -> Option<usize> {
+        match (self.controllers, player) {
+            ([Controller::Paddles, _], player) => Some(player),
+            ([_, Controller::Paddles], 1) => Some(2),
+            _ => None,
+        }
+    }
+
+    fn turn_paddle(&mut self, paddle: usize, direction: i8, value: bool) {
+        if value {
+            self.paddle_motion[paddle] = direction;
+        } else if self.paddle_motion[paddle] == direction {
+            self.paddle_motion[paddle] = 0;
+        }
+    }
+
+    /// Move the paddles being turned, called regularly (ie per frame).
+    pub fn update_paddles(&mut self) {
+        for (position, motion) in self.input.paddles.iter_mut().zip(self.paddle_motion) {
+            *position = (*position + motion as f32 * Joystick::PADDLE_STEP).clamp(0.0, 1.0);
+        }
+    }
+
+    // A joystick direction, or turning the player's paddle (left/right).
+    fn direction(&mut self, player: usize, value: bool, mask: u8, paddle_direction: i8) {
+        match self.paddle(player) {
+            Some(paddle) if paddle_direction != 0 => self.turn_paddle(paddle, paddle_direction, value),
+            Some(_) => {}
+            None => Joystick::set_input(value, &mut self.input.swcha, mask),
+        }
+    }
+
+    // The joystick button, or the player's paddle trigger.
+    fn fire(&mut self, player: usize, value: bool) {
+        match self.paddle(player) {
+            Some(paddle) => Joystick::set_input(value, &mut self.input.swcha, Joystick::PADDLE_FIRE_MASKS[paddle]),
+            None if 0 == player => Joystick::set_input(value, &mut self.input.input4, Input::INPUT_MASK),
+            None => Joystick::set_input(value, &mut self.input.input5, Input::INPUT_MASK),
+        }
     }
 
     pub fn set_input(value: bool, initial: &mut u8, mask: u8) {
@@ -55,35 +174,35 @@ impl Joystick {
     }
 
     pub fn j1_up(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x10);
+        self.direction(0, value, 0x10, 0);
     }
     pub fn j1_down(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x20);
+        self.direction(0, value, 0x20, 0);
     }
     pub fn j1_left(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x40);
+        self.direction(0, value, 0x40, -1);
     }
     pub fn j1_right(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x80);
+        self.direction(0, value, 0x80, 1);
     }
     pub fn j1_fire(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.input4, Input::INPUT_MASK);
+        self.fire(0, value);
     }
 
     pub fn j2_up(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x01);
+        self.direction(1, value, 0x01, 0);
     }
     pub fn j2_down(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x02);
+        self.direction(1, value, 0x02, 0);
     }
     pub fn j2_left(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x04);
+        self.direction(1, value, 0x04, -1);
     }
     pub fn j2_right(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.swcha, 0x08);
+        self.direction(1, value, 0x08, 1);
     }
     pub fn j2_fire(&mut self, value: bool) {
-        Joystick::set_input(value, &mut self.input.input5, Input::INPUT_MASK);
+        self.fire(1, value);
     }
 
     pub fn select(&mut self, value: bool) {
@@ -102,8 +221,20 @@ impl Joystick {
     }
 }
 
+/// Emulator (rather than console) actions triggered from the keyboard.
+#[derive(Debug, PartialEq)]
+pub enum StateAction {
+    Save,
+    Load,
+    NextSlot,
+    RewindStart,
+    RewindStop,
+}
+
+#[cfg(feature = "sdl")]
 pub struct UserInput {}
 
+#[cfg(feature = "sdl")]
 impl UserInput {
     const KEY_1_UP: keyboard::Keycode = keyboard::Keycode::Up;
     const KEY_1_DOWN: keyboard::Keycode = keyboard::Keycode::Down;
@@ -123,6 +254,11 @@ impl UserInput {
     const KEY_P1_DIFFICULTY: keyboard::Keycode = keyboard::Keycode::Num2;
     const KEY_QUIT: keyboard::Keycode = keyboard::Keycode::Escape;
 
+    const KEY_SAVE_STATE: keyboard::Keycode = keyboard::Keycode::F5;
+    const KEY_NEXT_STATE_SLOT: keyboard::Keycode = keyboard::Keycode::F6;
+    const KEY_LOAD_STATE: keyboard::Keycode = keyboard::Keycode::F9;
+    const KEY_REWIND: keyboard::Keycode = keyboard::Keycode::Backspace;
+
     pub fn print_keys() {
         println!("Key mappings (Joystick 1):");
         println!("Up: {}, Down: {}, Left: {}, Right: {}", UserInput::KEY_1_UP, UserInput::KEY_1_DOWN, UserInput::KEY_1_LEFT, UserInput::KEY_1_RIGHT);
@@ -131,10 +267,23 @@ impl UserInput {
         println!("Up: {}, Down: {}, Left: {}, Right: {}", UserInput::KEY_2_UP, UserInput::KEY_2_DOWN, UserInput::KEY_2_LEFT, UserInput::KEY_2_RIGHT);
         println!("Fire: {}", UserInput::KEY_2_FIRE);
         println!("Reset: {}", UserInput::KEY_RESET);
+        println!("Save state: {}, Load state: {}, Next state slot: {}", UserInput::KEY_SAVE_STATE, UserInput::KEY_LOAD_STATE, UserInput::KEY_NEXT_STATE_SLOT);
+        println!("Rewind (hold): {}", UserInput::KEY_REWIND);
         println!();
         println!("Quit: {}", UserInput::KEY_QUIT);
     }
 
+    pub fn state_action(event: &event::Event) -> Option<StateAction> {
+        match event {
+            event::Event::KeyDown { keycode: Some(UserInput::KEY_SAVE_STATE), repeat: false, .. } => Some(StateAction::Save),
+            event::Event::KeyDown { keycode: Some(UserInput::KEY_LOAD_STATE), repeat: false, .. } => Some(StateAction::Load),
+            event::Event::KeyDown { keycode: Some(UserInput::KEY_NEXT_STATE_SLOT), repeat: false, .. } => Some(StateAction::NextSlot),
+            event::Event::KeyDown { keycode: Some(UserInput::KEY_REWIND), repeat: false, .. } => Some(StateAction::RewindStart),
+            event::Event::KeyUp { keycode: Some(UserInput::KEY_REWIND), .. } => Some(StateAction::RewindStop),
+            _ => None,
+        }
+    }
+
     // Return 'true' if handled, otherwise 'false' (ie quit)
     pub fn handle_events(event: event::Event, joystick: &mut Joystick) -> bool {
         match event {
@@ -234,3 +383,33 @@ impl UserInput {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::inputs::Joystick;
+    use crate::atari2600::properties::Controller;
+
+    #[test]
+    fn test_paddles() {
+        let mut joystick = Joystick::new();
+        joystick.j1_right(true);
+        joystick.j2_fire(true);
+        assert_eq!(joystick.input.swcha, 0x7F);
+        assert_eq!(joystick.input.input5, 0x7F);
+
+        // Both players turn the left port's paddles, the buttons are on 'SWCHA'.
+        let mut joystick = Joystick::new();
+        joystick.set_controllers(Controller::Paddles, Controller::Joystick);
+        joystick.j1_right(true);
+        joystick.j2_left(true);
+        joystick.j2_fire(true);
+        joystick.update_paddles();
+        joystick.j1_right(false);
+        joystick.update_paddles();
+        assert_eq!(joystick.input.swcha, 0xBF);
+        assert_eq!(joystick.input.input5, 0xFF);
+        assert_eq!(joystick.input.paddles[0], 0.52);
+        assert!(joystick.input.paddles[1] < 0.47);
+        assert_eq!(joystick.input.paddles[2], 0.5);
+    }
+}
@@ -1,6 +1,7 @@
 use super::audio::soundchannel;
 use super::clocks;
 use super::inputs;
+use super::state;
 
 pub trait ReadWriteMemory {
     fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8;
@@ -11,14 +12,16 @@ pub trait DebugClock {
     fn debug_clock(&mut self) -> clocks::ClockType;
 }
 
-pub trait StellaIO: ReadWriteMemory + DebugClock {
+pub trait StellaIO: ReadWriteMemory + DebugClock + state::Snapshot {
     fn export(&mut self) -> bool;
     fn generate_display(&mut self, buffer: &mut [u8]);
+    fn set_vertical_offset(&mut self, offset: i16);
     fn set_inputs(&mut self, inputs: inputs::Input);
     fn get_next_audio_chunk(&mut self, length: u32) -> Vec<soundchannel::PlaybackType>;
+    fn take_audio_samples(&mut self) -> Vec<soundchannel::PlaybackType>;
     fn step_tia_sound(&mut self, clock: &clocks::Clock);
 }
 
-pub trait RiotIO: ReadWriteMemory {
+pub trait RiotIO: ReadWriteMemory + state::Snapshot {
     fn set_inputs(&mut self, inputs: inputs::Input);
 }
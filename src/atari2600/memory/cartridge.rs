@@ -1,14 +1,14 @@
+use super::super::clocks;
+use super::super::state;
+use super::cartridges;
 use strum_macros::EnumIter;
 use strum_macros::EnumString;
 
-type BankSizeType = u16;
 type NumBanksType = u8;
 
-const BANK_SIZE: BankSizeType = 0x0400;
-const MAX_BANKS: NumBanksType = 8;
-
-#[derive(Debug, EnumIter, EnumString)]
+#[derive(Clone, Copy, Debug, PartialEq, EnumIter, EnumString)]
 pub enum CartridgeType {
+    Auto, // Detect the type from the ROM data (see 'detect_cartridge_type').
     Default,
     F4,
     F4SC,
@@ -16,8 +16,40 @@ pub enum CartridgeType {
     F6SC,
     F8,
     F8SC,
-    Cbs,
+    #[strum(serialize = "Cbs", serialize = "FA")]
+    Cbs, // CBS RAM Plus
     Super,
+    F0, // Dynacom Megaboy
+    EF,
+    EFSC,
+    DF,
+    DFSC,
+    BF,
+    BFSC,
+    UA, // UA Limited
+    #[strum(serialize = "C0840", serialize = "0840")]
+    C0840, // Econobanking
+    SB, // SuperBanking
+    X07, // AtariAge
+    #[strum(serialize = "C4A50", serialize = "4A50")]
+    C4A50,
+    E0,
+    E7, // M-Network
+    FE, // Activision
+    #[strum(serialize = "Dpc", serialize = "DPC")]
+    Dpc, // Pitfall II
+    #[strum(serialize = "DpcPlus", serialize = "DPC+")]
+    DpcPlus, // Harmony/Melody ARM
+    #[strum(serialize = "Cdf", serialize = "CDF", serialize = "CDFJ")]
+    Cdf, // Harmony/Melody ARM
+    #[strum(serialize = "Supercharger", serialize = "AR")]
+    Supercharger, // Starpath
+    #[strum(serialize = "C3F", serialize = "3F")]
+    C3F, // Tigervision
+    #[strum(serialize = "C3E", serialize = "3E")]
+    C3E,
+    #[strum(serialize = "C3EPlus", serialize = "3E+", serialize = "3EPLUS")]
+    C3EPlus,
 }
 
 #[derive(Clone)]
@@ -31,17 +63,72 @@ impl Bank {
     }
 }
 
-pub trait Cartridge {
-    fn load(&mut self) -> std::io::Result<()>;
+/// Identification common to all of the cartridge types.
+pub struct CartridgeInfo {
+    pub name: String,
+    pub rom_hash: String,
+    pub cartridge_type: CartridgeType,
+    pub detected: bool,
+}
+
+impl CartridgeInfo {
+    pub fn new(name: &str, data: &[u8], cartridge_type: CartridgeType, detected: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            rom_hash: format!("{:x}", md5::compute(data)),
+            cartridge_type,
+            detected,
+        }
+    }
+
+    pub fn summary(&self) {
+        println!("cartridge read: {}", self.name);
+        println!(" md5: {}", self.rom_hash);
+        println!(" type: {:?}{}", self.cartridge_type, if self.detected { " (detected)" } else { "" });
+    }
+}
+
+pub trait Cartridge: state::Snapshot {
+    fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8;
+    fn write(&mut self, clock: &clocks::Clock, address: u16, data: u8);
+
+    /// The byte 'read' would return from ROM (or cartridge RAM), without any side effects (bank switching, register
+    /// reads etc), for the debugger/trace.
+    fn peek(&self, address: u16) -> u8;
 
-    fn read(&mut self, address: u16) -> u8;
-    fn write(&mut self, address: u16, data: u8);
+    /// Called for every bus access (after 'read'/'write' for cartridge addresses), with the data read or
+    /// written.  For schemes that switch banks on accesses outside of the cartridge, ie TIA writes (3F) or the stack (FE).
+    fn observe_bus(&mut self, _address: u16, _data: u8, _write: bool) {}
+
+    /// 6507 cycles to hold the CPU for after a write (while the cartridge's ARM code runs), cleared by the call.
+    fn take_stall_cycles(&mut self) -> u64 {
+        0
+    }
 
     fn summary(&self);
+
+    fn info(&self) -> &CartridgeInfo;
+
+    /// MD5 of the raw cartridge data (as a hex string), used to identify the ROM.
+    fn hash(&self) -> String {
+        self.info().rom_hash.clone()
+    }
+
+    /// The actual type (never 'Auto').
+    fn cartridge_type(&self) -> CartridgeType {
+        self.info().cartridge_type
+    }
+
+    /// The selected bank (for resolving debug symbols), 4K banks numbered in ROM order (the 6507 program's banks for
+    /// DPC/CDF/DPC+).  0 for schemes without banks, and for the schemes switching smaller slices (E0, E7, 3F, 3E,
+    /// 3E+, 4A50 and the Supercharger) which have no single bank, so their symbols resolve against the first 4K.
+    fn bank(&self) -> usize {
+        0
+    }
 }
 
 pub struct GenericCartridge {
-    filename: String,
+    info: CartridgeInfo,
     pub num_banks: NumBanksType,
     cartridge_banks: Vec<Bank>,
 
@@ -63,9 +150,9 @@ pub struct GenericCartridge {
 }
 
 impl GenericCartridge {
-    pub fn new(filename: &str, max_banks: u8, current_bank: u8, bank_size: u16, hot_swap: u16, ram_size: u16) -> Self {
+    pub fn new(name: &str, max_banks: u8, current_bank: u8, bank_size: u16, hot_swap: u16, ram_size: u16) -> Self {
         Self {
-            filename: filename.to_string(),
+            info: CartridgeInfo::new(name, &[], CartridgeType::Default, false),
             cartridge_banks: Vec::new(),
             ram: vec![0; ram_size as usize],
             bank_size,
@@ -79,38 +166,53 @@ impl GenericCartridge {
         }
     }
 
-    pub fn load(&mut self) -> std::io::Result<()> {
-        let mut buffer = Vec::new();
-
-        #[cfg(not(target_os = "emscripten"))]
-        { 
-            use std::fs::File;
-            use std::io::Read;
-
-            let f = File::open(&self.filename);
-            match f {
-                Ok(mut file) => {
-                    file.read_to_end(&mut buffer)?;
-                }
-                Err(e) => return Err(e),
-            }
-        }
-
-        #[cfg(target_os = "emscripten")]
-        {
-            JAVASCRIPT_DATA_STORE.with(|ref_cell_data| { buffer = ref_cell_data.borrow().raw_cart_data.clone();});
-        }
+    /// Create a cartridge (of the type in 'info'), loaded from the raw ROM data.
+    pub fn from_data(info: CartridgeInfo, data: &[u8]) -> Self {
+        let name = &info.name;
+        const NO_RAM: u16 = 0x0000;
+        const RAM_128_BYTES: u16 = 0x0080;
+        const RAM_256_BYTES: u16 = 0x0100;
+        let mut cartridge = match info.cartridge_type {
+            // name,  max_banks (4K banks), bank_size, hot_swap, ram_size
+            // 'hot_swap' values is the 'upper' value, generally, subsequent banks are selected via 'value - 1'.
+            // TODO: Confirm initial/starting bank for each type.
+            CartridgeType::Default => GenericCartridge::new(name, 8, 0, 0x1000, 0xFF9, NO_RAM),
+            CartridgeType::F4 => GenericCartridge::new(name, 8, 0, 0x1000, 0xFFB, NO_RAM),
+            CartridgeType::F4SC => GenericCartridge::new(name, 8, 0, 0x1000, 0xFFB, RAM_128_BYTES),
+
+            CartridgeType::F8 => GenericCartridge::new(name, 2, 1, 0x1000, 0xFF9, NO_RAM),
+            CartridgeType::F8SC => GenericCartridge::new(name, 2, 1, 0x1000, 0xFF9, RAM_128_BYTES),
+
+            CartridgeType::F6 => GenericCartridge::new(name, 4, 0, 0x1000, 0xFF9, NO_RAM),
+            CartridgeType::F6SC => GenericCartridge::new(name, 4, 0, 0x1000, 0xFF9, RAM_128_BYTES),
+
+            CartridgeType::Cbs => GenericCartridge::new(name, 3, 0, 0x1000, 0xFFA, RAM_256_BYTES),
+            CartridgeType::Super => GenericCartridge::new(name, 4, 0, 0x1000, 0xFF9, NO_RAM),
+
+            CartridgeType::EF => GenericCartridge::new(name, 16, 0, 0x1000, 0xFEF, NO_RAM),
+            CartridgeType::EFSC => GenericCartridge::new(name, 16, 0, 0x1000, 0xFEF, RAM_128_BYTES),
+            CartridgeType::DF => GenericCartridge::new(name, 32, 0, 0x1000, 0xFDF, NO_RAM),
+            CartridgeType::DFSC => GenericCartridge::new(name, 32, 0, 0x1000, 0xFDF, RAM_128_BYTES),
+            CartridgeType::BF => GenericCartridge::new(name, 64, 0, 0x1000, 0xFBF, NO_RAM),
+            CartridgeType::BFSC => GenericCartridge::new(name, 64, 0, 0x1000, 0xFBF, RAM_128_BYTES),
+
+            _ => panic!("{:?} isn't a generic cartridge type", info.cartridge_type),
+        };
+        cartridge.info = info;
+        cartridge.load_data(data);
+        cartridge
+    }
 
+    /// Load the banks from the raw ROM data.
+    pub fn load_data(&mut self, data: &[u8]) {
+        let mut buffer = data.to_vec();
+        self.info.rom_hash = format!("{:x}", md5::compute(&buffer));
         self.load_banks(&mut buffer);
-        self.summary();
-
-        Ok(())
     }
 
     fn load_banks(&mut self, source: &mut Vec<u8>) {
-        let total_bytes_read = 0;
 
-        for i in 0..self.max_banks {
+        for _ in 0..self.max_banks {
             if let (Some(bank), _n) = self.load_bank(source) {
                 // Grow the banks as they are read.
                 self.cartridge_banks.push(bank);
@@ -133,19 +235,19 @@ impl GenericCartridge {
             0 => (None, 0),
             n if 2048 == n && 0 == self.num_banks => {
                 println!("Assuming this to be a '2k' cartridge with no bank switching.");
-                bank.data = source[0..self.bank_size as usize].try_into().unwrap();
+                bank.data = source[0..self.bank_size as usize].into();
                 self.bank_size = n as u16;
                 (Some(bank), n as NumBanksType)
             }
             n if n < bank.data.len() => {
-                bank.data = source[0..n as usize].try_into().unwrap();
+                bank.data = source[0..n].into();
                 source.drain(0..n);
                 self.bank_size = bank.data.len() as u16;
                 println!("Bank incomplete ({} bytes found in last bank), will be padded with zeros", n);
                 (Some(bank), n as NumBanksType)
             }
-            n => {
-                bank.data = source[0..self.bank_size as usize].try_into().unwrap();
+            _ => {
+                bank.data = source[0..self.bank_size as usize].into();
                 source.drain(0..self.bank_size as usize);
                 self.bank_size = bank.data.len() as u16;
                 (Some(bank), self.bank_size as NumBanksType)
@@ -153,6 +255,17 @@ impl GenericCartridge {
         }
     }
 
+    fn peek(&self, address: u16) -> u8 {
+        let address = address & (self.bank_size - 1);
+        if (self.ram_size > 0) && (address < 2 * self.ram_size) && (address >= self.ram_size) {
+            self.ram[(address & self.ram_addr_mask) as usize]
+        } else if !self.cartridge_banks.is_empty() {
+            self.cartridge_banks[self.current_bank as usize].data[address as usize]
+        } else {
+            0
+        }
+    }
+
     fn read(&mut self, address: u16) -> u8 {
         // Mask the 'address' with the bank size (so the highest address lines are ignored).
         let address = address & (self.bank_size - 1);
@@ -187,98 +300,324 @@ impl GenericCartridge {
 }
 
 impl Cartridge for GenericCartridge {
-    fn load(&mut self) -> std::io::Result<()> {
-        self.load()
-    }
-
     fn summary(&self) {
-        println!("cartridge read: {}", self.filename);
+        self.info.summary();
         println!(" num banks: {}", self.num_banks);
-        if self.cartridge_banks.len() > 0 {
+        if !self.cartridge_banks.is_empty() {
             println!(" bank size = {}", self.cartridge_banks[0].data.len());
         }
     }
 
-    fn read(&mut self, address: u16) -> u8 {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
         self.read(address)
     }
 
-    fn write(&mut self, address: u16, data: u8) {
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
         self.write(address, data);
     }
-}
 
-struct JavaScriptData {
-    pub raw_cart_data: Vec<u8>,
+    fn info(&self) -> &CartridgeInfo {
+        &self.info
+    }
+
+    fn bank(&self) -> usize {
+        self.current_bank as usize
+    }
 }
-impl JavaScriptData {
-    pub fn new() -> Self {
-        Self {
-            raw_cart_data: Vec::new(),
+
+impl state::Snapshot for GenericCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.current_bank);
+        state.put_u8(self.bank_select);
+        state.put_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let current_bank = state.get_u8()?;
+        if current_bank as usize >= self.cartridge_banks.len().max(1) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid cartridge bank {}", current_bank)));
         }
+        self.current_bank = current_bank;
+        self.bank_select = state.get_u8()?;
+        state.get_bytes_into(&mut self.ram)
     }
 }
 
-use std::cell::RefCell;
+// Absolute addressing op codes that access memory, used when scanning for bank switching/RAM accesses.
+const ABSOLUTE_READS: [u8; 7] = [0xAD, 0xAE, 0xAC, 0x2C, 0xCD, 0x0C, 0xBD]; // LDA, LDX, LDY, BIT, CMP, NOP, LDA abs,X
+const ABSOLUTE_WRITES: [u8; 5] = [0x8D, 0x8E, 0x8C, 0x9D, 0x99]; // STA, STX, STY, STA abs,X, STA abs,Y
+
+// Find absolute accesses (any of 'op_codes') to the cartridge address space, returns the addresses (12 bit, cartridge relative).
+fn scan_cartridge_accesses(data: &[u8], op_codes: &[u8]) -> Vec<u16> {
+    data.windows(3)
+        .filter(|w| op_codes.contains(&w[0]))
+        .map(|w| u16::from_le_bytes([w[1], w[2]]))
+        .filter(|address| 0 != address & 0x1000)
+        .map(|address| address & 0xFFF)
+        .collect()
+}
 
-thread_local! {
-    static JAVASCRIPT_DATA_STORE: RefCell<JavaScriptData> = RefCell::new(JavaScriptData::new());
+/// Number of banks implied by the bank switching hotspots accessed in the code (the lowest hotspot
+/// used, ie $1FF4 for F4, $1FF6 for F6, $1FF8 for F8).  'None' if no hotspots are accessed.
+fn hotspot_banks(data: &[u8]) -> Option<u8> {
+    let lowest = scan_cartridge_accesses(data, &[&ABSOLUTE_READS[..], &ABSOLUTE_WRITES[..]].concat())
+        .into_iter()
+        .filter(|address| (0xFF4..=0xFF9).contains(address))
+        .min()?;
+    Some(match lowest {
+        0xFF4 | 0xFF5 => 8,
+        0xFF6 | 0xFF7 => 4,
+        _ => 2,
+    })
 }
 
-pub fn is_cart_ready() -> bool {
-    let mut is_ready = false;
-    JAVASCRIPT_DATA_STORE.with(|ref_cell_data| { is_ready = !ref_cell_data.borrow().raw_cart_data.is_empty();});
-    is_ready
+/// Parker Brothers 'E0' slice hotspots ($1FE0-$1FF7) are accessed, but not the F8 hotspots.
+fn has_e0_hotspots(data: &[u8]) -> bool {
+    let accesses = scan_cartridge_accesses(data, &[&ABSOLUTE_READS[..], &ABSOLUTE_WRITES[..]].concat());
+    accesses.iter().any(|address| (0xFE0..=0xFF7).contains(address)) && !accesses.iter().any(|address| (0xFF8..=0xFF9).contains(address))
 }
 
-#[no_mangle]
-pub extern fn display_data(raw_data_ptr: *const u8, raw_data_length: usize) {
-    // TODO: Although it's possible there's another way (alternate arguments), I'll just use the unsafe option for now.
-    let v = unsafe {std::slice::from_raw_parts(raw_data_ptr, raw_data_length)};
-    println!("Called from javascript {:x} {}", v[0], v.len());
+/// M-Network 'E7' ROM bank/RAM hotspots ($1FE0-$1FEB) are accessed, but not the F6/F8 hotspots.
+fn has_e7_hotspots(data: &[u8]) -> bool {
+    let accesses = scan_cartridge_accesses(data, &[&ABSOLUTE_READS[..], &ABSOLUTE_WRITES[..]].concat());
+    accesses.iter().any(|address| (0xFE0..=0xFEB).contains(address)) && !accesses.iter().any(|address| (0xFF6..=0xFF9).contains(address))
+}
 
-    JAVASCRIPT_DATA_STORE.with(|ref_cell_data| { ref_cell_data.borrow_mut().raw_cart_data = v.to_vec()});
+/// Activision 'FE' code calls subroutines in the $Dxxx bank (there are no hotspots to access).
+fn has_fe_calls(data: &[u8]) -> bool {
+    data.windows(3).any(|w| 0x20 == w[0] && (0xD0..=0xDF).contains(&w[2])) && hotspot_banks(data).is_none()
 }
 
+/// Accesses to the hotspots below $1000 (ie UA $0220/$0240), which aren't in the cartridge address space.
+fn has_low_hotspots(data: &[u8], hotspots: &[u16]) -> bool {
+    let op_codes = [&ABSOLUTE_READS[..], &ABSOLUTE_WRITES[..]].concat();
+    data.windows(3).any(|w| op_codes.contains(&w[0]) && hotspots.contains(&u16::from_le_bytes([w[1], w[2]])))
+}
 
-pub fn get_new_carterage(filename: &String, cartridge_type: &CartridgeType) -> Box<GenericCartridge> {
-    const NO_RAM: u16 = 0x0000;
-    const RAM_128_BYTES: u16 = 0x0080;
-    const RAM_256_BYTES: u16 = 0x0100;
-    let mut new_cartridge = match cartridge_type {
-        // filename,  max_banks (4K banks), bank_size, hot_swap, ram_size
-        // 'hot_swap' values is the 'upper' value, generally, subsequent banks are selected via 'value - 1'.
-        // TODO: Confirm initial/starting bank for each type.
-        CartridgeType::Default => Box::new(GenericCartridge::new(&filename, 8, 0, 0x1000, 0xFF9, NO_RAM)),
-        CartridgeType::F4 => Box::new(GenericCartridge::new(&filename, 8, 0, 0x1000, 0xFFB, NO_RAM)),
-        CartridgeType::F4SC => Box::new(GenericCartridge::new(&filename, 8, 0, 0x1000, 0xFFB, RAM_128_BYTES)),
+/// SuperBanking selects the bank via $0800-$0FFF (ie 'LDA $0800,X').
+fn has_sb_hotspots(data: &[u8]) -> bool {
+    data.windows(3).any(|w| ABSOLUTE_READS.contains(&w[0]) && 0x08 == w[2] & 0xF8)
+}
 
-        CartridgeType::F8 => Box::new(GenericCartridge::new(&filename, 2, 1, 0x1000, 0xFF9, NO_RAM)),
-        CartridgeType::F8SC => Box::new(GenericCartridge::new(&filename, 2, 1, 0x1000, 0xFF9, RAM_128_BYTES)),
+/// Superchip cartridges have 128 bytes of RAM, written via $1000-$107F.
+fn has_superchip_writes(data: &[u8]) -> bool {
+    scan_cartridge_accesses(data, &ABSOLUTE_WRITES).iter().any(|address| *address < 0x080)
+}
 
-        CartridgeType::F6 => Box::new(GenericCartridge::new(&filename, 4, 0, 0x1000, 0xFF9, NO_RAM)),
-        CartridgeType::F6SC => Box::new(GenericCartridge::new(&filename, 4, 0, 0x1000, 0xFF9, RAM_128_BYTES)),
+/// Number of zero page stores ('STA $xx') to the address, for the Tigervision style schemes.
+fn count_zero_page_writes(data: &[u8], address: u8) -> usize {
+    data.windows(2).filter(|w| 0x85 == w[0] && address == w[1]).count()
+}
 
-        CartridgeType::Cbs => Box::new(GenericCartridge::new(&filename, 3, 0, 0x1000, 0xFFA, RAM_256_BYTES)),
-        CartridgeType::Super => Box::new(GenericCartridge::new(&filename, 4, 0, 0x1000, 0xFF9, NO_RAM)),
-    };
+/// Tigervision style schemes (switched by TIA writes), 3E+ ROMs carry a 'TJ3E' signature.
+fn detect_tigervision(data: &[u8]) -> Option<CartridgeType> {
+    if data.windows(4).any(|w| w == b"TJ3E") {
+        Some(CartridgeType::C3EPlus)
+    } else if count_zero_page_writes(data, 0x3E) > 0 && count_zero_page_writes(data, 0x3F) > 0 {
+        Some(CartridgeType::C3E)
+    } else if count_zero_page_writes(data, 0x3F) > 1 {
+        Some(CartridgeType::C3F)
+    } else {
+        None
+    }
+}
 
-    // Load the cartridge.
-    match new_cartridge.load() {
-        Ok(()) => {
-            println!("Ok");
-        }
-        Err(e) => {
-            panic!("Error loading cartridge \"{}\".\n {}", filename, e);
+/// Guess the cartridge type from the ROM data.
+/// The size selects the bank switching family, falling back to the bank switching hotspots the code
+/// accesses (ie 'LDA $1FF8') for non-standard sizes (or different schemes of the same size, ie E0).
+/// Writes to $1000-$107F indicate Superchip RAM, stores to TIA $3E/$3F indicate 3E/3F.
+/// The ARM cartridges (DPC+, CDF/CDFJ) carry the driver's signature.
+/// 4A50 and X07 can't be told apart from EF by their size, so need to be given explicitly.
+pub fn detect_cartridge_type(data: &[u8]) -> CartridgeType {
+    if cartridges::supercharger::is_wav(data) || (!data.is_empty() && data.len().is_multiple_of(cartridges::tape::LOAD_SIZE)) {
+        return CartridgeType::Supercharger;
+    }
+
+    if data.windows(4).filter(|w| w == b"DPC+").count() >= 2 {
+        return CartridgeType::DpcPlus;
+    }
+    if data.windows(4).any(|w| w == b"CDFJ") || data.windows(3).filter(|w| w == b"CDF").count() >= 3 {
+        return CartridgeType::Cdf;
+    }
+
+    if data.len() > 0x1000 {
+        if let Some(cartridge_type) = detect_tigervision(data) {
+            return cartridge_type;
         }
     }
 
-    new_cartridge
+    let superchip = has_superchip_writes(data);
+    let banks = match data.len() {
+        0..=0x1000 => 1,
+        0x2000 if has_e0_hotspots(data) => return CartridgeType::E0,
+        0x2000 if has_fe_calls(data) => return CartridgeType::FE,
+        0x2000 if has_low_hotspots(data, &[0x0220, 0x0240]) => return CartridgeType::UA,
+        0x2000 if has_low_hotspots(data, &[0x0800, 0x0840]) => return CartridgeType::C0840,
+        0x2000 => 2,
+        0x2800 | 0x28FF => return CartridgeType::Dpc, // 8K program, 2K display data (and optionally 255 bytes of unused data)
+        0x3000 => return CartridgeType::Cbs,
+        0x4000 if has_e7_hotspots(data) => return CartridgeType::E7,
+        0x4000 => 4,
+        0x8000 => 8,
+        0x10000 if scan_cartridge_accesses(data, &ABSOLUTE_READS).contains(&0xFF0) => return CartridgeType::F0,
+        0x10000 => 16,
+        0x20000 | 0x40000 if has_sb_hotspots(data) => return CartridgeType::SB,
+        0x20000 => 32,
+        0x40000 => 64,
+        _ => hotspot_banks(data).unwrap_or(1),
+    };
+
+    match (banks, superchip) {
+        (2, false) => CartridgeType::F8,
+        (2, true) => CartridgeType::F8SC,
+        (4, false) => CartridgeType::F6,
+        (4, true) => CartridgeType::F6SC,
+        (8, false) => CartridgeType::F4,
+        (8, true) => CartridgeType::F4SC,
+        (16, false) => CartridgeType::EF,
+        (16, true) => CartridgeType::EFSC,
+        (32, false) => CartridgeType::DF,
+        (32, true) => CartridgeType::DFSC,
+        (64, false) => CartridgeType::BF,
+        (64, true) => CartridgeType::BFSC,
+        _ => CartridgeType::Default,
+    }
+}
+
+/// Create and load a cartridge of the given type from the raw ROM data, 'name' is only used for reporting.
+/// 'CartridgeType::Auto' detects the type from the data.
+pub fn get_new_carterage(name: &str, data: &[u8], cartridge_type: &CartridgeType) -> Box<dyn Cartridge> {
+    let (cartridge_type, detected) = match cartridge_type {
+        CartridgeType::Auto => (detect_cartridge_type(data), true),
+        _ => (*cartridge_type, false),
+    };
+    let info = CartridgeInfo::new(name, data, cartridge_type, detected);
+
+    let cartridge: Box<dyn Cartridge> = match cartridge_type {
+        CartridgeType::E0 => Box::new(cartridges::e0::E0Cartridge::new(info, data)),
+        CartridgeType::Supercharger => Box::new(cartridges::supercharger::SuperchargerCartridge::new(info, data)),
+        CartridgeType::Dpc => Box::new(cartridges::dpc::DpcCartridge::new(info, data)),
+        CartridgeType::DpcPlus => Box::new(cartridges::dpcplus::DpcPlusCartridge::new(info, data)),
+        CartridgeType::Cdf => Box::new(cartridges::cdf::CdfCartridge::new(info, data)),
+        CartridgeType::FE => Box::new(cartridges::fe::FECartridge::new(info, data)),
+        CartridgeType::E7 => Box::new(cartridges::e7::E7Cartridge::new(info, data)),
+        CartridgeType::C3F | CartridgeType::C3E => Box::new(cartridges::c3f::C3FCartridge::new(info, data)),
+        CartridgeType::C3EPlus => Box::new(cartridges::c3f::C3EPlusCartridge::new(info, data)),
+        CartridgeType::C4A50 => Box::new(cartridges::c4a50::C4A50Cartridge::new(info, data)),
+        CartridgeType::F0 => Box::new(cartridges::hotspots::HotspotCartridge::new(info, data, cartridges::hotspots::Scheme::F0)),
+        CartridgeType::UA => Box::new(cartridges::hotspots::HotspotCartridge::new(info, data, cartridges::hotspots::Scheme::UA)),
+        CartridgeType::C0840 => Box::new(cartridges::hotspots::HotspotCartridge::new(info, data, cartridges::hotspots::Scheme::C0840)),
+        CartridgeType::SB => Box::new(cartridges::hotspots::HotspotCartridge::new(info, data, cartridges::hotspots::Scheme::SB)),
+        CartridgeType::X07 => Box::new(cartridges::hotspots::HotspotCartridge::new(info, data, cartridges::hotspots::Scheme::X07)),
+        _ => Box::new(GenericCartridge::from_data(info, data)),
+    };
+    cartridge.summary();
+    cartridge
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::atari2600::memory::cartridge::GenericCartridge;
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{detect_cartridge_type, get_new_carterage, CartridgeType, GenericCartridge};
+    #[test]
+    fn test_cartridge_from_data() {
+        // 8K, with the first byte of each bank set to the bank number.
+        let mut data = vec![0_u8; 0x2000];
+        data[0x1000] = 1;
+
+        let mut cartridge = get_new_carterage("dummy", &data, &CartridgeType::F8);
+        let clock = Clock::new();
+        assert_eq!(cartridge.read(&clock, 0x000), 1); // F8 starts in the last bank.
+        cartridge.read(&clock, 0xFF8);
+        assert_eq!(cartridge.read(&clock, 0x000), 0);
+        assert_eq!(cartridge.hash(), format!("{:x}", md5::compute(&data)));
+    }
+
+    #[test]
+    fn test_detect_cartridge_type() {
+        let mut data = vec![0xEA_u8; 0x1000];
+        assert_eq!(detect_cartridge_type(&data), CartridgeType::Default);
+        assert_eq!(detect_cartridge_type(&data[..0x800]), CartridgeType::Default);
+
+        data.resize(0x2000, 0xEA);
+        data[0x100..0x103].copy_from_slice(&[0xAD, 0xF8, 0x1F]); // LDA $1FF8
+        assert_eq!(detect_cartridge_type(&data), CartridgeType::F8);
+
+        data[0x200..0x203].copy_from_slice(&[0x8D, 0x10, 0xF0]); // STA $F010 (Superchip RAM)
+        assert_eq!(detect_cartridge_type(&data), CartridgeType::F8SC);
+
+        let mut e0 = vec![0xEA_u8; 0x2000];
+        e0[0x100..0x103].copy_from_slice(&[0xAD, 0xE5, 0xFF]); // LDA $FFE5
+        assert_eq!(detect_cartridge_type(&e0), CartridgeType::E0);
+
+        assert_eq!(detect_cartridge_type(&vec![0xEA_u8; 0x28FF]), CartridgeType::Dpc);
+        assert_eq!(detect_cartridge_type(&vec![0xEA_u8; 2 * 8448]), CartridgeType::Supercharger);
+
+        let mut fe = vec![0xEA_u8; 0x2000];
+        fe[0x100..0x103].copy_from_slice(&[0x20, 0x00, 0xD0]); // JSR $D000
+        assert_eq!(detect_cartridge_type(&fe), CartridgeType::FE);
+
+        let mut tigervision = vec![0xEA_u8; 0x2000];
+        tigervision[0x100..0x102].copy_from_slice(&[0x85, 0x3F]); // STA $3F
+        tigervision[0x200..0x202].copy_from_slice(&[0x85, 0x3F]);
+        assert_eq!(detect_cartridge_type(&tigervision), CartridgeType::C3F);
+        tigervision[0x300..0x302].copy_from_slice(&[0x85, 0x3E]); // STA $3E
+        assert_eq!(detect_cartridge_type(&tigervision), CartridgeType::C3E);
+        tigervision[0x400..0x404].copy_from_slice(b"TJ3E");
+        assert_eq!(detect_cartridge_type(&tigervision), CartridgeType::C3EPlus);
+        assert_eq!("3E+".parse::<CartridgeType>(), Ok(CartridgeType::C3EPlus));
+        assert_eq!("DPC".parse::<CartridgeType>(), Ok(CartridgeType::Dpc));
+        assert_eq!("DPC+".parse::<CartridgeType>(), Ok(CartridgeType::DpcPlus));
+        assert_eq!("CDFJ".parse::<CartridgeType>(), Ok(CartridgeType::Cdf));
+
+        let mut arm = vec![0_u8; 0x8000];
+        arm[0x20..0x24].copy_from_slice(b"CDFJ");
+        assert_eq!(detect_cartridge_type(&arm), CartridgeType::Cdf);
+        arm[0x20..0x24].copy_from_slice(b"DPC+");
+        arm[0x7FF0..0x7FF4].copy_from_slice(b"DPC+");
+        assert_eq!(detect_cartridge_type(&arm), CartridgeType::DpcPlus);
+
+        data.resize(0x4000, 0xEA);
+        assert_eq!(detect_cartridge_type(&data), CartridgeType::F6SC);
+
+        let mut e7 = vec![0xEA_u8; 0x4000];
+        e7[0x100..0x103].copy_from_slice(&[0xAD, 0xE7, 0xFF]); // LDA $FFE7
+        assert_eq!(detect_cartridge_type(&e7), CartridgeType::E7);
+        data.resize(0x8000, 0xEA);
+        assert_eq!(detect_cartridge_type(&data), CartridgeType::F4SC);
+
+        // Non-standard size, use the hotspots.
+        let mut data = vec![0xEA_u8; 0x4800];
+        data[0x100..0x103].copy_from_slice(&[0x2C, 0xF6, 0xFF]); // BIT $FFF6
+        assert_eq!(detect_cartridge_type(&data), CartridgeType::F6);
+
+        let cartridge = get_new_carterage("dummy", &data[..0x3000], &CartridgeType::Auto);
+        assert_eq!(cartridge.cartridge_type(), CartridgeType::Cbs);
+        assert_eq!("FA".parse::<CartridgeType>(), Ok(CartridgeType::Cbs));
+
+        let mut ua = vec![0xEA_u8; 0x2000];
+        ua[0x100..0x103].copy_from_slice(&[0xAD, 0x40, 0x02]); // LDA $0240
+        assert_eq!(detect_cartridge_type(&ua), CartridgeType::UA);
+        ua[0x100..0x103].copy_from_slice(&[0x2C, 0x40, 0x08]); // BIT $0840
+        assert_eq!(detect_cartridge_type(&ua), CartridgeType::C0840);
+
+        let mut large = vec![0xEA_u8; 0x10000];
+        assert_eq!(detect_cartridge_type(&large), CartridgeType::EF);
+        large[0x100..0x103].copy_from_slice(&[0xAD, 0xF0, 0xFF]); // LDA $FFF0
+        assert_eq!(detect_cartridge_type(&large), CartridgeType::F0);
+        large.resize(0x20000, 0xEA);
+        large[0x100..0x103].copy_from_slice(&[0x8D, 0x00, 0xF0]); // STA $F000 (Superchip RAM)
+        assert_eq!(detect_cartridge_type(&large), CartridgeType::DFSC);
+        large.resize(0x40000, 0xEA);
+        assert_eq!(detect_cartridge_type(&large), CartridgeType::BFSC);
+        large[0x200..0x203].copy_from_slice(&[0xBD, 0x00, 0x08]); // LDA $0800,X
+        assert_eq!(detect_cartridge_type(&large), CartridgeType::SB);
+    }
+
     #[test]
     fn test_simple_generic_cartridge() {
         let mut sample_cartridge = GenericCartridge::new("dummy", 3, 0, 0x200, 0xF9, 40);
@@ -292,4 +631,25 @@ mod tests {
         assert_eq!(sample_cartridge.cartridge_banks.len(), 3);
         assert_eq!(sample_cartridge.cartridge_banks[0].data.len(), 0x200);
     }
+
+    #[test]
+    fn test_large_generic_cartridges() {
+        let clock = Clock::new();
+        // (type, banks, lowest hotspot), each 4K bank filled with its bank number.
+        for (cartridge_type, banks, hotspot) in [(CartridgeType::EF, 16, 0xFE0), (CartridgeType::DFSC, 32, 0xFC0), (CartridgeType::BF, 64, 0xF80)] {
+            let data: Vec<u8> = (0..banks as usize * 0x1000).map(|i| (i / 0x1000) as u8).collect();
+            let mut cartridge = get_new_carterage("dummy", &data, &cartridge_type);
+            assert_eq!(cartridge.read(&clock, 0x200), 0);
+            cartridge.read(&clock, hotspot + banks - 1);
+            assert_eq!(cartridge.read(&clock, 0x200), banks as u8 - 1);
+            cartridge.write(&clock, hotspot + 5, 0);
+            assert_eq!(cartridge.read(&clock, 0x200), 5);
+        }
+
+        // DFSC RAM, written via $1000-$107F, read via $1080-$10FF.
+        let data = vec![0_u8; 32 * 0x1000];
+        let mut cartridge = get_new_carterage("dummy", &data, &CartridgeType::DFSC);
+        cartridge.write(&clock, 0x010, 0x5A);
+        assert_eq!(cartridge.read(&clock, 0x090), 0x5A);
+    }
 }
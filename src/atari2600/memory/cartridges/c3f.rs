@@ -0,0 +1,319 @@
+//! Tigervision '3F' bank switching and the extended '3E'/'3E+' schemes.
+//!
+//! These are switched by writes to the TIA address range ($00-$3F), seen through 'observe_bus'.
+//!
+//! 3F: 2K banks, the lower slice ($1000-$17FF) is selected by writing the bank number to
+//!     $00-$3F, the upper slice ($1800-$1FFF) is fixed to the last bank.
+//!
+//! 3E: As 3F, but the ROM bank is only selected via $3F. Writing to $3E maps in a 1K RAM bank
+//!     (of 32) to the lower slice instead, read at $1000-$13FF and written at $1400-$17FF.
+//!
+//! 3E+: Four 1K slices, all switchable.  The top 2 bits of the value select the slice, the low
+//!      6 bits the bank. $3F selects a 1K ROM bank, $3E a 512 byte RAM bank (of 64), read at the
+//!      lower half of the slice and written at the upper half.  ROM bank 0 is in all slices at start up.
+
+use super::super::super::clocks;
+use super::super::super::state;
+use super::super::cartridge;
+
+// Stella generally returns the 'last' data bus value for reads from a RAM write port, zero will do.
+const WRITE_PORT_READ: u8 = 0;
+
+pub struct C3FCartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_bank: u8,
+    ram_bank: Option<u8>, // RAM mapped to the lower slice (3E only), instead of the ROM bank.
+}
+
+impl C3FCartridge {
+    const BANK_SIZE: usize = 0x800;
+    const RAM_BANK_SIZE: usize = 0x400;
+    const RAM_BANKS: usize = 32;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let mut rom = data.to_vec();
+        let banks = std::cmp::max(1, rom.len().div_ceil(C3FCartridge::BANK_SIZE));
+        rom.resize(banks * C3FCartridge::BANK_SIZE, 0);
+
+        let ram = if cartridge::CartridgeType::C3E == info.cartridge_type { vec![0; C3FCartridge::RAM_BANKS * C3FCartridge::RAM_BANK_SIZE] } else { Vec::new() };
+        Self { info, rom, ram, rom_bank: 0, ram_bank: None }
+    }
+
+    fn num_banks(&self) -> usize {
+        self.rom.len() / C3FCartridge::BANK_SIZE
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        let address = address as usize & 0xFFF;
+        if address >= C3FCartridge::BANK_SIZE {
+            return self.rom[(self.num_banks() - 1) * C3FCartridge::BANK_SIZE + address - C3FCartridge::BANK_SIZE];
+        }
+
+        match self.ram_bank {
+            Some(bank) if address < C3FCartridge::RAM_BANK_SIZE => self.ram[bank as usize * C3FCartridge::RAM_BANK_SIZE + address],
+            Some(_) => WRITE_PORT_READ,
+            None => self.rom[self.rom_bank as usize * C3FCartridge::BANK_SIZE + address],
+        }
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        let address = address as usize & 0xFFF;
+        if let Some(bank) = self.ram_bank {
+            if (C3FCartridge::RAM_BANK_SIZE..C3FCartridge::BANK_SIZE).contains(&address) {
+                self.ram[bank as usize * C3FCartridge::RAM_BANK_SIZE + address - C3FCartridge::RAM_BANK_SIZE] = data;
+            }
+        }
+    }
+
+    pub fn snoop_write(&mut self, address: u16, data: u8) {
+        let address = address & 0x1FFF;
+        match self.info.cartridge_type {
+            cartridge::CartridgeType::C3E if 0x3F == address => {
+                self.rom_bank = (data as usize % self.num_banks()) as u8;
+                self.ram_bank = None;
+            }
+            cartridge::CartridgeType::C3E if 0x3E == address => {
+                self.ram_bank = Some((data as usize % C3FCartridge::RAM_BANKS) as u8);
+            }
+            cartridge::CartridgeType::C3F if address <= 0x3F => {
+                self.rom_bank = (data as usize % self.num_banks()) as u8;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl cartridge::Cartridge for C3FCartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn observe_bus(&mut self, address: u16, data: u8, write: bool) {
+        if write {
+            self.snoop_write(address, data);
+        }
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" num banks: {} (2K)", self.num_banks());
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for C3FCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.rom_bank);
+        state.put_bool(self.ram_bank.is_some());
+        state.put_u8(self.ram_bank.unwrap_or(0));
+        state.put_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let rom_bank = state.get_u8()?;
+        let is_ram = state.get_bool()?;
+        let ram_bank = state.get_u8()?;
+        if rom_bank as usize >= self.num_banks() || ram_bank as usize >= C3FCartridge::RAM_BANKS {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid 3F/3E banks {} {}", rom_bank, ram_bank)));
+        }
+        self.rom_bank = rom_bank;
+        self.ram_bank = if is_ram { Some(ram_bank) } else { None };
+        state.get_bytes_into(&mut self.ram)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Slice {
+    Rom(u8),
+    Ram(u8),
+}
+
+pub struct C3EPlusCartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    slices: [Slice; 4],
+}
+
+impl C3EPlusCartridge {
+    const SLICE_SIZE: usize = 0x400;
+    const RAM_BANK_SIZE: usize = 0x200;
+    const RAM_BANKS: usize = 64;
+    const BANK_MASK: u8 = 0x3F;
+    const SLICE_SHIFT: u8 = 6;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let mut rom = data.to_vec();
+        let banks = std::cmp::max(1, rom.len().div_ceil(C3EPlusCartridge::SLICE_SIZE));
+        rom.resize(banks * C3EPlusCartridge::SLICE_SIZE, 0);
+
+        Self { info, rom, ram: vec![0; C3EPlusCartridge::RAM_BANKS * C3EPlusCartridge::RAM_BANK_SIZE], slices: [Slice::Rom(0); 4] }
+    }
+
+    fn num_banks(&self) -> usize {
+        self.rom.len() / C3EPlusCartridge::SLICE_SIZE
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        let address = address as usize & 0xFFF;
+        let offset = address % C3EPlusCartridge::SLICE_SIZE;
+        match self.slices[address / C3EPlusCartridge::SLICE_SIZE] {
+            Slice::Rom(bank) => self.rom[bank as usize * C3EPlusCartridge::SLICE_SIZE + offset],
+            Slice::Ram(bank) if offset < C3EPlusCartridge::RAM_BANK_SIZE => self.ram[bank as usize * C3EPlusCartridge::RAM_BANK_SIZE + offset],
+            Slice::Ram(_) => WRITE_PORT_READ,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        let address = address as usize & 0xFFF;
+        let offset = address % C3EPlusCartridge::SLICE_SIZE;
+        if let Slice::Ram(bank) = self.slices[address / C3EPlusCartridge::SLICE_SIZE] {
+            if offset >= C3EPlusCartridge::RAM_BANK_SIZE {
+                self.ram[bank as usize * C3EPlusCartridge::RAM_BANK_SIZE + offset - C3EPlusCartridge::RAM_BANK_SIZE] = data;
+            }
+        }
+    }
+
+    pub fn snoop_write(&mut self, address: u16, data: u8) {
+        let slice = (data >> C3EPlusCartridge::SLICE_SHIFT) as usize;
+        let bank = data & C3EPlusCartridge::BANK_MASK;
+        match address & 0x1FFF {
+            0x3F => self.slices[slice] = Slice::Rom((bank as usize % self.num_banks()) as u8),
+            0x3E => self.slices[slice] = Slice::Ram(bank),
+            _ => {}
+        }
+    }
+}
+
+impl cartridge::Cartridge for C3EPlusCartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn observe_bus(&mut self, address: u16, data: u8, write: bool) {
+        if write {
+            self.snoop_write(address, data);
+        }
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" num banks: {} (1K)", self.num_banks());
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for C3EPlusCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        for slice in self.slices {
+            match slice {
+                Slice::Rom(bank) => state.put_u8(bank),
+                Slice::Ram(bank) => state.put_u8(0x80 | bank),
+            }
+        }
+        state.put_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        for i in 0..self.slices.len() {
+            let value = state.get_u8()?;
+            let bank = value & C3EPlusCartridge::BANK_MASK;
+            self.slices[i] = if 0 != value & 0x80 {
+                Slice::Ram(bank)
+            } else if (bank as usize) < self.num_banks() {
+                Slice::Rom(bank)
+            } else {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid 3E+ bank {}", bank)));
+            };
+        }
+        state.get_bytes_into(&mut self.ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+
+    #[test]
+    fn test_3f_and_3e() {
+        // 8K, each 2K bank filled with its bank number.
+        let data: Vec<u8> = (0..0x2000).map(|i| (i / 0x800) as u8).collect();
+
+        let mut cartridge = get_new_carterage("3f", &data, &CartridgeType::C3F);
+        let clock = Clock::new();
+        assert_eq!((cartridge.read(&clock, 0x000), cartridge.read(&clock, 0xFFF)), (0, 3));
+        cartridge.observe_bus(0x01, 2, true); // Any TIA address
+        assert_eq!((cartridge.read(&clock, 0x7FF), cartridge.read(&clock, 0x800)), (2, 3));
+        cartridge.observe_bus(0x40, 1, true); // Outside of the range
+        cartridge.observe_bus(0x01, 1, false); // Reads are ignored
+        assert_eq!(cartridge.read(&clock, 0x000), 2);
+
+        let mut cartridge = get_new_carterage("3e", &data, &CartridgeType::C3E);
+        cartridge.observe_bus(0x01, 2, true); // Only $3F selects ROM
+        assert_eq!(cartridge.read(&clock, 0x000), 0);
+        cartridge.observe_bus(0x3F, 1, true);
+        assert_eq!(cartridge.read(&clock, 0x000), 1);
+
+        cartridge.observe_bus(0x3E, 5, true); // RAM bank 5
+        cartridge.write(&clock, 0x410, 0x42);
+        assert_eq!(cartridge.read(&clock, 0x010), 0x42);
+        cartridge.observe_bus(0x3E, 6, true);
+        assert_eq!(cartridge.read(&clock, 0x010), 0x00);
+        cartridge.observe_bus(0x3E, 5, true);
+        assert_eq!((cartridge.read(&clock, 0x010), cartridge.read(&clock, 0xFFF)), (0x42, 3));
+
+        cartridge.observe_bus(0x3F, 2, true); // Back to ROM
+        assert_eq!(cartridge.read(&clock, 0x010), 2);
+    }
+
+    #[test]
+    fn test_3e_plus() {
+        // 8K, each 1K bank filled with its bank number.
+        let data: Vec<u8> = (0..0x2000).map(|i| (i / 0x400) as u8).collect();
+        let mut cartridge = get_new_carterage("3e+", &data, &CartridgeType::C3EPlus);
+        let clock = Clock::new();
+        assert_eq!([0x000, 0x400, 0x800, 0xC00].map(|a| cartridge.read(&clock, a)), [0, 0, 0, 0]);
+
+        cartridge.observe_bus(0x3F, 0xC7, true); // Slice 3 -> ROM bank 7
+        cartridge.observe_bus(0x3F, 0x45, true); // Slice 1 -> ROM bank 5
+        cartridge.observe_bus(0x3E, 0x83, true); // Slice 2 -> RAM bank 3
+        cartridge.write(&clock, 0xA00, 0x99); // Write port (upper half)
+        assert_eq!([0x000, 0x400, 0x800, 0xC00].map(|a| cartridge.read(&clock, a)), [0, 5, 0x99, 7]);
+
+        cartridge.observe_bus(0x3E, 0x03, true); // RAM bank 3 in slice 0 too
+        assert_eq!(cartridge.read(&clock, 0x000), 0x99);
+    }
+}
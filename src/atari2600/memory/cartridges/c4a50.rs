@@ -0,0 +1,304 @@
+//! '4A50' bank switching (64K ROM, 32K RAM), by John Payson.
+//!
+//! The 4K cartridge window is split into:
+//!  - $1000-$17FF: Lower 2K slice of ROM or RAM
+//!  - $1800-$1DFF: Middle 1.5K slice of ROM or RAM
+//!  - $1E00-$1EFF: Upper 256 byte page of ROM or RAM
+//!  - $1F00-$1FFF: Fixed to the last page of ROM
+//!
+//! Slices are selected by:
+//!  - Zero page hotspots ($F4-$FB and mirrors), using the data read or written, ie 'STA $F9'
+//!  - Accesses to $1x00-$1xFF (below $1000, so TIA/RIOT mirrors), when the previous access read an opcode
+//!    $60-$7F from the cartridge (or zero page), ie 'RTS' followed by the dummy read of the address.
+//!  - Accesses to $1F00-$1FFF, in the same way, which select the upper page within its 4K.
+//!
+//! ROM images smaller than 128K are mirrored, the middle and upper slices use the second 64K.
+
+use super::super::super::clocks;
+use super::super::super::state;
+use super::super::cartridge;
+
+pub struct C4A50Cartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    slice_low: usize,
+    slice_middle: usize,
+    slice_high: usize,
+    is_rom_low: bool,
+    is_rom_middle: bool,
+    is_rom_high: bool,
+    // Previous bus access, to detect the bank switching sequences.
+    last_data: u8,
+    last_address: u16,
+}
+
+impl C4A50Cartridge {
+    const ROM_SIZE: usize = 0x20000;
+    const RAM_SIZE: usize = 0x8000;
+    const UPPER_ROM: usize = 0x10000;
+    const SLICE_SIZE: usize = 0x800;
+    const MIDDLE_END: u16 = 0xDFF;
+    const FIXED_START: u16 = 0xF00;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        // 32K, 64K or 128K, repeated to fill 128K.
+        let size = match data.len() {
+            0..=0xFFFF => 0x8000,
+            0x10000..=0x1FFFF => 0x10000,
+            _ => C4A50Cartridge::ROM_SIZE,
+        };
+        if data.len() != size {
+            println!("4A50 cartridge expected {} bytes, found {} (will be truncated/padded with zeros)", size, data.len());
+        }
+        let mut image = data.to_vec();
+        image.resize(size, 0);
+        let rom = image.repeat(C4A50Cartridge::ROM_SIZE / size);
+
+        Self {
+            info,
+            rom,
+            ram: vec![0; C4A50Cartridge::RAM_SIZE],
+            slice_low: 0,
+            slice_middle: 0,
+            slice_high: 0,
+            is_rom_low: true,
+            is_rom_middle: true,
+            is_rom_high: true,
+            last_data: 0xFF,
+            last_address: 0xFFFF,
+        }
+    }
+
+    // The previous access makes the next a bank switch, an opcode ($60-$7F) from the cartridge or zero page.
+    fn switch_enabled(&self) -> bool {
+        0x60 == self.last_data & 0xE0 && (self.last_address >= 0x1000 || self.last_address < 0x200)
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        if address >= C4A50Cartridge::FIXED_START {
+            self.select_high_page(address);
+        }
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        let offset = address as usize & (C4A50Cartridge::SLICE_SIZE - 1);
+        if address < C4A50Cartridge::SLICE_SIZE as u16 {
+            if self.is_rom_low { self.rom[self.slice_low + offset] } else { self.ram[self.slice_low + offset] }
+        } else if address <= C4A50Cartridge::MIDDLE_END {
+            if self.is_rom_middle { self.rom[C4A50Cartridge::UPPER_ROM + self.slice_middle + offset] } else { self.ram[self.slice_middle + offset] }
+        } else if address < C4A50Cartridge::FIXED_START {
+            let offset = offset & 0xFF;
+            if self.is_rom_high { self.rom[C4A50Cartridge::UPPER_ROM + self.slice_high + offset] } else { self.ram[self.slice_high + offset] }
+        } else {
+            self.rom[C4A50Cartridge::ROM_SIZE - 0x100 + (address as usize & 0xFF)]
+        }
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        let address = address & 0xFFF;
+        let offset = address as usize & (C4A50Cartridge::SLICE_SIZE - 1);
+        if address < C4A50Cartridge::SLICE_SIZE as u16 {
+            if !self.is_rom_low {
+                self.ram[self.slice_low + offset] = data;
+            }
+        } else if address <= C4A50Cartridge::MIDDLE_END {
+            if !self.is_rom_middle {
+                self.ram[self.slice_middle + offset] = data;
+            }
+        } else if address < C4A50Cartridge::FIXED_START {
+            if !self.is_rom_high {
+                self.ram[self.slice_high + (offset & 0xFF)] = data;
+            }
+        } else {
+            self.select_high_page(address);
+        }
+    }
+
+    // $1F00-$1FFF: A3 and A4-A6 select the page (within the 4K) of the upper slice.
+    fn select_high_page(&mut self, address: u16) {
+        if self.switch_enabled() {
+            let address = address as usize;
+            self.slice_high = (self.slice_high & 0xF0FF) | ((address & 0x8) << 8) | ((address & 0x70) << 4);
+        }
+    }
+
+    // Hotspots below $1000, 'data' is the value read (from the TIA/RIOT) or written.
+    fn check_bank_switch(&mut self, address: u16, data: u8) {
+        let address = address as usize;
+        let data = data as usize;
+        if self.switch_enabled() {
+            match address & 0xF00 {
+                0xC00 => {
+                    self.is_rom_high = true;
+                    self.slice_high = (address & 0xFF) << 8;
+                }
+                0xD00 => {
+                    self.is_rom_high = false;
+                    self.slice_high = (address & 0x7F) << 8;
+                }
+                0xE00 if 0 == address & 0x40 => {
+                    self.is_rom_low = true;
+                    self.slice_low = (address & 0x1F) << 11;
+                }
+                0xE00 => {
+                    self.is_rom_low = false;
+                    self.slice_low = (address & 0xF) << 11;
+                }
+                0xF00 if 0 == address & 0x40 => {
+                    self.is_rom_middle = true;
+                    self.slice_middle = (address & 0x1F) << 11;
+                }
+                0xF00 if 0 == address & 0x10 => {
+                    self.is_rom_middle = false;
+                    self.slice_middle = (address & 0xF) << 11;
+                }
+                0x400 => self.slice_low ^= 0x800,
+                0x500 => self.slice_low ^= 0x1000,
+                0x800 => self.slice_middle ^= 0x800,
+                0x900 => self.slice_middle ^= 0x1000,
+                _ => {}
+            }
+        }
+
+        // Zero page hotspots, $F4-$F7 (and mirrors) for the upper page, $F8-$FB for the lower/middle slices.
+        if 0x74 == address & 0xF75 {
+            self.is_rom_high = true;
+            self.slice_high = data << 8;
+        } else if 0x75 == address & 0xF75 {
+            self.is_rom_high = false;
+            self.slice_high = (data & 0x7F) << 8;
+        } else if 0x78 == address & 0xF7C {
+            match data & 0xF0 {
+                0x00 => {
+                    self.is_rom_low = true;
+                    self.slice_low = (data & 0xF) << 11;
+                }
+                0x40 => {
+                    self.is_rom_low = false;
+                    self.slice_low = (data & 0xF) << 11;
+                }
+                0x90 => {
+                    self.is_rom_middle = true;
+                    self.slice_middle = ((data & 0xF) | 0x10) << 11;
+                }
+                0xC0 => {
+                    self.is_rom_middle = false;
+                    self.slice_middle = (data & 0xF) << 11;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn observe_bus(&mut self, address: u16, data: u8) {
+        let address = address & 0x1FFF;
+        if address < 0x1000 {
+            self.check_bank_switch(address, data);
+        }
+        self.last_data = data;
+        self.last_address = address;
+    }
+}
+
+impl cartridge::Cartridge for C4A50Cartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn observe_bus(&mut self, address: u16, data: u8, _write: bool) {
+        self.observe_bus(address, data);
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        let kind = |is_rom: bool| if is_rom { "ROM" } else { "RAM" };
+        println!(" low: {} {:#X}", kind(self.is_rom_low), self.slice_low);
+        println!(" middle: {} {:#X}", kind(self.is_rom_middle), self.slice_middle);
+        println!(" high: {} {:#X}", kind(self.is_rom_high), self.slice_high);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for C4A50Cartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_bytes(&self.ram);
+        state.put_u32(self.slice_low as u32);
+        state.put_u32(self.slice_middle as u32);
+        state.put_u32(self.slice_high as u32);
+        state.put_bool(self.is_rom_low);
+        state.put_bool(self.is_rom_middle);
+        state.put_bool(self.is_rom_high);
+        state.put_u8(self.last_data);
+        state.put_u16(self.last_address);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        state.get_bytes_into(&mut self.ram)?;
+        let slices = [state.get_u32()? as usize, state.get_u32()? as usize, state.get_u32()? as usize];
+        if slices.iter().any(|slice| *slice >= C4A50Cartridge::UPPER_ROM) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid 4A50 slices {:X?}", slices)));
+        }
+        [self.slice_low, self.slice_middle, self.slice_high] = slices;
+        self.is_rom_low = state.get_bool()?;
+        self.is_rom_middle = state.get_bool()?;
+        self.is_rom_high = state.get_bool()?;
+        if (!self.is_rom_low && self.slice_low >= C4A50Cartridge::RAM_SIZE) || (!self.is_rom_middle && self.slice_middle >= C4A50Cartridge::RAM_SIZE) || (!self.is_rom_high && self.slice_high >= C4A50Cartridge::RAM_SIZE) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid 4A50 RAM slice"));
+        }
+        self.last_data = state.get_u8()?;
+        self.last_address = state.get_u16()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+
+    #[test]
+    fn test_4a50_slices() {
+        // Each 2K of the 64K ROM filled with its 2K bank number.
+        let data: Vec<u8> = (0..0x10000).map(|i| (i / 0x800) as u8).collect();
+        let mut cartridge = get_new_carterage("4a50", &data, &CartridgeType::C4A50);
+        let clock = Clock::new();
+        assert_eq!(cartridge.read(&clock, 0x000), 0);
+        assert_eq!(cartridge.read(&clock, 0xFFF), 31); // Fixed to the last page.
+
+        // 'STA $F8' with $03 selects ROM bank 3 for the lower slice, with $43 RAM bank 3.
+        cartridge.observe_bus(0x00F8, 0x03, true);
+        assert_eq!(cartridge.read(&clock, 0x000), 3);
+        cartridge.observe_bus(0x00F8, 0x43, true);
+        cartridge.write(&clock, 0x010, 0xAB);
+        assert_eq!(cartridge.read(&clock, 0x010), 0xAB);
+
+        // 'STA $FA' with $95 selects ROM bank 21 (second 64K, mirrored) for the middle slice.
+        cartridge.observe_bus(0x00FA, 0x95, true);
+        assert_eq!(cartridge.read(&clock, 0x800), 21);
+
+        // An opcode fetch of $60 (RTS) from the cartridge, then an access to $0E05 selects ROM bank 5 (lower).
+        cartridge.observe_bus(0xF123, 0x60, false);
+        cartridge.observe_bus(0x0E05, 0x00, false);
+        assert_eq!(cartridge.read(&clock, 0x000), 5);
+
+        // Without the opcode, ignored.
+        cartridge.observe_bus(0xF124, 0xEA, false);
+        cartridge.observe_bus(0x0E07, 0x00, false);
+        assert_eq!(cartridge.read(&clock, 0x000), 5);
+    }
+}
@@ -0,0 +1,488 @@
+//! 'CDF' and 'CDFJ' (Harmony/Melody ARM cartridge), data streams and custom ARM code.
+//!
+//! The 32K image is:
+//!  - $0000-$07FF: The ARM driver (emulated here), copied to the ARM RAM (which holds the data stream registers)
+//!  - $0800-$0FFF: The custom ARM code (entry at $0808), which may continue into the banks
+//!  - $1000-$7FFF: Seven 4K banks for the 6507 (hotspots $1FF5-$1FFB)
+//!
+//! The version comes from the driver's signature ("CDF" followed by the version, or "CDFJ").
+//!
+//! The 6507 accesses the data streams with fast fetch ('LDA #n' reads data stream n, CDFJ also has 'LDX #'/'LDY #'),
+//! the data streams are 32 bit pointers (12.20 fixed point, into the display data after the driver RAM) and
+//! increments (8.12) in the ARM RAM, so the ARM code can set them up.  Stream $20 is for communication with the
+//! ARM code, the next (two for CDFJ) are the jump streams ('JMP $0000'/'JMP $0001' reads the address from them),
+//! and the last is the audio amplitude.
+//!
+//! Write registers:
+//!  - $1FF0: Write to the communication stream
+//!  - $1FF1: Set the communication stream pointer (write the high, then the low byte)
+//!  - $1FF2: Mode, low nybble 0 enables fast fetch, high nybble 0 enables digital audio (samples rather than waveforms)
+//!  - $1FF3: Call function 254/255 runs the custom ARM code (the 6507 is held while it runs, see 'DpcPlusCartridge')
+//!
+//! The ARM code calls the driver to set the music notes/waveforms (emulated here).
+
+use super::super::super::clocks;
+use super::super::super::cpu::core;
+use super::super::super::state;
+use super::super::cartridge;
+use super::thumb;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Version {
+    Cdf0,
+    Cdf1,
+    Cdfj,
+}
+
+// Where the version keeps the data streams in the RAM (and the related options).
+struct Layout {
+    datastreams: usize,
+    increments: usize,
+    waveforms: usize,
+    amplitude_stream: u8,
+    jump_stream_mask: u8,
+    ldx_ldy: bool,
+}
+
+impl Version {
+    fn layout(&self) -> Layout {
+        match self {
+            Version::Cdf0 => Layout {
+                datastreams: 0x06E0,
+                increments: 0x0768,
+                waveforms: 0x07F0,
+                amplitude_stream: 0x22,
+                jump_stream_mask: 0xFF,
+                ldx_ldy: false,
+            },
+            Version::Cdf1 => Layout {
+                datastreams: 0x00A0,
+                increments: 0x0128,
+                waveforms: 0x01B0,
+                amplitude_stream: 0x22,
+                jump_stream_mask: 0xFF,
+                ldx_ldy: false,
+            },
+            Version::Cdfj => Layout {
+                datastreams: 0x0098,
+                increments: 0x0124,
+                waveforms: 0x01B0,
+                amplitude_stream: 0x23,
+                jump_stream_mask: 0xFE,
+                ldx_ldy: true,
+            },
+        }
+    }
+
+    /// The version from the driver's signature.
+    pub fn detect(data: &[u8]) -> Option<Version> {
+        let position = data.windows(3).position(|w| w == b"CDF")?;
+        match data.get(position + 3) {
+            Some(b'J') => Some(Version::Cdfj),
+            Some(0) => Some(Version::Cdf0),
+            _ => Some(Version::Cdf1),
+        }
+    }
+}
+
+pub struct CdfCartridge {
+    info: cartridge::CartridgeInfo,
+    version: Version,
+    layout: Layout,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    bank: u8,
+    mode: u8,
+
+    // Fast fetch/jump operand addresses, and the jump stream.
+    immediate_operand: Option<u16>,
+    jump_operand: Option<u16>,
+    jump_stream: u8,
+
+    music_counters: [u32; 3],
+    music_frequencies: [u32; 3],
+    music_waveform_sizes: [u8; 3],
+    last_ticks: clocks::ClockType,
+    oscillator_remainder: u64, // Fractional oscillator clocks (scaled by CLOCK_HZ).
+
+    arm: thumb::Thumb,
+    stall_cycles: u64, // 6507 cycles for the last ARM call.
+}
+
+impl CdfCartridge {
+    const ROM_SIZE: usize = 0x8000;
+    const RAM_SIZE: usize = 0x2000;
+    const DRIVER_SIZE: usize = 0x800;
+    const PROGRAM: usize = 0x1000;
+    const BANK_SIZE: usize = 0x1000;
+    const NUM_BANKS: u8 = 7;
+    const DISPLAY: usize = 0x800; // Offset in the RAM.
+    const HOTSPOT_START: u16 = 0xFF5;
+    const HOTSPOT_END: u16 = 0xFFB;
+    const DSWRITE: u16 = 0xFF0;
+    const DSPTR: u16 = 0xFF1;
+    const SETMODE: u16 = 0xFF2;
+    const CALLFN: u16 = 0xFF3;
+    const COMM_STREAM: u8 = 0x20;
+    const JUMP_STREAM: u8 = 0x21;
+    const DEFAULT_WAVEFORM_SIZE: u8 = 27; // 32 byte waveforms (shift of the 32 bit counter).
+    const OSCILLATOR_HZ: u64 = 20000;
+
+    const ARM_ENTRY: u32 = 0x808;
+    const ARM_RETURN: u32 = 0x800;
+    const ARM_STACK: u32 = thumb::RAM_BASE + 0x1FB4;
+
+    // Driver routines called by the ARM code (the address of their 'BX' to ARM code).
+    const SET_NOTE: u32 = 0x6E2;
+    const RESET_WAVE: u32 = 0x6E6;
+    const GET_WAVE_POINTER: u32 = 0x6EA;
+    const SET_WAVE_SIZE: u32 = 0x6EE;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        if data.len() != CdfCartridge::ROM_SIZE {
+            println!("CDF cartridge expected {} bytes, found {} (will be truncated/padded with zeros)", CdfCartridge::ROM_SIZE, data.len());
+        }
+        let mut rom = data.to_vec();
+        rom.resize(CdfCartridge::ROM_SIZE, 0);
+
+        let version = Version::detect(&rom[..CdfCartridge::DRIVER_SIZE]).unwrap_or(Version::Cdfj);
+        let mut ram = vec![0; CdfCartridge::RAM_SIZE];
+        ram[..CdfCartridge::DRIVER_SIZE].copy_from_slice(&rom[..CdfCartridge::DRIVER_SIZE]);
+
+        Self {
+            info,
+            version,
+            layout: version.layout(),
+            rom,
+            ram,
+            bank: CdfCartridge::NUM_BANKS - 1,
+            mode: 0xFF,
+            immediate_operand: None,
+            jump_operand: None,
+            jump_stream: CdfCartridge::JUMP_STREAM,
+            music_counters: [0; 3],
+            music_frequencies: [0; 3],
+            music_waveform_sizes: [CdfCartridge::DEFAULT_WAVEFORM_SIZE; 3],
+            last_ticks: 0,
+            oscillator_remainder: 0,
+            arm: thumb::Thumb::new(),
+            stall_cycles: 0,
+        }
+    }
+
+    fn fast_fetch(&self) -> bool {
+        0 == self.mode & 0x0F
+    }
+
+    fn digital_audio(&self) -> bool {
+        0 == self.mode & 0xF0
+    }
+
+    fn ram_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.ram[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn set_ram_u32(&mut self, offset: usize, value: u32) {
+        self.ram[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn display(&self, index: u32) -> u8 {
+        self.ram[(CdfCartridge::DISPLAY + index as usize) % CdfCartridge::RAM_SIZE]
+    }
+
+    fn pointer(&self, stream: u8) -> u32 {
+        self.ram_u32(self.layout.datastreams + 4 * stream as usize)
+    }
+
+    fn set_pointer(&mut self, stream: u8, pointer: u32) {
+        self.set_ram_u32(self.layout.datastreams + 4 * stream as usize, pointer);
+    }
+
+    // Read the data stream and advance it by its increment (only the low 16 bits are used).
+    fn read_stream(&mut self, stream: u8) -> u8 {
+        let pointer = self.pointer(stream);
+        let increment = self.ram_u32(self.layout.increments + 4 * stream as usize) & 0xFFFF;
+        self.set_pointer(stream, pointer.wrapping_add(increment << 12));
+        self.display(pointer >> 20)
+    }
+
+    // Advance the music counters by the oscillator clocks since the last update.
+    fn update_music(&mut self, clock: &clocks::Clock) {
+        let elapsed = clock.ticks.wrapping_sub(self.last_ticks);
+        self.last_ticks = clock.ticks;
+
+        let scaled = elapsed * CdfCartridge::OSCILLATOR_HZ + self.oscillator_remainder;
+        let oscillator_clocks = (scaled / core::Constants::CLOCK_HZ as u64) as u32;
+        self.oscillator_remainder = scaled % core::Constants::CLOCK_HZ as u64;
+        for (counter, frequency) in self.music_counters.iter_mut().zip(self.music_frequencies) {
+            *counter = counter.wrapping_add(frequency.wrapping_mul(oscillator_clocks));
+        }
+    }
+
+    fn amplitude(&mut self, clock: &clocks::Clock) -> u8 {
+        self.update_music(clock);
+        if self.digital_audio() {
+            // 4 bit samples (packed two per byte, high nybble first) at the address in the first waveform.
+            let address = self.ram_u32(self.layout.waveforms).wrapping_add(self.music_counters[0] >> 21);
+            let sample = if (address as usize) < CdfCartridge::ROM_SIZE {
+                self.rom[address as usize]
+            } else if address >= thumb::RAM_BASE && ((address - thumb::RAM_BASE) as usize) < CdfCartridge::RAM_SIZE {
+                self.ram[(address - thumb::RAM_BASE) as usize]
+            } else {
+                0
+            };
+            if 0 == self.music_counters[0] & (1 << 20) {
+                sample >> 4
+            } else {
+                sample & 0x0F
+            }
+        } else {
+            // Sum of the 3 waveforms (pointers to the display data, as ARM addresses).
+            (0..3)
+                .map(|i| {
+                    let waveform = self.ram_u32(self.layout.waveforms + 4 * i).wrapping_sub(thumb::RAM_BASE + CdfCartridge::DISPLAY as u32);
+                    self.display(waveform.wrapping_add(self.music_counters[i] >> self.music_waveform_sizes[i])) as u32
+                })
+                .sum::<u32>() as u8
+        }
+    }
+
+    fn run_arm(&mut self) {
+        self.arm.call(CdfCartridge::ARM_ENTRY, CdfCartridge::ARM_RETURN, CdfCartridge::ARM_STACK);
+        loop {
+            let exit = self.arm.run(&mut thumb::Bus { rom: &self.rom, ram: &mut self.ram });
+            let channel = self.arm.register(2) as usize;
+            let value = self.arm.register(3);
+            match exit {
+                thumb::Exit::Arm(address) if channel < 3 && CdfCartridge::SET_NOTE == address => self.music_frequencies[channel] = value,
+                thumb::Exit::Arm(address) if channel < 3 && CdfCartridge::RESET_WAVE == address => self.music_counters[channel] = 0,
+                thumb::Exit::Arm(address) if channel < 3 && CdfCartridge::GET_WAVE_POINTER == address => self.arm.set_register(2, self.music_counters[channel]),
+                thumb::Exit::Arm(address) if channel < 3 && CdfCartridge::SET_WAVE_SIZE == address => self.music_waveform_sizes[channel] = value as u8,
+                thumb::Exit::Arm(_) => return,
+                thumb::Exit::Fault(message) => {
+                    println!("CDF ARM code: {}", message);
+                    return;
+                }
+            }
+            self.arm.resume();
+        }
+    }
+
+    fn switch_banks(&mut self, address: u16) {
+        if (CdfCartridge::HOTSPOT_START..=CdfCartridge::HOTSPOT_END).contains(&address) {
+            self.bank = (address - CdfCartridge::HOTSPOT_START) as u8;
+        }
+    }
+
+    fn program(&self, address: u16) -> u8 {
+        self.rom[CdfCartridge::PROGRAM + self.bank as usize * CdfCartridge::BANK_SIZE + (address as usize & 0xFFF)]
+    }
+
+    pub fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        let value = self.program(address);
+
+        // The operands of 'JMP $0000' come from the jump stream.
+        if Some(address) == self.jump_operand {
+            // The low byte (following the opcode), then the high byte.
+            self.jump_operand = (0x4C == self.program(address.wrapping_sub(1))).then_some(address + 1);
+            let pointer = self.pointer(self.jump_stream);
+            self.set_pointer(self.jump_stream, pointer.wrapping_add(1 << 20));
+            return self.display(pointer >> 20);
+        }
+        self.jump_operand = None;
+
+        if self.fast_fetch() {
+            if 0x4C == value && 0 == self.program(address + 1) & self.layout.jump_stream_mask && 0 == self.program(address + 2) {
+                self.jump_stream = CdfCartridge::JUMP_STREAM + self.program(address + 1);
+                self.jump_operand = Some(address + 1);
+                return value;
+            }
+
+            if Some(address) == self.immediate_operand.take() && value <= self.layout.amplitude_stream {
+                return if value == self.layout.amplitude_stream { self.amplitude(clock) } else { self.read_stream(value) };
+            }
+
+            if 0xA9 == value || (self.layout.ldx_ldy && (0xA2 == value || 0xA0 == value)) {
+                self.immediate_operand = Some(address + 1);
+                return value;
+            }
+        }
+
+        self.switch_banks(address);
+        value
+    }
+
+    pub fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        let address = address & 0xFFF;
+        match address {
+            CdfCartridge::DSWRITE => {
+                let pointer = self.pointer(CdfCartridge::COMM_STREAM);
+                let index = (CdfCartridge::DISPLAY + (pointer >> 20) as usize) % CdfCartridge::RAM_SIZE;
+                self.ram[index] = data;
+                self.set_pointer(CdfCartridge::COMM_STREAM, pointer.wrapping_add(1 << 20));
+            }
+            CdfCartridge::DSPTR => {
+                let pointer = ((self.pointer(CdfCartridge::COMM_STREAM) << 8) & 0xF000_0000) | ((data as u32) << 20);
+                self.set_pointer(CdfCartridge::COMM_STREAM, pointer);
+            }
+            CdfCartridge::SETMODE => self.mode = data,
+            CdfCartridge::CALLFN => {
+                if data >= 254 {
+                    let start_cycles = self.arm.cycles;
+                    self.run_arm();
+                    self.stall_cycles += thumb::cpu_cycles(self.arm.cycles - start_cycles);
+                }
+            }
+            _ => self.switch_banks(address),
+        }
+    }
+}
+
+impl cartridge::Cartridge for CdfCartridge {
+    fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(clock, address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.program(address)
+    }
+
+    fn bank(&self) -> usize {
+        self.bank as usize
+    }
+
+    fn write(&mut self, clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(clock, address, data);
+    }
+
+    fn take_stall_cycles(&mut self) -> u64 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" version: {:?}", self.version);
+        println!(" bank: {}", self.bank);
+        println!(" ARM cycles: {}", self.arm.cycles);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for CdfCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_bytes(&self.ram);
+        state.put_u8(self.bank);
+        state.put_u8(self.mode);
+        state.put_u16(self.immediate_operand.unwrap_or(0));
+        state.put_u16(self.jump_operand.unwrap_or(0));
+        state.put_u8(self.jump_stream);
+        for i in 0..3 {
+            state.put_u32(self.music_counters[i]);
+            state.put_u32(self.music_frequencies[i]);
+            state.put_u8(self.music_waveform_sizes[i]);
+        }
+        state.put_u64(self.last_ticks);
+        state.put_u64(self.oscillator_remainder);
+        state.put_u64(self.arm.cycles);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        state.get_bytes_into(&mut self.ram)?;
+        let bank = state.get_u8()?;
+        if bank >= CdfCartridge::NUM_BANKS {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid CDF bank {}", bank)));
+        }
+        self.bank = bank;
+        self.mode = state.get_u8()?;
+        // The operands are never at $1000 (they follow an opcode).
+        self.immediate_operand = Some(state.get_u16()? & 0xFFF).filter(|x| 0 != *x);
+        self.jump_operand = Some(state.get_u16()? & 0xFFF).filter(|x| 0 != *x);
+        self.jump_stream = state.get_u8()?;
+        if self.jump_stream > self.layout.amplitude_stream {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid CDF jump stream {}", self.jump_stream)));
+        }
+        for i in 0..3 {
+            self.music_counters[i] = state.get_u32()?;
+            self.music_frequencies[i] = state.get_u32()?;
+            self.music_waveform_sizes[i] = state.get_u8()? & 0x1F;
+        }
+        self.last_ticks = state.get_u64()?;
+        self.oscillator_remainder = state.get_u64()? % core::Constants::CLOCK_HZ as u64;
+        self.arm.cycles = state.get_u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::CartridgeInfo;
+    use crate::atari2600::memory::cartridge::CartridgeType;
+    use crate::atari2600::memory::cartridges::cdf::{CdfCartridge, Version};
+    use crate::atari2600::memory::cartridges::thumb::cpu_cycles;
+
+    fn put_code(data: &mut [u8], address: usize, code: &[u16]) {
+        for (i, x) in code.iter().enumerate() {
+            data[address + 2 * i..address + 2 * i + 2].copy_from_slice(&x.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_cdfj_streams_and_arm() {
+        let mut data = vec![0_u8; 0x8000];
+        data[0x10..0x14].copy_from_slice(b"CDFJ");
+        assert_eq!(Version::detect(&data), Some(Version::Cdfj));
+
+        // Driver 'SetNote': MOVS R4, #0; BX R4
+        put_code(&mut data, 0x6E0, &[0x2400, 0x4720]);
+        // ARM code: set the increment of the communication stream to 1, 'SetNote(1, $55)', return.
+        #[rustfmt::skip]
+        put_code(&mut data, 0x808, &[
+            0xB500, // PUSH {LR}
+            0x4805, // LDR R0, [PC, #20] ($820)
+            0x2101, // MOVS R1, #1
+            0x0209, // LSLS R1, R1, #8
+            0x6001, // STR R1, [R0]
+            0x2201, // MOVS R2, #1
+            0x2355, // MOVS R3, #$55
+            0xF7FF, // BL $6E0
+            0xFF63,
+            0xBD00, // POP {PC}
+            0x46C0, // NOP
+            0x46C0, // NOP
+            0x01A4, // $400001A4 (increment of stream $20)
+            0x4000,
+        ]);
+        // Bank 6 $1100: LDA #$20; LDA #$20; JMP $0000
+        data[0x7100..0x7107].copy_from_slice(&[0xA9, 0x20, 0xA9, 0x20, 0x4C, 0x00, 0x00]);
+
+        let mut cartridge = CdfCartridge::new(CartridgeInfo::new("cdfj", &data, CartridgeType::Cdf, false), &data);
+        let clock = Clock::new();
+        cartridge.write(&clock, 0xFF2, 0x00); // Fast fetch on
+
+        // Write $34, $12 via the communication stream (to the start of the display data).
+        cartridge.write(&clock, 0xFF1, 0x00);
+        cartridge.write(&clock, 0xFF1, 0x00);
+        cartridge.write(&clock, 0xFF0, 0x34);
+        cartridge.write(&clock, 0xFF0, 0x12);
+        assert_eq!(cartridge.ram[0x800..0x802], [0x34, 0x12]);
+
+        cartridge.write(&clock, 0xFF3, 254);
+        assert_eq!(cartridge.music_frequencies, [0, 0x55, 0]);
+        assert_eq!(cartridge.stall_cycles, cpu_cycles(cartridge.arm.cycles));
+
+        // Read it back via fast fetch (now the stream increments).
+        cartridge.write(&clock, 0xFF1, 0x00);
+        cartridge.write(&clock, 0xFF1, 0x00);
+        let reads: Vec<u8> = (0x100..0x104).map(|address| cartridge.read(&clock, address)).collect();
+        assert_eq!(reads, [0xA9, 0x34, 0xA9, 0x12]);
+
+        // 'JMP $0000' reads the address from the jump stream (also at the start of the display data).
+        let reads: Vec<u8> = (0x104..0x107).map(|address| cartridge.read(&clock, address)).collect();
+        assert_eq!(reads, [0x4C, 0x34, 0x12]);
+    }
+}
@@ -0,0 +1,344 @@
+//! David Crane's Display Processor Chip 'DPC' (Pitfall II).
+//!
+//! 8K of program ROM (F8 style banks, $1FF8/$1FF9), plus 2K of display data only accessible via the
+//! 8 data fetchers.  Each fetcher has an 11 bit counter (decremented on each data fetch), and top/bottom
+//! registers that set/clear its flag when the low byte of the counter matches.
+//!
+//! Registers (read $1000-$103F, write $1040-$107F), bits 0-2 select the data fetcher:
+//!  - Read  $1000-$1003: Random number (8 bit LFSR)
+//!  - Read  $1004-$1007: Music amplitude (from the flags of fetchers 5-7 in music mode)
+//!  - Read  $1008-$100F: Display data
+//!  - Read  $1010-$1017: Display data AND flag
+//!  - Read  $1038-$103F: Flag
+//!  - Write $1040-$1047: Top count (clears the flag)
+//!  - Write $1048-$104F: Bottom count
+//!  - Write $1050-$1057: Counter low byte
+//!  - Write $1058-$105F: Counter high bits (and music mode enable for fetchers 5-7)
+//!  - Write $1070-$1077: Reset the random number generator
+//!
+//! In music mode fetchers 5-7 are clocked by the (approximately 20KHz) oscillator rather than data fetches, so the
+//! cartridge needs the clock.  The game reads the amplitude and writes it to AUDV0, which drives 'TiaSound'.
+
+use super::super::super::clocks;
+use super::super::super::cpu::core;
+use super::super::super::state;
+use super::super::cartridge;
+
+pub struct DpcCartridge {
+    info: cartridge::CartridgeInfo,
+    program: Vec<u8>,
+    display: Vec<u8>,
+    bank: u8,
+
+    tops: [u8; 8],
+    bottoms: [u8; 8],
+    counters: [u16; 8],
+    flags: [u8; 8],
+    music_mode: [bool; 3],
+    random: u8,
+
+    last_ticks: clocks::ClockType,
+    oscillator_remainder: u64, // Fractional oscillator clocks (scaled by CLOCK_HZ).
+}
+
+impl DpcCartridge {
+    const BANK_SIZE: usize = 0x1000;
+    const NUM_BANKS: u8 = 2;
+    const DISPLAY_SIZE: usize = 0x800;
+    const COUNTER_MASK: u16 = 0x7FF;
+    const HOTSPOT_BANK0: u16 = 0xFF8;
+    const HOTSPOT_BANK1: u16 = 0xFF9;
+    const READ_END: u16 = 0x40;
+    const WRITE_END: u16 = 0x80;
+    const MUSIC_FETCHER: usize = 5;
+    const OSCILLATOR_HZ: u64 = 20000;
+
+    // Amplitude for each combination of the music fetcher flags (mixed as 3 square waves).
+    const MUSIC_AMPLITUDES: [u8; 8] = [0x00, 0x04, 0x05, 0x09, 0x06, 0x0A, 0x0B, 0x0F];
+
+    // Input bit of the random number shift register (XNOR of bits 7, 5, 4 and 3).
+    const RANDOM_FEEDBACK: [u8; 16] = [1, 0, 0, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 0, 0, 1];
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let program_size = DpcCartridge::NUM_BANKS as usize * DpcCartridge::BANK_SIZE;
+        if data.len() < program_size + DpcCartridge::DISPLAY_SIZE {
+            println!("DPC cartridge expected at least {} bytes, found {} (will be padded with zeros)", program_size + DpcCartridge::DISPLAY_SIZE, data.len());
+        }
+        let mut rom = data.to_vec();
+        rom.resize(std::cmp::max(rom.len(), program_size + DpcCartridge::DISPLAY_SIZE), 0);
+
+        Self {
+            info,
+            program: rom[..program_size].to_vec(),
+            display: rom[program_size..program_size + DpcCartridge::DISPLAY_SIZE].to_vec(),
+            bank: DpcCartridge::NUM_BANKS - 1,
+            tops: [0; 8],
+            bottoms: [0; 8],
+            counters: [0; 8],
+            flags: [0; 8],
+            music_mode: [false; 3],
+            random: 1,
+            last_ticks: 0,
+            oscillator_remainder: 0,
+        }
+    }
+
+    fn clock_random(&mut self) {
+        let bit = DpcCartridge::RANDOM_FEEDBACK[(((self.random >> 3) & 0x07) | if 0 != self.random & 0x80 { 0x08 } else { 0x00 }) as usize];
+        self.random = (self.random << 1) | bit;
+    }
+
+    // Advance the music mode fetchers by the oscillator clocks since the last update.
+    fn update_music(&mut self, clock: &clocks::Clock) {
+        let elapsed = clock.ticks.wrapping_sub(self.last_ticks);
+        self.last_ticks = clock.ticks;
+
+        let scaled = elapsed * DpcCartridge::OSCILLATOR_HZ + self.oscillator_remainder;
+        let oscillator_clocks = scaled / core::Constants::CLOCK_HZ as u64;
+        self.oscillator_remainder = scaled % core::Constants::CLOCK_HZ as u64;
+        if 0 == oscillator_clocks {
+            return;
+        }
+
+        for index in DpcCartridge::MUSIC_FETCHER..8 {
+            if !self.music_mode[index - DpcCartridge::MUSIC_FETCHER] {
+                continue;
+            }
+
+            // The low byte counts down from 'top' to 0 and wraps.
+            let top = self.tops[index] as u64;
+            let mut low = (self.counters[index] & 0xFF) as i64;
+            if 0 != top {
+                low -= (oscillator_clocks % (top + 1)) as i64;
+                if low < 0 {
+                    low += top as i64 + 1;
+                }
+            } else {
+                low = 0;
+            }
+
+            if low <= self.bottoms[index] as i64 {
+                self.flags[index] = 0x00;
+            } else if low <= top as i64 {
+                self.flags[index] = 0xFF;
+            }
+            self.counters[index] = (self.counters[index] & 0x700) | low as u16;
+        }
+    }
+
+    fn is_music_fetcher(&self, index: usize) -> bool {
+        index >= DpcCartridge::MUSIC_FETCHER && self.music_mode[index - DpcCartridge::MUSIC_FETCHER]
+    }
+
+    fn switch_banks(&mut self, address: u16) {
+        match address {
+            DpcCartridge::HOTSPOT_BANK0 => self.bank = 0,
+            DpcCartridge::HOTSPOT_BANK1 => self.bank = 1,
+            _ => {}
+        }
+    }
+
+    fn read_register(&mut self, address: u16) -> u8 {
+        let index = (address & 0x07) as usize;
+        let function = (address >> 3) & 0x07;
+
+        // Update the flag, before the fetch.
+        let low = (self.counters[index] & 0xFF) as u8;
+        if low == self.tops[index] {
+            self.flags[index] = 0xFF;
+        } else if low == self.bottoms[index] {
+            self.flags[index] = 0x00;
+        }
+
+        let display = self.display[DpcCartridge::DISPLAY_SIZE - 1 - self.counters[index] as usize];
+        let result = match function {
+            0 if index < 4 => self.random,
+            0 => {
+                let amplitude = (0..3).filter(|i| self.music_mode[*i] && 0 != self.flags[DpcCartridge::MUSIC_FETCHER + *i]).fold(0, |a, i| a | (1 << i));
+                DpcCartridge::MUSIC_AMPLITUDES[amplitude]
+            }
+            1 => display,
+            2 => display & self.flags[index],
+            7 => self.flags[index],
+            _ => 0,
+        };
+
+        if !self.is_music_fetcher(index) {
+            self.counters[index] = self.counters[index].wrapping_sub(1) & DpcCartridge::COUNTER_MASK;
+        }
+        result
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        let index = (address & 0x07) as usize;
+        let function = (address >> 3) & 0x07;
+
+        match function {
+            0 => {
+                self.tops[index] = data;
+                self.flags[index] = 0x00;
+            }
+            1 => self.bottoms[index] = data,
+            2 => {
+                // Music mode fetchers reload the low byte from 'top'.
+                let low = if self.is_music_fetcher(index) { self.tops[index] } else { data };
+                self.counters[index] = (self.counters[index] & 0x700) | low as u16;
+            }
+            3 => {
+                self.counters[index] = (((data & 0x07) as u16) << 8) | (self.counters[index] & 0xFF);
+                if index >= DpcCartridge::MUSIC_FETCHER {
+                    self.music_mode[index - DpcCartridge::MUSIC_FETCHER] = 0 != data & 0x10;
+                }
+            }
+            6 => self.random = 1,
+            _ => {}
+        }
+    }
+
+    pub fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        self.clock_random();
+        self.update_music(clock);
+
+        if address < DpcCartridge::READ_END {
+            self.read_register(address)
+        } else {
+            self.switch_banks(address);
+            self.peek(address)
+        }
+    }
+
+    // The program byte (the registers aren't read).
+    pub fn peek(&self, address: u16) -> u8 {
+        self.program[self.bank as usize * DpcCartridge::BANK_SIZE + (address as usize & 0xFFF)]
+    }
+
+    pub fn write(&mut self, clock: &clocks::Clock, address: u16, data: u8) {
+        let address = address & 0xFFF;
+        self.clock_random();
+        self.update_music(clock);
+
+        if (DpcCartridge::READ_END..DpcCartridge::WRITE_END).contains(&address) {
+            self.write_register(address, data);
+        } else {
+            self.switch_banks(address);
+        }
+    }
+}
+
+impl cartridge::Cartridge for DpcCartridge {
+    fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(clock, address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn bank(&self) -> usize {
+        self.bank as usize
+    }
+
+    fn write(&mut self, clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(clock, address, data);
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" bank: {}", self.bank);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for DpcCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.bank);
+        state.put_bytes(&self.tops);
+        state.put_bytes(&self.bottoms);
+        for counter in self.counters {
+            state.put_u16(counter);
+        }
+        state.put_bytes(&self.flags);
+        for music_mode in self.music_mode {
+            state.put_bool(music_mode);
+        }
+        state.put_u8(self.random);
+        state.put_u64(self.last_ticks);
+        state.put_u64(self.oscillator_remainder);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let bank = state.get_u8()?;
+        if bank >= DpcCartridge::NUM_BANKS {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid DPC bank {}", bank)));
+        }
+        self.bank = bank;
+        state.get_bytes_into(&mut self.tops)?;
+        state.get_bytes_into(&mut self.bottoms)?;
+        for counter in self.counters.iter_mut() {
+            *counter = state.get_u16()? & DpcCartridge::COUNTER_MASK;
+        }
+        state.get_bytes_into(&mut self.flags)?;
+        for music_mode in self.music_mode.iter_mut() {
+            *music_mode = state.get_bool()?;
+        }
+        self.random = state.get_u8()?;
+        self.last_ticks = state.get_u64()?;
+        self.oscillator_remainder = state.get_u64()? % core::Constants::CLOCK_HZ as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::cpu::core::Constants;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+
+    #[test]
+    fn test_dpc_fetchers_and_music() {
+        // 8K program (each bank filled with its number), 2K display data (stored reversed, so display[counter] = counter).
+        let mut data: Vec<u8> = (0..0x2000).map(|i| (i / 0x1000) as u8).collect();
+        data.extend((0..0x800).rev().map(|i| i as u8));
+        let mut cartridge = get_new_carterage("dpc", &data, &CartridgeType::Dpc);
+        let mut clock = Clock::new();
+
+        // Banks.
+        assert_eq!(cartridge.read(&clock, 0x800), 1);
+        cartridge.read(&clock, 0xFF8);
+        assert_eq!(cartridge.read(&clock, 0x800), 0);
+
+        // Data fetcher 0: counter = 0x010, top 0x0F, bottom 0x0C.
+        cartridge.write(&clock, 0x040, 0x0F);
+        cartridge.write(&clock, 0x048, 0x0C);
+        cartridge.write(&clock, 0x050, 0x10);
+        cartridge.write(&clock, 0x058, 0x00);
+        assert_eq!(cartridge.read(&clock, 0x038), 0x00); // Flag, counter now 0x0F
+        assert_eq!(cartridge.read(&clock, 0x008), 0x0F); // Top reached, sets the flag, counter now 0x0E
+        assert_eq!(cartridge.read(&clock, 0x010), 0x0E); // Masked display data (flag set)
+        assert_eq!(cartridge.read(&clock, 0x010), 0x0D);
+        assert_eq!(cartridge.read(&clock, 0x010), 0x00); // Bottom reached, flag cleared
+
+        // Random numbers are clocked on every access, and can be reset.
+        cartridge.write(&clock, 0x070, 0);
+        let first = cartridge.read(&clock, 0x000);
+        assert_ne!(first, cartridge.read(&clock, 0x000));
+        cartridge.write(&clock, 0x070, 0);
+        assert_eq!(first, cartridge.read(&clock, 0x000));
+
+        // Music mode on fetcher 5, a square wave with a period of 4 oscillator clocks (top 3, bottom 1).
+        cartridge.write(&clock, 0x045, 3);
+        cartridge.write(&clock, 0x04D, 1);
+        cartridge.write(&clock, 0x05D, 0x10);
+        cartridge.write(&clock, 0x055, 0);
+        let mut amplitudes = Vec::new();
+        for i in 1..=8 {
+            clock.ticks = (i * Constants::CLOCK_HZ as u64).div_ceil(20000); // One oscillator clock each
+            amplitudes.push(cartridge.read(&clock, 0x005));
+        }
+        assert_eq!(amplitudes, [0x04, 0x00, 0x00, 0x04, 0x04, 0x00, 0x00, 0x04]);
+    }
+}
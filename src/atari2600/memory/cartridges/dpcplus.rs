@@ -0,0 +1,471 @@
+//! 'DPC+' (Harmony/Melody ARM cartridge), an extended version of the DPC with custom ARM code.
+//!
+//! The 32K image is:
+//!  - $0000-$0BFF: The ARM driver (emulated here, so may be missing, for 29K images)
+//!  - $0C00-$6BFF: Six 4K banks for the 6507 (hotspots $1FF6-$1FFB), the custom ARM code is in bank 0
+//!  - $6C00-$7BFF: 4K of display data, copied to the ARM RAM (so it can be written)
+//!  - $7C00-$7FFF: 1K of note frequencies, copied to the ARM RAM
+//!
+//! Registers (read $1000-$1027, write $1028-$107F), bits 0-2 select one of the 8 data fetchers:
+//!  - Read  $1000-$1004: Random number (advance, step back, bytes 0-3), $1005: Music amplitude
+//!  - Read  $1008-$100F: Display data (incrementing the counter), $1010-$1017: AND flag
+//!  - Read  $1018-$101F: Display data via the fractional counter, $1020-$1023: Flag
+//!  - Write $1028-$104F: Fractional counter low/high and increment, top and bottom of the flag window
+//!  - Write $1050-$1057: Counter low byte, $1060-$1067: Push data, $1068-$106F: Counter high bits
+//!  - Write $1058-$105F: Fast fetch, parameter, call function, waveforms
+//!  - Write $1070-$1077: Reset/write the random number, notes, $1078-$107F: Write data
+//!
+//! Fast fetch mode turns 'LDA #n' (n < $28) into a read of register n.  Calling function 254/255 runs the custom
+//! ARM code, which runs to completion within the write.  On the real cartridge the driver feeds the 6507 NOPs until
+//! the ARM code returns, here the 6507 is held for the ARM code's run time (the TIA/RIOT keep going), so the 6507
+//! continues at the following instruction with the results ready, having lost the same time.
+
+use super::super::super::clocks;
+use super::super::super::cpu::core;
+use super::super::super::state;
+use super::super::cartridge;
+use super::thumb;
+
+pub struct DpcPlusCartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    bank: u8,
+
+    tops: [u8; 8],
+    bottoms: [u8; 8],
+    counters: [u16; 8],
+    fractional_counters: [u32; 8],
+    fractional_increments: [u8; 8],
+    flags: [u8; 8],
+    fast_fetch: bool,
+    lda_immediate: bool,
+    parameters: Vec<u8>,
+    random: u32,
+
+    music_counters: [u32; 3],
+    music_frequencies: [u32; 3],
+    music_waveforms: [u8; 3],
+    last_ticks: clocks::ClockType,
+    oscillator_remainder: u64, // Fractional oscillator clocks (scaled by CLOCK_HZ).
+
+    arm: thumb::Thumb,
+    stall_cycles: u64, // 6507 cycles for the last ARM call.
+}
+
+impl DpcPlusCartridge {
+    const ROM_SIZE: usize = 0x8000;
+    const RAM_SIZE: usize = 0x2000;
+    const DRIVER_SIZE: usize = 0xC00;
+    const BANK_SIZE: usize = 0x1000;
+    const NUM_BANKS: u8 = 6;
+    const DISPLAY: usize = 0xC00; // Offset in the RAM (and ROM, after the banks).
+    const DISPLAY_SIZE: usize = 0x1000;
+    const FREQUENCIES: usize = 0x1C00;
+    const HOTSPOT_START: u16 = 0xFF6;
+    const HOTSPOT_END: u16 = 0xFFB;
+    const READ_END: u16 = 0x28;
+    const WRITE_END: u16 = 0x80;
+    const MAX_PARAMETERS: usize = 8;
+    const RANDOM_RESET: u32 = 0x2B43_5044; // "DPC+"
+    const RANDOM_TAPS: u32 = 0x10AD_AB1E;
+    const OSCILLATOR_HZ: u64 = 20000;
+
+    const ARM_ENTRY: u32 = 0xC08;
+    const ARM_RETURN: u32 = 0xC00;
+    const ARM_STACK: u32 = thumb::RAM_BASE + 0x1FB4;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let mut rom = data.to_vec();
+        if DpcPlusCartridge::ROM_SIZE - DpcPlusCartridge::DRIVER_SIZE == data.len() {
+            // Without the driver.
+            rom.splice(0..0, vec![0; DpcPlusCartridge::DRIVER_SIZE]);
+        } else if data.len() != DpcPlusCartridge::ROM_SIZE {
+            println!("DPC+ cartridge expected {} bytes, found {} (will be truncated/padded with zeros)", DpcPlusCartridge::ROM_SIZE, data.len());
+        }
+        rom.resize(DpcPlusCartridge::ROM_SIZE, 0);
+
+        let mut ram = vec![0; DpcPlusCartridge::RAM_SIZE];
+        let rom_display = DpcPlusCartridge::DRIVER_SIZE + DpcPlusCartridge::NUM_BANKS as usize * DpcPlusCartridge::BANK_SIZE;
+        ram[DpcPlusCartridge::DISPLAY..].copy_from_slice(&rom[rom_display..]);
+
+        Self {
+            info,
+            rom,
+            ram,
+            bank: DpcPlusCartridge::NUM_BANKS - 1,
+            tops: [0; 8],
+            bottoms: [0; 8],
+            counters: [0; 8],
+            fractional_counters: [0; 8],
+            fractional_increments: [0; 8],
+            flags: [0; 8],
+            fast_fetch: false,
+            lda_immediate: false,
+            parameters: Vec::new(),
+            random: DpcPlusCartridge::RANDOM_RESET,
+            music_counters: [0; 3],
+            music_frequencies: [0; 3],
+            music_waveforms: [0; 3],
+            last_ticks: 0,
+            oscillator_remainder: 0,
+            arm: thumb::Thumb::new(),
+            stall_cycles: 0,
+        }
+    }
+
+    fn display(&self, index: usize) -> u8 {
+        self.ram[DpcPlusCartridge::DISPLAY + (index & (DpcPlusCartridge::DISPLAY_SIZE - 1))]
+    }
+
+    fn set_display(&mut self, index: usize, data: u8) {
+        self.ram[DpcPlusCartridge::DISPLAY + (index & (DpcPlusCartridge::DISPLAY_SIZE - 1))] = data;
+    }
+
+    fn clock_random(&mut self) {
+        let taps = if 0 != self.random & (1 << 10) { DpcPlusCartridge::RANDOM_TAPS } else { 0 };
+        self.random = taps ^ self.random.rotate_right(11);
+    }
+
+    fn prior_random(&mut self) {
+        let taps = if 0 != self.random & (1 << 31) { DpcPlusCartridge::RANDOM_TAPS } else { 0 };
+        self.random = (taps ^ self.random).rotate_left(11);
+    }
+
+    // Advance the music counters by the oscillator clocks since the last update.
+    fn update_music(&mut self, clock: &clocks::Clock) {
+        let elapsed = clock.ticks.wrapping_sub(self.last_ticks);
+        self.last_ticks = clock.ticks;
+
+        let scaled = elapsed * DpcPlusCartridge::OSCILLATOR_HZ + self.oscillator_remainder;
+        let oscillator_clocks = (scaled / core::Constants::CLOCK_HZ as u64) as u32;
+        self.oscillator_remainder = scaled % core::Constants::CLOCK_HZ as u64;
+        for (counter, frequency) in self.music_counters.iter_mut().zip(self.music_frequencies) {
+            *counter = counter.wrapping_add(frequency.wrapping_mul(oscillator_clocks));
+        }
+    }
+
+    fn run_arm(&mut self) {
+        let start_cycles = self.arm.cycles;
+        self.arm.call(DpcPlusCartridge::ARM_ENTRY, DpcPlusCartridge::ARM_RETURN, DpcPlusCartridge::ARM_STACK);
+        if let thumb::Exit::Fault(message) = self.arm.run(&mut thumb::Bus { rom: &self.rom, ram: &mut self.ram }) {
+            println!("DPC+ ARM code: {}", message);
+        }
+        self.stall_cycles += thumb::cpu_cycles(self.arm.cycles - start_cycles);
+    }
+
+    fn call_function(&mut self, function: u8) {
+        let parameters: Vec<usize> = (0..4).map(|i| *self.parameters.get(i).unwrap_or(&0) as usize).collect();
+        match function {
+            0 => self.parameters.clear(),
+            1 => {
+                // Copy ROM (from bank 0) to the display data of a fetcher.
+                let source = DpcPlusCartridge::DRIVER_SIZE + (parameters[1] << 8) + parameters[0];
+                let destination = self.counters[parameters[2] & 0x7] as usize;
+                for i in 0..parameters[3] {
+                    self.set_display(destination + i, self.rom[(source + i) % DpcPlusCartridge::ROM_SIZE]);
+                }
+                self.parameters.clear();
+            }
+            2 => {
+                // Fill the display data of a fetcher.
+                let destination = self.counters[parameters[2] & 0x7] as usize;
+                for i in 0..parameters[3] {
+                    self.set_display(destination + i, parameters[0] as u8);
+                }
+                self.parameters.clear();
+            }
+            254 | 255 => self.run_arm(),
+            _ => {}
+        }
+    }
+
+    fn read_register(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        let index = (address & 0x07) as usize;
+        let function = (address >> 3) & 0x07;
+
+        // Update the flag, before the fetch.
+        let low = (self.counters[index] & 0xFF) as u8;
+        if low == self.tops[index] {
+            self.flags[index] = 0xFF;
+        } else if low == self.bottoms[index] {
+            self.flags[index] = 0x00;
+        }
+
+        match function {
+            0 => match index {
+                0 => {
+                    self.clock_random();
+                    self.random as u8
+                }
+                1 => {
+                    self.prior_random();
+                    self.random as u8
+                }
+                2..=4 => (self.random >> (8 * (index - 1))) as u8,
+                5 => {
+                    self.update_music(clock);
+                    (0..3).map(|i| self.display(((self.music_waveforms[i] as usize) << 5) + (self.music_counters[i] >> 27) as usize) as u32).sum::<u32>() as u8
+                }
+                _ => 0,
+            },
+            1 | 2 => {
+                let data = self.display(self.counters[index] as usize);
+                self.counters[index] = (self.counters[index] + 1) & 0xFFF;
+                if 2 == function {
+                    data & self.flags[index]
+                } else {
+                    data
+                }
+            }
+            3 => {
+                let data = self.display((self.fractional_counters[index] >> 8) as usize);
+                self.fractional_counters[index] = (self.fractional_counters[index] + self.fractional_increments[index] as u32) & 0xFFFFF;
+                data
+            }
+            4 if index < 4 => self.flags[index],
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        let index = (address & 0x07) as usize;
+        let function = (address - DpcPlusCartridge::READ_END) >> 3;
+
+        match function {
+            0 => self.fractional_counters[index] = (self.fractional_counters[index] & 0xF0000) | ((data as u32) << 8),
+            1 => self.fractional_counters[index] = (((data & 0x0F) as u32) << 16) | (self.fractional_counters[index] & 0xFFFF),
+            2 => {
+                self.fractional_increments[index] = data;
+                self.fractional_counters[index] &= 0xFFF00;
+            }
+            3 => self.tops[index] = data,
+            4 => self.bottoms[index] = data,
+            5 => self.counters[index] = (self.counters[index] & 0xF00) | data as u16,
+            6 => match index {
+                0 => self.fast_fetch = 0 == data,
+                1 if self.parameters.len() < DpcPlusCartridge::MAX_PARAMETERS => self.parameters.push(data),
+                2 => self.call_function(data),
+                5..=7 => self.music_waveforms[index - 5] = data & 0x7F,
+                _ => {}
+            },
+            7 => {
+                self.counters[index] = self.counters[index].wrapping_sub(1) & 0xFFF;
+                self.set_display(self.counters[index] as usize, data);
+            }
+            8 => self.counters[index] = (((data & 0x0F) as u16) << 8) | (self.counters[index] & 0xFF),
+            9 => match index {
+                0 => self.random = DpcPlusCartridge::RANDOM_RESET,
+                1..=4 => {
+                    let shift = 8 * (index - 1);
+                    self.random = (self.random & !(0xFF << shift)) | ((data as u32) << shift);
+                }
+                _ => {
+                    let offset = DpcPlusCartridge::FREQUENCIES + ((data as usize) << 2);
+                    self.music_frequencies[index - 5] = u32::from_le_bytes(self.ram[offset..offset + 4].try_into().unwrap());
+                }
+            },
+            _ => {
+                self.set_display(self.counters[index] as usize, data);
+                self.counters[index] = (self.counters[index] + 1) & 0xFFF;
+            }
+        }
+    }
+
+    fn switch_banks(&mut self, address: u16) {
+        if (DpcPlusCartridge::HOTSPOT_START..=DpcPlusCartridge::HOTSPOT_END).contains(&address) {
+            self.bank = (address - DpcPlusCartridge::HOTSPOT_START) as u8;
+        }
+    }
+
+    pub fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        let value = self.peek(address);
+
+        // Fast fetch, the operand of 'LDA #' selects the register.
+        let register = if self.fast_fetch && self.lda_immediate && value < DpcPlusCartridge::READ_END as u8 { value as u16 } else { address };
+        self.lda_immediate = false;
+
+        if register < DpcPlusCartridge::READ_END {
+            self.read_register(clock, register)
+        } else {
+            self.switch_banks(address);
+            self.lda_immediate = self.fast_fetch && 0xA9 == value;
+            value
+        }
+    }
+
+    // The program byte (the registers aren't read).
+    pub fn peek(&self, address: u16) -> u8 {
+        self.rom[DpcPlusCartridge::DRIVER_SIZE + self.bank as usize * DpcPlusCartridge::BANK_SIZE + (address as usize & 0xFFF)]
+    }
+
+    pub fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        let address = address & 0xFFF;
+        if (DpcPlusCartridge::READ_END..DpcPlusCartridge::WRITE_END).contains(&address) {
+            self.write_register(address, data);
+        } else {
+            self.switch_banks(address);
+        }
+    }
+}
+
+impl cartridge::Cartridge for DpcPlusCartridge {
+    fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(clock, address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn bank(&self) -> usize {
+        self.bank as usize
+    }
+
+    fn write(&mut self, clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(clock, address, data);
+    }
+
+    fn take_stall_cycles(&mut self) -> u64 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" bank: {}", self.bank);
+        println!(" ARM cycles: {}", self.arm.cycles);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for DpcPlusCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_bytes(&self.ram);
+        state.put_u8(self.bank);
+        state.put_bytes(&self.tops);
+        state.put_bytes(&self.bottoms);
+        for counter in self.counters {
+            state.put_u16(counter);
+        }
+        for counter in self.fractional_counters {
+            state.put_u32(counter);
+        }
+        state.put_bytes(&self.fractional_increments);
+        state.put_bytes(&self.flags);
+        state.put_bool(self.fast_fetch);
+        state.put_bool(self.lda_immediate);
+        state.put_bytes(&self.parameters);
+        state.put_u32(self.random);
+        for i in 0..3 {
+            state.put_u32(self.music_counters[i]);
+            state.put_u32(self.music_frequencies[i]);
+            state.put_u8(self.music_waveforms[i]);
+        }
+        state.put_u64(self.last_ticks);
+        state.put_u64(self.oscillator_remainder);
+        state.put_u64(self.arm.cycles);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        state.get_bytes_into(&mut self.ram)?;
+        let bank = state.get_u8()?;
+        if bank >= DpcPlusCartridge::NUM_BANKS {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid DPC+ bank {}", bank)));
+        }
+        self.bank = bank;
+        state.get_bytes_into(&mut self.tops)?;
+        state.get_bytes_into(&mut self.bottoms)?;
+        for counter in self.counters.iter_mut() {
+            *counter = state.get_u16()? & 0xFFF;
+        }
+        for counter in self.fractional_counters.iter_mut() {
+            *counter = state.get_u32()? & 0xFFFFF;
+        }
+        state.get_bytes_into(&mut self.fractional_increments)?;
+        state.get_bytes_into(&mut self.flags)?;
+        self.fast_fetch = state.get_bool()?;
+        self.lda_immediate = state.get_bool()?;
+        self.parameters = state.get_bytes()?;
+        self.parameters.truncate(DpcPlusCartridge::MAX_PARAMETERS);
+        self.random = state.get_u32()?;
+        for i in 0..3 {
+            self.music_counters[i] = state.get_u32()?;
+            self.music_frequencies[i] = state.get_u32()?;
+            self.music_waveforms[i] = state.get_u8()? & 0x7F;
+        }
+        self.last_ticks = state.get_u64()?;
+        self.oscillator_remainder = state.get_u64()? % core::Constants::CLOCK_HZ as u64;
+        self.arm.cycles = state.get_u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::graphics::stella::Stella;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+    use crate::atari2600::memory::memory::{Bus, Memory};
+    use crate::atari2600::memory::riot::Riot;
+
+    #[test]
+    fn test_dpc_plus_fetchers_and_arm() {
+        // Banks filled with their number, display data with the low byte of its index.
+        let mut data = vec![0_u8; 0xC00];
+        data.extend((0..0x6000).map(|i| (i / 0x1000) as u8));
+        data.extend((0..0x1000).map(|i| i as u8));
+        data.extend(vec![0_u8; 0x400]);
+
+        // ARM code (bank 0, entry $C08): store 0x5A to display data $0100 (RAM $40000D00), return.
+        let arm: [u16; 6] = [
+            0x4801, // LDR R0, [PC, #4]
+            0x215A, // MOVS R1, #$5A
+            0x7001, // STRB R1, [R0]
+            0x4770, // BX LR
+            0x0D00, // $40000D00
+            0x4000,
+        ];
+        for (i, x) in arm.iter().enumerate() {
+            data[0xC08 + 2 * i..0xC0A + 2 * i].copy_from_slice(&x.to_le_bytes());
+        }
+        data[0xC00 + 0x100] = 0xA9; // Bank 0 $1100: LDA #$08 (fast fetch, fetcher 0 data)
+        data[0xC00 + 0x101] = 0x08;
+
+        let mut cartridge = get_new_carterage("dpc+", &data, &CartridgeType::DpcPlus);
+        let clock = Clock::new();
+        assert_eq!(cartridge.read(&clock, 0x800), 5);
+        cartridge.read(&clock, 0xFF6);
+        assert_eq!(cartridge.read(&clock, 0x800), 0);
+
+        // Fetcher 0 at $0FE, reads increment.
+        cartridge.write(&clock, 0x050, 0xFE); // DF0LOW
+        cartridge.write(&clock, 0x068, 0x00); // DF0HI
+        assert_eq!(cartridge.read(&clock, 0x008), 0xFE);
+        assert_eq!(cartridge.read(&clock, 0x008), 0xFF);
+
+        // Call the ARM code, which writes to the display data, then a fast fetch reads it.
+        cartridge.write(&clock, 0x05A, 254);
+        cartridge.write(&clock, 0x058, 0); // FASTFETCH on
+        assert_eq!(cartridge.read(&clock, 0x100), 0xA9);
+        assert_eq!(cartridge.read(&clock, 0x101), 0x5A);
+        assert_eq!(cartridge.read(&clock, 0x008), 0x01);
+
+        // Random numbers step forward and back.
+        cartridge.write(&clock, 0x070, 0); // RRESET
+        let first = cartridge.read(&clock, 0x000);
+        cartridge.read(&clock, 0x000);
+        assert_eq!(cartridge.read(&clock, 0x001), first);
+
+        // The 6507 is held while the ARM code runs (4 instructions and a store, so 1 cycle).
+        let mut memory = Memory::new(get_new_carterage("dpc+", &data, &CartridgeType::DpcPlus), Box::new(Stella::new(false, false, false)), Box::new(Riot::new()));
+        let mut clock = Clock::new();
+        memory.write(&mut clock, 0x1058, 0);
+        assert_eq!(clock.ticks, 0);
+        memory.write(&mut clock, 0x105A, 254);
+        assert_eq!(clock.ticks, 3);
+    }
+}
@@ -0,0 +1,126 @@
+//! Parker Brothers 'E0' bank switching (8K).
+//!
+//! The 4K cartridge window is split into four 1K slices, the first three are switched
+//! independently (to any of the eight 1K banks) and the last is fixed to the last bank:
+//!  - $1FE0-$1FE7: Select bank 0-7 for slice 0 ($1000-$13FF)
+//!  - $1FE8-$1FEF: Select bank 0-7 for slice 1 ($1400-$17FF)
+//!  - $1FF0-$1FF7: Select bank 0-7 for slice 2 ($1800-$1BFF)
+//!
+//! Hotspots are triggered by reads or writes.
+
+use super::super::super::clocks;
+use super::super::super::state;
+use super::super::cartridge;
+
+pub struct E0Cartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    slices: [u8; 4],
+}
+
+impl E0Cartridge {
+    const SLICE_SIZE: usize = 0x400;
+    const NUM_BANKS: u8 = 8;
+    const HOTSPOT_START: u16 = 0xFE0;
+    const HOTSPOT_END: u16 = 0xFF7;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let rom_size = E0Cartridge::NUM_BANKS as usize * E0Cartridge::SLICE_SIZE;
+        if data.len() != rom_size {
+            println!("E0 cartridge expected {} bytes, found {} (will be truncated/padded with zeros)", rom_size, data.len());
+        }
+        let mut rom = data.to_vec();
+        rom.resize(rom_size, 0);
+
+        // Start up slices as Stella does, the last slice is always the last bank.
+        Self { info, rom, slices: [4, 5, 6, E0Cartridge::NUM_BANKS - 1] }
+    }
+
+    fn switch_banks(&mut self, address: u16) {
+        if (E0Cartridge::HOTSPOT_START..=E0Cartridge::HOTSPOT_END).contains(&address) {
+            let offset = address - E0Cartridge::HOTSPOT_START;
+            self.slices[(offset / E0Cartridge::NUM_BANKS as u16) as usize] = (offset % E0Cartridge::NUM_BANKS as u16) as u8;
+        }
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        self.switch_banks(address);
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        let slice = address as usize / E0Cartridge::SLICE_SIZE;
+        self.rom[self.slices[slice] as usize * E0Cartridge::SLICE_SIZE + (address as usize % E0Cartridge::SLICE_SIZE)]
+    }
+
+    pub fn write(&mut self, address: u16, _data: u8) {
+        self.switch_banks(address & 0xFFF);
+    }
+}
+
+impl cartridge::Cartridge for E0Cartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" slices: {:?}", self.slices);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for E0Cartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_bytes(&self.slices[..3]);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let mut slices = [0_u8; 3];
+        state.get_bytes_into(&mut slices)?;
+        if slices.iter().any(|bank| *bank >= E0Cartridge::NUM_BANKS) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid E0 slices {:?}", slices)));
+        }
+        self.slices[..3].copy_from_slice(&slices);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+
+    #[test]
+    fn test_e0_slices() {
+        // Each 1K bank filled with its bank number.
+        let data: Vec<u8> = (0..0x2000).map(|i| (i / 0x400) as u8).collect();
+        let mut cartridge = get_new_carterage("e0", &data, &CartridgeType::E0);
+        let clock = Clock::new();
+
+        assert_eq!([0x000, 0x400, 0x800, 0xC00].map(|a| cartridge.read(&clock, a)), [4, 5, 6, 7]);
+
+        cartridge.read(&clock, 0xFE1); // Slice 0 -> bank 1
+        cartridge.write(&clock, 0xFEB, 0); // Slice 1 -> bank 3
+        cartridge.read(&clock, 0x1FF0); // Slice 2 -> bank 0 (with A12 set)
+        assert_eq!([0x000, 0x400, 0x800, 0xC00].map(|a| cartridge.read(&clock, a)), [1, 3, 0, 7]);
+        assert_eq!(cartridge.read(&clock, 0x7FF), 3);
+
+        // Last slice is fixed, and hotspots outside the range are ignored.
+        cartridge.read(&clock, 0xFF8);
+        assert_eq!([0x000, 0x400, 0x800, 0xC00].map(|a| cartridge.read(&clock, a)), [1, 3, 0, 7]);
+    }
+}
@@ -0,0 +1,188 @@
+//! M-Network 'E7' bank switching (16K ROM, 2K RAM).
+//!
+//! The 4K cartridge window is split into:
+//!  - $1000-$17FF: One of the first seven 2K ROM banks, or 1K of RAM (written via $1000-$13FF, read via $1400-$17FF)
+//!  - $1800-$19FF: One of four 256 byte RAM pages (written via $1800-$18FF, read via $1900-$19FF)
+//!  - $1A00-$1FFF: Fixed to the last 1.5K of the last ROM bank
+//!
+//! Hotspots (triggered by reads or writes):
+//!  - $1FE0-$1FE6: Select ROM bank 0-6 for the lower slice
+//!  - $1FE7: Select the 1K RAM for the lower slice
+//!  - $1FE8-$1FEB: Select RAM page 0-3
+
+use super::super::super::clocks;
+use super::super::super::state;
+use super::super::cartridge;
+
+// Reads from a RAM write port, the real hardware would return (and write) whatever is on the bus.
+const WRITE_PORT_READ: u8 = 0;
+
+pub struct E7Cartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    lower_bank: u8, // 'RAM_BANK' for the 1K RAM.
+    ram_page: u8,
+}
+
+impl E7Cartridge {
+    const BANK_SIZE: usize = 0x800;
+    const NUM_BANKS: usize = 8;
+    const RAM_BANK: u8 = 7;
+    const LOWER_RAM_SIZE: usize = 0x400;
+    const PAGE_SIZE: usize = 0x100;
+    const NUM_PAGES: u8 = 4;
+    const PAGES_START: usize = 0x800;
+    const FIXED_START: usize = 0xA00;
+    const HOTSPOT_START: u16 = 0xFE0;
+    const HOTSPOT_END: u16 = 0xFEB;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let rom_size = E7Cartridge::NUM_BANKS * E7Cartridge::BANK_SIZE;
+        if data.len() != rom_size {
+            println!("E7 cartridge expected {} bytes, found {} (will be truncated/padded with zeros)", rom_size, data.len());
+        }
+        let mut rom = data.to_vec();
+        rom.resize(rom_size, 0);
+
+        let ram_size = E7Cartridge::LOWER_RAM_SIZE + E7Cartridge::NUM_PAGES as usize * E7Cartridge::PAGE_SIZE;
+        Self { info, rom, ram: vec![0; ram_size], lower_bank: 0, ram_page: 0 }
+    }
+
+    fn switch_banks(&mut self, address: u16) {
+        if (E7Cartridge::HOTSPOT_START..=E7Cartridge::HOTSPOT_END).contains(&address) {
+            let offset = (address - E7Cartridge::HOTSPOT_START) as u8;
+            if offset <= E7Cartridge::RAM_BANK {
+                self.lower_bank = offset;
+            } else {
+                self.ram_page = offset - E7Cartridge::RAM_BANK - 1;
+            }
+        }
+    }
+
+    // Offset into 'ram' of the page RAM ($1800-$19FF), and whether it's the read port.
+    fn page_offset(&self, address: usize) -> (usize, bool) {
+        let offset = address - E7Cartridge::PAGES_START;
+        (E7Cartridge::LOWER_RAM_SIZE + self.ram_page as usize * E7Cartridge::PAGE_SIZE + offset % E7Cartridge::PAGE_SIZE, offset >= E7Cartridge::PAGE_SIZE)
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        self.switch_banks(address);
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        let address = address as usize & 0xFFF;
+        if address >= E7Cartridge::FIXED_START {
+            self.rom[(E7Cartridge::NUM_BANKS - 1) * E7Cartridge::BANK_SIZE + address - E7Cartridge::BANK_SIZE]
+        } else if address >= E7Cartridge::PAGES_START {
+            match self.page_offset(address) {
+                (offset, true) => self.ram[offset],
+                (_, false) => WRITE_PORT_READ,
+            }
+        } else if self.lower_bank == E7Cartridge::RAM_BANK {
+            if address >= E7Cartridge::LOWER_RAM_SIZE {
+                self.ram[address - E7Cartridge::LOWER_RAM_SIZE]
+            } else {
+                WRITE_PORT_READ
+            }
+        } else {
+            self.rom[self.lower_bank as usize * E7Cartridge::BANK_SIZE + address]
+        }
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        let address = address & 0xFFF;
+        self.switch_banks(address);
+        let address = address as usize;
+
+        if (E7Cartridge::PAGES_START..E7Cartridge::FIXED_START).contains(&address) {
+            if let (offset, false) = self.page_offset(address) {
+                self.ram[offset] = data;
+            }
+        } else if self.lower_bank == E7Cartridge::RAM_BANK && address < E7Cartridge::LOWER_RAM_SIZE {
+            self.ram[address] = data;
+        }
+    }
+}
+
+impl cartridge::Cartridge for E7Cartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" lower bank: {} ram page: {}", self.lower_bank, self.ram_page);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for E7Cartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.lower_bank);
+        state.put_u8(self.ram_page);
+        state.put_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let lower_bank = state.get_u8()?;
+        let ram_page = state.get_u8()?;
+        if lower_bank > E7Cartridge::RAM_BANK || ram_page >= E7Cartridge::NUM_PAGES {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid E7 banks {} {}", lower_bank, ram_page)));
+        }
+        self.lower_bank = lower_bank;
+        self.ram_page = ram_page;
+        state.get_bytes_into(&mut self.ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+
+    #[test]
+    fn test_e7_banks_and_ram() {
+        // Each 2K bank filled with its bank number.
+        let data: Vec<u8> = (0..0x4000).map(|i| (i / 0x800) as u8).collect();
+        let mut cartridge = get_new_carterage("e7", &data, &CartridgeType::E7);
+        let clock = Clock::new();
+
+        assert_eq!((cartridge.read(&clock, 0x000), cartridge.read(&clock, 0xA00), cartridge.read(&clock, 0xFFF)), (0, 7, 7));
+        cartridge.read(&clock, 0xFE3);
+        assert_eq!(cartridge.read(&clock, 0x7FF), 3);
+
+        // 1K RAM in the lower slice.
+        cartridge.write(&clock, 0xFE7, 0);
+        cartridge.write(&clock, 0x010, 0x55);
+        assert_eq!((cartridge.read(&clock, 0x410), cartridge.read(&clock, 0x010)), (0x55, 0));
+
+        // RAM pages, independent of the lower slice.
+        cartridge.read(&clock, 0xFE9);
+        cartridge.write(&clock, 0x820, 0x11);
+        cartridge.read(&clock, 0xFEB);
+        cartridge.write(&clock, 0x820, 0x33);
+        assert_eq!(cartridge.read(&clock, 0x920), 0x33);
+        cartridge.read(&clock, 0xFE9);
+        assert_eq!(cartridge.read(&clock, 0x920), 0x11);
+
+        // Back to ROM, the RAM is kept.
+        cartridge.read(&clock, 0xFE6);
+        assert_eq!((cartridge.read(&clock, 0x410), cartridge.read(&clock, 0x920)), (6, 0x11));
+        cartridge.read(&clock, 0xFE7);
+        assert_eq!(cartridge.read(&clock, 0x410), 0x55);
+    }
+}
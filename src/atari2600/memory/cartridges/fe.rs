@@ -0,0 +1,132 @@
+//! Activision 'FE' bank switching (8K, two 4K banks).
+//!
+//! There are no hotspots in the cartridge space, instead the cartridge watches the bus for an access
+//! to $01FE (the stack, during JSR/RTS) and selects the bank from the data of the following access,
+//! which is the high byte of the jump/return address:
+//!  - Bit 5 set ($Fxxx): bank 0
+//!  - Bit 5 clear ($Dxxx): bank 1
+
+use super::super::super::clocks;
+use super::super::super::state;
+use super::super::cartridge;
+
+pub struct FECartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    bank: u8,
+    last_access_was_fe: bool,
+}
+
+impl FECartridge {
+    const BANK_SIZE: usize = 0x1000;
+    const NUM_BANKS: u8 = 2;
+    const STACK_HOTSPOT: u16 = 0x01FE;
+    const BANK_BIT: u8 = 0x20;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let rom_size = FECartridge::NUM_BANKS as usize * FECartridge::BANK_SIZE;
+        if data.len() != rom_size {
+            println!("FE cartridge expected {} bytes, found {} (will be truncated/padded with zeros)", rom_size, data.len());
+        }
+        let mut rom = data.to_vec();
+        rom.resize(rom_size, 0);
+
+        Self { info, rom, bank: 0, last_access_was_fe: false }
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        self.rom[self.bank as usize * FECartridge::BANK_SIZE + (address as usize & 0xFFF)]
+    }
+
+    pub fn write(&mut self, _address: u16, _data: u8) {}
+
+    pub fn observe_bus(&mut self, address: u16, data: u8) {
+        if self.last_access_was_fe {
+            self.bank = if 0 != data & FECartridge::BANK_BIT { 0 } else { 1 };
+        }
+        self.last_access_was_fe = FECartridge::STACK_HOTSPOT == address & 0x1FFF;
+    }
+}
+
+impl cartridge::Cartridge for FECartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn bank(&self) -> usize {
+        self.bank as usize
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn observe_bus(&mut self, address: u16, data: u8, _write: bool) {
+        self.observe_bus(address, data);
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" bank: {}", self.bank);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for FECartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.bank);
+        state.put_bool(self.last_access_was_fe);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let bank = state.get_u8()?;
+        if bank >= FECartridge::NUM_BANKS {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid FE bank {}", bank)));
+        }
+        self.bank = bank;
+        self.last_access_was_fe = state.get_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+
+    #[test]
+    fn test_fe_stack_switching() {
+        // Each 4K bank filled with its bank number.
+        let data: Vec<u8> = (0..0x2000).map(|i| (i / 0x1000) as u8).collect();
+        let mut cartridge = get_new_carterage("fe", &data, &CartridgeType::FE);
+        let clock = Clock::new();
+        assert_eq!(cartridge.read(&clock, 0x000), 0);
+
+        // JSR $D000: push PCH ($01FF), PCL ($01FE), then read the high byte of the target.
+        cartridge.observe_bus(0x01FF, 0xF0, true);
+        cartridge.observe_bus(0x01FE, 0x12, true);
+        cartridge.observe_bus(0xF002, 0xD0, false);
+        assert_eq!(cartridge.read(&clock, 0x000), 1);
+
+        // Zero page $FE (without the stack page) is ignored.
+        cartridge.observe_bus(0x00FE, 0x00, true);
+        cartridge.observe_bus(0x0080, 0xF0, false);
+        assert_eq!(cartridge.read(&clock, 0x000), 1);
+
+        // RTS: pull PCL ($01FE), then PCH ($01FF) back to $Fxxx.
+        cartridge.observe_bus(0x01FE, 0x12, false);
+        cartridge.observe_bus(0x01FF, 0xF0, false);
+        assert_eq!(cartridge.read(&clock, 0x000), 0);
+    }
+}
@@ -0,0 +1,210 @@
+//! Bank switching schemes that swap the whole 4K cartridge window, but with hotspots that aren't a
+//! contiguous range at the top of the cartridge (see 'GenericCartridge' for those).
+//!  - F0 (Dynacom Megaboy, 64K): Accessing $1FF0 selects the next bank (wrapping after 16 banks)
+//!  - UA (UA Limited, 8K): Accessing $0220 selects bank 0, $0240 bank 1
+//!  - 0840 (Econobanking, 8K): Accessing $0800 selects bank 0, $0840 bank 1
+//!  - SB (SuperBanking, 128K/256K): Accessing $0800-$0FFF selects bank (address & (banks - 1))
+//!  - X07 (AtariAge, 64K): Accessing $080D-$0FFD (address & $180F == $080D) selects bank (address >> 4) & 15,
+//!    from banks 14 and 15 a TIA access (address & $1880 == 0) selects bank 14 | A6.
+//!
+//! Hotspots below $1000 are seen via 'observe_bus', so they work for reads and writes (where the TIA/RIOT
+//! also responds).
+
+use super::super::super::clocks;
+use super::super::super::state;
+use super::super::cartridge;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scheme {
+    F0,
+    UA,
+    C0840,
+    SB,
+    X07,
+}
+
+pub struct HotspotCartridge {
+    info: cartridge::CartridgeInfo,
+    rom: Vec<u8>,
+    scheme: Scheme,
+    num_banks: usize,
+    bank: usize,
+}
+
+impl HotspotCartridge {
+    const BANK_SIZE: usize = 0x1000;
+    const F0_HOTSPOT: u16 = 0xFF0;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8], scheme: Scheme) -> Self {
+        let num_banks = match scheme {
+            Scheme::F0 | Scheme::X07 => 16,
+            Scheme::UA | Scheme::C0840 => 2,
+            // 128K or 256K (power of two banks, the hotspot is masked with the bank count).
+            Scheme::SB => data.len().div_ceil(HotspotCartridge::BANK_SIZE).clamp(2, 64).next_power_of_two(),
+        };
+        let rom_size = num_banks * HotspotCartridge::BANK_SIZE;
+        if data.len() != rom_size {
+            println!("{:?} cartridge expected {} bytes, found {} (will be truncated/padded with zeros)", scheme, rom_size, data.len());
+        }
+        let mut rom = data.to_vec();
+        rom.resize(rom_size, 0);
+
+        let bank = match scheme {
+            Scheme::F0 | Scheme::SB => num_banks - 1,
+            Scheme::UA | Scheme::C0840 | Scheme::X07 => 0,
+        };
+        Self { info, rom, scheme, num_banks, bank }
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        self.switch_banks(address);
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        self.rom[self.bank * HotspotCartridge::BANK_SIZE + (address as usize & 0xFFF)]
+    }
+
+    pub fn write(&mut self, address: u16, _data: u8) {
+        self.switch_banks(address & 0xFFF);
+    }
+
+    // Hotspots in the cartridge address space.
+    fn switch_banks(&mut self, address: u16) {
+        if Scheme::F0 == self.scheme && HotspotCartridge::F0_HOTSPOT == address {
+            self.bank = (self.bank + 1) % self.num_banks;
+        }
+    }
+
+    // Hotspots below $1000 (the TIA/RIOT address space).
+    pub fn observe_bus(&mut self, address: u16) {
+        let address = address & 0x1FFF;
+        match self.scheme {
+            Scheme::UA => match address & 0x1260 {
+                0x0220 => self.bank = 0,
+                0x0240 => self.bank = 1,
+                _ => {}
+            },
+            Scheme::C0840 => match address & 0x1840 {
+                0x0800 => self.bank = 0,
+                0x0840 => self.bank = 1,
+                _ => {}
+            },
+            Scheme::SB => {
+                if 0x0800 == address & 0x1800 {
+                    self.bank = address as usize & (self.num_banks - 1);
+                }
+            }
+            Scheme::X07 => {
+                if 0x080D == address & 0x180F {
+                    self.bank = (address as usize >> 4) & 0xF;
+                } else if 0 == address & 0x1880 && 0xE == self.bank & 0xE {
+                    self.bank = 0xE | ((address as usize >> 6) & 1);
+                }
+            }
+            Scheme::F0 => {}
+        }
+    }
+}
+
+impl cartridge::Cartridge for HotspotCartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn bank(&self) -> usize {
+        self.bank
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn observe_bus(&mut self, address: u16, _data: u8, _write: bool) {
+        self.observe_bus(address);
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" num banks: {}", self.num_banks);
+        println!(" bank: {}", self.bank);
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for HotspotCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_u8(self.bank as u8);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        let bank = state.get_u8()? as usize;
+        if bank >= self.num_banks {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid {:?} bank {}", self.scheme, bank)));
+        }
+        self.bank = bank;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, CartridgeType};
+
+    // Each 4K bank filled with its bank number.
+    fn banked_data(banks: usize) -> Vec<u8> {
+        (0..banks * 0x1000).map(|i| (i / 0x1000) as u8).collect()
+    }
+
+    #[test]
+    fn test_hotspot_cartridges() {
+        let clock = Clock::new();
+
+        let mut f0 = get_new_carterage("f0", &banked_data(16), &CartridgeType::F0);
+        assert_eq!(f0.read(&clock, 0x000), 15);
+        f0.read(&clock, 0xFF0);
+        assert_eq!(f0.read(&clock, 0x000), 0);
+        f0.write(&clock, 0xFF0, 0);
+        assert_eq!(f0.read(&clock, 0x000), 1);
+
+        let mut ua = get_new_carterage("ua", &banked_data(2), &CartridgeType::UA);
+        assert_eq!(ua.read(&clock, 0x000), 0);
+        ua.observe_bus(0x0240, 0, false);
+        assert_eq!(ua.read(&clock, 0x000), 1);
+        ua.observe_bus(0x0220, 0, true);
+        assert_eq!(ua.read(&clock, 0x000), 0);
+
+        let mut c0840 = get_new_carterage("0840", &banked_data(2), &CartridgeType::C0840);
+        c0840.observe_bus(0x0840, 0, false);
+        assert_eq!(c0840.read(&clock, 0x000), 1);
+        c0840.observe_bus(0x0800, 0, false);
+        assert_eq!(c0840.read(&clock, 0x000), 0);
+
+        let mut sb = get_new_carterage("sb", &banked_data(32), &CartridgeType::SB);
+        assert_eq!(sb.read(&clock, 0x000), 31);
+        sb.observe_bus(0x0805, 0, false);
+        assert_eq!(sb.read(&clock, 0x000), 5);
+        sb.observe_bus(0x0835, 0, false); // Masked to 32 banks.
+        assert_eq!(sb.read(&clock, 0x000), 21);
+
+        let mut x07 = get_new_carterage("x07", &banked_data(16), &CartridgeType::X07);
+        assert_eq!(x07.read(&clock, 0x000), 0);
+        x07.observe_bus(0x0002, 0, true); // TIA access, ignored outside of banks 14/15.
+        assert_eq!(x07.read(&clock, 0x000), 0);
+        x07.observe_bus(0x08ED, 0, false);
+        assert_eq!(x07.read(&clock, 0x000), 14);
+        x07.observe_bus(0x0042, 0, false);
+        assert_eq!(x07.read(&clock, 0x000), 15);
+        x07.observe_bus(0x0002, 0, true);
+        assert_eq!(x07.read(&clock, 0x000), 14);
+    }
+}
@@ -0,0 +1,12 @@
+pub mod c3f;
+pub mod c4a50;
+pub mod cdf;
+pub mod dpc;
+pub mod dpcplus;
+pub mod e0;
+pub mod e7;
+pub mod fe;
+pub mod hotspots;
+pub mod supercharger;
+pub mod tape;
+pub mod thumb;
@@ -0,0 +1,317 @@
+//! Starpath Supercharger 'AR'.
+//!
+//! 6K of RAM (three 2K banks) and a 2K BIOS ROM, mapped into the lower ($1000-$17FF) and upper
+//! ($1800-$1FFF) slices by the bank configuration:
+//!  - Reading $1000-$10FF latches the low byte of the address in the data hold register.
+//!  - Accessing $1FF8 sets the configuration from the data hold register, bits 2-4 select the banks
+//!    (see 'CONFIGURATIONS'), bit 1 enables writes and bit 0 powers off the ROM.
+//!  - With writes enabled, the data hold register is written to the RAM address accessed exactly
+//!    5 (distinct) bus accesses after it was latched, ie 'CMP $F0xx, NOP, CMP $Fyyy'.
+//!
+//! The tape loading BIOS isn't emulated, instead loads are copied directly from the multi-load image
+//! (or the demodulated tape) when the BIOS reads $1850.  The load number is the last value written to
+//! $80 (set by the game before jumping to the BIOS at $F800 for multi-load games).
+
+use super::super::super::clocks;
+use super::super::super::state;
+use super::super::cartridge;
+use super::tape;
+
+pub struct SuperchargerCartridge {
+    info: cartridge::CartridgeInfo,
+    ram: Vec<u8>,
+    bios: Vec<u8>,
+    loads: Vec<u8>,
+    slices: [u8; 2], // RAM banks, or 'ROM_BANK'.
+    write_enabled: bool,
+    data_hold: u8,
+    write_pending: bool,
+    hold_accesses: u32,
+    distinct_accesses: u32,
+    last_address: u16,
+    requested_load: u8,
+}
+
+impl SuperchargerCartridge {
+    const SLICE_SIZE: usize = 0x800;
+    const NUM_RAM_BANKS: usize = 3;
+    const ROM_BANK: u8 = 3;
+    const DATA_HOLD_MASK: u16 = 0xF00;
+    const CONFIGURATION_HOTSPOT: u16 = 0xFF8;
+    const LOAD_HOTSPOT: u16 = 0x850;
+    const WRITE_DELAY: u32 = 5;
+    const LOAD_NUMBER_ADDRESS: u16 = 0x80;
+
+    // Lower and upper slice banks, for bits 2-4 of the configuration.
+    const CONFIGURATIONS: [[u8; 2]; 8] = [[2, 3], [0, 3], [2, 0], [0, 2], [2, 3], [1, 3], [2, 1], [1, 2]];
+
+    // BIOS replacement, at $F800: Call the load hotspot, copy the trampoline (below) to $F0 and run it.
+    const BIOS_CODE: [u8; 21] = [
+        0x78, 0xD8, 0xA2, 0xFF, 0x9A, // SEI, CLD, LDX #$FF, TXS
+        0xAD, 0x50, 0xF8, // LDA $F850 (load the RAM)
+        0xA2, 0x08, // LDX #8
+        0xBD, 0x80, 0xF8, // LDA $F880,X
+        0x95, 0xF0, // STA $F0,X
+        0xCA, // DEX
+        0x10, 0xF8, // BPL $F80A
+        0x4C, 0xF0, 0x00, // JMP $00F0
+    ];
+
+    // Run from RAM as the BIOS may be switched out, set the configuration and start the load.
+    const TRAMPOLINE: usize = 0x080;
+    const TRAMPOLINE_CODE: [u8; 9] = [
+        0xCD, 0x00, 0xF0, // CMP $F0xx (configuration)
+        0xCD, 0xF8, 0xFF, // CMP $FFF8
+        0x4C, 0x00, 0x00, // JMP start
+    ];
+    const TRAMPOLINE_CONFIGURATION: usize = SuperchargerCartridge::TRAMPOLINE + 1;
+    const TRAMPOLINE_START: usize = SuperchargerCartridge::TRAMPOLINE + 7;
+
+    pub fn new(info: cartridge::CartridgeInfo, data: &[u8]) -> Self {
+        let loads = if is_wav(data) {
+            tape::demodulate_wav(std::io::Cursor::new(data)).unwrap_or_else(|e| {
+                println!("Error reading the Supercharger tape: {}", e);
+                Vec::new()
+            })
+        } else if !data.is_empty() && data.len().is_multiple_of(tape::LOAD_SIZE) {
+            data.to_vec()
+        } else {
+            println!("Supercharger image expected a multiple of {} bytes, found {}", tape::LOAD_SIZE, data.len());
+            Vec::new()
+        };
+
+        let mut bios = vec![0; SuperchargerCartridge::SLICE_SIZE];
+        bios[..SuperchargerCartridge::BIOS_CODE.len()].copy_from_slice(&SuperchargerCartridge::BIOS_CODE);
+        bios[SuperchargerCartridge::TRAMPOLINE..SuperchargerCartridge::TRAMPOLINE + SuperchargerCartridge::TRAMPOLINE_CODE.len()].copy_from_slice(&SuperchargerCartridge::TRAMPOLINE_CODE);
+        // NMI, reset and IRQ vectors all start the BIOS.
+        bios[0x7FA..].copy_from_slice(&[0x00, 0xF8, 0x00, 0xF8, 0x00, 0xF8]);
+
+        // Start with the first load on the 'tape'.
+        let requested_load = loads.get(tape::LOAD_DATA_SIZE + 5).copied().unwrap_or(0);
+
+        let mut cartridge = Self {
+            info,
+            ram: vec![0; SuperchargerCartridge::NUM_RAM_BANKS * SuperchargerCartridge::SLICE_SIZE],
+            bios,
+            loads,
+            slices: [0; 2],
+            write_enabled: false,
+            data_hold: 0,
+            write_pending: false,
+            hold_accesses: 0,
+            distinct_accesses: 0,
+            last_address: 0,
+            requested_load,
+        };
+        cartridge.configure(0);
+        cartridge
+    }
+
+    fn num_loads(&self) -> usize {
+        self.loads.len() / tape::LOAD_SIZE
+    }
+
+    fn configure(&mut self, configuration: u8) {
+        self.slices = SuperchargerCartridge::CONFIGURATIONS[((configuration >> 2) & 0x07) as usize];
+        self.write_enabled = 0 != configuration & 0x02;
+    }
+
+    // Copy the load's pages into RAM, and set up the BIOS to start it.
+    fn load(&mut self, load_number: u8) {
+        let Some(load) = self.loads.chunks(tape::LOAD_SIZE).find(|load| load[tape::LOAD_DATA_SIZE + 5] == load_number) else {
+            println!("Supercharger load {} is missing", load_number);
+            return;
+        };
+
+        let header = &load[tape::LOAD_DATA_SIZE..];
+        if tape::checksum(&header[..tape::HEADER_LENGTH]) != tape::CHECKSUM {
+            println!("Supercharger load {} has an invalid header checksum", load_number);
+        }
+
+        for page in 0..std::cmp::min(header[3] as usize, tape::MAX_PAGES) {
+            let location = header[tape::PAGE_LOCATIONS + page];
+            let bank = (location & 0x03) as usize;
+            let offset = ((location >> 2) & 0x07) as usize * tape::PAGE_SIZE;
+            if bank < SuperchargerCartridge::NUM_RAM_BANKS {
+                let destination = bank * SuperchargerCartridge::SLICE_SIZE + offset;
+                self.ram[destination..destination + tape::PAGE_SIZE].copy_from_slice(&load[page * tape::PAGE_SIZE..(page + 1) * tape::PAGE_SIZE]);
+            }
+        }
+
+        self.bios[SuperchargerCartridge::TRAMPOLINE_CONFIGURATION] = header[2];
+        self.bios[SuperchargerCartridge::TRAMPOLINE_START] = header[0];
+        self.bios[SuperchargerCartridge::TRAMPOLINE_START + 1] = header[1];
+    }
+
+    // Reads and writes are the same, only the address is used.
+    fn access(&mut self, address: u16) {
+        let accesses = self.distinct_accesses.wrapping_sub(self.hold_accesses);
+        if self.write_pending && accesses > SuperchargerCartridge::WRITE_DELAY {
+            self.write_pending = false;
+        }
+
+        if 0 == address & SuperchargerCartridge::DATA_HOLD_MASK && (!self.write_enabled || !self.write_pending) {
+            self.data_hold = address as u8;
+            self.hold_accesses = self.distinct_accesses;
+            self.write_pending = true;
+        } else if SuperchargerCartridge::CONFIGURATION_HOTSPOT == address {
+            self.write_pending = false;
+            self.configure(self.data_hold);
+        } else if self.write_enabled && self.write_pending && SuperchargerCartridge::WRITE_DELAY == accesses {
+            let bank = self.slices[address as usize / SuperchargerCartridge::SLICE_SIZE];
+            if bank != SuperchargerCartridge::ROM_BANK {
+                self.ram[bank as usize * SuperchargerCartridge::SLICE_SIZE + address as usize % SuperchargerCartridge::SLICE_SIZE] = self.data_hold;
+            }
+            self.write_pending = false;
+        } else if SuperchargerCartridge::LOAD_HOTSPOT == address && SuperchargerCartridge::ROM_BANK == self.slices[1] {
+            self.load(self.requested_load);
+        }
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        self.access(address);
+        self.peek(address)
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        let address = address & 0xFFF;
+        let offset = address as usize % SuperchargerCartridge::SLICE_SIZE;
+        match self.slices[address as usize / SuperchargerCartridge::SLICE_SIZE] {
+            SuperchargerCartridge::ROM_BANK => self.bios[offset],
+            bank => self.ram[bank as usize * SuperchargerCartridge::SLICE_SIZE + offset],
+        }
+    }
+
+    pub fn write(&mut self, address: u16, _data: u8) {
+        self.access(address & 0xFFF);
+    }
+
+    pub fn observe_bus(&mut self, address: u16, data: u8, write: bool) {
+        if write && SuperchargerCartridge::LOAD_NUMBER_ADDRESS == address & 0x1FFF {
+            self.requested_load = data;
+        }
+        if address != self.last_address {
+            self.distinct_accesses = self.distinct_accesses.wrapping_add(1);
+            self.last_address = address;
+        }
+    }
+}
+
+/// WAV (RIFF) audio, rather than a binary image.
+pub fn is_wav(data: &[u8]) -> bool {
+    data.len() > 12 && b"RIFF" == &data[..4] && b"WAVE" == &data[8..12]
+}
+
+impl cartridge::Cartridge for SuperchargerCartridge {
+    fn read(&mut self, _clock: &clocks::Clock, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, _clock: &clocks::Clock, address: u16, data: u8) {
+        self.write(address, data);
+    }
+
+    fn observe_bus(&mut self, address: u16, data: u8, write: bool) {
+        self.observe_bus(address, data, write);
+    }
+
+    fn summary(&self) {
+        self.info.summary();
+        println!(" loads: {}", self.num_loads());
+    }
+
+    fn info(&self) -> &cartridge::CartridgeInfo {
+        &self.info
+    }
+}
+
+impl state::Snapshot for SuperchargerCartridge {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        state.put_bytes(&self.ram);
+        state.put_bytes(&self.bios);
+        state.put_bytes(&self.slices);
+        state.put_bool(self.write_enabled);
+        state.put_u8(self.data_hold);
+        state.put_bool(self.write_pending);
+        state.put_u32(self.hold_accesses);
+        state.put_u32(self.distinct_accesses);
+        state.put_u16(self.last_address);
+        state.put_u8(self.requested_load);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        state.get_bytes_into(&mut self.ram)?;
+        state.get_bytes_into(&mut self.bios)?;
+        let mut slices = [0_u8; 2];
+        state.get_bytes_into(&mut slices)?;
+        if slices.iter().any(|bank| *bank > SuperchargerCartridge::ROM_BANK) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid Supercharger banks {:?}", slices)));
+        }
+        self.slices = slices;
+        self.write_enabled = state.get_bool()?;
+        self.data_hold = state.get_u8()?;
+        self.write_pending = state.get_bool()?;
+        self.hold_accesses = state.get_u32()?;
+        self.distinct_accesses = state.get_u32()?;
+        self.last_address = state.get_u16()?;
+        self.requested_load = state.get_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::cartridge::{get_new_carterage, Cartridge, CartridgeType};
+    use crate::atari2600::memory::cartridges::tape;
+
+    #[test]
+    fn test_supercharger_load_and_write() {
+        // Single load, one page (0x5A filled) into bank 2 page 1, configuration $0E (bank 0 lower, bank 2 upper, writes enabled).
+        let mut data = vec![0x5A_u8; tape::LOAD_SIZE];
+        data[tape::LOAD_DATA_SIZE..tape::LOAD_DATA_SIZE + tape::HEADER_LENGTH].copy_from_slice(&[0x00, 0xF1, 0x0E, 1, 0, 7, 0, 0]);
+        data[tape::LOAD_DATA_SIZE + tape::PAGE_LOCATIONS] = (1 << 2) | 2;
+        let mut cartridge = get_new_carterage("ar", &data, &CartridgeType::Supercharger);
+        let clock = Clock::new();
+
+        // Starts with bank 2 (lower) and the BIOS (upper), reset to $F800.
+        assert_eq!((cartridge.read(&clock, 0xFFC), cartridge.read(&clock, 0xFFD)), (0x00, 0xF8));
+        assert_eq!(cartridge.read(&clock, 0x100), 0x00);
+        cartridge.read(&clock, 0x850);
+        assert_eq!((cartridge.read(&clock, 0x100), cartridge.read(&clock, 0x0FF)), (0x5A, 0x00));
+        assert_eq!([0x881, 0x887, 0x888].map(|a| cartridge.read(&clock, a)), [0x0E, 0x00, 0xF1]);
+
+        // Configure, 'CMP $F00E, CMP $FFF8'.
+        cartridge.read(&clock, 0x00E);
+        cartridge.observe_bus(0xF00E, 0, false);
+        cartridge.read(&clock, 0xFF8);
+        cartridge.observe_bus(0xFFF8, 0, false);
+        assert_eq!(cartridge.read(&clock, 0x900), 0x5A);
+
+        // Write $42 to $F123, 5 accesses after the data hold.
+        let write = |cartridge: &mut Box<dyn Cartridge>, value: u16, address: u16, delay: u16| {
+            cartridge.read(&clock, value);
+            cartridge.observe_bus(0xF000 | value, 0, false);
+            for i in 0..delay - 1 {
+                cartridge.observe_bus(0xF900 + i, 0, false);
+            }
+            cartridge.read(&clock, address);
+        };
+        write(&mut cartridge, 0x42, 0x123, 5);
+        assert_eq!(cartridge.read(&clock, 0x123), 0x42);
+        write(&mut cartridge, 0x43, 0x124, 6);
+        assert_eq!(cartridge.read(&clock, 0x124), 0x00);
+
+        // Multi-load, the requested load is written to $80.
+        cartridge.observe_bus(0x0080, 3, true);
+        cartridge.read(&clock, 0x004); // Bank 0 lower, BIOS upper
+        cartridge.read(&clock, 0xFF8);
+        cartridge.read(&clock, 0x850); // Load 3 is missing
+        assert_eq!((cartridge.read(&clock, 0x123), cartridge.read(&clock, 0x888)), (0x42, 0xF1));
+    }
+}
@@ -0,0 +1,244 @@
+//! Supercharger cassette tape demodulator.
+//!
+//! Converts the audio from a Supercharger tape (WAV) into the same multi-load image format as '.bin'
+//! files, so both can be 'loaded' by the Supercharger cartridge.
+//!
+//! Each bit on the tape is a single cycle of the audio, a short cycle for '0' and a long cycle for '1'
+//! (bytes are sent most significant bit first).  A load is:
+//!  - Pilot tone ($55 bytes, alternating 0/1 bits), ending with a sync byte of $54
+//!  - 8 byte header: start address (low, high), bank configuration, page count, checksum, load number,
+//!    progress bar speed (2 bytes).
+//!  - For each page: page location (bits 0-1 bank, bits 2-4 page), page checksum and 256 data bytes.
+//!
+//! Sums of the header bytes, and of each page (including its location and checksum) are $55.
+
+use std::io::{Error, ErrorKind};
+
+/// Size of each load in a multi-load image, 32 pages of data, followed by a 256 byte header.
+pub const LOAD_SIZE: usize = 8448;
+pub const LOAD_DATA_SIZE: usize = 8192;
+pub const HEADER_LENGTH: usize = 8;
+pub const PAGE_LOCATIONS: usize = 16; // Header offset of the page locations.
+pub const PAGE_CHECKSUMS: usize = 64; // Header offset of the page checksums.
+pub const PAGE_SIZE: usize = 256;
+pub const MAX_PAGES: usize = LOAD_DATA_SIZE / PAGE_SIZE;
+pub const CHECKSUM: u8 = 0x55;
+
+const PILOT_BYTE: u32 = 0x55;
+const SYNC_BYTE: u32 = 0x54;
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0_u8, |sum, x| sum.wrapping_add(*x))
+}
+
+/// Decode the WAV data of a Supercharger tape, returns the loads (each 'LOAD_SIZE' bytes).
+pub fn demodulate_wav<R: std::io::Read>(reader: R) -> std::io::Result<Vec<u8>> {
+    let wav = hound::WavReader::new(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    let spec = wav.spec();
+    let samples: Vec<i64> = match spec.sample_format {
+        hound::SampleFormat::Int => wav.into_samples::<i32>().map(|s| s.map(|x| x as i64)).collect::<Result<_, _>>(),
+        hound::SampleFormat::Float => wav.into_samples::<f32>().map(|s| s.map(|x| (x * i32::MAX as f32) as i64)).collect::<Result<_, _>>(),
+    }
+    .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+    // Only the first channel is used.
+    let mono: Vec<i64> = samples.iter().step_by(spec.channels.max(1) as usize).copied().collect();
+    demodulate(&mono)
+}
+
+/// Lengths (in samples) of each audio cycle, measured between rising edges (with some hysteresis to ignore noise).
+fn cycle_lengths(samples: &[i64]) -> Vec<usize> {
+    let hysteresis = samples.iter().map(|x| x.abs()).max().unwrap_or(0) / 8;
+    let mut lengths = Vec::new();
+    let mut high = false;
+    let mut last_edge: Option<usize> = None;
+
+    for (i, sample) in samples.iter().enumerate() {
+        if !high && *sample > hysteresis {
+            high = true;
+            if let Some(last) = last_edge {
+                lengths.push(i - last);
+            }
+            last_edge = Some(i);
+        } else if high && *sample < -hysteresis {
+            high = false;
+        }
+    }
+    lengths
+}
+
+/// Split the cycle lengths into short ('0') and long ('1'), returns the threshold and the length of a '1'.
+fn bit_threshold(lengths: &[usize]) -> (f64, f64) {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_unstable();
+    let median = sorted[sorted.len() / 2] as f64;
+
+    // Ignore gaps between loads (much longer than a bit).
+    let bits: Vec<f64> = sorted.into_iter().map(|x| x as f64).filter(|x| *x < 4.0 * median).collect();
+    let mut threshold = bits.iter().sum::<f64>() / bits.len() as f64;
+    let mut one = threshold;
+    for _ in 0..16 {
+        let (zeros, ones): (Vec<f64>, Vec<f64>) = bits.iter().partition(|x| **x < threshold);
+        if zeros.is_empty() || ones.is_empty() {
+            break;
+        }
+        one = ones.iter().sum::<f64>() / ones.len() as f64;
+        threshold = (zeros.iter().sum::<f64>() / zeros.len() as f64 + one) / 2.0;
+    }
+    (threshold, one)
+}
+
+fn demodulate(samples: &[i64]) -> std::io::Result<Vec<u8>> {
+    let lengths = cycle_lengths(samples);
+    if lengths.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "No signal found in the tape"));
+    }
+    let (threshold, one) = bit_threshold(&lengths);
+
+    // 'None' for gaps in the signal.
+    let bits: Vec<Option<u8>> = lengths.iter().map(|x| *x as f64).map(|x| if x > 2.0 * one { None } else { Some((x >= threshold) as u8) }).collect();
+
+    let mut loads = Vec::new();
+    let mut position = 0;
+    while let Some(start) = find_sync(&bits, position) {
+        let mut reader = ByteReader { bits: &bits, position: start };
+        match read_load(&mut reader) {
+            Some(load) => loads.extend(load),
+            None => println!("Supercharger tape: incomplete load at bit {}", start),
+        }
+        position = reader.position;
+    }
+
+    if loads.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "No Supercharger loads found in the tape"));
+    }
+    Ok(loads)
+}
+
+// Find the end of the next pilot tone (at least a couple of pilot bytes, then the sync byte), returns the next bit.
+fn find_sync(bits: &[Option<u8>], start: usize) -> Option<usize> {
+    let mut shift: u32 = 0;
+    for (i, bit) in bits.iter().enumerate().skip(start) {
+        shift = match bit {
+            Some(bit) => (shift << 1) | *bit as u32,
+            None => 0,
+        };
+        if (shift & 0xFFFFFF) == ((PILOT_BYTE << 16) | (PILOT_BYTE << 8) | SYNC_BYTE) {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+struct ByteReader<'a> {
+    bits: &'a [Option<u8>],
+    position: usize,
+}
+
+impl ByteReader<'_> {
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = (byte << 1) | (*self.bits.get(self.position)?)?;
+            self.position += 1;
+        }
+        Some(byte)
+    }
+
+    fn read_into(&mut self, data: &mut [u8]) -> Option<()> {
+        for x in data.iter_mut() {
+            *x = self.read_byte()?;
+        }
+        Some(())
+    }
+}
+
+fn read_load(reader: &mut ByteReader) -> Option<Vec<u8>> {
+    let mut load = vec![0; LOAD_SIZE];
+    let (data, header) = load.split_at_mut(LOAD_DATA_SIZE);
+
+    reader.read_into(&mut header[..HEADER_LENGTH])?;
+    if checksum(&header[..HEADER_LENGTH]) != CHECKSUM {
+        println!("Supercharger tape: invalid header checksum");
+    }
+
+    let pages = header[3] as usize;
+    if pages > MAX_PAGES {
+        println!("Supercharger tape: invalid page count {}", pages);
+        return None;
+    }
+
+    for page in 0..pages {
+        header[PAGE_LOCATIONS + page] = reader.read_byte()?;
+        header[PAGE_CHECKSUMS + page] = reader.read_byte()?;
+        reader.read_into(&mut data[page * PAGE_SIZE..(page + 1) * PAGE_SIZE])?;
+
+        let sum = checksum(&data[page * PAGE_SIZE..(page + 1) * PAGE_SIZE]).wrapping_add(header[PAGE_LOCATIONS + page]).wrapping_add(header[PAGE_CHECKSUMS + page]);
+        if sum != CHECKSUM {
+            println!("Supercharger tape: invalid checksum for page {}", page);
+        }
+    }
+    Some(load)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::memory::cartridges::tape;
+
+    // Modulate bytes as one (sine) cycle per bit, 'zero'/'one' samples long.
+    fn modulate(bytes: &[u8], zero: usize, one: usize, samples: &mut Vec<i16>) {
+        for byte in bytes {
+            for bit in (0..8).rev() {
+                let length = if 0 != (byte >> bit) & 1 { one } else { zero };
+                samples.extend((0..length).map(|i| ((i as f64 + 0.5) * std::f64::consts::TAU / length as f64).sin() * 12000.0).map(|x| x as i16));
+            }
+        }
+    }
+
+    #[test]
+    fn test_demodulate_wav() {
+        // Two loads, with 1 and 2 pages.
+        let mut expected = Vec::new();
+        let mut tape_bytes = Vec::new();
+        for (load_number, pages) in [(0_u8, 1_usize), (1, 2)] {
+            let mut load = vec![0_u8; tape::LOAD_SIZE];
+            let mut header = [0x00, 0xF0, 0x0D, pages as u8, 0, load_number, 0x40, 0x00];
+            header[4] = tape::CHECKSUM.wrapping_sub(tape::checksum(&header));
+            load[tape::LOAD_DATA_SIZE..tape::LOAD_DATA_SIZE + tape::HEADER_LENGTH].copy_from_slice(&header);
+
+            tape_bytes.extend(vec![0x55; 64]);
+            tape_bytes.push(0x54);
+            tape_bytes.extend(header);
+            for page in 0..pages {
+                let data: Vec<u8> = (0..tape::PAGE_SIZE).map(|i| (i * 7 + page + load_number as usize) as u8).collect();
+                let location = (page << 2) as u8;
+                let page_checksum = tape::CHECKSUM.wrapping_sub(tape::checksum(&data)).wrapping_sub(location);
+                load[page * tape::PAGE_SIZE..(page + 1) * tape::PAGE_SIZE].copy_from_slice(&data);
+                load[tape::LOAD_DATA_SIZE + tape::PAGE_LOCATIONS + page] = location;
+                load[tape::LOAD_DATA_SIZE + tape::PAGE_CHECKSUMS + page] = page_checksum;
+                tape_bytes.extend([location, page_checksum]);
+                tape_bytes.extend(data);
+            }
+            tape_bytes.extend(vec![0x55; 8]);
+            expected.extend(load);
+        }
+
+        // Some silence before the tape starts.
+        let mut samples = vec![0_i16; 1000];
+        modulate(&tape_bytes, 10, 15, &mut samples);
+
+        let mut wav = std::io::Cursor::new(Vec::new());
+        let spec = hound::WavSpec { channels: 1, sample_rate: 44100, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let loads = tape::demodulate_wav(std::io::Cursor::new(wav.into_inner())).unwrap();
+        assert_eq!(loads.len(), 2 * tape::LOAD_SIZE);
+        assert!(loads == expected);
+
+        assert!(tape::demodulate_wav(std::io::Cursor::new(vec![0_u8; 16])).is_err());
+    }
+}
@@ -0,0 +1,476 @@
+//! ARM7TDMI 'Thumb' instruction set interpreter, for the ARM based (Harmony/Melody) cartridges.
+//!
+//! Only the Thumb (16 bit) instructions are interpreted.  A 'BX' to an ARM (even) address stops the
+//! interpreter, this is how the custom code returns to the cartridge driver (and so the 6507), or calls
+//! one of the driver's ARM routines (which the cartridge emulates, then resumes the Thumb code).
+//!
+//! Memory map (LPC2103):
+//!  - $00000000: Flash (the cartridge ROM image)
+//!  - $40000000: SRAM
+//!  - $E0000000: Peripherals, only the timer 1 counter ($E0008008) is emulated (counting cycles)
+//!
+//! The cycle count is approximate (no flash/RAM wait states), it's used for the timer and to hold the 6507
+//! for the time the code takes, at the Harmony's 70MHz (see 'cpu_cycles').
+
+pub const RAM_BASE: u32 = 0x4000_0000;
+const PERIPHERAL_BASE: u32 = 0xE000_0000;
+const TIMER1_COUNTER: u32 = 0xE000_8008;
+
+// Harmony/Melody ARM clock and the (NTSC) 6507 clock.
+const ARM_HZ: u64 = 70_000_000;
+const CPU_HZ: u64 = 1_193_182;
+
+/// The 6507 cycles taken by a number of ARM cycles (rounded up).
+pub fn cpu_cycles(arm_cycles: u64) -> u64 {
+    (arm_cycles * CPU_HZ).div_ceil(ARM_HZ)
+}
+
+// Stops runaway code (ie waiting on a peripheral that isn't emulated).
+const INSTRUCTION_LIMIT: u32 = 500_000;
+
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+
+/// Why the interpreter stopped.
+#[derive(Debug, PartialEq)]
+pub enum Exit {
+    /// 'BX' (or 'POP {PC}') to an ARM address, the address of the instruction.
+    Arm(u32),
+    /// Invalid instruction/memory access, or the instruction limit was reached.
+    Fault(String),
+}
+
+/// The memory the ARM can access.
+pub struct Bus<'a> {
+    pub rom: &'a [u8],
+    pub ram: &'a mut [u8],
+}
+
+impl Bus<'_> {
+    fn read(&self, address: u32, size: u32, cycles: u64) -> Result<u32, Exit> {
+        let address = address & !(size - 1);
+        let (memory, offset): (&[u8], usize) = if (address as usize) < self.rom.len() {
+            (self.rom, address as usize)
+        } else if address >= RAM_BASE && ((address - RAM_BASE) as usize) < self.ram.len() {
+            (self.ram, (address - RAM_BASE) as usize)
+        } else if TIMER1_COUNTER == address {
+            return Ok(cycles as u32);
+        } else if address >= PERIPHERAL_BASE {
+            return Ok(0);
+        } else {
+            return Err(Exit::Fault(format!("Read from {:#010X}", address)));
+        };
+        Ok((0..size as usize).fold(0, |value, i| value | (memory[offset + i] as u32) << (8 * i)))
+    }
+
+    fn write(&mut self, address: u32, size: u32, data: u32) -> Result<(), Exit> {
+        let address = address & !(size - 1);
+        if address >= RAM_BASE && ((address - RAM_BASE) as usize) < self.ram.len() {
+            let offset = (address - RAM_BASE) as usize;
+            for i in 0..size as usize {
+                self.ram[offset + i] = (data >> (8 * i)) as u8;
+            }
+            Ok(())
+        } else if address >= PERIPHERAL_BASE {
+            Ok(())
+        } else {
+            Err(Exit::Fault(format!("Write to {:#010X}", address)))
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Shift {
+    Lsl,
+    Lsr,
+    Asr,
+    Ror,
+}
+
+pub struct Thumb {
+    registers: [u32; 16], // PC is the address of the current instruction (reads add the pipeline offset).
+    n: bool,
+    z: bool,
+    c: bool,
+    v: bool,
+    /// Approximate ARM cycles executed (one per instruction, plus memory accesses and branches).
+    pub cycles: u64,
+}
+
+impl Default for Thumb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Thumb {
+    pub fn new() -> Self {
+        Self {
+            registers: [0; 16],
+            n: false,
+            z: false,
+            c: false,
+            v: false,
+            cycles: 0,
+        }
+    }
+
+    pub fn register(&self, register: usize) -> u32 {
+        self.registers[register]
+    }
+
+    pub fn set_register(&mut self, register: usize, value: u32) {
+        self.registers[register] = value;
+    }
+
+    /// Start a call to the Thumb code at 'entry', which returns (via 'BX LR') to the ARM 'return_address'.
+    pub fn call(&mut self, entry: u32, return_address: u32, stack: u32) {
+        self.registers = [0; 16];
+        self.registers[PC] = entry & !1;
+        self.registers[LR] = return_address;
+        self.registers[SP] = stack;
+        (self.n, self.z, self.c, self.v) = (false, false, false, false);
+    }
+
+    /// Return from an ARM routine (after 'Exit::Arm'), back to the Thumb code that called it.
+    pub fn resume(&mut self) {
+        self.registers[PC] = self.registers[LR] & !1;
+    }
+
+    /// Run until the code switches to ARM mode (or faults).
+    pub fn run(&mut self, bus: &mut Bus) -> Exit {
+        for _ in 0..INSTRUCTION_LIMIT {
+            if let Err(exit) = self.step(bus) {
+                return exit;
+            }
+        }
+        Exit::Fault(format!("Instruction limit reached at {:#010X}", self.registers[PC]))
+    }
+
+    fn set_nz(&mut self, result: u32) {
+        self.n = 0 != result & 0x8000_0000;
+        self.z = 0 == result;
+    }
+
+    fn add_with_carry(&mut self, a: u32, b: u32, carry: bool) -> u32 {
+        let result = a as u64 + b as u64 + carry as u64;
+        self.set_nz(result as u32);
+        self.c = result > 0xFFFF_FFFF;
+        self.v = 0 != (!(a ^ b) & (a ^ result as u32)) & 0x8000_0000;
+        result as u32
+    }
+
+    fn subtract(&mut self, a: u32, b: u32) -> u32 {
+        self.add_with_carry(a, !b, true)
+    }
+
+    // Shift by a register amount (immediate shifts of 0 for LSR/ASR mean 32), sets the carry (unless 'amount' is 0).
+    fn shift(&mut self, shift: Shift, value: u32, amount: u32) -> u32 {
+        if 0 == amount {
+            return value;
+        }
+        let (result, carry) = match shift {
+            Shift::Lsl if amount < 32 => (value << amount, 0 != (value >> (32 - amount)) & 1),
+            Shift::Lsl => (0, 32 == amount && 0 != value & 1),
+            Shift::Lsr if amount < 32 => (value >> amount, 0 != (value >> (amount - 1)) & 1),
+            Shift::Lsr => (0, 32 == amount && 0 != value & 0x8000_0000),
+            Shift::Asr if amount < 32 => (((value as i32) >> amount) as u32, 0 != (value >> (amount - 1)) & 1),
+            Shift::Asr => (((value as i32) >> 31) as u32, 0 != value & 0x8000_0000),
+            Shift::Ror => {
+                let result = value.rotate_right(amount & 31);
+                (result, 0 != result & 0x8000_0000)
+            }
+        };
+        self.c = carry;
+        result
+    }
+
+    fn condition(&self, condition: u16) -> bool {
+        match condition {
+            0x0 => self.z,
+            0x1 => !self.z,
+            0x2 => self.c,
+            0x3 => !self.c,
+            0x4 => self.n,
+            0x5 => !self.n,
+            0x6 => self.v,
+            0x7 => !self.v,
+            0x8 => self.c && !self.z,
+            0x9 => !self.c || self.z,
+            0xA => self.n == self.v,
+            0xB => self.n != self.v,
+            0xC => !self.z && self.n == self.v,
+            _ => self.z || self.n != self.v,
+        }
+    }
+
+    fn branch(&mut self, address: u32) {
+        self.registers[PC] = address & !1;
+        self.cycles += 2;
+    }
+
+    fn load(&mut self, bus: &Bus, address: u32, size: u32) -> Result<u32, Exit> {
+        self.cycles += 1;
+        bus.read(address, size, self.cycles)
+    }
+
+    fn store(&mut self, bus: &mut Bus, address: u32, size: u32, data: u32) -> Result<(), Exit> {
+        self.cycles += 1;
+        bus.write(address, size, data)
+    }
+
+    fn step(&mut self, bus: &mut Bus) -> Result<(), Exit> {
+        let address = self.registers[PC];
+        let instruction = bus.read(address, 2, self.cycles)? as u16;
+        self.registers[PC] = address.wrapping_add(2);
+        self.cycles += 1;
+
+        // PC as an operand (the address of the instruction plus 4).
+        let pc = address.wrapping_add(4);
+        let rd = (instruction & 0x7) as usize;
+        let rs = ((instruction >> 3) & 0x7) as usize;
+        let rb = rs;
+        let offset5 = ((instruction >> 6) & 0x1F) as u32;
+        let r8 = ((instruction >> 8) & 0x7) as usize;
+        let immediate8 = (instruction & 0xFF) as u32;
+
+        match instruction >> 11 {
+            // Move shifted register.
+            0b00000 => self.registers[rd] = self.shift(Shift::Lsl, self.registers[rs], offset5),
+            0b00001 => self.registers[rd] = self.shift(Shift::Lsr, self.registers[rs], if 0 == offset5 { 32 } else { offset5 }),
+            0b00010 => self.registers[rd] = self.shift(Shift::Asr, self.registers[rs], if 0 == offset5 { 32 } else { offset5 }),
+            0b00011 => {
+                // Add/subtract, register or 3 bit immediate.
+                let operand = if 0 != instruction & 0x400 { offset5 & 0x7 } else { self.registers[(offset5 & 0x7) as usize] };
+                self.registers[rd] = if 0 != instruction & 0x200 {
+                    self.subtract(self.registers[rs], operand)
+                } else {
+                    self.add_with_carry(self.registers[rs], operand, false)
+                };
+            }
+            // Move/compare/add/subtract immediate.
+            0b00100 => {
+                self.registers[r8] = immediate8;
+                self.set_nz(immediate8);
+            }
+            0b00101 => {
+                self.subtract(self.registers[r8], immediate8);
+            }
+            0b00110 => self.registers[r8] = self.add_with_carry(self.registers[r8], immediate8, false),
+            0b00111 => self.registers[r8] = self.subtract(self.registers[r8], immediate8),
+            0b01000 if 0 == instruction & 0x400 => self.alu((instruction >> 6) & 0xF, rd, rs),
+            0b01000 => {
+                // Hi register operations/branch exchange.
+                let rd = rd | ((instruction >> 4) & 0x8) as usize;
+                let rs = ((instruction >> 3) & 0xF) as usize;
+                let value = if PC == rs { pc } else { self.registers[rs] };
+                match (instruction >> 8) & 0x3 {
+                    0 => {
+                        let result = if PC == rd { pc } else { self.registers[rd] }.wrapping_add(value);
+                        if PC == rd {
+                            self.branch(result)
+                        } else {
+                            self.registers[rd] = result
+                        }
+                    }
+                    1 => {
+                        self.subtract(if PC == rd { pc } else { self.registers[rd] }, value);
+                    }
+                    2 if PC == rd => self.branch(value),
+                    2 => self.registers[rd] = value,
+                    _ if 0 != value & 1 => self.branch(value),
+                    _ => return Err(Exit::Arm(address)),
+                }
+            }
+            0b01001 => self.registers[r8] = self.load(bus, (pc & !3).wrapping_add(immediate8 << 2), 4)?,
+            0b01010 | 0b01011 => {
+                // Load/store with register offset, and sign extended byte/halfword.
+                let address = self.registers[rb].wrapping_add(self.registers[((instruction >> 6) & 0x7) as usize]);
+                match (instruction >> 9) & 0x7 {
+                    0b000 => self.store(bus, address, 4, self.registers[rd])?,
+                    0b001 => self.store(bus, address, 2, self.registers[rd])?,
+                    0b010 => self.store(bus, address, 1, self.registers[rd])?,
+                    0b011 => self.registers[rd] = self.load(bus, address, 1)? as i8 as u32,
+                    0b100 => self.registers[rd] = self.load(bus, address, 4)?,
+                    0b101 => self.registers[rd] = self.load(bus, address, 2)?,
+                    0b110 => self.registers[rd] = self.load(bus, address, 1)?,
+                    _ => self.registers[rd] = self.load(bus, address, 2)? as i16 as u32,
+                }
+            }
+            // Load/store with immediate offset.
+            0b01100 => self.store(bus, self.registers[rb].wrapping_add(offset5 << 2), 4, self.registers[rd])?,
+            0b01101 => self.registers[rd] = self.load(bus, self.registers[rb].wrapping_add(offset5 << 2), 4)?,
+            0b01110 => self.store(bus, self.registers[rb].wrapping_add(offset5), 1, self.registers[rd])?,
+            0b01111 => self.registers[rd] = self.load(bus, self.registers[rb].wrapping_add(offset5), 1)?,
+            0b10000 => self.store(bus, self.registers[rb].wrapping_add(offset5 << 1), 2, self.registers[rd])?,
+            0b10001 => self.registers[rd] = self.load(bus, self.registers[rb].wrapping_add(offset5 << 1), 2)?,
+            // SP relative load/store.
+            0b10010 => self.store(bus, self.registers[SP].wrapping_add(immediate8 << 2), 4, self.registers[r8])?,
+            0b10011 => self.registers[r8] = self.load(bus, self.registers[SP].wrapping_add(immediate8 << 2), 4)?,
+            // Load address.
+            0b10100 => self.registers[r8] = (pc & !3).wrapping_add(immediate8 << 2),
+            0b10101 => self.registers[r8] = self.registers[SP].wrapping_add(immediate8 << 2),
+            0b10110 | 0b10111 => match (instruction >> 8) & 0xF {
+                0b0000 => {
+                    let offset = (instruction as u32 & 0x7F) << 2;
+                    self.registers[SP] = if 0 != instruction & 0x80 { self.registers[SP].wrapping_sub(offset) } else { self.registers[SP].wrapping_add(offset) };
+                }
+                0b0100 | 0b0101 => {
+                    // PUSH {rlist, LR}
+                    let mut registers: Vec<usize> = (0..8).filter(|r| 0 != instruction & (1 << r)).collect();
+                    if 0 != instruction & 0x100 {
+                        registers.push(LR);
+                    }
+                    let mut address = self.registers[SP].wrapping_sub(4 * registers.len() as u32);
+                    self.registers[SP] = address;
+                    for r in registers {
+                        self.store(bus, address, 4, self.registers[r])?;
+                        address = address.wrapping_add(4);
+                    }
+                }
+                0b1100 | 0b1101 => {
+                    // POP {rlist, PC}
+                    let mut address = self.registers[SP];
+                    for r in (0..8).filter(|r| 0 != instruction & (1 << r)) {
+                        self.registers[r] = self.load(bus, address, 4)?;
+                        address = address.wrapping_add(4);
+                    }
+                    if 0 != instruction & 0x100 {
+                        let target = self.load(bus, address, 4)?;
+                        address = address.wrapping_add(4);
+                        self.registers[SP] = address;
+                        // An ARM address returns (as ARMv5, not ARMv4T, which would stay in Thumb state).
+                        if 0 == target & 1 {
+                            return Err(Exit::Arm(self.registers[PC].wrapping_sub(2)));
+                        }
+                        self.branch(target);
+                    }
+                    self.registers[SP] = address;
+                }
+                _ => return Err(Exit::Fault(format!("Undefined instruction {:04X} at {:#010X}", instruction, address))),
+            },
+            // Multiple load/store (increment after, with write back).
+            0b11000 => {
+                let mut address = self.registers[r8];
+                for r in (0..8).filter(|r| 0 != instruction & (1 << r)) {
+                    self.store(bus, address, 4, self.registers[r])?;
+                    address = address.wrapping_add(4);
+                }
+                self.registers[r8] = address;
+            }
+            0b11001 => {
+                let mut address = self.registers[r8];
+                for r in (0..8).filter(|r| 0 != instruction & (1 << r)) {
+                    self.registers[r] = self.load(bus, address, 4)?;
+                    address = address.wrapping_add(4);
+                }
+                if 0 == instruction & (1 << r8) {
+                    self.registers[r8] = address;
+                }
+            }
+            0b11010 | 0b11011 => match (instruction >> 8) & 0xF {
+                0xE | 0xF => return Err(Exit::Fault(format!("Unsupported instruction {:04X} at {:#010X}", instruction, address))),
+                condition => {
+                    if self.condition(condition) {
+                        self.branch(pc.wrapping_add(((immediate8 as i8 as i32) << 1) as u32));
+                    }
+                }
+            },
+            0b11100 => self.branch(pc.wrapping_add((((instruction as i32) << 21) >> 20) as u32)),
+            // Long branch with link, the offset high bits, then the low bits (and branch).
+            0b11110 => self.registers[LR] = pc.wrapping_add((((instruction as i32) << 21) >> 9) as u32),
+            0b11111 => {
+                let target = self.registers[LR].wrapping_add(((instruction & 0x7FF) as u32) << 1);
+                self.registers[LR] = self.registers[PC] | 1;
+                self.branch(target);
+            }
+            _ => return Err(Exit::Fault(format!("Undefined instruction {:04X} at {:#010X}", instruction, address))),
+        }
+        Ok(())
+    }
+
+    fn alu(&mut self, operation: u16, rd: usize, rs: usize) {
+        let a = self.registers[rd];
+        let b = self.registers[rs];
+        let result = match operation {
+            0x0 => a & b,
+            0x1 => a ^ b,
+            0x2 => self.shift(Shift::Lsl, a, b & 0xFF),
+            0x3 => self.shift(Shift::Lsr, a, b & 0xFF),
+            0x4 => self.shift(Shift::Asr, a, b & 0xFF),
+            0x5 => self.add_with_carry(a, b, self.c),
+            0x6 => self.add_with_carry(a, !b, self.c),
+            0x7 => self.shift(Shift::Ror, a, b & 0xFF),
+            0x8 => {
+                self.set_nz(a & b);
+                return;
+            }
+            0x9 => self.subtract(0, b),
+            0xA => {
+                self.subtract(a, b);
+                return;
+            }
+            0xB => {
+                self.add_with_carry(a, b, false);
+                return;
+            }
+            0xC => a | b,
+            0xD => {
+                self.cycles += 2;
+                a.wrapping_mul(b)
+            }
+            0xE => a & !b,
+            _ => !b,
+        };
+        self.set_nz(result);
+        self.registers[rd] = result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::memory::cartridges::thumb::{Bus, Exit, Thumb, RAM_BASE};
+
+    #[test]
+    fn test_thumb_program() {
+        let program: [u16; 16] = [
+            0xB500, // 00: PUSH {LR}
+            0x2000, // 02: MOVS R0, #0
+            0x210A, // 04: MOVS R1, #10
+            0x1840, // 06: ADDS R0, R0, R1
+            0x3901, // 08: SUBS R1, #1
+            0xD1FC, // 0A: BNE $06
+            0xF000, // 0C: BL $1C
+            0xF806, // 0E:
+            0x4A01, // 10: LDR R2, [PC, #4] ($18)
+            0x6050, // 12: STR R0, [R2, #4]
+            0xBD00, // 14: POP {PC}
+            0x46C0, // 16: NOP (MOV R8, R8)
+            0x0000, // 18: RAM_BASE
+            0x4000, // 1A:
+            0x0040, // 1C: LSLS R0, R0, #1
+            0x4770, // 1E: BX LR
+        ];
+        let rom: Vec<u8> = program.iter().flat_map(|x| x.to_le_bytes()).collect();
+        let mut ram = vec![0_u8; 0x100];
+
+        let mut thumb = Thumb::new();
+        thumb.call(0x00, 0x800, RAM_BASE + 0x100);
+        let exit = thumb.run(&mut Bus { rom: &rom, ram: &mut ram });
+
+        // Sum of 1-10, doubled, stored in RAM, then returns to the ARM address 0x800 (via the stack).
+        assert_eq!(exit, Exit::Arm(0x14));
+        assert_eq!(thumb.register(0), 110);
+        assert_eq!(ram[4..8], [110, 0, 0, 0]);
+        assert_eq!(ram[0xFC..0x100], [0x00, 0x08, 0, 0]);
+        assert_eq!(thumb.register(13), RAM_BASE + 0x100);
+
+        // Flags, 5 - 7 borrows (carry clear): MOVS R0, #5; SUBS R0, #7; BCC $08; BX LR; MOVS R1, #1; BX LR
+        let rom: Vec<u8> = [0x2005_u16, 0x3807, 0xD300, 0x4770, 0x2101, 0x4770].iter().flat_map(|x| x.to_le_bytes()).collect();
+        thumb.call(0x00, 0x800, RAM_BASE + 0x100);
+        assert_eq!(thumb.run(&mut Bus { rom: &rom, ram: &mut ram }), Exit::Arm(0x0A));
+        assert_eq!(thumb.register(0), -2_i32 as u32);
+        assert_eq!(thumb.register(1), 1);
+    }
+}
@@ -1,11 +1,45 @@
 use super::super::clocks;
+use super::super::cpu::pc_state;
 use super::super::io;
+use super::super::state;
 use super::cartridge;
 
+// The CPU's view of memory, every access is a bus cycle (at the time of the clock).  The console's memory map is
+// 'Memory', tests can run the CPU against a flat bus.
+pub trait Bus {
+    fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8;
+    fn write(&mut self, clock: &mut clocks::Clock, address: u16, data: u8);
+
+    fn read_sp(&mut self, clock: &clocks::Clock, address: u8) -> u8 {
+        self.read(clock, address as u16 + Memory::STACK_OFFSET)
+    }
+
+    fn write_sp(&mut self, clock: &mut clocks::Clock, address: u8, data: u8) {
+        self.write(clock, address as u16 + Memory::STACK_OFFSET, data);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// An access matching a (debugger) watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    pub address: u16,
+    pub data: u8,
+    pub access: Access,
+}
+
 pub struct Memory {
     cartridge: Box<dyn cartridge::Cartridge>,
     pub stella: Box<dyn io::StellaIO>,
     pub riot: Box<dyn io::RiotIO>,
+
+    watchpoints: Vec<(u16, Access)>,
+    watch_hit: Option<WatchHit>,
 }
 
 impl Memory {
@@ -17,16 +51,60 @@ impl Memory {
     const RIOT_ADDR: u16 = 0x80;
     const ROM_MASK: u16 = 0xD000;
     const ROM_ADDRLINE: u16 = 0x1000;
+    const RIOT_SELECT: u16 = 0x80;
+    const STELLA_MIRROR: u16 = 0x3F;
+    const RIOT_MIRROR: u16 = 0x2FF;
+    const RIOT_RAM_SELECT: u16 = 0x200; // Low for RAM, high for the I/O and timer.
+    const ADDRESS_MASK: u16 = 0x1FFF; // The 6507 only has 13 address lines.
+
+    pub fn new(cartridge: Box<dyn cartridge::Cartridge>, stella: Box<dyn io::StellaIO>, riot: Box<dyn io::RiotIO>) -> Self {
+        Self { cartridge, stella, riot, watchpoints: Vec::new(), watch_hit: None }
+    }
 
-    pub fn new(cartridge_name: &String, cartridge_type: &cartridge::CartridgeType, stella: Box<dyn io::StellaIO>, riot: Box<dyn io::RiotIO>) -> Self {
-        Self { cartridge: cartridge::get_new_carterage(&cartridge_name, cartridge_type), stella, riot }
+    /// Watch for accesses to the addresses (compared on the 6507's 13 address lines, so not the mirrors).
+    pub fn set_watchpoints(&mut self, watchpoints: &[(u16, Access)]) {
+        self.watchpoints = watchpoints.to_vec();
+        self.watch_hit = None;
     }
 
-    pub fn reset(&mut self, cartridge_name: &String, cartridge_type: &cartridge::CartridgeType) {
-        self.cartridge = cartridge::get_new_carterage(cartridge_name, cartridge_type);
+    /// The first watched access since the last call.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
     }
 
-    pub fn write(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+    fn check_watchpoints(&mut self, address: u16, data: u8, access: Access) {
+        let watched = self.watchpoints.iter().any(|&(watch_address, watch_access)| watch_access == access && 0 == (watch_address ^ address) & Memory::ADDRESS_MASK);
+        if watched && self.watch_hit.is_none() {
+            self.watch_hit = Some(WatchHit { address: address & Memory::ADDRESS_MASK, data, access });
+        }
+    }
+
+    pub fn cartridge_hash(&self) -> String {
+        self.cartridge.hash()
+    }
+
+    pub fn cartridge_type(&self) -> cartridge::CartridgeType {
+        self.cartridge.cartridge_type()
+    }
+
+    pub fn cartridge_bank(&self) -> usize {
+        self.cartridge.bank()
+    }
+
+    /// Read without any side effects (for the trace/debugger): the cartridge's ROM/RAM and the RIOT RAM, the TIA and
+    /// RIOT registers read as 0.
+    pub fn peek(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        let address = address & Memory::ADDRESS_MASK;
+        if (address & Memory::ROM_ADDRLINE) == Memory::ROM_ADDRLINE {
+            self.cartridge.peek(address & !Memory::ROM_MASK)
+        } else if Memory::RIOT_SELECT == address & (Memory::RIOT_SELECT | Memory::RIOT_RAM_SELECT) {
+            self.riot.read(clock, address)
+        } else {
+            0
+        }
+    }
+
+    fn write_bus(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
         if ((address & 0xFFEF) & Memory::STELLA_MASK) == Memory::STELLA_ADDR {
             self.stella.write(clock, address & !Memory::STELLA_MASK, data);
         } else if (address & Memory::RIOT_MASK) == Memory::RIOT_ADDR {
@@ -35,17 +113,21 @@ impl Memory {
             self.riot.write(clock, address, data);
         } else if (address & Memory::ROM_ADDRLINE) == Memory::ROM_ADDRLINE {
             // Only address lines 1-13 are connected, higher bits ignored.
-            return self.cartridge.write(address & !Memory::ROM_MASK, data);
+            self.cartridge.write(clock, address & !Memory::ROM_MASK, data);
+            let stall_cycles = self.cartridge.take_stall_cycles();
+            clock.increment((stall_cycles * pc_state::PcState::CYCLES_TO_CLOCK as u64) as u32);
+        } else if 0 == address & Memory::RIOT_SELECT {
+            // Mirrors not covered above (A12 low), A7 selects the TIA or RIOT.
+            self.stella.write(clock, address & Memory::STELLA_MIRROR, data);
         } else {
-            println!("Write: {:#X}", address);
-            panic!("invalid_write_address {}", address);
+            self.riot.write(clock, address & Memory::RIOT_MIRROR, data);
         }
     }
 
-    pub fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+    fn read_bus(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
         // Only address lines 1-13 are connected, higher bits ignored.
         if (address & Memory::ROM_ADDRLINE) == Memory::ROM_ADDRLINE {
-            return self.cartridge.read(address & !Memory::ROM_MASK);
+            return self.cartridge.read(clock, address & !Memory::ROM_MASK);
         }
 
         if (address & Memory::RIOT_MASK) == Memory::RIOT_ADDR {
@@ -60,18 +142,48 @@ impl Memory {
             return self.riot.read(clock, address);
         }
 
-        self.cartridge.read(address & Memory::ROM_MASK)
+        // Mirrors not covered above (A12 low), A7 selects the TIA or RIOT.
+        if 0 == address & Memory::RIOT_SELECT {
+            self.stella.read(clock, address & Memory::STELLA_MIRROR)
+        } else {
+            self.riot.read(clock, address & Memory::RIOT_MIRROR)
+        }
     }
 
     pub fn read16(&mut self, clock: &clocks::Clock, address: u16) -> u16 {
         self.read(clock, address) as u16 + ((self.read(clock, address + 1) as u16) << 8)
     }
+}
+
+impl Bus for Memory {
+    fn write(&mut self, clock: &mut clocks::Clock, address: u16, data: u8) {
+        self.write_bus(clock, address, data);
+        self.cartridge.observe_bus(address, data, true);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, data, Access::Write);
+        }
+    }
+
+    fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
+        let data = self.read_bus(clock, address);
+        self.cartridge.observe_bus(address, data, false);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, data, Access::Read);
+        }
+        data
+    }
+}
 
-    pub fn read_sp(&mut self, clock: &clocks::Clock, address: u8) -> u8 {
-        self.read(clock, address as u16 + Memory::STACK_LENGTH)
+impl state::Snapshot for Memory {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        self.stella.save_state(state);
+        self.riot.save_state(state);
+        self.cartridge.save_state(state);
     }
 
-    pub fn write_sp(&mut self, clock: &mut clocks::Clock, address: u8, data: u8) {
-        self.write(clock, address as u16 + Memory::STACK_LENGTH, data);
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.stella.load_state(state)?;
+        self.riot.load_state(state)?;
+        self.cartridge.load_state(state)
     }
 }
@@ -1,4 +1,6 @@
 pub mod addressing;
 pub mod cartridge;
+pub mod cartridges;
+#[allow(clippy::module_inception)]
 pub mod memory;
 pub mod riot;
@@ -1,6 +1,7 @@
 use super::super::clocks;
 use super::super::inputs;
 use super::super::io;
+use super::super::state;
 
 #[derive(Clone, Copy)]
 enum Interval {
@@ -10,6 +11,18 @@ enum Interval {
     Tim1024 = 1024,
 }
 
+impl Interval {
+    fn from_value(value: u16) -> std::io::Result<Self> {
+        match value {
+            1 => Ok(Interval::Tim1),
+            8 => Ok(Interval::Tim8),
+            64 => Ok(Interval::Tim64),
+            1024 => Ok(Interval::Tim1024),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid riot interval {}", value))),
+        }
+    }
+}
+
 pub struct Riot {
     //inputs:
     input: inputs::Input,
@@ -20,6 +33,12 @@ pub struct Riot {
 
 type AddressType = u16;
 
+impl Default for Riot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Riot {
     const CYCLES_TO_CLOCK: clocks::ClockType = 3;
     const RAMSIZE: u8 = 128;
@@ -49,7 +68,8 @@ impl Riot {
     pub fn read(&mut self, clock: &clocks::Clock, address: u16) -> u8 {
         let mut value: u8 = 0;
 
-        let future_clock = clock.ticks + 12;
+        // The CPU reads at the exact cycle of the access (the clock is already at the end of the cycle).
+        let future_clock = clock.ticks;
 
         if 0 == (address & Riot::NOT_RAMSELECT) {
             return self.ram[(address as u8 & Riot::RIOT_ADDRMASK) as usize];
@@ -116,6 +136,22 @@ impl io::ReadWriteMemory for Riot {
     }
 }
 
+impl state::Snapshot for Riot {
+    fn save_state(&self, state: &mut state::StateWriter) {
+        self.input.save_state(state);
+        state.put_u16(self.interval as u16);
+        state.put_u64(self.expiration_time);
+        state.put_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut state::StateReader) -> std::io::Result<()> {
+        self.input.load_state(state)?;
+        self.interval = Interval::from_value(state.get_u16()?)?;
+        self.expiration_time = state.get_u64()?;
+        state.get_bytes_into(&mut self.ram)
+    }
+}
+
 impl io::RiotIO for Riot {
     fn set_inputs(&mut self, inputs: inputs::Input) {
         self.input = inputs;
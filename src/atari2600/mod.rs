@@ -1,9 +1,18 @@
+#[allow(clippy::module_inception)]
 pub mod atari2600;
 pub mod audio;
 pub mod clocks;
+pub mod debugger;
 pub mod cpu;
+pub mod emulator;
 pub mod graphics;
 pub mod inputs;
 pub mod io;
 pub mod memory;
 pub mod ports;
+pub mod properties;
+pub mod replay;
+pub mod rewind;
+pub mod state;
+pub mod symbols;
+pub mod trace;
@@ -4,6 +4,12 @@ pub struct Ports {
     pub joysticks: inputs::Joystick,
 }
 
+impl Default for Ports {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ports {
     pub fn new() -> Self {
         Self { joysticks: inputs::Joystick::new() }
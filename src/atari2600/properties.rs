@@ -0,0 +1,278 @@
+//! ROM properties database.
+//!
+//! Per game settings (cartridge type, TV format, controllers, difficulty switches and vertical
+//! display offset) keyed by the MD5 or SHA1 of the ROM.  Uses the same text format as Stella's
+//! '.pro' files, quoted key/value pairs with entries separated by an empty value:
+//!
+//!   `"Cart.MD5" "0123456789abcdef0123456789abcdef"`
+//!   `"Cart.Name" "Example"`
+//!   `"Cart.Type" "F8SC"`
+//!   `""`
+//!
+//! Supported keys are 'Cart.MD5', 'Cart.SHA1', 'Cart.Name', 'Cart.Type', 'Display.Format',
+//! 'Display.VCenter', 'Controller.Left', 'Controller.Right', 'Console.LeftDifficulty' and
+//! 'Console.RightDifficulty', anything else is ignored.  Lines starting with ';' are comments.
+//!
+//! The built-in database ('properties.pro') can be extended with a user file, user entries
+//! override the built-in values (field by field).
+
+use super::inputs;
+use super::memory::cartridge;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+const BUILT_IN: &str = include_str!("../../properties.pro");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TvFormat {
+    Ntsc,
+    Pal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Controller {
+    Joystick,
+    Paddles,
+    Driving,
+    Keyboard,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    A,
+    B,
+}
+
+/// Properties for a ROM, 'None' for anything that isn't specified.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomProperties {
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
+    pub name: Option<String>,
+    pub cartridge_type: Option<cartridge::CartridgeType>,
+    pub tv_format: Option<TvFormat>,
+    pub left_controller: Option<Controller>,
+    pub right_controller: Option<Controller>,
+    pub left_difficulty: Option<Difficulty>,
+    pub right_difficulty: Option<Difficulty>,
+    pub vertical_offset: Option<i16>, // Scan lines to move the display window down (ie picture up).
+}
+
+impl RomProperties {
+    // Left difficulty is SWCHB bit 6, right is bit 7 (set for 'A').
+    const LEFT_DIFFICULTY_MASK: u8 = 0x40;
+    const RIGHT_DIFFICULTY_MASK: u8 = 0x80;
+
+    /// Fill in the fields set in 'other' (overriding existing values).
+    pub fn merge(&mut self, other: &RomProperties) {
+        fn take<T: Clone>(field: &mut Option<T>, other: &Option<T>) {
+            if other.is_some() {
+                field.clone_from(other);
+            }
+        }
+        take(&mut self.md5, &other.md5);
+        take(&mut self.sha1, &other.sha1);
+        take(&mut self.name, &other.name);
+        take(&mut self.cartridge_type, &other.cartridge_type);
+        take(&mut self.tv_format, &other.tv_format);
+        take(&mut self.left_controller, &other.left_controller);
+        take(&mut self.right_controller, &other.right_controller);
+        take(&mut self.left_difficulty, &other.left_difficulty);
+        take(&mut self.right_difficulty, &other.right_difficulty);
+        take(&mut self.vertical_offset, &other.vertical_offset);
+    }
+
+    /// Apply the console switch defaults to the inputs.
+    pub fn apply_input_defaults(&self, input: &mut inputs::Input) {
+        for (difficulty, mask) in [(self.left_difficulty, RomProperties::LEFT_DIFFICULTY_MASK), (self.right_difficulty, RomProperties::RIGHT_DIFFICULTY_MASK)] {
+            match difficulty {
+                Some(Difficulty::A) => input.swchb |= mask,
+                Some(Difficulty::B) => input.swchb &= !mask,
+                None => {}
+            }
+        }
+    }
+
+    fn matches(&self, md5: &str, sha1: &str) -> bool {
+        self.md5.as_deref().is_some_and(|x| x.eq_ignore_ascii_case(md5)) || self.sha1.as_deref().is_some_and(|x| x.eq_ignore_ascii_case(sha1))
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let upper = value.to_ascii_uppercase();
+        match key {
+            "Cart.MD5" => self.md5 = Some(value.to_string()),
+            "Cart.SHA1" => self.sha1 = Some(value.to_string()),
+            "Cart.Name" => self.name = Some(value.to_string()),
+            "Cart.Type" => {
+                self.cartridge_type = match upper.as_str() {
+                    "AUTO" => None,
+                    "2K" | "4K" => Some(cartridge::CartridgeType::Default),
+                    _ => Some(cartridge::CartridgeType::from_str(value).map_err(|_| format!("unknown cartridge type '{}'", value))?),
+                }
+            }
+            "Display.Format" => {
+                self.tv_format = match upper.as_str() {
+                    "NTSC" | "NTSC50" => Some(TvFormat::Ntsc),
+                    "PAL" | "PAL60" => Some(TvFormat::Pal),
+                    _ => None, // 'AUTO' or unsupported (SECAM)
+                }
+            }
+            "Display.VCenter" => self.vertical_offset = Some(value.parse().map_err(|_| format!("invalid vertical offset '{}'", value))?),
+            "Controller.Left" => self.left_controller = Some(RomProperties::parse_controller(&upper)),
+            "Controller.Right" => self.right_controller = Some(RomProperties::parse_controller(&upper)),
+            "Console.LeftDifficulty" => self.left_difficulty = Some(RomProperties::parse_difficulty(&upper)?),
+            "Console.RightDifficulty" => self.right_difficulty = Some(RomProperties::parse_difficulty(&upper)?),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn parse_controller(value: &str) -> Controller {
+        match value {
+            "JOYSTICK" => Controller::Joystick,
+            "PADDLES" | "PADDLES_IAXIS" | "PADDLES_IAXDR" => Controller::Paddles,
+            "DRIVING" => Controller::Driving,
+            "KEYBOARD" => Controller::Keyboard,
+            _ => Controller::Other,
+        }
+    }
+
+    fn parse_difficulty(value: &str) -> Result<Difficulty, String> {
+        match value {
+            "A" => Ok(Difficulty::A),
+            "B" => Ok(Difficulty::B),
+            _ => Err(format!("invalid difficulty '{}'", value)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PropertiesDatabase {
+    entries: Vec<RomProperties>,
+}
+
+impl PropertiesDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn built_in() -> Self {
+        let mut database = PropertiesDatabase::new();
+        database.add(BUILT_IN).expect("Invalid built-in properties");
+        database
+    }
+
+    /// Add the entries from a user file, overriding any earlier entries.
+    pub fn load_file(&mut self, filename: &str) -> std::io::Result<()> {
+        let text = std::fs::read_to_string(filename)?;
+        self.add(&text)
+    }
+
+    /// Parse and add the entries (in '.pro' format).
+    pub fn add(&mut self, text: &str) -> std::io::Result<()> {
+        let mut entry = RomProperties::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('"').skip(1).step_by(2).collect();
+            match fields.as_slice() {
+                [""] => {
+                    if entry.md5.is_some() || entry.sha1.is_some() {
+                        self.entries.push(std::mem::take(&mut entry));
+                    }
+                }
+                [key, value] => entry.set(key, value).map_err(|e| Error::new(ErrorKind::InvalidData, format!("Properties line {}: {}", line_number + 1, e)))?,
+                _ => return Err(Error::new(ErrorKind::InvalidData, format!("Properties line {}: expected '\"key\" \"value\"'", line_number + 1))),
+            }
+        }
+
+        // Allow the last entry to be unterminated.
+        if entry.md5.is_some() || entry.sha1.is_some() {
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Properties for the ROM, combining all of the matching entries (later ones taking precedence).
+    pub fn lookup(&self, rom: &[u8]) -> RomProperties {
+        let md5 = format!("{:x}", md5::compute(rom));
+        let sha1 = sha1::Sha1::from(rom).digest().to_string();
+        self.lookup_hash(&md5, &sha1)
+    }
+
+    /// Properties for the ROM with the (hex) MD5 and SHA1 hashes.
+    pub fn lookup_hash(&self, md5: &str, sha1: &str) -> RomProperties {
+        let mut properties = RomProperties::default();
+        for entry in self.entries.iter().filter(|entry| entry.matches(md5, sha1)) {
+            properties.merge(entry);
+        }
+        properties
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::inputs::Input;
+    use crate::atari2600::memory::cartridge::CartridgeType;
+    use crate::atari2600::properties::{Controller, Difficulty, PropertiesDatabase, TvFormat};
+
+    #[test]
+    fn test_lookup_and_override() {
+        let rom = vec![0xEA_u8; 0x2000];
+        let md5 = format!("{:x}", md5::compute(&rom));
+        let sha1 = sha1::Sha1::from(&rom).digest().to_string();
+
+        let mut database = PropertiesDatabase::new();
+        database
+            .add(&format!(
+                "\"Cart.MD5\" \"{}\"\n\"Cart.Name\" \"Test\"\n\"Cart.Type\" \"F8SC\"\n\"Display.Format\" \"PAL\"\n\"Controller.Left\" \"PADDLES\"\n\"Console.RightDifficulty\" \"A\"\n\"Unknown.Key\" \"x\"\n\"\"\n",
+                md5
+            ))
+            .unwrap();
+        // User override, by SHA1.
+        database.add(&format!("\"Cart.SHA1\" \"{}\"\n\"Display.VCenter\" \"-3\"\n\"Cart.Type\" \"F8\"\n", sha1.to_uppercase())).unwrap();
+        assert_eq!(database.len(), 2);
+
+        let properties = database.lookup(&rom);
+        assert_eq!(properties.name.as_deref(), Some("Test"));
+        assert_eq!(properties.cartridge_type, Some(CartridgeType::F8));
+        assert_eq!(properties.tv_format, Some(TvFormat::Pal));
+        assert_eq!(properties.left_controller, Some(Controller::Paddles));
+        assert_eq!(properties.right_difficulty, Some(Difficulty::A));
+        assert_eq!(properties.vertical_offset, Some(-3));
+
+        let mut input = Input::new();
+        properties.apply_input_defaults(&mut input);
+        assert_eq!(input.swchb, 0xBF);
+
+        assert_eq!(database.lookup(&rom[..0x1000]), Default::default());
+        database.add("\"Cart.MD5\" \"1234\"\n\"Cart.Type\" \"FA\"\n").unwrap();
+        assert_eq!(database.lookup_hash("1234", "").cartridge_type, Some(CartridgeType::Cbs));
+        assert!(database.add("\"Cart.MD5\" \"1234\"\n\"Console.LeftDifficulty\" \"C\"\n").is_err());
+        assert!(PropertiesDatabase::built_in().add("not a property").is_err());
+    }
+
+    #[test]
+    fn test_built_in() {
+        let database = PropertiesDatabase::built_in();
+        assert!(database.len() >= 9);
+
+        let properties = database.lookup_hash("6d842c96d5a01967be9680080dd5be54", "");
+        assert_eq!(properties.name.as_deref(), Some("Pitfall II - Lost Caverns (1984) (Activision)"));
+        assert_eq!(properties.cartridge_type, Some(CartridgeType::Dpc));
+        assert_eq!(database.lookup_hash("3347A6DD59049B15A38394AA2DAFA585", "").cartridge_type, Some(CartridgeType::E0));
+        assert_eq!(database.lookup_hash("5428cdfada281c569c74c7308c7f2c26", "").left_controller, Some(Controller::Paddles));
+    }
+}
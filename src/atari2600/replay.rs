@@ -0,0 +1,249 @@
+//! Input recording and playback ('replay' files).
+//!
+//! A replay stores the console inputs ('inputs::Input') against the frame number they were
+//! applied on, so a session can be re-run exactly.  Inputs are only applied at frame
+//! boundaries (when 'Stella::export' signals a new frame) while a replay is active, this
+//! keeps recording and playback in step.
+//!
+//! The file also holds the ROM hash and cartridge type, playback is refused if they don't
+//! match the loaded cartridge.
+//!
+//! File layout (little endian):
+//!  - Magic "RA26RPLY", format version (u8)
+//!  - ROM MD5 (length prefixed hex string)
+//!  - Cartridge type (length prefixed string)
+//!  - Number of entries (u32)
+//!  - Entries: frame number (u32), 'Input::to_bytes'
+//!
+//! Only frames where the input changed are stored.
+
+use super::inputs;
+use super::memory::cartridge;
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Debug, PartialEq)]
+pub enum ReplayMode {
+    Record,
+    Playback,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ReplayFrame {
+    pub frame: u32,
+    pub input: inputs::Input,
+}
+
+pub struct Replay {
+    filename: String,
+    mode: ReplayMode,
+    rom_hash: String,
+    cartridge_type: String,
+    frames: Vec<ReplayFrame>,
+    position: usize,
+    current_input: Option<inputs::Input>,
+}
+
+impl Replay {
+    const MAGIC: &'static [u8; 8] = b"RA26RPLY";
+    const VERSION: u8 = 1;
+
+    pub fn new_recording(filename: &str, rom_hash: &str, cartridge_type: &cartridge::CartridgeType) -> Self {
+        Self {
+            filename: filename.to_string(),
+            mode: ReplayMode::Record,
+            rom_hash: rom_hash.to_string(),
+            cartridge_type: format!("{:?}", cartridge_type),
+            frames: Vec::new(),
+            position: 0,
+            current_input: None,
+        }
+    }
+
+    /// Load a replay for playback, checking it was recorded against the same cartridge.
+    pub fn load_playback(filename: &str, rom_hash: &str, cartridge_type: &cartridge::CartridgeType) -> std::io::Result<Self> {
+        let mut file = std::fs::File::open(filename)?;
+        let mut replay = Replay::read_from(&mut file)?;
+        replay.filename = filename.to_string();
+        replay.check_cartridge(rom_hash, cartridge_type)?;
+        Ok(replay)
+    }
+
+    pub fn mode(&self) -> &ReplayMode {
+        &self.mode
+    }
+
+    pub fn frames(&self) -> &[ReplayFrame] {
+        &self.frames
+    }
+
+    pub fn check_cartridge(&self, rom_hash: &str, cartridge_type: &cartridge::CartridgeType) -> std::io::Result<()> {
+        if self.rom_hash != rom_hash {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Replay recorded with ROM {}, but loaded ROM is {}", self.rom_hash, rom_hash)));
+        }
+
+        let cartridge_type = format!("{:?}", cartridge_type);
+        if self.cartridge_type != cartridge_type {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Replay recorded with cartridge type {}, but cartridge type is {}", self.cartridge_type, cartridge_type)));
+        }
+
+        Ok(())
+    }
+
+    /// Called at the start of each frame, returns the input to apply for the frame.
+    /// When recording, 'live_input' is stored (if it changed) and returned.
+    pub fn next_input(&mut self, frame: u32, live_input: inputs::Input) -> inputs::Input {
+        match self.mode {
+            ReplayMode::Record => {
+                if self.current_input != Some(live_input) {
+                    self.frames.push(ReplayFrame { frame, input: live_input });
+                    self.current_input = Some(live_input);
+                }
+                live_input
+            }
+            ReplayMode::Playback => {
+                while self.position < self.frames.len() && self.frames[self.position].frame <= frame {
+                    self.current_input = Some(self.frames[self.position].input);
+                    self.position += 1;
+                    if self.position == self.frames.len() {
+                        println!("Replay: last input applied (frame {})", frame);
+                    }
+                }
+                // Keep the quit flag from the 'live' input, so playback can still be stopped.
+                let mut input = self.current_input.unwrap_or_default();
+                input.quit = live_input.quit;
+                input
+            }
+        }
+    }
+
+    /// Go back to the start (on console reset, as the frame numbers restart).  Playback replays from the first input,
+    /// a recording drops the inputs so far, so it's the session from the reset.
+    pub fn restart(&mut self) {
+        if ReplayMode::Record == self.mode {
+            self.frames.clear();
+        }
+        self.position = 0;
+        self.current_input = None;
+    }
+
+    /// Write the recording out (no-op for playback).
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if ReplayMode::Record == self.mode {
+            let mut file = std::fs::File::create(&self.filename)?;
+            self.write_to(&mut file)?;
+            println!("Replay saved: {} ({} entries)", self.filename, self.frames.len());
+        }
+        Ok(())
+    }
+
+    fn write_string<W: Write>(writer: &mut W, value: &str) -> std::io::Result<()> {
+        writer.write_all(&[value.len() as u8])?;
+        writer.write_all(value.as_bytes())
+    }
+
+    fn read_string<R: Read>(reader: &mut R) -> std::io::Result<String> {
+        let mut length = [0_u8; 1];
+        reader.read_exact(&mut length)?;
+        let mut value = vec![0_u8; length[0] as usize];
+        reader.read_exact(&mut value)?;
+        String::from_utf8(value).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(Replay::MAGIC)?;
+        writer.write_all(&[Replay::VERSION])?;
+        Replay::write_string(writer, &self.rom_hash)?;
+        Replay::write_string(writer, &self.cartridge_type)?;
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        for entry in &self.frames {
+            writer.write_all(&entry.frame.to_le_bytes())?;
+            writer.write_all(&entry.input.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut magic = [0_u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != Replay::MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a replay file"));
+        }
+
+        let mut version = [0_u8; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != Replay::VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported replay version {}", version[0])));
+        }
+
+        let rom_hash = Replay::read_string(reader)?;
+        let cartridge_type = Replay::read_string(reader)?;
+
+        let mut count = [0_u8; 4];
+        reader.read_exact(&mut count)?;
+
+        let mut frames = Vec::new();
+        for _ in 0..u32::from_le_bytes(count) {
+            let mut frame = [0_u8; 4];
+            reader.read_exact(&mut frame)?;
+            let mut input = [0_u8; inputs::Input::SERIALISED_SIZE];
+            reader.read_exact(&mut input)?;
+            frames.push(ReplayFrame { frame: u32::from_le_bytes(frame), input: inputs::Input::from_bytes(&input) });
+        }
+
+        Ok(Self {
+            filename: String::new(),
+            mode: ReplayMode::Playback,
+            rom_hash,
+            cartridge_type,
+            frames,
+            position: 0,
+            current_input: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::inputs::Input;
+    use crate::atari2600::memory::cartridge::CartridgeType;
+    use crate::atari2600::replay::Replay;
+
+    #[test]
+    fn test_record_and_playback() {
+        let mut recording = Replay::new_recording("dummy", "0123", &CartridgeType::F8);
+        let mut pressed = Input::new();
+        pressed.swcha = 0xEF;
+        pressed.paddles[1] = 0.25;
+
+        recording.next_input(1, Input::new());
+        recording.next_input(2, Input::new());
+        recording.next_input(3, pressed);
+        recording.next_input(4, Input::new());
+        // Unchanged frames aren't stored.
+        assert_eq!(recording.frames().len(), 3);
+
+        let mut data = Vec::new();
+        recording.write_to(&mut data).unwrap();
+
+        let mut playback = Replay::read_from(&mut data.as_slice()).unwrap();
+        assert!(playback.check_cartridge("0123", &CartridgeType::F8).is_ok());
+        assert!(playback.check_cartridge("0123", &CartridgeType::F8SC).is_err());
+        assert!(playback.check_cartridge("4567", &CartridgeType::F8).is_err());
+
+        assert_eq!(playback.next_input(1, pressed), Input::new());
+        assert_eq!(playback.next_input(2, pressed), Input::new());
+        assert_eq!(playback.next_input(3, Input::new()), pressed);
+        assert_eq!(playback.next_input(4, pressed), Input::new());
+        assert_eq!(playback.next_input(5, pressed), Input::new());
+
+        // Restarted, the inputs are applied again from frame 0.
+        playback.restart();
+        assert_eq!(playback.next_input(1, pressed), Input::new());
+        assert_eq!(playback.next_input(3, Input::new()), pressed);
+
+        recording.restart();
+        assert!(recording.frames().is_empty());
+        recording.next_input(1, pressed);
+        assert_eq!(recording.frames().len(), 1);
+    }
+}
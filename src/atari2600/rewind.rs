@@ -0,0 +1,111 @@
+//! Rewind buffer.
+//!
+//! Machine snapshots ('state::Snapshot') are taken at frame boundaries (every 'interval'
+//! frames), compressed and held in a ring buffer limited by a memory budget, the oldest
+//! snapshots are dropped first.
+//!
+//! Rewinding restores the most recent snapshot from before the frame currently on screen,
+//! the frame is then re-emulated so it's drawn, so each step moves back 'interval' frames.
+
+use miniz_oxide::deflate;
+use miniz_oxide::inflate;
+use std::collections::VecDeque;
+
+struct RewindSnapshot {
+    frame: u32,
+    data: Vec<u8>,
+}
+
+pub struct Rewind {
+    interval: u32,
+    memory_budget: usize,
+    used_memory: usize,
+    snapshots: VecDeque<RewindSnapshot>,
+}
+
+impl Rewind {
+    const COMPRESSION_LEVEL: u8 = 1; // Favour speed, snapshots are taken every frame.
+
+    pub fn new(interval: u32, memory_budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            memory_budget,
+            used_memory: 0,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Snapshots are only taken every 'interval' frames.
+    pub fn is_snapshot_frame(&self, frame: u32) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    pub fn push(&mut self, frame: u32, state: &[u8]) {
+        let data = deflate::compress_to_vec(state, Rewind::COMPRESSION_LEVEL);
+        self.used_memory += data.len();
+        self.snapshots.push_back(RewindSnapshot { frame, data });
+
+        while self.used_memory > self.memory_budget {
+            match self.snapshots.pop_front() {
+                Some(dropped) => self.used_memory -= dropped.data.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Drop all the snapshots (on reset, they're from before it).
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.used_memory = 0;
+    }
+
+    /// Remove and return the latest snapshot taken at or before 'frame' (later snapshots are discarded).
+    pub fn pop_before(&mut self, frame: u32) -> Option<(u32, Vec<u8>)> {
+        while let Some(snapshot) = self.snapshots.pop_back() {
+            self.used_memory -= snapshot.data.len();
+            if snapshot.frame <= frame {
+                return match inflate::decompress_to_vec(&snapshot.data) {
+                    Ok(data) => Some((snapshot.frame, data)),
+                    Err(e) => {
+                        println!("Error decompressing rewind snapshot. {:?}", e);
+                        None
+                    }
+                };
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::rewind::Rewind;
+
+    #[test]
+    fn test_rewind_budget_and_order() {
+        // Small budget, so only the most recent few snapshots remain.
+        let mut rewind = Rewind::new(1, 64);
+        for frame in 0..100_u32 {
+            rewind.push(frame, &[frame as u8; 256]);
+        }
+        assert!(rewind.used_memory() <= 64);
+        assert!(rewind.len() < 100);
+
+        assert_eq!(rewind.pop_before(98), Some((98, vec![98_u8; 256])));
+        assert_eq!(rewind.pop_before(97), Some((97, vec![97_u8; 256])));
+        assert_eq!(rewind.pop_before(0), None);
+        assert!(rewind.is_empty());
+    }
+}
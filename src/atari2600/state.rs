@@ -0,0 +1,199 @@
+//! Machine state snapshots ('save states').
+//!
+//! Each component implements 'Snapshot', writing its state into a flat little endian
+//! byte stream and reading it back in the same order.  Derived data (lookup tables,
+//! pre-calculated scan lines, generated display/audio) isn't stored, it's re-generated
+//! from the restored registers.
+//!
+//! The stream isn't self describing, so the complete snapshot is prefixed with a version
+//! (and the ROM hash), any change to a component's layout should bump 'VERSION'.
+
+use std::io::{Error, ErrorKind};
+
+pub const VERSION: u8 = 2;
+const MAGIC: &[u8; 8] = b"RA26STAT";
+
+pub trait Snapshot {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> std::io::Result<()>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn put_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Length prefixed byte block.
+    pub fn put_bytes(&mut self, value: &[u8]) {
+        self.put_u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn put_string(&mut self, value: &str) {
+        self.put_bytes(value.as_bytes());
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> std::io::Result<&'a [u8]> {
+        if self.position + length > self.data.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "State data truncated"));
+        }
+        let slice = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(slice)
+    }
+
+    pub fn get_u8(&mut self) -> std::io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_bool(&mut self) -> std::io::Result<bool> {
+        Ok(0 != self.get_u8()?)
+    }
+
+    pub fn get_u16(&mut self) -> std::io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn get_u32(&mut self) -> std::io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn get_u64(&mut self) -> std::io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn get_f32(&mut self) -> std::io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn get_bytes(&mut self) -> std::io::Result<Vec<u8>> {
+        let length = self.get_u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    /// Read a byte block that must match the existing size (ie RAM).
+    pub fn get_bytes_into(&mut self, destination: &mut [u8]) -> std::io::Result<()> {
+        let bytes = self.get_bytes()?;
+        if bytes.len() != destination.len() {
+            return Err(Error::new(ErrorKind::InvalidData, format!("State block size mismatch, expected {} got {}", destination.len(), bytes.len())));
+        }
+        destination.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    pub fn get_string(&mut self) -> std::io::Result<String> {
+        String::from_utf8(self.get_bytes()?).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+/// Serialise a complete machine snapshot, with a header to identify the format and ROM.
+pub fn save_machine(rom_hash: &str, machine: &dyn Snapshot) -> Vec<u8> {
+    let mut state = StateWriter::new();
+    for b in MAGIC {
+        state.put_u8(*b);
+    }
+    state.put_u8(VERSION);
+    state.put_string(rom_hash);
+    machine.save_state(&mut state);
+    state.into_inner()
+}
+
+/// Restore a complete machine snapshot, refusing snapshots from other versions or ROMs.  The machine is unchanged
+/// on failure.
+pub fn load_machine(rom_hash: &str, machine: &mut dyn Snapshot, data: &[u8]) -> std::io::Result<()> {
+    let mut state = StateReader::new(data);
+    if state.take(MAGIC.len())? != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Not a save state"));
+    }
+
+    let version = state.get_u8()?;
+    if version != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported save state version {} (expected {})", version, VERSION)));
+    }
+
+    let state_hash = state.get_string()?;
+    if state_hash != rom_hash {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Save state is for ROM {}, but loaded ROM is {}", state_hash, rom_hash)));
+    }
+
+    // Keep the current state, to put back if the snapshot is truncated/corrupt (rather than leave it part loaded).
+    let mut backup = StateWriter::new();
+    machine.save_state(&mut backup);
+    let result = machine.load_state(&mut state);
+    if result.is_err() {
+        machine.load_state(&mut StateReader::new(&backup.into_inner())).expect("Error restoring the state before the failed load");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atari2600::clocks::Clock;
+    use crate::atari2600::memory::riot::Riot;
+    use crate::atari2600::state;
+
+    #[test]
+    fn test_riot_state_round_trip() {
+        let mut clock = Clock::new();
+        clock.increment(1000);
+
+        let mut riot = Riot::new();
+        riot.write(&clock, 0x05, 0x42); // RAM
+        riot.write(&clock, 0x296, 0x20); // TIM64T
+
+        let saved = state::save_machine("abcd", &riot);
+
+        let mut restored = Riot::new();
+        state::load_machine("abcd", &mut restored, &saved).unwrap();
+        assert_eq!(restored.read(&clock, 0x05), 0x42);
+        assert_eq!(restored.read(&clock, 0x284), riot.read(&clock, 0x284));
+
+        // Different ROM or a truncated snapshot are rejected.
+        assert!(state::load_machine("efgh", &mut restored, &saved).is_err());
+        assert!(state::load_machine("abcd", &mut restored, &saved[..saved.len() - 1]).is_err());
+    }
+}
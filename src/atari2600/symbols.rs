@@ -0,0 +1,277 @@
+//! DASM debug symbols.
+//!
+//! Labels and source lines from the '.sym' and '.lst' files DASM writes next to the ROM (with '-s' and '-l'),
+//! for the trace output, the disassembler and the debugger.
+//!
+//! The '.sym' file lists every symbol and its value ('Start  f000  (R )'), but has no bank information, so a
+//! bank switched ROM's labels are ambiguous.  The listing gives the address of each source line, and the
+//! bank comes from the origin ('org') it was assembled at.  Banks are numbered in ROM order (as for the
+//! cartridge's 'bank'), 4K each, counting from the lowest origin in the listing.
+//!
+//! Labels are only taken from the listing for symbols that are in the '.sym' file (DASM doesn't mark labels
+//! in the listing).  Otherwise a symbol with the value of a RAM address ('Counter = $80') is used as its label, as
+//! are symbols with ROM addresses when there's no listing.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const BANK_SIZE: usize = 0x1000;
+
+// The 6507 only has 13 address lines.
+const ADDRESS_MASK: u16 = 0x1FFF;
+
+#[derive(Default)]
+pub struct Symbols {
+    values: HashMap<String, u16>,
+    // First (alphabetically) symbol for each value, from the '.sym' file.
+    names: HashMap<u16, String>,
+    // Labels by bank and address, 'None' for RAM (labels in uninitialised segments).
+    labels: HashMap<(Option<usize>, u16), String>,
+    source: HashMap<(usize, u16), String>,
+}
+
+fn masked(address: u16) -> u16 {
+    address & ADDRESS_MASK
+}
+
+fn is_ram(address: u16) -> bool {
+    (0x80..=0xFF).contains(&address)
+}
+
+fn parse_address(token: &str) -> Option<u16> {
+    if token.len() >= 4 && token.chars().all(|c| c.is_ascii_hexdigit()) {
+        u16::from_str_radix(token, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn is_byte(token: &str) -> bool {
+    token.len() == 2 && token.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+struct ListingLine<'a> {
+    address: u16,
+    rorg: u16,
+    uninitialised: bool,
+    bytes: usize,
+    source: &'a str,
+}
+
+// '     12  f000		       a9 00	   Start      lda	#0'
+fn parse_listing_line(line: &str) -> Option<ListingLine<'_>> {
+    let mut rest = line.trim_start();
+    let next_token = |rest: &mut &str| -> String {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let token = rest[..end].to_string();
+        *rest = rest[end..].trim_start();
+        token
+    };
+
+    next_token(&mut rest).parse::<u32>().ok()?;
+    let address_token = next_token(&mut rest);
+    let (uninitialised, address_token) = match address_token.strip_prefix('U') {
+        Some(address) => (true, address),
+        None => (false, address_token.as_str()),
+    };
+    let address = parse_address(address_token)?;
+
+    // Outside of a segment with an origin (equates etc) the address is unknown.
+    if rest.starts_with("????") {
+        return None;
+    }
+
+    // With 'rorg' the relocated address follows.
+    let mut rorg = address;
+    if let Some(relocated) = rest.split_whitespace().next().and_then(parse_address) {
+        rorg = relocated;
+        next_token(&mut rest);
+    }
+
+    let mut bytes = 0;
+    while rest.split_whitespace().next().is_some_and(is_byte) {
+        next_token(&mut rest);
+        bytes += 1;
+    }
+
+    Some(ListingLine {
+        address,
+        rorg,
+        uninitialised,
+        bytes,
+        source: rest.trim_end(),
+    })
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load '<rom>.sym' and '<rom>.lst' (either may be missing), 'None' if there are neither.
+    pub fn load(rom_filename: &str) -> io::Result<Option<Symbols>> {
+        let path = Path::new(rom_filename);
+        let (sym, lst) = (path.with_extension("sym"), path.with_extension("lst"));
+        if !sym.exists() && !lst.exists() {
+            return Ok(None);
+        }
+
+        let mut symbols = Symbols::new();
+        if sym.exists() {
+            symbols.parse_sym(&fs::read_to_string(&sym)?);
+        }
+        if lst.exists() {
+            symbols.parse_lst(&fs::read_to_string(&lst)?);
+        }
+        Ok(Some(symbols))
+    }
+
+    /// Add the symbols from a '.sym' file, 'NAME  value  (flags)' per line.
+    pub fn parse_sym(&mut self, text: &str) {
+        for line in text.lines().filter(|line| !line.starts_with("---")) {
+            let mut tokens = line.split_whitespace();
+            if let (Some(name), Some(value)) = (tokens.next(), tokens.next()) {
+                if let Ok(value) = u16::from_str_radix(value, 16) {
+                    self.values.insert(name.to_string(), value);
+                    let entry = self.names.entry(masked(value)).or_insert_with(|| name.to_string());
+                    if name < entry.as_str() {
+                        *entry = name.to_string();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Add the labels and source lines from a '.lst' file.  Parse the '.sym' file first, it identifies the labels.
+    pub fn parse_lst(&mut self, text: &str) {
+        let lines: Vec<ListingLine> = text.lines().filter(|line| !line.starts_with("---")).filter_map(parse_listing_line).collect();
+
+        // The ROM starts at the lowest origin with any data.
+        let Some(rom_start) = lines.iter().filter(|line| !line.uninitialised && line.bytes > 0).map(|line| line.address).min() else {
+            return;
+        };
+
+        for line in &lines {
+            let bank = (!line.uninitialised && line.address >= rom_start).then(|| (line.address - rom_start) as usize / BANK_SIZE);
+            let address = masked(line.rorg);
+
+            // The source is kept without the label, it's shown separately.
+            let mut source: Vec<&str> = line.source.split_whitespace().collect();
+            if let Some(name) = source.first().copied() {
+                if self.values.get(name).is_some_and(|value| masked(*value) == address) {
+                    self.labels.entry((bank, address)).or_insert_with(|| name.to_string());
+                    source.remove(0);
+                }
+            }
+
+            if let (Some(bank), true) = (bank, line.bytes > 0) {
+                self.source.entry((bank, address)).or_insert_with(|| source.join(" "));
+            }
+        }
+    }
+
+    /// The label for an address in a bank.
+    pub fn label(&self, bank: usize, address: u16) -> Option<&str> {
+        let address = masked(address);
+        self.labels
+            .get(&(Some(bank), address))
+            .or_else(|| self.labels.get(&(None, address)))
+            .or_else(|| if is_ram(address) || (self.labels.is_empty() && address >= 0x1000) { self.names.get(&address) } else { None })
+            .map(|label| label.as_str())
+    }
+
+    /// The value of a symbol.
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.values.get(name).copied()
+    }
+
+    /// The source line assembled at an address in a bank.
+    pub fn source_line(&self, bank: usize, address: u16) -> Option<&str> {
+        self.source.get(&(bank, masked(address))).map(|line| line.as_str())
+    }
+
+    /// 'label' or 'label+offset' for an address, using the closest label up to 'max_offset' before it.
+    pub fn label_offset(&self, bank: usize, address: u16, max_offset: u16) -> Option<String> {
+        (0..=max_offset).find_map(|offset| {
+            let label = self.label(bank, address.wrapping_sub(offset))?;
+            Some(if offset == 0 { label.to_string() } else { format!("{}+{}", label, offset) })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM: &str = "--- Symbol List (sorted by symbol)
+Counter                  0080              (R )
+Frame                    f00e              (R )
+Loop                     f002              (R )
+Loop2                    f002              (R )
+Other                    f000              (R )
+Start                    f000              (R )
+WSYNC                    0002              (R )
+--- End of Symbol List.
+";
+
+    const LST: &str = "------- FILE test.asm LEVEL 1 PASS 2
+      1  0000 ????				      processor	6502
+      3  0000 ????	       00 02	   WSYNC      =	$02
+      4 U0080 ????				      seg.u	vars
+      5 U0080					      org	$80
+      6 U0080		       00	   Counter    ds	1
+      8  0000 ????				      seg	code
+      9  1000					      org	$1000
+     10  1000					      rorg	$F000
+     11  1000 f000				   Start
+     12  1000 f000	       a9 00		      lda	#0
+     13  1002 f002	       85 02	   Loop       sta	WSYNC
+     14  1004 f004	       4c 02 f0 	      jmp	Loop
+     15  2000					      org	$2000
+     16  2000					      rorg	$F000
+     17  2000 f000				   Other
+     18  2000 f000	       e6 80		      inc	Counter
+";
+
+    fn symbols() -> Symbols {
+        let mut symbols = Symbols::new();
+        symbols.parse_sym(SYM);
+        symbols.parse_lst(LST);
+        symbols
+    }
+
+    #[test]
+    fn test_parse_sym() {
+        let mut symbols = Symbols::new();
+        symbols.parse_sym(SYM);
+        assert_eq!(symbols.address_of("Start"), Some(0xF000));
+        assert_eq!(symbols.address_of("WSYNC"), Some(0x0002));
+        assert_eq!(symbols.address_of("Missing"), None);
+
+        // Without a listing, labels come from the values (RAM and ROM only).
+        assert_eq!(symbols.label(0, 0xF002), Some("Loop"));
+        assert_eq!(symbols.label(0, 0x1002), Some("Loop"));
+        assert_eq!(symbols.label(0, 0x0080), Some("Counter"));
+        assert_eq!(symbols.label(0, 0x0002), None);
+    }
+
+    #[test]
+    fn test_labels_per_bank() {
+        let symbols = symbols();
+        assert_eq!(symbols.label(0, 0xF000), Some("Start"));
+        assert_eq!(symbols.label(1, 0xF000), Some("Other"));
+        assert_eq!(symbols.label(0, 0xF002), Some("Loop"));
+        assert_eq!(symbols.label(1, 0xF002), None);
+        assert_eq!(symbols.label(1, 0x0080), Some("Counter"));
+
+        assert_eq!(symbols.source_line(0, 0xF002), Some("sta WSYNC"));
+        assert_eq!(symbols.source_line(0, 0x1004), Some("jmp Loop"));
+        assert_eq!(symbols.source_line(1, 0xF000), Some("inc Counter"));
+        assert_eq!(symbols.source_line(1, 0xF002), None);
+
+        assert_eq!(symbols.label_offset(0, 0xF005, 3), Some("Loop+3".to_string()));
+        assert_eq!(symbols.label_offset(1, 0xF005, 3), None);
+    }
+}
@@ -0,0 +1,229 @@
+//! Execution trace log, a line per instruction (before it runs).
+//!
+//! Two layouts:
+//!   'default': `F000 0  A9 02     LDA #$02             A:00 X:00 Y:00 S:FD P:24 CYC:7 SL:0 CLK:21` followed by the
+//!              label and source line when there are debug symbols.
+//!   'nintendulator': the layout of the 'nestest' reference logs, used by many 6502 emulators, so traces can be
+//!              diffed line by line, `F000  A9 02     LDA #$02    ...    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+//!              (the TIA scanline and colour clock take the place of the PPU's).
+//!
+//! CYC is the CPU cycle count since power on.  The trace can be limited to a range of PCs (compared on the 6507's
+//! 13 address lines, so mirrors match) and/or a range of frames.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+const ADDRESS_MASK: u16 = 0x1FFF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    Default,
+    Nintendulator,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<TraceFormat, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "default" => Ok(TraceFormat::Default),
+            "nintendulator" | "nestest" => Ok(TraceFormat::Nintendulator),
+            _ => Err(format!("Unknown trace format '{}', expected 'default' or 'nintendulator'", input)),
+        }
+    }
+}
+
+/// The state for an instruction's trace line.
+pub struct TraceLine<'a> {
+    pub pc: u16,
+    pub bank: usize,
+    pub bytes: &'a [u8],
+    pub text: &'a str,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub s: u8,
+    pub p: u8,
+    pub cycle: u64,
+    pub scanline: u64,
+    pub colour_clock: u64,
+    pub label: Option<&'a str>,
+    pub source: Option<&'a str>,
+}
+
+impl fmt::Display for TraceLine<'_> {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(
+            dest,
+            "{:04X} {:<2} {:<9} {:<20} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{:02X} CYC:{} SL:{} CLK:{}",
+            self.pc,
+            self.bank,
+            bytes.join(" "),
+            self.text,
+            self.a,
+            self.x,
+            self.y,
+            self.s,
+            self.p,
+            self.cycle,
+            self.scanline,
+            self.colour_clock
+        )?;
+        match (self.label, self.source) {
+            (Some(label), source) => write!(dest, "  ; {}: {}", label, source.unwrap_or("")),
+            (None, Some(source)) => write!(dest, "  ; {}", source),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl TraceLine<'_> {
+    fn nintendulator(&self) -> String {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        format!(
+            "{:04X}  {:<8}  {:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            self.pc,
+            bytes.join(" "),
+            self.text,
+            self.a,
+            self.x,
+            self.y,
+            self.p,
+            self.s,
+            self.scanline,
+            self.colour_clock,
+            self.cycle
+        )
+    }
+}
+
+/// Parse a PC range, 'start-end' in hex (eg 'F000-F0FF'), or a single address.
+pub fn parse_pc_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let parse = |text: &str| u16::from_str_radix(text.trim().trim_start_matches('$').trim_start_matches("0x"), 16).map_err(|_| format!("Invalid PC range '{}'", text));
+    match text.split_once('-') {
+        Some((start, end)) => Ok(parse(start)?..=parse(end)?),
+        None => parse(text).map(|address| address..=address),
+    }
+}
+
+/// Parse a frame range, 'first-last' (eg '10-20'), 'first-' for all frames from 'first', or a single frame.
+pub fn parse_frame_range(text: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse = |text: &str| text.trim().parse::<u32>().map_err(|_| format!("Invalid frame range '{}'", text));
+    match text.split_once('-') {
+        Some((first, "")) => Ok(parse(first)?..=u32::MAX),
+        Some((first, last)) => Ok(parse(first)?..=parse(last)?),
+        None => parse(text).map(|frame| frame..=frame),
+    }
+}
+
+pub struct Trace {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    pc_range: Option<RangeInclusive<u16>>,
+    frame_range: Option<RangeInclusive<u32>>,
+    frame: u32,
+}
+
+impl Trace {
+    pub fn new(writer: Box<dyn Write>, format: TraceFormat) -> Self {
+        Self { writer, format, pc_range: None, frame_range: None, frame: 0 }
+    }
+
+    /// Trace to a file.
+    pub fn create(filename: &str, format: TraceFormat) -> io::Result<Self> {
+        Ok(Trace::new(Box::new(io::BufWriter::new(fs::File::create(filename)?)), format))
+    }
+
+    pub fn set_pc_range(&mut self, pc_range: Option<RangeInclusive<u16>>) {
+        self.pc_range = pc_range.map(|range| (range.start() & ADDRESS_MASK)..=(range.end() & ADDRESS_MASK));
+    }
+
+    pub fn set_frame_range(&mut self, frame_range: Option<RangeInclusive<u32>>) {
+        self.frame_range = frame_range;
+    }
+
+    /// The current frame number, for the frame range.
+    pub fn set_frame(&mut self, frame: u32) {
+        self.frame = frame;
+    }
+
+    /// Whether an instruction at 'pc' (in the current frame) is traced.
+    pub fn is_active(&self, pc: u16) -> bool {
+        self.pc_range.as_ref().is_none_or(|range| range.contains(&(pc & ADDRESS_MASK))) && self.frame_range.as_ref().is_none_or(|range| range.contains(&self.frame))
+    }
+
+    pub fn format_line(&self, line: &TraceLine) -> String {
+        match self.format {
+            TraceFormat::Default => line.to_string(),
+            TraceFormat::Nintendulator => line.nintendulator(),
+        }
+    }
+
+    /// Write a line, if the write fails the rest of the trace is dropped.
+    pub fn write_line(&mut self, line: &TraceLine) {
+        let text = self.format_line(line);
+        if let Err(err) = writeln!(self.writer, "{}", text) {
+            println!("Error writing the trace, tracing stopped.\n {}", err);
+            self.writer = Box::new(io::sink());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line() -> TraceLine<'static> {
+        TraceLine {
+            pc: 0xF002,
+            bank: 1,
+            bytes: &[0x85, 0x02],
+            text: "STA WSYNC",
+            a: 0x02,
+            x: 0xFF,
+            y: 0,
+            s: 0xFD,
+            p: 0x24,
+            cycle: 7,
+            scanline: 3,
+            colour_clock: 21,
+            label: Some("Loop"),
+            source: Some("sta WSYNC"),
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let trace = Trace::new(Box::new(io::sink()), TraceFormat::Default);
+        assert_eq!(trace.format_line(&line()), "F002 1  85 02     STA WSYNC            A:02 X:FF Y:00 S:FD P:24 CYC:7 SL:3 CLK:21  ; Loop: sta WSYNC");
+
+        let trace = Trace::new(Box::new(io::sink()), TraceFormat::Nintendulator);
+        assert_eq!(trace.format_line(&line()), "F002  85 02     STA WSYNC                       A:02 X:FF Y:00 P:24 SP:FD PPU:  3, 21 CYC:7");
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut trace = Trace::new(Box::new(io::sink()), TraceFormat::Default);
+        assert!(trace.is_active(0xF000));
+
+        trace.set_pc_range(Some(parse_pc_range("F000-F0FF").unwrap()));
+        assert!(trace.is_active(0xF0FF));
+        assert!(trace.is_active(0x1080));
+        assert!(!trace.is_active(0xF100));
+
+        trace.set_frame_range(Some(parse_frame_range("2-").unwrap()));
+        assert!(!trace.is_active(0xF000));
+        trace.set_frame(2);
+        assert!(trace.is_active(0xF000));
+
+        assert_eq!(parse_frame_range("3-5"), Ok(3..=5));
+        assert_eq!(parse_pc_range("$F000"), Ok(0xF000..=0xF000));
+        assert!(parse_pc_range("F000-").is_err());
+        assert_eq!("nestest".parse::<TraceFormat>(), Ok(TraceFormat::Nintendulator));
+    }
+}
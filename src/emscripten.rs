@@ -37,6 +37,25 @@ pub mod emscripten {
         static MAIN_LOOP_CLOSURE: RefCell<Option<Box<dyn FnMut()>>> = RefCell::new(None);
     }
 
+    thread_local! {
+        // Cartridge data supplied from javascript, held until the main loop takes it.
+        static CARTRIDGE_DATA: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+    }
+
+    #[no_mangle]
+    pub extern "C" fn display_data(raw_data_ptr: *const u8, raw_data_length: usize) {
+        // TODO: Although it's possible there's another way (alternate arguments), I'll just use the unsafe option for now.
+        let v = unsafe {std::slice::from_raw_parts(raw_data_ptr, raw_data_length)};
+        println!("Called from javascript {:x} {}", v[0], v.len());
+
+        CARTRIDGE_DATA.with(|d| { *d.borrow_mut() = Some(v.to_vec()); });
+    }
+
+    // Take the cartridge data (if any has been supplied since the last call).
+    pub fn take_cartridge_data() -> Option<Vec<u8>> {
+        CARTRIDGE_DATA.with(|d| d.borrow_mut().take())
+    }
+
     // Schedules the given callback to be run over and over in a loop until it returns MainLoopEvent::Terminate.
     // Retains ownership of the passed callback
     pub fn set_main_loop_callback<F: 'static>(callback: F)
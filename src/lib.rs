@@ -0,0 +1,14 @@
+//! Rusty Atari 2600 emulator library.
+//!
+//! The emulation is frontend agnostic, 'Emulator' takes the ROM data, inputs and runs a frame at
+//! a time, leaving the display/audio (framebuffer and samples) to the caller.  The SDL/headless
+//! frontend ('atari2600::atari2600::Atari2600', used by the binary) is built on top of it.
+
+// The library and binary share the manifest's dependencies, the command line parsing is only used by the binary.
+use argh as _;
+
+pub mod atari2600;
+
+pub use atari2600::emulator::Emulator;
+pub use atari2600::inputs::Input;
+pub use atari2600::memory::cartridge::CartridgeType;
@@ -28,15 +28,22 @@
 //!     (particularly if the args get promoted to be switches/nobs/dials via some
 //!     other mechanism)..
 
-#![allow(dead_code)]
-#![allow(unused_variables)]
-
-mod atari2600;
 
 use argh::FromArgs;
+use rusted_atari2600::atari2600;
 use std::str::FromStr;
 use strum::IntoEnumIterator;
 
+// The binary and library share the manifest's dependencies, these are only used by the library.
+use bitfield as _;
+use hound as _;
+use md5 as _;
+use miniz_oxide as _;
+use sha1 as _;
+use strum_macros as _;
+#[cfg(test)]
+use serde_json as _;
+
 #[cfg(target_os = "emscripten")]
 pub mod emscripten;
 
@@ -48,8 +55,6 @@ impl Drop for Core {
     }
 }
 
-fn default_cart() -> String {"".to_string()}
-
 /// Rusty Atari 2600 Emulator.
 #[derive(FromArgs)]
 struct RustAtari2600Args {
@@ -73,20 +78,94 @@ struct RustAtari2600Args {
     #[argh(switch, short = 'p')]
     pal_palette: bool,
 
+    /// run without a display/audio device (always the case when built without the 'sdl' feature)
+    #[argh(switch)]
+    headless: bool,
+
+    /// run the interactive debugger console (no display/audio device)
+    #[argh(switch, short = 'g')]
+    debugger: bool,
+
+    /// number of frames to run in headless mode (0 to run until the stop clock)
+    #[argh(option, default = "0")]
+    frames: u32,
+
+    /// filename prefix for the headless outputs (<output>.ppm, <output>.wav, <output>.ram)
+    #[argh(option, default = "String::from(\"headless\")")]
+    output: String,
+
     /// list SDL drivers
     #[argh(switch, short = 'l')]
     list_drivers: bool,
 
     /// name of cartridge to run
     #[argh(positional)]
-    cartridge_name: String,
+    cartridge_name: Option<String>,
 
-    /// replay file
+    /// replay file to play back (or record to, with '--record')
     #[argh(option, short = 'r')]
     replay_file: Option<String>,
 
-    /// cartridge type.  (Specifying an invalid option will display available options).
-    #[argh(option, short = 'c', default = "atari2600::memory::cartridge::CartridgeType::Default", from_str_fn(parse_cartridge))]
+    /// record inputs to the replay file, rather than playing it back
+    #[argh(switch)]
+    record: bool,
+
+    /// save state file to load on start-up
+    #[argh(option)]
+    load_state: Option<String>,
+
+    /// memory to use for rewind snapshots, in KB (0, the default, disables rewind)
+    #[argh(option, default = "0")]
+    rewind_memory: usize,
+
+    /// number of frames between rewind snapshots
+    #[argh(option, default = "1")]
+    rewind_interval: u32,
+
+    /// ROM properties file, to add to (or override) the built-in database
+    #[argh(option)]
+    properties: Option<String>,
+
+    /// cartridge type, detected from the ROM if not set.  (Specifying an invalid option will display available options).
+    #[argh(option, short = 'c', default = "atari2600::memory::cartridge::CartridgeType::Auto", from_str_fn(parse_cartridge))]
+    cartridge_type: atari2600::memory::cartridge::CartridgeType,
+
+    /// write an execution trace (a line per instruction) to the file
+    #[argh(option)]
+    trace: Option<String>,
+
+    /// trace layout, 'default' or 'nintendulator' (the 'nestest' log layout)
+    #[argh(option, default = "atari2600::trace::TraceFormat::Default")]
+    trace_format: atari2600::trace::TraceFormat,
+
+    /// only trace PCs in the range, 'start-end' in hex (eg F000-F0FF)
+    #[argh(option, from_str_fn(parse_pc_range))]
+    trace_pc: Option<std::ops::RangeInclusive<u16>>,
+
+    /// only trace frames in the range, 'first-last' or 'first-' (eg 10-20)
+    #[argh(option, from_str_fn(parse_frame_range))]
+    trace_frames: Option<std::ops::RangeInclusive<u32>>,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Disasm(DisasmArgs),
+}
+
+/// Disassemble every bank of a cartridge (to stdout).
+#[derive(FromArgs)]
+#[argh(subcommand, name = "disasm")]
+struct DisasmArgs {
+    /// name of cartridge to disassemble
+    #[argh(positional)]
+    cartridge_name: String,
+
+    /// cartridge type (sets the bank size), detected from the ROM if not set.
+    #[argh(option, short = 'c', default = "atari2600::memory::cartridge::CartridgeType::Auto", from_str_fn(parse_cartridge))]
     cartridge_type: atari2600::memory::cartridge::CartridgeType,
 }
 
@@ -101,6 +180,37 @@ fn parse_cartridge(value: &str) -> Result<atari2600::memory::cartridge::Cartridg
     }
 }
 
+fn parse_pc_range(value: &str) -> Result<std::ops::RangeInclusive<u16>, String> {
+    atari2600::trace::parse_pc_range(value)
+}
+
+fn parse_frame_range(value: &str) -> Result<std::ops::RangeInclusive<u32>, String> {
+    atari2600::trace::parse_frame_range(value)
+}
+
+fn disassemble_cartridge(args: &DisasmArgs) -> std::io::Result<()> {
+    let data = std::fs::read(&args.cartridge_name)?;
+    let cartridge_type = match args.cartridge_type {
+        atari2600::memory::cartridge::CartridgeType::Auto => atari2600::memory::cartridge::detect_cartridge_type(&data),
+        cartridge_type => cartridge_type,
+    };
+
+    let symbols = atari2600::symbols::Symbols::load(&args.cartridge_name)?;
+
+    println!("; {} ({:?})", args.cartridge_name, cartridge_type);
+    for (bank, origin, lines) in atari2600::cpu::disassembler::disassemble_banks(&data, cartridge_type, symbols.as_ref()) {
+        println!("\n; Bank {} at ${:04X}", bank, origin);
+        for line in lines {
+            if let Some(label) = &line.label {
+                println!("{}:", label);
+            }
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sdl")]
 fn full_description_string() -> String {
     let mut description = "Possible audio drivers, to use prefix command with: SDL_AUDIODRIVER=<driver>\n".to_owned();
     description += &sdl2::audio::drivers().map(|s| s.to_string()).reduce(|cur: String, nxt: String| cur + ", " + &nxt).unwrap();
@@ -116,23 +226,56 @@ fn main() {
 
     let args: RustAtari2600Args = argh::from_env();
 
+    if let Some(Command::Disasm(disasm_args)) = &args.command {
+        if let Err(e) = disassemble_cartridge(disasm_args) {
+            panic!("Error disassembling \"{}\".\n {}", disasm_args.cartridge_name, e);
+        }
+        return;
+    }
+    let cartridge_name = args.cartridge_name.clone().unwrap_or_else(|| panic!("No cartridge given."));
+
+    #[cfg(feature = "sdl")]
     if args.list_drivers {
         println!("{}", full_description_string());
     }
+    #[cfg(not(feature = "sdl"))]
+    if args.list_drivers {
+        println!("No SDL drivers, built without the 'sdl' feature.");
+    }
+
+    let headless = args.headless || cfg!(not(feature = "sdl"));
+    if headless && !args.debugger && 0 == args.frames && args.stop_clock.is_none() {
+        panic!("Headless mode needs a limit, set '--frames' and/or '--stop-clock'.");
+    }
+
+    // Headless runs as fast as possible, there's nothing to keep in time with.
+    let realtime = !args.no_delay && !headless;
+
+    let mut atari_machine = atari2600::atari2600::Atari2600::new(args.debug, realtime, args.stop_clock.unwrap_or(0), args.fullscreen, args.pal_palette);
+
+    if let Some(properties_file) = &args.properties {
+        if let Err(e) = atari_machine.emulator().load_properties_file(properties_file) {
+            panic!("Error loading properties \"{}\".\n {}", properties_file, e);
+        }
+    }
 
-    let mut atari_machine = atari2600::atari2600::Atari2600::new(args.debug, !args.no_delay, args.stop_clock.unwrap_or(0), &args.cartridge_name, &args.cartridge_type, args.fullscreen, args.pal_palette);
+    // Rewind needs the display/keyboard, there's no way to use it headless or from the debugger.
+    if args.rewind_memory > 0 && !headless && !args.debugger {
+        atari_machine.emulator().enable_rewind(args.rewind_interval, args.rewind_memory * 1024);
+    }
 
     #[cfg(target_os = "emscripten")]
     {
         let mut main_loop = move || {
-            if atari2600::memory::cartridge::is_cart_ready() {
+            // The cartridge is supplied from javascript (via 'display_data'), (re)start when one arrives.
+            if let Some(data) = emscripten::take_cartridge_data() {
+                atari_machine.insert_cartridge(&cartridge_name, &data, &args.cartridge_type);
                 if !atari_machine.powered {
-                    atari_machine.reset(&args.cartridge_name, &args.cartridge_type);
                     atari_machine.power_atari2600();
-                    false
-                } else {
-                    atari2600::atari2600::Atari2600::run_atari2600(&mut atari_machine)
                 }
+                false
+            } else if atari_machine.powered {
+                atari2600::atari2600::Atari2600::run_atari2600(&mut atari_machine)
             } else {
                 false
             }
@@ -148,8 +291,48 @@ fn main() {
 
     #[cfg(not(target_os = "emscripten"))]
     {
-        atari_machine.power_atari2600();
-        loop {if !atari2600::atari2600::Atari2600::run_atari2600(&mut atari_machine) { break;}} 
+        if let Err(e) = atari_machine.load_cartridge_file(&cartridge_name, &args.cartridge_type) {
+            panic!("Error loading cartridge \"{}\".\n {}", cartridge_name, e);
+        }
+
+        if let Some(trace_file) = &args.trace {
+            match atari2600::trace::Trace::create(trace_file, args.trace_format) {
+                Ok(mut trace) => {
+                    trace.set_pc_range(args.trace_pc.clone());
+                    trace.set_frame_range(args.trace_frames.clone());
+                    atari_machine.emulator().set_trace(Some(trace));
+                }
+                Err(e) => panic!("Error creating trace \"{}\".\n {}", trace_file, e),
+            }
+        }
+
+        if let Some(replay_file) = &args.replay_file {
+            if let Err(e) = atari_machine.emulator().start_replay(replay_file, args.record) {
+                panic!("Error loading replay \"{}\".\n {}", replay_file, e);
+            }
+        }
+
+        if let Some(state_file) = &args.load_state {
+            if let Err(e) = atari_machine.load_state_file(state_file) {
+                panic!("Error loading state \"{}\".\n {}", state_file, e);
+            }
+        }
+
+        if args.debugger {
+            if let Err(e) = atari_machine.run_debugger() {
+                panic!("Debugger error.\n {}", e);
+            }
+        } else if headless {
+            if let Err(e) = atari_machine.run_headless(args.frames, &args.output) {
+                panic!("Error writing headless output \"{}\".\n {}", args.output, e);
+            }
+        } else {
+            #[cfg(feature = "sdl")]
+            {
+                atari_machine.power_atari2600();
+                loop {if !atari2600::atari2600::Atari2600::run_atari2600(&mut atari_machine) { break;}} 
+            }
+        }
     }
 
     println!("Finished");